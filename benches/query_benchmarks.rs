@@ -1,12 +1,26 @@
-use divan::{black_box, Bencher};
+use divan::{Bencher, black_box};
+use rand::Rng;
 use range_filters::{
+    BitVec, Key, RankSelect, XFastTrie,
+    arf::Arf,
+    blocked_bloom_filter::BlockedBloomFilter,
     bloom_filter::BloomFilter,
     data_gen::generate_smooth_u64,
     diva::Diva,
+    grafite_bucket_filter::GrafiteBucketFilter,
     grafite_filter::GrafiteFilter,
-    Key,
+    memento::MementoFilter,
+    prefix_bloom_filter::PrefixBloomFilter,
+    proteus::Proteus,
+    rank,
+    rencoder::REncoder,
+    rosetta_filter::RosettaFilter,
+    select, set_bit,
+    snarf::Snarf,
+    surf::{SuffixMode, Surf},
 };
-use rand::Rng;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
 
 fn main() {
     divan::main();
@@ -77,6 +91,27 @@ fn diva_point_query(bencher: Bencher, size: usize) {
     });
 }
 
+#[divan::bench(args = SIZES)]
+fn diva_point_query_batch(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let diva = Diva::new_with_keys(&keys, 1024, 0.01);
+
+    // same mix as diva_point_query, answered as one batch
+    let mut rng = rand::thread_rng();
+    let query_keys: Vec<Key> = (0..1000)
+        .map(|i| {
+            if i % 2 == 0 {
+                keys[rng.gen_range(0..keys.len())]
+            } else {
+                let idx = rng.gen_range(0..keys.len().saturating_sub(1));
+                (keys[idx] + keys[idx + 1]) / 2
+            }
+        })
+        .collect();
+
+    bencher.bench_local(|| black_box(diva.contains_many(black_box(&query_keys))));
+}
+
 #[divan::bench(args = SIZES)]
 fn diva_range_query_small(bencher: Bencher, size: usize) {
     let keys = generate_smooth_u64(Some(size));
@@ -91,6 +126,15 @@ fn diva_range_query_small(bencher: Bencher, size: usize) {
     });
 }
 
+#[divan::bench(args = SIZES)]
+fn diva_range_query_small_batch(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let diva = Diva::new_with_keys(&keys, 1024, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    bencher.bench_local(|| black_box(diva.range_query_many(black_box(&query_ranges))));
+}
+
 #[divan::bench(args = SIZES)]
 fn diva_range_query_medium(bencher: Bencher, size: usize) {
     let keys = generate_smooth_u64(Some(size));
@@ -181,9 +225,7 @@ fn diva_delete_infix(bencher: Bencher, size: usize) {
 
             (diva, delete_key)
         })
-        .bench_local_values(|(mut diva, delete_key)| {
-            black_box(diva.delete(black_box(delete_key)))
-        });
+        .bench_local_values(|(mut diva, delete_key)| black_box(diva.delete(black_box(delete_key))));
 }
 
 // ============================================================================
@@ -241,6 +283,15 @@ fn bloom_range_query_small(bencher: Bencher, size: usize) {
     });
 }
 
+#[divan::bench(args = SIZES)]
+fn bloom_range_query_small_batch(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let bloom = BloomFilter::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    bencher.bench_local(|| black_box(bloom.range_query_many(black_box(&query_ranges))));
+}
+
 #[divan::bench(args = SIZES)]
 fn bloom_range_query_medium(bencher: Bencher, size: usize) {
     let keys = generate_smooth_u64(Some(size));
@@ -269,6 +320,303 @@ fn bloom_range_query_large(bencher: Bencher, size: usize) {
     });
 }
 
+// ============================================================================
+// Blocked Bloom Filter Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn blocked_bloom_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| {
+        black_box(BlockedBloomFilter::new_with_keys(
+            black_box(&keys),
+            black_box(0.01),
+        ))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn blocked_bloom_point_query(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let bloom = BlockedBloomFilter::new_with_keys(&keys, 0.01);
+
+    let mut rng = rand::thread_rng();
+    let query_keys: Vec<Key> = (0..1000)
+        .map(|i| {
+            if i % 2 == 0 {
+                keys[rng.gen_range(0..keys.len())]
+            } else {
+                rng.gen_range(0..Key::MAX)
+            }
+        })
+        .collect();
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let key = query_keys[query_idx % query_keys.len()];
+        query_idx += 1;
+        black_box(bloom.point_query(black_box(key)))
+    });
+}
+
+// ============================================================================
+// Prefix Bloom Filter Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn prefix_bloom_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| {
+        black_box(PrefixBloomFilter::new_with_keys(
+            black_box(&keys),
+            black_box(24),
+            black_box(0.01),
+        ))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn prefix_bloom_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let prefix_bloom = PrefixBloomFilter::new_with_keys(&keys, 24, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(prefix_bloom.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// Rosetta Filter Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn rosetta_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| {
+        black_box(RosettaFilter::new_with_keys(
+            black_box(&keys),
+            black_box(0.01),
+        ))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn rosetta_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let rosetta = RosettaFilter::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(rosetta.range_query(black_box(start), black_box(end)))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn rosetta_range_query_large(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let rosetta = RosettaFilter::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.4, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(rosetta.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// REncoder Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn rencoder_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| black_box(REncoder::new_with_keys(black_box(&keys), black_box(0.01))));
+}
+
+#[divan::bench(args = SIZES)]
+fn rencoder_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let rencoder = REncoder::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(rencoder.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// ARF Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn arf_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| black_box(Arf::new_with_keys(black_box(&keys), black_box(4 * size))));
+}
+
+#[divan::bench(args = SIZES)]
+fn arf_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let arf = Arf::new_with_keys(&keys, 4 * size);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(arf.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// SuRF Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn surf_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| {
+        black_box(Surf::new_with_keys(
+            black_box(&keys),
+            black_box(SuffixMode::Real(8)),
+        ))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn surf_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let surf = Surf::new_with_keys(&keys, SuffixMode::Real(8));
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(surf.range_query(black_box(start), black_box(end)))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn surf_range_query_large(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let surf = Surf::new_with_keys(&keys, SuffixMode::Real(8));
+    let query_ranges = generate_query_ranges(&keys, 0.4, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(surf.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// Proteus Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn proteus_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let sample = generate_query_ranges(&keys, 0.01, 200);
+
+    bencher.bench_local(|| {
+        black_box(Proteus::new_with_keys(
+            black_box(&keys),
+            black_box(12.0),
+            black_box(&sample),
+        ))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn proteus_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let proteus = Proteus::new_with_keys(&keys, 12.0, &generate_query_ranges(&keys, 0.01, 200));
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(proteus.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// SNARF Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn snarf_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| black_box(Snarf::new_with_keys(black_box(&keys), black_box(0.01))));
+}
+
+#[divan::bench(args = SIZES)]
+fn snarf_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let snarf = Snarf::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(snarf.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// Memento Filter Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn memento_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| {
+        black_box(MementoFilter::new_with_keys(
+            black_box(&keys),
+            black_box(1 << 32),
+            black_box(0.01),
+        ))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn memento_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let memento = MementoFilter::new_with_keys(&keys, 1 << 32, 0.01).unwrap();
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(memento.range_query(black_box(start), black_box(end)))
+    });
+}
+
 // ============================================================================
 // Grafite Filter Benchmarks
 // ============================================================================
@@ -324,6 +672,15 @@ fn grafite_range_query_small(bencher: Bencher, size: usize) {
     });
 }
 
+#[divan::bench(args = SIZES)]
+fn grafite_range_query_small_batch(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let grafite = GrafiteFilter::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    bencher.bench_local(|| black_box(grafite.range_query_many(black_box(&query_ranges))));
+}
+
 #[divan::bench(args = SIZES)]
 fn grafite_range_query_medium(bencher: Bencher, size: usize) {
     let keys = generate_smooth_u64(Some(size));
@@ -351,3 +708,127 @@ fn grafite_range_query_large(bencher: Bencher, size: usize) {
         black_box(grafite.range_query(black_box(start), black_box(end)))
     });
 }
+
+// ============================================================================
+// Grafite Bucketing Filter Benchmarks
+// ============================================================================
+
+#[divan::bench(args = SIZES)]
+fn grafite_bucket_construction(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+
+    bencher.bench_local(|| {
+        black_box(GrafiteBucketFilter::new_with_keys(
+            black_box(&keys),
+            black_box(0.01),
+        ))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn grafite_bucket_range_query_small(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let grafite = GrafiteBucketFilter::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.01, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(grafite.range_query(black_box(start), black_box(end)))
+    });
+}
+
+#[divan::bench(args = SIZES)]
+fn grafite_bucket_range_query_large(bencher: Bencher, size: usize) {
+    let keys = generate_smooth_u64(Some(size));
+    let grafite = GrafiteBucketFilter::new_with_keys(&keys, 0.01);
+    let query_ranges = generate_query_ranges(&keys, 0.4, 1000);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let (start, end) = query_ranges[query_idx % query_ranges.len()];
+        query_idx += 1;
+        black_box(grafite.range_query(black_box(start), black_box(end)))
+    });
+}
+
+// ============================================================================
+// Bitmap Benchmarks
+// ============================================================================
+
+// runends-sized bitmap of the largest size grade with a set bit density, and
+// random ranks to select
+fn random_bitmap(density: f64) -> (Vec<u64>, Vec<usize>) {
+    let mut rng = rand::thread_rng();
+    let num_bits: usize = 2326;
+    let mut data = vec![0u64; num_bits.div_ceil(64)];
+    for pos in 0..num_bits {
+        if rng.gen_bool(density) {
+            set_bit(&mut data, pos);
+        }
+    }
+    let ones = rank(&data, num_bits);
+    let ranks = (0..1000).map(|_| rng.gen_range(0..ones)).collect();
+    (data, ranks)
+}
+
+#[divan::bench(args = [0.1, 0.5, 0.9])]
+fn bitmap_select(bencher: Bencher, density: f64) {
+    let (data, ranks) = random_bitmap(density);
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let rank_val = ranks[query_idx % ranks.len()];
+        query_idx += 1;
+        black_box(select(black_box(&data), black_box(rank_val)))
+    });
+}
+
+#[divan::bench(args = [0.1, 0.5, 0.9])]
+fn rank_select_select(bencher: Bencher, density: f64) {
+    let (data, ranks) = random_bitmap(density);
+    let index = RankSelect::new(BitVec::from_words(data, 2326));
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let rank_val = ranks[query_idx % ranks.len()];
+        query_idx += 1;
+        black_box(black_box(&index).select(black_box(rank_val)))
+    });
+}
+
+// ============================================================================
+// X-Fast Trie Benchmarks
+// ============================================================================
+
+// predecessor queries over the samples of a 1M key Diva, 64-bit keys
+fn bench_x_fast_predecessor<S: BuildHasher + Clone>(bencher: Bencher, mut trie: XFastTrie<S>) {
+    let mut rng = rand::thread_rng();
+    for _ in 0..10_000 {
+        trie.insert(rng.gen_range(0..=Key::MAX));
+    }
+    let queries: Vec<Key> = (0..1000).map(|_| rng.gen_range(0..=Key::MAX)).collect();
+
+    let mut query_idx = 0;
+    bencher.bench_local(|| {
+        let key = queries[query_idx % queries.len()];
+        query_idx += 1;
+        black_box(black_box(&trie).predecessor(black_box(key)))
+    });
+}
+
+#[divan::bench]
+fn x_fast_predecessor_fxhash(bencher: Bencher) {
+    bench_x_fast_predecessor(bencher, XFastTrie::new(64));
+}
+
+#[divan::bench]
+fn x_fast_predecessor_siphash(bencher: Bencher) {
+    bench_x_fast_predecessor(bencher, XFastTrie::with_hasher(64, RandomState::new()));
+}
+
+#[divan::bench(args = [2, 4, 8])]
+fn x_fast_predecessor_level_stride(bencher: Bencher, level_stride: usize) {
+    bench_x_fast_predecessor(bencher, XFastTrie::with_level_stride(64, level_stride));
+}
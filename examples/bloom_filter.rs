@@ -14,7 +14,12 @@ fn main() {
     let mut bloom = BloomFilter::with_false_pos(0.01).expected_items(1000);
 
     let keys = vec![100u64, 500, 1000, 5000, 10000];
-    bloom.insert_all(&keys.iter().map(|&num| num.to_string()).collect::<Vec<String>>());
+    bloom.insert_all(
+        &keys
+            .iter()
+            .map(|&num| num.to_string())
+            .collect::<Vec<String>>(),
+    );
 
     println!("Keys:");
     println!("{:?}", keys);
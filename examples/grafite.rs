@@ -21,7 +21,16 @@ fn main() {
     println!("Range queries:");
     println!("  Range [3, 5] has values: {}", filter.range_query(3, 5));
     println!("  Range [9, 16] has values: {}", filter.range_query(9, 16));
-    println!("  Range [10, 14] has values: {}", filter.range_query(10, 14));
-    println!("  Range [10, 15] has values: {}", filter.range_query(10, 15));
-    println!("  Range [21, 30] has values: {}", filter.range_query(21, 30));
+    println!(
+        "  Range [10, 14] has values: {}",
+        filter.range_query(10, 14)
+    );
+    println!(
+        "  Range [10, 15] has values: {}",
+        filter.range_query(10, 15)
+    );
+    println!(
+        "  Range [21, 30] has values: {}",
+        filter.range_query(21, 30)
+    );
 }
@@ -15,12 +15,9 @@ fn main() {
     println!("testing predecessor queries:");
     let queries = vec![2, 6, 8, 11, 13, 20];
     for query in queries {
-        if let Some(pred) = trie.predecessor(query) {
-            if let Ok(pred_guard) = pred.read() {
-                println!("predecessor of {} is {}", query, pred_guard.key);
-            }
-        } else {
-            println!("predecessor of {} is None", query);
+        match trie.predecessor(query) {
+            Some(pred) => println!("predecessor of {} is {}", query, pred),
+            None => println!("predecessor of {} is None", query),
         }
     }
 }
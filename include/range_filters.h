@@ -0,0 +1,127 @@
+#ifndef RANGE_FILTERS_H
+#define RANGE_FILTERS_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Result of a fallible call, mirroring `RangeFilterError`
+ */
+typedef enum DivaStatus {
+  DivaStatus_Ok = 0,
+  DivaStatus_KeyOutOfUniverse = 1,
+  DivaStatus_PartitionFull = 2,
+  DivaStatus_KeyNotFound = 3,
+  DivaStatus_InvalidConfig = 4,
+  DivaStatus_LockPoisoned = 5,
+  DivaStatus_CorruptedStore = 6,
+  DivaStatus_NullPointer = 7,
+} DivaStatus;
+
+typedef struct Diva Diva;
+
+/**
+ * Bytes allocated by Rust and handed to C, see `diva_serialize`
+ */
+typedef struct DivaBuffer {
+  uint8_t *data;
+  uintptr_t len;
+} DivaBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Build a filter over `len` keys at `keys`, in any order
+ *
+ * Returns null if `keys` is null with a non-zero `len` or if `target_size`
+ * or `fpr` are invalid.
+ *
+ * # Safety
+ * `keys` must point to `len` readable `uint64_t` values or be null when `len` is 0.
+ */
+struct Diva *diva_new_with_keys(const uint64_t *keys, uintptr_t len, uintptr_t target_size, double fpr);
+
+/**
+ * Release a filter. Null is ignored.
+ *
+ * # Safety
+ * `diva` must come from `diva_new_with_keys` or `diva_deserialize` and not be
+ * used afterwards.
+ */
+void diva_free(struct Diva *diva);
+
+/**
+ * Point query; false for a null filter
+ *
+ * # Safety
+ * `diva` must be a live filter or null.
+ */
+bool diva_contains(const struct Diva *diva, uint64_t key);
+
+/**
+ * Range query over [start, end] inclusive; false for a null filter
+ *
+ * # Safety
+ * `diva` must be a live filter or null.
+ */
+bool diva_range_query(const struct Diva *diva, uint64_t start, uint64_t end);
+
+/**
+ * # Safety
+ * `diva` must be a live filter or null, not used concurrently from other threads.
+ */
+enum DivaStatus diva_insert(struct Diva *diva, uint64_t key);
+
+/**
+ * # Safety
+ * `diva` must be a live filter or null, not used concurrently from other threads.
+ */
+enum DivaStatus diva_delete(struct Diva *diva, uint64_t key);
+
+/**
+ * Number of keys in the filter; 0 for a null filter
+ *
+ * # Safety
+ * `diva` must be a live filter or null.
+ */
+uintptr_t diva_num_keys(const struct Diva *diva);
+
+/**
+ * Serialize a filter in the `Diva::save_to` format
+ *
+ * Returns an empty buffer (null `data`) for a null filter. Release the buffer
+ * with `diva_buffer_free`.
+ *
+ * # Safety
+ * `diva` must be a live filter or null.
+ */
+struct DivaBuffer diva_serialize(const struct Diva *diva);
+
+/**
+ * Release a buffer returned by `diva_serialize`
+ *
+ * # Safety
+ * `buffer` must come from `diva_serialize` and not be used afterwards.
+ */
+void diva_buffer_free(struct DivaBuffer buffer);
+
+/**
+ * Open a filter from `len` bytes written by `diva_serialize` or `Diva::save_to`
+ *
+ * Returns null if the bytes are not a valid filter.
+ *
+ * # Safety
+ * `data` must point to `len` readable bytes or be null when `len` is 0.
+ */
+struct Diva *diva_deserialize(const uint8_t *data, uintptr_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RANGE_FILTERS_H */
@@ -0,0 +1,478 @@
+//! Adaptive Range Filter (ARF)
+//!
+//! A binary trie over the key domain: every node halves the range of its
+//! parent and every leaf says whether its range may hold a key. A query visits
+//! the leaves overlapping `[start, end]` and is positive if one is occupied.
+//! Occupied leaves cover every key, so there are no false negatives; how many
+//! false positives there are depends on the shape of the trie.
+//!
+//! The shape adapts to query feedback instead of being fixed by the keys. A
+//! range reported as a false positive is escalated: the leaves around its
+//! bounds are split until the range is covered by leaves of its own, which are
+//! marked empty. To stay within `max_leaves`, sibling leaves are merged back
+//! into one leaf, occupied if either was, picked by a clock over the inner
+//! nodes: leaves a query was reported on since the clock last passed are
+//! spared once. Regions queried often keep their precision and the rest of
+//! the trie coarsens.
+//!
+//! The trie is kept as an arena of nodes, not in ARF's compact bit encoding,
+//! so the memory use is that of the arena.
+
+use std::collections::VecDeque;
+
+use crate::Key;
+use crate::range_filter::RangeFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    // left and right child
+    Inner([u32; 2]),
+    Leaf { occupied: bool, used: bool },
+    // arena slot on the free list, not part of the trie
+    Free,
+}
+
+const ROOT: u32 = 0;
+
+pub struct Arf {
+    nodes: Vec<Node>,
+    // arena slots of merged nodes, reused by splits
+    free: Vec<u32>,
+    num_leaves: usize,
+    max_leaves: usize,
+    // next arena slot the clock looks at
+    clock: usize,
+    num_keys: usize,
+}
+
+/// keys below a node at `depth` besides its lowest one
+fn span(depth: u32) -> u64 {
+    u64::MAX.checked_shr(depth).unwrap_or(0)
+}
+
+/// lowest key of the right child of a node at `depth` starting at `lo`
+fn right_child_lo(lo: Key, depth: u32) -> Key {
+    lo | (1 << (63 - depth))
+}
+
+impl Arf {
+    /// Create a new ARF with the given keys and at most `max_leaves` leaves.
+    ///
+    /// The trie starts out split breadth first around the keys, so the
+    /// coarsest gaps between them are empty leaves until the budget is used
+    /// up, and then adapts to `report_false_positive`.
+    ///
+    /// Panics if `max_leaves` is 0.
+    pub fn new_with_keys(keys: &[Key], max_leaves: usize) -> Self {
+        assert!(max_leaves > 0, "max_leaves must be at least 1");
+        let mut sorted_keys = keys.to_vec();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+
+        let mut filter = Self {
+            nodes: vec![Node::Leaf {
+                occupied: !sorted_keys.is_empty(),
+                used: false,
+            }],
+            free: Vec::new(),
+            num_leaves: 1,
+            max_leaves,
+            clock: 0,
+            num_keys: keys.len(),
+        };
+        // (node, depth, lowest key, keys in the node), occupied leaves only
+        let mut queue = VecDeque::from([(ROOT, 0, 0, &sorted_keys[..])]);
+        while let Some((node, depth, lo, node_keys)) = queue.pop_front() {
+            // a leaf whose keys fill its range is exact already
+            if filter.num_leaves >= max_leaves
+                || node_keys.is_empty()
+                || node_keys.len() as u128 > span(depth) as u128
+            {
+                continue;
+            }
+            let mid = right_child_lo(lo, depth);
+            let (left_keys, right_keys) =
+                node_keys.split_at(node_keys.partition_point(|&k| k < mid));
+            let [left, right] = filter.split(node, false);
+            for (child, child_lo, child_keys) in [(left, lo, left_keys), (right, mid, right_keys)] {
+                filter.nodes[child as usize] = Node::Leaf {
+                    occupied: !child_keys.is_empty(),
+                    used: false,
+                };
+                if !child_keys.is_empty() {
+                    queue.push_back((child, depth + 1, child_lo, child_keys));
+                }
+            }
+        }
+        filter
+    }
+
+    /// turn a leaf into an inner node of two leaves with its occupied bit
+    fn split(&mut self, node: u32, used: bool) -> [u32; 2] {
+        let Node::Leaf { occupied, .. } = self.nodes[node as usize] else {
+            unreachable!("only leaves are split");
+        };
+        let children = [0, 1].map(|_| {
+            let leaf = Node::Leaf { occupied, used };
+            match self.free.pop() {
+                Some(slot) => {
+                    self.nodes[slot as usize] = leaf;
+                    slot
+                }
+                None => {
+                    self.nodes.push(leaf);
+                    (self.nodes.len() - 1) as u32
+                }
+            }
+        });
+        self.nodes[node as usize] = Node::Inner(children);
+        self.num_leaves += 1;
+        children
+    }
+
+    /// Insert a key after construction, marking its leaf occupied.
+    pub fn insert(&mut self, key: Key) {
+        let (mut node, mut depth, mut lo) = (ROOT, 0, 0);
+        while let Node::Inner(children) = self.nodes[node as usize] {
+            let mid = right_child_lo(lo, depth);
+            (node, lo) = if key < mid {
+                (children[0], lo)
+            } else {
+                (children[1], mid)
+            };
+            depth += 1;
+        }
+        self.nodes[node as usize] = Node::Leaf {
+            occupied: true,
+            used: true,
+        };
+        self.num_keys += 1;
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the leaf of the key is occupied
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.range_query(key, key)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// # Returns
+    /// * `true` if a leaf overlapping the range is occupied
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        start <= end && self.query(ROOT, 0, 0, start, end)
+    }
+
+    fn query(&self, node: u32, depth: u32, lo: Key, start: Key, end: Key) -> bool {
+        if lo > end || lo + span(depth) < start {
+            return false;
+        }
+        match self.nodes[node as usize] {
+            Node::Leaf { occupied, .. } => occupied,
+            Node::Inner([left, right]) => {
+                self.query(left, depth + 1, lo, start, end)
+                    || self.query(right, depth + 1, right_child_lo(lo, depth), start, end)
+            }
+            Node::Free => unreachable!("free slots are not linked into the trie"),
+        }
+    }
+
+    /// Adapt to a range the filter reports as non-empty but the backing store
+    /// confirmed empty, so it is not reported again until the trie coarsens.
+    ///
+    /// Splits the leaves around the bounds of the range and marks the ones
+    /// inside it empty, then merges leaves elsewhere to get back within
+    /// `max_leaves`. The range must really be empty: a key inside it would
+    /// become a false negative.
+    ///
+    /// Returns false if the range was already reported empty.
+    pub fn report_false_positive(&mut self, start: Key, end: Key) -> bool {
+        if !self.range_query(start, end) {
+            return false;
+        }
+        self.mark_empty(ROOT, 0, 0, start, end);
+        self.shrink_to_budget();
+        true
+    }
+
+    /// Record a query over `[start, end]` that the backing store confirmed
+    /// non-empty, sparing its leaves from the next pass of the clock.
+    pub fn report_true_positive(&mut self, start: Key, end: Key) {
+        if start <= end {
+            self.mark_used(ROOT, 0, 0, start, end);
+        }
+    }
+
+    fn mark_empty(&mut self, node: u32, depth: u32, lo: Key, start: Key, end: Key) {
+        let hi = lo + span(depth);
+        if lo > end || hi < start {
+            return;
+        }
+        if start <= lo && hi <= end {
+            self.release_children(node);
+            self.nodes[node as usize] = Node::Leaf {
+                occupied: false,
+                used: true,
+            };
+            return;
+        }
+        let children = match self.nodes[node as usize] {
+            Node::Leaf {
+                occupied: false, ..
+            } => return,
+            // partly inside the range, so wider than one key
+            Node::Leaf { occupied: true, .. } => self.split(node, true),
+            Node::Inner(children) => children,
+            Node::Free => unreachable!("free slots are not linked into the trie"),
+        };
+        self.mark_empty(children[0], depth + 1, lo, start, end);
+        let mid = right_child_lo(lo, depth);
+        self.mark_empty(children[1], depth + 1, mid, start, end);
+    }
+
+    fn mark_used(&mut self, node: u32, depth: u32, lo: Key, start: Key, end: Key) {
+        if lo > end || lo + span(depth) < start {
+            return;
+        }
+        match &mut self.nodes[node as usize] {
+            Node::Leaf { used, .. } => *used = true,
+            &mut Node::Inner([left, right]) => {
+                self.mark_used(left, depth + 1, lo, start, end);
+                let mid = right_child_lo(lo, depth);
+                self.mark_used(right, depth + 1, mid, start, end);
+            }
+            Node::Free => unreachable!("free slots are not linked into the trie"),
+        }
+    }
+
+    /// free the subtree below a node, leaving the node itself to the caller
+    ///
+    /// Freed slots become `Node::Free`, so neither the clock nor a later
+    /// release sees their old contents and no slot is freed twice.
+    fn release_children(&mut self, node: u32) {
+        if let Node::Inner(children) = self.nodes[node as usize] {
+            for child in children {
+                self.release_children(child);
+                debug_assert_ne!(self.nodes[child as usize], Node::Free);
+                self.nodes[child as usize] = Node::Free;
+                self.free.push(child);
+            }
+            self.num_leaves -= 1;
+        }
+    }
+
+    /// merge sibling leaves picked by the clock until within `max_leaves`
+    ///
+    /// The clock passes over leaves and free slots, only inner nodes with two
+    /// leaf children are merged.
+    fn shrink_to_budget(&mut self) {
+        while self.num_leaves > self.max_leaves {
+            self.clock = (self.clock + 1) % self.nodes.len();
+            let Node::Inner([left, right]) = self.nodes[self.clock] else {
+                continue;
+            };
+            let (
+                Node::Leaf {
+                    occupied: left_occupied,
+                    used: left_used,
+                },
+                Node::Leaf {
+                    occupied: right_occupied,
+                    used: right_used,
+                },
+            ) = (self.nodes[left as usize], self.nodes[right as usize])
+            else {
+                continue;
+            };
+            if left_used || right_used {
+                // second chance
+                for child in [left, right] {
+                    if let Node::Leaf { used, .. } = &mut self.nodes[child as usize] {
+                        *used = false;
+                    }
+                }
+                continue;
+            }
+            self.release_children(self.clock as u32);
+            self.nodes[self.clock] = Node::Leaf {
+                occupied: left_occupied || right_occupied,
+                used: false,
+            };
+        }
+    }
+
+    /// Get the number of keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of leaves of the trie.
+    pub fn num_leaves(&self) -> usize {
+        self.num_leaves
+    }
+
+    /// Get the most leaves the trie keeps.
+    pub fn max_leaves(&self) -> usize {
+        self.max_leaves
+    }
+
+    /// Get the memory used by the filter: the struct plus the node arena.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + std::mem::size_of_val(self.nodes.as_slice())
+            + std::mem::size_of_val(self.free.as_slice())
+    }
+
+    /// Get the memory per key in bits. The arena grows up to the
+    /// `max_leaves` budget rather than with the keys, so this mostly reflects
+    /// the budget.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for Arf {
+    fn point_query(&self, key: Key) -> bool {
+        Arf::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        Arf::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_no_false_negatives_within_budget() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..2000).map(|_| rng.gen_range(0..1u64 << 40)).collect();
+        let filter = Arf::new_with_keys(&keys, 4000);
+        assert!(filter.num_leaves() <= 4000);
+        for &key in &keys {
+            assert!(filter.point_query(key));
+            assert!(filter.range_query(key.saturating_sub(1000), key));
+        }
+        assert!(!filter.range_query(1 << 40, Key::MAX));
+        assert!(!filter.range_query(10, 5));
+    }
+
+    #[test]
+    fn test_false_positives_adapt_away() {
+        let mut rng = rand::thread_rng();
+        let keys: BTreeSet<Key> = (0..2000).map(|_| rng.gen_range(0..1u64 << 40)).collect();
+        let key_list: Vec<Key> = keys.iter().copied().collect();
+        let mut filter = Arf::new_with_keys(&key_list, 5000);
+
+        // a workload asking a few hot ranges over and over
+        let hot: Vec<(Key, Key)> = (0..20)
+            .map(|_| {
+                let start = rng.gen_range(0..1u64 << 40);
+                (start, start + rng.gen_range(0..1 << 16))
+            })
+            .collect();
+        let mut false_positives = |filter: &mut Arf, adapt: bool| {
+            let mut count = 0;
+            for _ in 0..2000 {
+                let (start, end) = hot[rng.gen_range(0..hot.len())];
+                if keys.range(start..=end).next().is_some() {
+                    assert!(filter.range_query(start, end));
+                    filter.report_true_positive(start, end);
+                } else if filter.range_query(start, end) {
+                    count += 1;
+                    if adapt {
+                        assert!(filter.report_false_positive(start, end));
+                        assert!(!filter.range_query(start, end));
+                        assert!(!filter.report_false_positive(start, end));
+                    }
+                }
+                assert!(filter.num_leaves() <= filter.max_leaves());
+            }
+            count
+        };
+        let before = false_positives(&mut filter, true);
+        let after = false_positives(&mut filter, false);
+        // each hot false positive is reported once and then gone
+        assert!(
+            before <= hot.len() && after == 0,
+            "{} then {}",
+            before,
+            after
+        );
+        assert!(key_list.iter().all(|&key| filter.point_query(key)));
+    }
+
+    #[test]
+    fn test_clock_merges_within_budget() {
+        let keys: Vec<Key> = (0..100).map(|i| i << 30).collect();
+        let mut filter = Arf::new_with_keys(&keys, 1000);
+        assert_eq!(filter.num_leaves(), 1000);
+        for i in 0..100u64 {
+            // split down to single keys at the lower bound
+            let start = (i << 30) + 1;
+            assert!(filter.report_false_positive(start, start + 1000));
+            assert!(filter.num_leaves() <= 1000);
+        }
+        // merging only ever turns leaves occupied
+        assert!(keys.iter().all(|&key| filter.point_query(key)));
+        let last = (99 << 30) + 1;
+        assert!(!filter.range_query(last, last + 1000));
+    }
+
+    #[test]
+    fn test_random_feedback_keeps_every_key() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        for seed in 0..32 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let keys: BTreeSet<Key> = (0..300).map(|_| rng.gen_range(0..1u64 << 20)).collect();
+            let key_list: Vec<Key> = keys.iter().copied().collect();
+            let mut filter = Arf::new_with_keys(&key_list, rng.gen_range(2..1024));
+            for _ in 0..2000 {
+                let start = rng.gen_range(0..1u64 << 20);
+                let width_bits = rng.gen_range(0..20);
+                let end = start + rng.gen_range(0..1 << width_bits);
+                if keys.range(start..=end).next().is_some() {
+                    assert!(filter.range_query(start, end), "seed {}", seed);
+                    filter.report_true_positive(start, end);
+                } else {
+                    filter.report_false_positive(start, end);
+                }
+                assert!(filter.num_leaves() <= filter.max_leaves());
+            }
+            let lost = key_list
+                .iter()
+                .filter(|&&key| !filter.point_query(key))
+                .count();
+            assert_eq!(lost, 0, "seed {} lost keys", seed);
+        }
+    }
+
+    #[test]
+    fn test_insert_and_edges() {
+        let mut filter = Arf::new_with_keys(&[], 200);
+        assert!(!filter.range_query(0, Key::MAX));
+        filter.insert(Key::MAX);
+        assert!(filter.point_query(Key::MAX) && filter.range_query(0, Key::MAX));
+        // one leaf over the whole domain, the first report splits it
+        assert!(filter.report_false_positive(0, 1 << 62));
+        assert!(!filter.range_query(0, 1 << 62));
+        assert!(filter.range_query((1 << 62) + 1, (1 << 62) + 1));
+        assert!(filter.num_leaves() <= 200);
+
+        let exact = Arf::new_with_keys(&[0, 1, 2, 3], 100);
+        assert!(exact.range_query(0, 3) && !exact.range_query(4, Key::MAX));
+    }
+}
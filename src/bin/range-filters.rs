@@ -0,0 +1,226 @@
+//! Build, inspect and query Diva filters from the command line
+//!
+//! ```text
+//! range-filters build <keys-file> <filter-file> [--target-size N] [--fpr F]
+//! range-filters query <filter-file>
+//! range-filters stats <filter-file>
+//! range-filters bench <filter-file> [--queries N] [--range-size N]
+//! ```
+//!
+//! Key files hold one unsigned 64-bit key per line. `query` reads one query per
+//! line from stdin, either `<key>` or `<start> <end>`, and prints `true` or
+//! `false` for each.
+
+use rand::Rng;
+use range_filters::Diva;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process::ExitCode;
+use std::time::Instant;
+
+const USAGE: &str = "usage:
+  range-filters build <keys-file> <filter-file> [--target-size N] [--fpr F]
+  range-filters query <filter-file>             answer `<key>` or `<start> <end>` lines from stdin
+  range-filters stats <filter-file>
+  range-filters bench <filter-file> [--queries N] [--range-size N]";
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("build") => build(&args[1..]),
+        Some("query") => query(&args[1..]),
+        Some("stats") => stats(&args[1..]),
+        Some("bench") => bench(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// positional arguments and `--name value` options of a subcommand
+struct Args<'a> {
+    positional: Vec<&'a str>,
+    options: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Args<'a> {
+    fn parse(args: &'a [String], positional: usize, options: &[&str]) -> Result<Self, String> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if options.contains(&name) => {
+                    let value = args.next().ok_or(format!("--{} needs a value", name))?;
+                    parsed.options.push((name, value));
+                }
+                Some(_) => return Err(format!("unknown option {}\n{}", arg, USAGE)),
+                None => parsed.positional.push(arg),
+            }
+        }
+        if parsed.positional.len() != positional {
+            return Err(format!("expected {} arguments\n{}", positional, USAGE));
+        }
+        Ok(parsed)
+    }
+
+    fn option<T: std::str::FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        match self
+            .options
+            .iter()
+            .rev()
+            .find(|(option, _)| *option == name)
+        {
+            Some((_, value)) => value
+                .parse()
+                .map_err(|_| format!("invalid value for --{}: {}", name, value)),
+            None => Ok(default),
+        }
+    }
+}
+
+fn parse_key(token: &str, line: usize) -> Result<u64, String> {
+    token
+        .parse()
+        .map_err(|_| format!("line {}: invalid key {:?}", line, token))
+}
+
+fn load(path: &str) -> Result<Diva, Box<dyn Error>> {
+    let mut file = BufReader::new(File::open(path).map_err(|e| format!("{}: {}", path, e))?);
+    Ok(Diva::load_from(&mut file).map_err(|e| format!("{}: {}", path, e))?)
+}
+
+fn build(args: &[String]) -> CliResult {
+    let args = Args::parse(args, 2, &["target-size", "fpr"])?;
+    let (keys_path, filter_path) = (args.positional[0], args.positional[1]);
+
+    let reader =
+        BufReader::new(File::open(keys_path).map_err(|e| format!("{}: {}", keys_path, e))?);
+    let mut keys = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let token = line.trim();
+        if !token.is_empty() {
+            keys.push(parse_key(token, index + 1)?);
+        }
+    }
+
+    let start = Instant::now();
+    let diva = Diva::builder()
+        .target_size(args.option("target-size", 1024)?)
+        .fpr(args.option("fpr", 0.01)?)
+        .build_with_keys(&keys)?;
+    let elapsed = start.elapsed();
+
+    let mut writer = BufWriter::new(File::create(filter_path)?);
+    diva.save_to(&mut writer)?;
+    writer.flush()?;
+    eprintln!(
+        "built filter over {} keys in {:.2?}, {:.2} bits/key",
+        diva.num_keys(),
+        elapsed,
+        diva.bits_per_key()
+    );
+    Ok(())
+}
+
+fn query(args: &[String]) -> CliResult {
+    let args = Args::parse(args, 1, &[])?;
+    let diva = load(args.positional[0])?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    for (index, line) in io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let result = match tokens[..] {
+            [] => continue,
+            [key] => diva.contains(parse_key(key, index + 1)?),
+            [start, end] => {
+                diva.range_query(parse_key(start, index + 1)?, parse_key(end, index + 1)?)
+            }
+            _ => {
+                return Err(
+                    format!("line {}: expected `<key>` or `<start> <end>`", index + 1).into(),
+                );
+            }
+        };
+        writeln!(out, "{}", result)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn stats(args: &[String]) -> CliResult {
+    let args = Args::parse(args, 1, &[])?;
+    let diva = load(args.positional[0])?;
+
+    println!("keys:          {}", diva.num_keys());
+    println!("samples:       {}", diva.sample_keys().count());
+    println!("target size:   {}", diva.target_size());
+    println!("target fpr:    {}", diva.fpr());
+    println!("expected fpr:  {:.6}", diva.expected_fpr());
+    println!("size:          {} bytes", diva.size_in_bytes());
+    println!("bits per key:  {:.2}", diva.bits_per_key());
+    Ok(())
+}
+
+fn bench(args: &[String]) -> CliResult {
+    let args = Args::parse(args, 1, &["queries", "range-size"])?;
+    let diva = load(args.positional[0])?;
+    let queries: usize = args.option("queries", 100_000)?;
+    let range_size: u64 = args.option("range-size", 100)?;
+    if queries == 0 || range_size == 0 {
+        return Err("--queries and --range-size must be at least 1".into());
+    }
+
+    // query between the smallest and largest key, where the filter has partitions
+    let (low, high) = match (diva.sample_keys().next(), diva.sample_keys().last()) {
+        (Some(low), Some(high)) if low < high => (low, high),
+        _ => return Err("bench needs a filter with at least two distinct keys".into()),
+    };
+    let mut rng = rand::thread_rng();
+    let points: Vec<u64> = (0..queries).map(|_| rng.gen_range(low..=high)).collect();
+
+    let start = Instant::now();
+    let point_hits = points.iter().filter(|&&key| diva.contains(key)).count();
+    let point_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let range_hits = points
+        .iter()
+        .filter(|&&key| diva.range_query(key, key.saturating_add(range_size - 1)))
+        .count();
+    let range_elapsed = start.elapsed();
+
+    let per_query = |elapsed: std::time::Duration| elapsed.as_nanos() as f64 / queries as f64;
+    println!(
+        "point queries: {:.0} ns/query, {:.4} positive",
+        per_query(point_elapsed),
+        point_hits as f64 / queries as f64
+    );
+    println!(
+        "range queries (size {}): {:.0} ns/query, {:.4} positive",
+        range_size,
+        per_query(range_elapsed),
+        range_hits as f64 / queries as f64
+    );
+    Ok(())
+}
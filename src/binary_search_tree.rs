@@ -1,277 +1,513 @@
+//! Balanced binary search tree over the keys of one y-fast trie group
+//!
+//! Each node pairs a sample key with an optional payload shared as
+//! `Arc<RwLock<P>>`, the InfixStore of the partition starting at that key in
+//! Diva. Besides ordered queries the tree answers which payload covers a key,
+//! see `predecessor_infix_store`, and payloads can be set, swapped and taken
+//! per key without touching the tree shape.
+
 use crate::Key;
 use crate::infix_store::InfixStore;
-use std::fmt;
-use std::sync::{Arc, RwLock};
-
-// #[derive(Debug, Default)]
-// pub struct InfixStore;
+use crate::rep_list::RepPayload;
+use crate::sync::{Arc, RwLock};
+use crate::utils::arc_allocation_size;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::FusedIterator;
+use core::ops::{Bound, RangeBounds};
 
-// TODO: add cached count
-#[derive(Debug, Default)]
-pub struct BinarySearchTreeGroup {
-    pub root: Option<Box<TreeNode>>,
+/// Balanced BST over the keys of one y-fast trie group
+///
+/// Every key can carry a payload of type `P`, an InfixStore in Diva.
+/// Inserts and deletes rebalance with AVL rotations, so the height stays
+/// logarithmic in the group size even for keys arriving in sorted order.
+///
+/// The number of keys is counted on every insert and delete, so nodes should
+/// only be added or removed through those and not by editing `root` directly.
+#[derive(Debug)]
+pub struct BinarySearchTreeGroup<P = InfixStore> {
+    pub root: Option<Box<TreeNode<P>>>,
+    len: usize,
 }
 
-#[derive(Clone, Debug)]
-pub struct TreeNode {
+#[derive(Debug)]
+pub struct TreeNode<P = InfixStore> {
     pub key: Key,
-    pub left: Option<Box<TreeNode>>,
-    pub right: Option<Box<TreeNode>>,
-    pub infix_store: Option<Arc<RwLock<InfixStore>>>,
+    pub left: Option<Box<TreeNode<P>>>,
+    pub right: Option<Box<TreeNode<P>>>,
+    pub infix_store: Option<Arc<RwLock<P>>>,
+    // nodes on the longest path down to a leaf, 1 for a leaf
+    height: u32,
+}
+
+/// the node holding a key and the closest smaller and larger nodes on its search path
+type SearchPath<'a, P> = (
+    Option<&'a TreeNode<P>>,
+    Option<&'a TreeNode<P>>,
+    Option<&'a TreeNode<P>>,
+);
+
+/// nodes taken off a search path from the root down, each with whether the
+/// path went on to its left child
+type DetachedPath<P> = Vec<(Box<TreeNode<P>>, bool)>;
+
+impl<P> Default for BinarySearchTreeGroup<P> {
+    fn default() -> Self {
+        Self { root: None, len: 0 }
+    }
 }
 
 impl BinarySearchTreeGroup {
     pub fn new() -> Self {
-        Self { root: None }
+        Self::default()
     }
 
     pub fn new_with_keys(keys: &[Key]) -> Self {
+        Self::from_keys(keys)
+    }
+}
+
+impl<P> BinarySearchTreeGroup<P> {
+    /// balanced tree over `keys`, without payloads
+    pub fn from_keys(keys: &[Key]) -> Self {
         if keys.is_empty() {
-            return Self { root: None };
+            return Self::default();
         }
 
         let mut sorted_keys = keys.to_vec();
         sorted_keys.sort();
 
-        let root = Self::top_down_bst_insertion(&sorted_keys, 0, sorted_keys.len() as isize - 1);
-        Self { root }
+        let root = Self::top_down_bst_insertion(&sorted_keys);
+        Self {
+            root,
+            len: sorted_keys.len(),
+        }
     }
 
-    fn top_down_bst_insertion(keys: &[Key], start: isize, end: isize) -> Option<Box<TreeNode>> {
-        if start > end {
-            return None;
+    // builds the subtree of every key range in post-order from an explicit
+    // stack: a range is visited once to split it at its middle key and once
+    // more to join the two halves built meanwhile
+    fn top_down_bst_insertion(keys: &[Key]) -> Option<Box<TreeNode<P>>> {
+        let mut ranges = vec![(0, keys.len(), false)];
+        let mut subtrees: Vec<Option<Box<TreeNode<P>>>> = Vec::new();
+        while let Some((start, end, halves_built)) = ranges.pop() {
+            if start == end {
+                subtrees.push(None);
+                continue;
+            }
+
+            let mid = (start + end - 1) / 2;
+            if !halves_built {
+                ranges.push((start, end, true));
+                ranges.push((mid + 1, end, false));
+                ranges.push((start, mid, false));
+                continue;
+            }
+            let right = subtrees.pop().flatten();
+            let left = subtrees.pop().flatten();
+            let mut root = Box::new(TreeNode {
+                key: keys[mid],
+                left,
+                right,
+                infix_store: None,
+                height: 1,
+            });
+            Self::update_height(&mut root);
+            subtrees.push(Some(root));
         }
+        subtrees.pop().flatten()
+    }
+
+    /// number of keys, counted as they are inserted and deleted
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 
-        let mid = ((start + end) / 2) as usize;
-        let root = Box::new(TreeNode {
-            key: keys[mid],
-            left: Self::top_down_bst_insertion(keys, start, mid as isize - 1),
-            right: Self::top_down_bst_insertion(keys, mid as isize + 1, end),
+    /// the smallest key
+    pub fn min(&self) -> Option<Key> {
+        Self::min_key(&self.root)
+    }
+
+    /// the largest key
+    pub fn max(&self) -> Option<Key> {
+        Self::max_key(&self.root)
+    }
+
+    // walks down and relinks the search path with an explicit stack, equal
+    // keys going right
+    pub fn insert(&mut self, key: Key) {
+        let mut path = Vec::new();
+        let mut node = self.root.take();
+        while let Some(mut n) = node {
+            let went_left = key < n.key;
+            node = if went_left {
+                n.left.take()
+            } else {
+                n.right.take()
+            };
+            path.push((n, went_left));
+        }
+        let leaf = Box::new(TreeNode {
+            key,
+            left: None,
+            right: None,
             infix_store: None,
+            height: 1,
         });
-        Some(root)
+        self.root = Self::relink(path, Some(leaf));
+        self.len += 1;
     }
 
-    // TODO: use cached length
-    pub fn len(&self) -> usize {
-        Self::len_recursive(&self.root)
+    /// remove `key` and its payload, returning whether the tree held it
+    ///
+    /// A node with two children takes over the key and payload of its in-order
+    /// successor, which is unlinked from the right subtree in its place.
+    pub fn delete(&mut self, key: Key) -> bool {
+        let mut path = Vec::new();
+        let mut node = self.root.take();
+        let found = loop {
+            match node {
+                Some(n) if n.key == key => break Some(n),
+                Some(mut n) => {
+                    let went_left = key < n.key;
+                    node = if went_left {
+                        n.left.take()
+                    } else {
+                        n.right.take()
+                    };
+                    path.push((n, went_left));
+                }
+                None => break None,
+            }
+        };
+        let Some(mut n) = found else {
+            self.root = Self::relink(path, None);
+            return false;
+        };
+
+        let replacement = match (n.left.take(), n.right.take()) {
+            (Some(left), Some(right)) => {
+                let (successor, right) = Self::take_min(right);
+                n.key = successor.key;
+                n.infix_store = successor.infix_store;
+                n.left = Some(left);
+                n.right = right;
+                let mut node = Some(n);
+                Self::rebalance(&mut node);
+                node
+            }
+            (left, right) => left.or(right),
+        };
+        self.root = Self::relink(path, replacement);
+        self.len -= 1;
+        true
     }
 
-    fn len_recursive(node: &Option<Box<TreeNode>>) -> usize {
-        match node {
-            None => 0,
-            Some(n) => 1 + Self::len_recursive(&n.left) + Self::len_recursive(&n.right),
+    /// hang `subtree` back below the nodes of a detached path, from the bottom
+    /// up, rebalancing every node on the way, and return the new root
+    fn relink(
+        mut path: DetachedPath<P>,
+        mut subtree: Option<Box<TreeNode<P>>>,
+    ) -> Option<Box<TreeNode<P>>> {
+        while let Some((mut n, went_left)) = path.pop() {
+            if went_left {
+                n.left = subtree;
+            } else {
+                n.right = subtree;
+            }
+            subtree = Some(n);
+            Self::rebalance(&mut subtree);
         }
+        subtree
     }
 
-    pub fn insert(&mut self, key: Key) {
-        Self::insert_recursive(&mut self.root, key);
+    /// unlink the node of the smallest key, returning it and the rest of the tree
+    fn take_min(node: Box<TreeNode<P>>) -> (Box<TreeNode<P>>, Option<Box<TreeNode<P>>>) {
+        let mut path = Vec::new();
+        let mut min = node;
+        while let Some(left) = min.left.take() {
+            path.push((min, true));
+            min = left;
+        }
+        let right = min.right.take();
+        (min, Self::relink(path, right))
     }
 
-    fn insert_recursive(node: &mut Option<Box<TreeNode>>, key: Key) {
-        match node {
-            None => {
-                *node = Some(Box::new(TreeNode {
-                    key,
-                    left: None,
-                    right: None,
-                    infix_store: None,
-                }));
+    /// nodes on the longest path from the root down to a leaf, 0 when empty
+    pub fn height(&self) -> usize {
+        Self::height_of(&self.root) as usize
+    }
+
+    fn height_of(node: &Option<Box<TreeNode<P>>>) -> u32 {
+        node.as_ref().map_or(0, |n| n.height)
+    }
+
+    fn update_height(n: &mut TreeNode<P>) {
+        n.height = 1 + Self::height_of(&n.left).max(Self::height_of(&n.right));
+    }
+
+    /// left subtree height minus right subtree height
+    fn balance_factor(n: &TreeNode<P>) -> i64 {
+        Self::height_of(&n.left) as i64 - Self::height_of(&n.right) as i64
+    }
+
+    fn rotate_right(mut n: Box<TreeNode<P>>) -> Box<TreeNode<P>> {
+        let Some(mut left) = n.left.take() else {
+            return n;
+        };
+        n.left = left.right.take();
+        Self::update_height(&mut n);
+        left.right = Some(n);
+        Self::update_height(&mut left);
+        left
+    }
+
+    fn rotate_left(mut n: Box<TreeNode<P>>) -> Box<TreeNode<P>> {
+        let Some(mut right) = n.right.take() else {
+            return n;
+        };
+        n.right = right.left.take();
+        Self::update_height(&mut n);
+        right.left = Some(n);
+        Self::update_height(&mut right);
+        right
+    }
+
+    /// restore the height of a node whose subtrees changed, and rotate it back
+    /// into balance if they now differ in height by more than one
+    fn rebalance(node: &mut Option<Box<TreeNode<P>>>) {
+        let Some(mut n) = node.take() else {
+            return;
+        };
+        Self::update_height(&mut n);
+        let balance = Self::balance_factor(&n);
+        if balance > 1 {
+            if n.left
+                .as_deref()
+                .is_some_and(|left| Self::balance_factor(left) < 0)
+            {
+                n.left = n.left.take().map(Self::rotate_left);
             }
-            Some(n) => {
-                if key < n.key {
-                    Self::insert_recursive(&mut n.left, key);
-                } else {
-                    Self::insert_recursive(&mut n.right, key);
-                }
+            n = Self::rotate_right(n);
+        } else if balance < -1 {
+            if n.right
+                .as_deref()
+                .is_some_and(|right| Self::balance_factor(right) > 0)
+            {
+                n.right = n.right.take().map(Self::rotate_right);
             }
+            n = Self::rotate_left(n);
         }
+        *node = Some(n);
     }
 
     pub fn contains(&self, key: Key) -> bool {
-        Self::contains_recursive(&self.root, key)
+        self.search(key).0.is_some()
     }
 
-    fn contains_recursive(node: &Option<Box<TreeNode>>, key: Key) -> bool {
-        match node {
-            None => false,
-            Some(n) => {
-                if key == n.key {
-                    true
-                } else if key < n.key {
-                    Self::contains_recursive(&n.left, key)
-                } else {
-                    Self::contains_recursive(&n.right, key)
-                }
+    /// follow the search path of `key` down from the root: the node holding
+    /// `key`, if any, and the last nodes passed with a smaller and a larger key
+    fn search(&self, key: Key) -> SearchPath<'_, P> {
+        let (mut below, mut above) = (None, None);
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            if key == n.key {
+                return (Some(n), below, above);
+            } else if key < n.key {
+                above = Some(n);
+                node = n.left.as_deref();
+            } else {
+                below = Some(n);
+                node = n.right.as_deref();
             }
         }
+        (None, below, above)
     }
 
-    fn find_node_mut(node: &mut Option<Box<TreeNode>>, key: Key) -> Option<&mut TreeNode> {
-        match node {
-            None => None,
-            Some(n) => {
-                if key == n.key {
-                    Some(n.as_mut())
-                } else if key < n.key {
-                    Self::find_node_mut(&mut n.left, key)
-                } else {
-                    Self::find_node_mut(&mut n.right, key)
-                }
+    fn find_node_mut(&mut self, key: Key) -> Option<&mut TreeNode<P>> {
+        let mut node = self.root.as_deref_mut();
+        while let Some(n) = node {
+            if key == n.key {
+                return Some(n);
             }
+            node = if key < n.key {
+                n.left.as_deref_mut()
+            } else {
+                n.right.as_deref_mut()
+            };
         }
+        None
     }
 
-    pub fn set_infix_store(&mut self, key: Key, infix_store: InfixStore) {
-        if let Some(node) = Self::find_node_mut(&mut self.root, key) {
+    pub fn set_infix_store(&mut self, key: Key, infix_store: P) {
+        if let Some(node) = self.find_node_mut(key) {
             node.infix_store = Some(Arc::new(RwLock::new(infix_store)));
         }
     }
 
-    pub fn get_infix_store(&self, key: Key) -> Option<Arc<RwLock<InfixStore>>> {
-        Self::get_infix_store_recursive(&self.root, key)
+    /// attach an already shared store to a key, e.g. when moving it between groups
+    pub fn attach_infix_store(&mut self, key: Key, infix_store: Arc<RwLock<P>>) {
+        if let Some(node) = self.find_node_mut(key) {
+            node.infix_store = Some(infix_store);
+        }
     }
 
-    fn get_infix_store_recursive(
-        node: &Option<Box<TreeNode>>,
+    /// swap the store of `key` for `infix_store`, returning the previous one,
+    /// or None if the group does not hold `key`
+    pub fn replace_infix_store(
+        &mut self,
         key: Key,
-    ) -> Option<Arc<RwLock<InfixStore>>> {
-        match node {
-            None => None,
-            Some(n) => {
-                if key == n.key {
-                    n.infix_store.clone()
-                } else if key < n.key {
-                    Self::get_infix_store_recursive(&n.left, key)
-                } else {
-                    Self::get_infix_store_recursive(&n.right, key)
-                }
-            }
+        infix_store: Option<Arc<RwLock<P>>>,
+    ) -> Option<Option<Arc<RwLock<P>>>> {
+        let node = self.find_node_mut(key)?;
+        Some(core::mem::replace(&mut node.infix_store, infix_store))
+    }
+
+    pub fn remove_infix_store(&mut self, key: Key) {
+        if let Some(node) = self.find_node_mut(key) {
+            node.infix_store = None;
         }
     }
 
-    pub fn predecessor_infix_store(&self, key: Key) -> Option<Arc<RwLock<InfixStore>>> {
-        Self::predecessor_store_recursive(&self.root, key, None)
+    /// detach the store of `key` and return it, the key stays in the tree
+    pub fn take_infix_store(&mut self, key: Key) -> Option<Arc<RwLock<P>>> {
+        self.find_node_mut(key)?.infix_store.take()
     }
 
-    pub fn predecessor(&self, key: Key) -> Option<Key> {
-        Self::predecessor_recursive(&self.root, key, None)
+    pub fn get_infix_store(&self, key: Key) -> Option<Arc<RwLock<P>>> {
+        self.search(key).0?.infix_store.clone()
     }
 
-    fn predecessor_recursive(
-        node: &Option<Box<TreeNode>>,
-        key: Key,
-        best: Option<Key>,
-    ) -> Option<Key> {
-        match node {
-            None => best,
-            Some(n) => {
-                if n.key == key {
-                    Some(n.key)
-                } else if key < n.key {
-                    Self::predecessor_recursive(&n.left, key, best)
-                } else {
-                    Self::predecessor_recursive(&n.right, key, Some(n.key))
-                }
+    /// the keys in sorted order with their payloads
+    pub fn entries(&self) -> Vec<(Key, Option<Arc<RwLock<P>>>)> {
+        self.iter()
+            .map(|(key, infix_store)| (key, infix_store.cloned()))
+            .collect()
+    }
+
+    /// empty the tree, moving its keys out in sorted order with their payloads
+    pub fn drain(&mut self) -> Vec<(Key, Option<Arc<RwLock<P>>>)> {
+        let mut entries = Vec::with_capacity(self.len);
+        let mut stack: Vec<Box<TreeNode<P>>> = Vec::new();
+        let mut node = self.root.take();
+        self.len = 0;
+        loop {
+            while let Some(mut n) = node {
+                node = n.left.take();
+                stack.push(n);
             }
+            let Some(mut n) = stack.pop() else {
+                break;
+            };
+            node = n.right.take();
+            entries.push((n.key, n.infix_store.take()));
         }
+        entries
     }
 
-    pub fn successor(&self, key: Key) -> Option<Key> {
-        Self::successor_recursive(&self.root, key, None)
+    /// consume the tree and return its keys in sorted order
+    pub fn into_sorted_vec(mut self) -> Vec<Key> {
+        self.drain().into_iter().map(|(key, _)| key).collect()
     }
 
-    fn successor_recursive(
-        node: &Option<Box<TreeNode>>,
-        key: Key,
-        best: Option<Key>,
-    ) -> Option<Key> {
-        match node {
-            None => best,
-            Some(n) => {
-                if n.key == key {
-                    Some(n.key)
-                } else if key < n.key {
-                    Self::successor_recursive(&n.left, key, Some(n.key))
-                } else {
-                    Self::successor_recursive(&n.right, key, best)
-                }
+    /// keys in ascending order with their payloads, see `range`
+    pub fn iter(&self) -> BstIter<'_, P> {
+        self.range(..)
+    }
+
+    /// keys within `range` in ascending order with their payloads
+    ///
+    /// Walks the tree with an explicit stack of the nodes still to visit, at most
+    /// the tree height deep, and borrows the payloads instead of cloning them.
+    pub fn range<R: RangeBounds<Key>>(&self, range: R) -> BstIter<'_, P> {
+        let mut stack = Vec::new();
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            let after_start = match range.start_bound() {
+                Bound::Included(&start) => n.key >= start,
+                Bound::Excluded(&start) => n.key > start,
+                Bound::Unbounded => true,
+            };
+            if after_start {
+                stack.push(n);
+                node = n.left.as_deref();
+            } else {
+                node = n.right.as_deref();
             }
         }
+        BstIter {
+            stack,
+            end: range.end_bound().cloned(),
+        }
     }
 
-    fn predecessor_store_recursive(
-        node: &Option<Box<TreeNode>>,
-        key: Key,
-        best: Option<Arc<RwLock<InfixStore>>>,
-    ) -> Option<Arc<RwLock<InfixStore>>> {
-        match node {
-            None => best,
-            Some(n) => {
-                if n.key == key {
-                    n.infix_store.clone().or(best)
-                } else if key < n.key {
-                    Self::predecessor_store_recursive(&n.left, key, best)
-                } else {
-                    Self::predecessor_store_recursive(&n.right, key, n.infix_store.clone())
-                }
-            }
+    /// the largest key at most `key`, with its store
+    pub fn floor_entry(&self, key: Key) -> Option<(Key, Option<Arc<RwLock<P>>>)> {
+        let (exact, below, _) = self.search(key);
+        exact.or(below).map(|n| (n.key, n.infix_store.clone()))
+    }
+
+    /// the smallest key at least `key`, with its store
+    pub fn ceiling_entry(&self, key: Key) -> Option<(Key, Option<Arc<RwLock<P>>>)> {
+        let (exact, _, above) = self.search(key);
+        exact.or(above).map(|n| (n.key, n.infix_store.clone()))
+    }
+
+    pub fn predecessor_infix_store(&self, key: Key) -> Option<Arc<RwLock<P>>> {
+        let (exact, below, _) = self.search(key);
+        let below = below.and_then(|n| n.infix_store.clone());
+        match exact {
+            Some(n) => n.infix_store.clone().or(below),
+            None => below,
         }
     }
 
-    pub fn successor_infix_store(&self, key: Key) -> Option<Arc<RwLock<InfixStore>>> {
-        Self::successor_store_recursive(&self.root, key, None)
+    /// the largest key at most `key`
+    pub fn predecessor(&self, key: Key) -> Option<Key> {
+        let (exact, below, _) = self.search(key);
+        exact.or(below).map(|n| n.key)
     }
 
-    fn successor_store_recursive(
-        node: &Option<Box<TreeNode>>,
-        key: Key,
-        best: Option<Arc<RwLock<InfixStore>>>,
-    ) -> Option<Arc<RwLock<InfixStore>>> {
-        match node {
-            None => best,
-            Some(n) => {
-                if n.key == key {
-                    n.infix_store.clone().or(best)
-                } else if key < n.key {
-                    Self::successor_store_recursive(&n.left, key, n.infix_store.clone())
-                } else {
-                    Self::successor_store_recursive(&n.right, key, best)
-                }
-            }
+    /// the smallest key at least `key`
+    pub fn successor(&self, key: Key) -> Option<Key> {
+        let (exact, _, above) = self.search(key);
+        exact.or(above).map(|n| n.key)
+    }
+
+    pub fn successor_infix_store(&self, key: Key) -> Option<Arc<RwLock<P>>> {
+        let (exact, _, above) = self.search(key);
+        let above = above.and_then(|n| n.infix_store.clone());
+        match exact {
+            Some(n) => n.infix_store.clone().or(above),
+            None => above,
         }
     }
 
-    #[allow(dead_code)]
-    fn min_key(node: &Option<Box<TreeNode>>) -> Option<Key> {
-        match node {
-            None => None,
-            Some(n) => {
-                if n.left.is_none() {
-                    Some(n.key)
-                } else {
-                    Self::min_key(&n.left)
-                }
-            }
+    fn min_key(node: &Option<Box<TreeNode<P>>>) -> Option<Key> {
+        let mut n = node.as_deref()?;
+        while let Some(left) = n.left.as_deref() {
+            n = left;
         }
+        Some(n.key)
     }
 
-    #[allow(dead_code)]
-    fn max_key(node: &Option<Box<TreeNode>>) -> Option<Key> {
-        match node {
-            None => None,
-            Some(n) => {
-                if n.right.is_none() {
-                    Some(n.key)
-                } else {
-                    Self::max_key(&n.right)
-                }
-            }
+    fn max_key(node: &Option<Box<TreeNode<P>>>) -> Option<Key> {
+        let mut n = node.as_deref()?;
+        while let Some(right) = n.right.as_deref() {
+            n = right;
         }
+        Some(n.key)
     }
 
     #[allow(dead_code)]
-    fn min_node(node: &Option<Box<TreeNode>>) -> Option<&TreeNode> {
+    fn min_node(node: &Option<Box<TreeNode<P>>>) -> Option<&TreeNode<P>> {
         match node {
             None => None,
             Some(n) => {
@@ -285,7 +521,7 @@ impl BinarySearchTreeGroup {
     }
 
     #[allow(dead_code)]
-    fn max_node(node: &Option<Box<TreeNode>>) -> Option<&TreeNode> {
+    fn max_node(node: &Option<Box<TreeNode<P>>>) -> Option<&TreeNode<P>> {
         match node {
             None => None,
             Some(n) => {
@@ -298,12 +534,13 @@ impl BinarySearchTreeGroup {
         }
     }
 
+    #[cfg(feature = "std")]
     pub fn pretty_print(&self) {
         print!("{}", self);
     }
 
     fn format_tree(
-        node: &Option<Box<TreeNode>>,
+        node: &Option<Box<TreeNode<P>>>,
         prefix: &str,
         is_tail: bool,
         f: &mut fmt::Formatter,
@@ -332,7 +569,68 @@ impl BinarySearchTreeGroup {
     }
 }
 
-impl fmt::Display for BinarySearchTreeGroup {
+impl<P: RepPayload> BinarySearchTreeGroup<P> {
+    /// memory used by the group: tree nodes and the payloads they own
+    pub fn size_in_bytes(&self) -> usize {
+        core::mem::size_of::<Self>() + Self::size_recursive(&self.root)
+    }
+
+    fn size_recursive(node: &Option<Box<TreeNode<P>>>) -> usize {
+        match node {
+            None => 0,
+            Some(n) => {
+                let store_size = n
+                    .infix_store
+                    .as_ref()
+                    .and_then(|store| store.read().ok().map(|s| s.size_in_bytes()))
+                    .map(|size| {
+                        size + arc_allocation_size::<RwLock<P>>() - core::mem::size_of::<P>()
+                    })
+                    .unwrap_or(0);
+                core::mem::size_of::<TreeNode<P>>()
+                    + store_size
+                    + Self::size_recursive(&n.left)
+                    + Self::size_recursive(&n.right)
+            }
+        }
+    }
+}
+
+/// In-order iterator over a BinarySearchTreeGroup, see `BinarySearchTreeGroup::iter`
+/// and `BinarySearchTreeGroup::range`
+#[derive(Debug)]
+pub struct BstIter<'a, P = InfixStore> {
+    // nodes whose key and right subtree are not visited yet, the smallest on top
+    stack: Vec<&'a TreeNode<P>>,
+    end: Bound<Key>,
+}
+
+impl<'a, P> Iterator for BstIter<'a, P> {
+    type Item = (Key, Option<&'a Arc<RwLock<P>>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.stack.pop()?;
+        let before_end = match self.end {
+            Bound::Included(end) => n.key <= end,
+            Bound::Excluded(end) => n.key < end,
+            Bound::Unbounded => true,
+        };
+        if !before_end {
+            self.stack.clear();
+            return None;
+        }
+        let mut node = n.right.as_deref();
+        while let Some(child) = node {
+            self.stack.push(child);
+            node = child.left.as_deref();
+        }
+        Some((n.key, n.infix_store.as_ref()))
+    }
+}
+
+impl<P> FusedIterator for BstIter<'_, P> {}
+
+impl<P> fmt::Display for BinarySearchTreeGroup<P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "\n=== Binary Search Tree ===")?;
         if self.root.is_none() {
@@ -390,6 +688,315 @@ mod tests {
         assert!(!bst.contains(10));
     }
 
+    #[test]
+    fn test_predecessor_successor() {
+        let mut bst = BinarySearchTreeGroup::new_with_keys(&[10, 20, 30, 40, 50]);
+        bst.insert(35);
+
+        // exact matches are their own predecessor and successor
+        for key in [10, 30, 35, 50] {
+            assert_eq!(bst.predecessor(key), Some(key));
+            assert_eq!(bst.successor(key), Some(key));
+        }
+        assert_eq!(bst.predecessor(34), Some(30));
+        assert_eq!(bst.successor(31), Some(35));
+        assert_eq!(bst.predecessor(39), Some(35));
+        assert_eq!(bst.successor(41), Some(50));
+        // outside the keys
+        assert_eq!(bst.predecessor(9), None);
+        assert_eq!(bst.successor(9), Some(10));
+        assert_eq!(bst.predecessor(Key::MAX), Some(50));
+        assert_eq!(bst.successor(51), None);
+
+        let empty = BinarySearchTreeGroup::new();
+        assert_eq!(empty.predecessor(10), None);
+        assert_eq!(empty.successor(10), None);
+    }
+
+    #[test]
+    fn test_iter_and_range() {
+        let mut bst = BinarySearchTreeGroup::new_with_keys(&[10, 20, 30, 40, 50, 60, 70]);
+        bst.insert(35);
+        bst.insert(5);
+        bst.set_infix_store(40, InfixStore::default());
+        let keys = |iter: BstIter| iter.map(|(key, _)| key).collect::<Vec<_>>();
+
+        assert_eq!(keys(bst.iter()), vec![5, 10, 20, 30, 35, 40, 50, 60, 70]);
+        assert_eq!(keys(bst.range(30..=50)), vec![30, 35, 40, 50]);
+        assert_eq!(keys(bst.range(31..50)), vec![35, 40]);
+        assert_eq!(
+            keys(bst.range((Bound::Excluded(5), Bound::Included(10)))),
+            vec![10]
+        );
+        assert_eq!(keys(bst.range(..20)), vec![5, 10]);
+        assert_eq!(keys(bst.range(65..)), vec![70]);
+        assert_eq!(keys(bst.range(41..50)), Vec::<Key>::new());
+        assert_eq!(keys(bst.range(71..)), Vec::<Key>::new());
+        assert!(BinarySearchTreeGroup::new().iter().next().is_none());
+
+        // payloads are borrowed, not cloned
+        let store_40 = bst.get_infix_store(40).unwrap();
+        let (key, store) = bst.range(36..).next().unwrap();
+        assert_eq!(key, 40);
+        assert!(Arc::ptr_eq(store.unwrap(), &store_40));
+        assert_eq!(Arc::strong_count(&store_40), 2);
+        assert!(
+            bst.iter()
+                .filter(|(key, _)| *key != 40)
+                .all(|(_, store)| store.is_none())
+        );
+    }
+
+    #[test]
+    fn test_delete_all_node_shapes() {
+        //        40
+        //     20     60
+        //   10  30  50  70
+        //  5              80
+        let keys = |bst: &BinarySearchTreeGroup| -> Vec<Key> {
+            bst.entries().into_iter().map(|(key, _)| key).collect()
+        };
+        let mut bst = BinarySearchTreeGroup::new_with_keys(&[10, 20, 30, 40, 50, 60, 70]);
+        bst.insert(5);
+        bst.insert(80);
+
+        // missing key
+        assert!(!bst.delete(45));
+        assert_eq!(bst.len(), 9);
+        // leaf
+        assert!(bst.delete(30));
+        assert_eq!(keys(&bst), vec![5, 10, 20, 40, 50, 60, 70, 80]);
+        // only a left child
+        assert!(bst.delete(10));
+        assert_eq!(keys(&bst), vec![5, 20, 40, 50, 60, 70, 80]);
+        // only a right child
+        assert!(bst.delete(70));
+        assert_eq!(keys(&bst), vec![5, 20, 40, 50, 60, 80]);
+        // two children, the successor is the right child
+        assert!(bst.delete(60));
+        assert_eq!(keys(&bst), vec![5, 20, 40, 50, 80]);
+        // two children at the root, the successor sits deeper on the left
+        assert!(bst.delete(40));
+        assert_eq!(bst.root.as_ref().unwrap().key, 50);
+        assert_eq!(keys(&bst), vec![5, 20, 50, 80]);
+        assert!(!bst.contains(40) && !bst.delete(40));
+
+        for key in [20, 5, 80, 50] {
+            assert!(bst.delete(key));
+        }
+        assert!(bst.root.is_none());
+        assert!(!bst.delete(50));
+    }
+
+    #[test]
+    fn test_delete_moves_successor_store() {
+        let mut bst = BinarySearchTreeGroup::new_with_keys(&[10, 20, 30, 40, 50]);
+        for key in [10, 20, 30, 40, 50] {
+            bst.set_infix_store(key, InfixStore::default());
+        }
+        let store_40 = bst.get_infix_store(40).unwrap();
+
+        // 30 is the root, replaced by its successor 40
+        assert!(bst.delete(30));
+        assert!(Arc::ptr_eq(&bst.get_infix_store(40).unwrap(), &store_40));
+        assert!(bst.get_infix_store(30).is_none());
+        assert_eq!(bst.predecessor(35), Some(20));
+        assert_eq!(bst.successor(35), Some(40));
+        assert!(bst.entries().iter().all(|(_, store)| store.is_some()));
+    }
+
+    /// checks the cached heights and the AVL balance of every node
+    fn check_balanced(node: &Option<Box<TreeNode>>) -> u32 {
+        let Some(n) = node else { return 0 };
+        let (left, right) = (check_balanced(&n.left), check_balanced(&n.right));
+        assert!(left.abs_diff(right) <= 1, "unbalanced at {}", n.key);
+        assert_eq!(n.height, 1 + left.max(right), "stale height at {}", n.key);
+        n.height
+    }
+
+    #[test]
+    fn test_sorted_inserts_stay_balanced() {
+        for keys in [
+            (0..1000).collect::<Vec<Key>>(),
+            (0..1000).rev().collect(),
+            (0..1000)
+                .map(|i| if i % 2 == 0 { i } else { 2000 - i })
+                .collect(),
+        ] {
+            let mut bst = BinarySearchTreeGroup::new();
+            for &key in &keys {
+                bst.insert(key);
+                check_balanced(&bst.root);
+            }
+            // an AVL tree of 1000 nodes is at most 14 levels deep
+            assert!(bst.height() <= 14, "height {}", bst.height());
+            assert_eq!(bst.len(), 1000);
+            let mut sorted = keys.clone();
+            sorted.sort();
+            assert_eq!(bst.iter().map(|(key, _)| key).collect::<Vec<_>>(), sorted);
+            assert!(keys.iter().all(|&key| bst.contains(key)));
+        }
+    }
+
+    #[test]
+    fn test_deletes_stay_balanced() {
+        let keys: Vec<Key> = (0..512).collect();
+        let mut bst = BinarySearchTreeGroup::new_with_keys(&keys);
+        check_balanced(&bst.root);
+        for &key in &keys {
+            bst.set_infix_store(key, InfixStore::default());
+        }
+        let store_510 = bst.get_infix_store(510).unwrap();
+
+        // drain one side, then every third key of the rest
+        for key in 0..256 {
+            assert!(bst.delete(key));
+            check_balanced(&bst.root);
+        }
+        for key in (256..512).step_by(3) {
+            assert!(bst.delete(key));
+            check_balanced(&bst.root);
+        }
+        let remaining: Vec<Key> = (256..512).filter(|key| (key - 256) % 3 != 0).collect();
+        assert_eq!(
+            bst.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            remaining
+        );
+        assert!(bst.height() <= 10, "height {}", bst.height());
+        // rotations move nodes, not payloads
+        assert!(Arc::ptr_eq(&bst.get_infix_store(510).unwrap(), &store_510));
+        assert!(bst.iter().all(|(_, store)| store.is_some()));
+    }
+
+    #[test]
+    fn test_payloads() {
+        let mut bst = BinarySearchTreeGroup::new_with_keys(&[10, 20, 30]);
+        assert!(bst.get_infix_store(10).is_none());
+
+        bst.set_infix_store(10, InfixStore::default());
+        let store_10 = bst.get_infix_store(10).unwrap();
+        let shared = Arc::new(RwLock::new(InfixStore::default()));
+        bst.attach_infix_store(20, shared.clone());
+        assert!(Arc::ptr_eq(&bst.get_infix_store(20).unwrap(), &shared));
+
+        // replace hands back the previous store, None for a missing key
+        let previous = bst.replace_infix_store(20, Some(store_10.clone()));
+        assert!(Arc::ptr_eq(&previous.flatten().unwrap(), &shared));
+        assert!(bst.replace_infix_store(25, None).is_none());
+        assert_eq!(
+            bst.replace_infix_store(30, None)
+                .map(|store| store.is_none()),
+            Some(true)
+        );
+
+        // take detaches the store but keeps the key
+        assert!(Arc::ptr_eq(&bst.take_infix_store(10).unwrap(), &store_10));
+        assert!(bst.take_infix_store(10).is_none());
+        assert!(bst.take_infix_store(15).is_none());
+        assert!(bst.contains(10));
+        bst.remove_infix_store(20);
+        assert!(bst.entries().iter().all(|(_, store)| store.is_none()));
+
+        // a key inserted without a store falls under its predecessor's
+        bst.set_infix_store(10, InfixStore::default());
+        bst.insert(15);
+        assert!(bst.get_infix_store(15).is_none());
+        assert!(bst.predecessor_infix_store(15).is_some());
+        // setting a store on a missing key is a no-op
+        bst.set_infix_store(99, InfixStore::default());
+        assert!(!bst.contains(99));
+    }
+
+    #[test]
+    fn test_len_height_min_max() {
+        let mut bst = BinarySearchTreeGroup::new();
+        assert!(bst.is_empty());
+        assert_eq!(
+            (bst.len(), bst.height(), bst.min(), bst.max()),
+            (0, 0, None, None)
+        );
+
+        for key in (1..=100).rev() {
+            bst.insert(key * 10);
+        }
+        assert_eq!(bst.len(), 100);
+        // 100 keys need at least 7 levels, AVL keeps them within 9
+        assert!((7..=9).contains(&bst.height()), "height {}", bst.height());
+        assert_eq!((bst.min(), bst.max()), (Some(10), Some(1000)));
+
+        assert!(bst.delete(10) && bst.delete(1000));
+        assert!(!bst.delete(10));
+        assert_eq!(bst.len(), 98);
+        assert_eq!((bst.min(), bst.max()), (Some(20), Some(990)));
+
+        let bst = BinarySearchTreeGroup::new_with_keys(&[3, 1, 2, 7]);
+        assert_eq!((bst.len(), bst.height()), (4, 3));
+        assert_eq!((bst.min(), bst.max()), (Some(1), Some(7)));
+    }
+
+    #[test]
+    fn test_million_ascending_inserts() {
+        // a degenerate tree this size would overflow the small stack
+        let worker = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let mut bst = BinarySearchTreeGroup::new();
+                for key in 0..1_000_000 {
+                    bst.insert(key);
+                }
+                assert_eq!(bst.len(), 1_000_000);
+                assert!(bst.height() <= 29, "height {}", bst.height());
+                assert!(bst.contains(0) && bst.contains(999_999) && !bst.contains(1_000_000));
+                assert_eq!(bst.predecessor(Key::MAX), Some(999_999));
+                assert_eq!(bst.successor(500_000), Some(500_000));
+                assert_eq!(bst.iter().count(), 1_000_000);
+
+                for key in 0..500_000 {
+                    assert!(bst.delete(key * 2));
+                }
+                assert_eq!(bst.len(), 500_000);
+                assert!(!bst.contains(0) && bst.contains(1) && bst.contains(999_999));
+
+                let odd: Vec<Key> = (0..500_000).map(|key| key * 2 + 1).collect();
+                let built = BinarySearchTreeGroup::new_with_keys(&odd);
+                assert_eq!(built.height(), 19);
+                assert!(
+                    built
+                        .iter()
+                        .map(|(key, _)| key)
+                        .eq(bst.iter().map(|(key, _)| key))
+                );
+            })
+            .unwrap();
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_drain_and_into_sorted_vec() {
+        let mut bst = BinarySearchTreeGroup::new_with_keys(&[40, 10, 30]);
+        bst.insert(20);
+        bst.insert(50);
+        bst.set_infix_store(30, InfixStore::default());
+        let store_30 = bst.get_infix_store(30).unwrap();
+
+        let entries = bst.drain();
+        assert_eq!(
+            entries.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![10, 20, 30, 40, 50]
+        );
+        // the store moved out rather than being cloned
+        assert!(Arc::ptr_eq(entries[2].1.as_ref().unwrap(), &store_30));
+        assert_eq!(Arc::strong_count(&store_30), 2);
+        assert!(bst.is_empty() && bst.root.is_none() && bst.drain().is_empty());
+
+        // the drained tree is reusable
+        bst.insert(7);
+        assert_eq!(bst.into_sorted_vec(), vec![7]);
+        let keys: Vec<Key> = (0..100).rev().collect();
+        let bst = BinarySearchTreeGroup::new_with_keys(&keys);
+        assert_eq!(bst.into_sorted_vec(), (0..100).collect::<Vec<_>>());
+    }
+
     #[test]
     fn test_predecessor_infix_store() {
         let mut bst = BinarySearchTreeGroup::new_with_keys(&[10, 20, 30, 40, 50]);
@@ -1,21 +1,37 @@
+use alloc::vec::Vec;
+
 const U64_BIT_SIZE: usize = 64;
 
 #[inline]
 pub fn set_bit(data: &mut [u64], pos: usize) {
-    if pos >= data.len() * U64_BIT_SIZE {
-        println!("pos: {} is out of bounds", pos);
-        println!("data.len(): {}", data.len());
-    }
+    debug_assert!(
+        pos < data.len() * U64_BIT_SIZE,
+        "pos: {} is out of bounds for {} words",
+        pos,
+        data.len()
+    );
     data[pos / U64_BIT_SIZE] |= 1 << (pos % U64_BIT_SIZE);
 }
 
 #[inline]
 pub fn clear_bit(data: &mut [u64], pos: usize) {
+    debug_assert!(
+        pos < data.len() * U64_BIT_SIZE,
+        "pos: {} is out of bounds for {} words",
+        pos,
+        data.len()
+    );
     data[pos / U64_BIT_SIZE] &= !(1 << (pos % U64_BIT_SIZE));
 }
 
 #[inline]
 pub fn get_bit(data: &[u64], pos: usize) -> bool {
+    debug_assert!(
+        pos < data.len() * U64_BIT_SIZE,
+        "pos: {} is out of bounds for {} words",
+        pos,
+        data.len()
+    );
     data[pos / U64_BIT_SIZE] & (1 << (pos % U64_BIT_SIZE)) != 0
 }
 
@@ -59,19 +75,54 @@ pub fn select(data: &[u64], rank: usize) -> Option<usize> {
     None
 }
 
+/// Position of the set bit with the given rank within one word
+///
+/// On x86_64 with BMI2 (detected at runtime with `std`, at compile time
+/// without it) PDEP deposits a single bit at the rank-th set bit of the word
+/// and TZCNT reads its position, otherwise the lower set bits are cleared one
+/// at a time.
 #[inline]
-fn select_in_word(word: u64, rank: usize) -> Option<usize> {
-    let mut count = 0;
+pub(crate) fn select_in_word(word: u64, rank: usize) -> Option<usize> {
+    if rank >= word.count_ones() as usize {
+        return None;
+    }
+    #[cfg(target_arch = "x86_64")]
+    if x86::has_bmi2() {
+        // SAFETY: the CPU supports BMI2
+        return Some(unsafe { x86::select_in_word_pdep(word, rank) });
+    }
+    Some(select_in_word_portable(word, rank))
+}
 
-    for i in 0..U64_BIT_SIZE {
-        if word & (1 << i) != 0 {
-            if count == rank {
-                return Some(i);
-            }
-            count += 1;
+/// Portable version of `select_in_word` for a rank below the popcount
+#[inline]
+fn select_in_word_portable(mut word: u64, rank: usize) -> usize {
+    for _ in 0..rank {
+        word &= word - 1;
+    }
+    word.trailing_zeros() as usize
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use core::arch::x86_64::_pdep_u64;
+
+    #[inline]
+    pub(super) fn has_bmi2() -> bool {
+        #[cfg(feature = "std")]
+        {
+            std::is_x86_feature_detected!("bmi2")
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            cfg!(target_feature = "bmi2")
         }
     }
-    None
+
+    #[target_feature(enable = "bmi2")]
+    pub(super) fn select_in_word_pdep(word: u64, rank: usize) -> usize {
+        _pdep_u64(1 << rank, word).trailing_zeros() as usize
+    }
 }
 
 /// optimized rank using cached halfway popcount
@@ -175,6 +226,393 @@ pub fn has_bits_in_range(data: &[u64], start_pos: usize, end_pos: usize) -> bool
     false
 }
 
+/// Position of the first set bit at or after `pos`, if any
+#[inline]
+pub fn next_set_bit(data: &[u64], pos: usize) -> Option<usize> {
+    let mut word_idx = pos / U64_BIT_SIZE;
+    if word_idx >= data.len() {
+        return None;
+    }
+    let mut word = data[word_idx] & (u64::MAX << (pos % U64_BIT_SIZE));
+    loop {
+        if word != 0 {
+            return Some(word_idx * U64_BIT_SIZE + word.trailing_zeros() as usize);
+        }
+        word_idx += 1;
+        word = *data.get(word_idx)?;
+    }
+}
+
+/// Positions of the set bits in `data` in ascending order
+///
+/// Skips zero words and takes each set bit with `trailing_zeros`, so the cost
+/// follows the number of words plus the number of set bits rather than the
+/// number of bits.
+#[inline]
+pub fn iter_ones(data: &[u64]) -> Ones<'_> {
+    Ones {
+        words: data,
+        word_idx: 0,
+        word: data.first().copied().unwrap_or(0),
+    }
+}
+
+/// Read `len` bits (at most 64) starting at bit `pos`
+#[inline]
+pub fn read_bits(data: &[u64], pos: usize, len: usize) -> u64 {
+    debug_assert!(len <= U64_BIT_SIZE);
+    if len == 0 {
+        return 0;
+    }
+    let word = pos / U64_BIT_SIZE;
+    let offset = pos % U64_BIT_SIZE;
+
+    let mut value = data[word] >> offset;
+    if offset + len > U64_BIT_SIZE {
+        value |= data[word + 1] << (U64_BIT_SIZE - offset);
+    }
+    if len < U64_BIT_SIZE {
+        value &= (1 << len) - 1;
+    }
+    value
+}
+
+/// Overwrite `len` bits (at most 64) starting at bit `pos` with the low bits of `value`
+#[inline]
+pub fn write_bits(data: &mut [u64], pos: usize, len: usize, value: u64) {
+    debug_assert!(len <= U64_BIT_SIZE);
+    if len == 0 {
+        return;
+    }
+    let mask = if len == U64_BIT_SIZE {
+        u64::MAX
+    } else {
+        (1 << len) - 1
+    };
+    let value = value & mask;
+    let word = pos / U64_BIT_SIZE;
+    let offset = pos % U64_BIT_SIZE;
+
+    data[word] = (data[word] & !(mask << offset)) | (value << offset);
+    if offset + len > U64_BIT_SIZE {
+        // bits spilling into the next word
+        let spill_mask = (1 << (offset + len - U64_BIT_SIZE)) - 1;
+        data[word + 1] = (data[word + 1] & !spill_mask) | (value >> (U64_BIT_SIZE - offset));
+    }
+}
+
+/// Copy the `len` bits at `src` to `dst`, 64 bits at a time; the regions may
+/// overlap (memmove semantics)
+#[inline]
+pub fn move_bits(data: &mut [u64], src: usize, dst: usize, len: usize) {
+    if dst > src {
+        // copy back to front so no source bit is overwritten before it is read
+        let mut remaining = len;
+        while remaining > 0 {
+            let chunk = remaining.min(U64_BIT_SIZE);
+            remaining -= chunk;
+            let value = read_bits(data, src + remaining, chunk);
+            write_bits(data, dst + remaining, chunk, value);
+        }
+    } else if dst < src {
+        let mut copied = 0;
+        while copied < len {
+            let chunk = (len - copied).min(U64_BIT_SIZE);
+            let value = read_bits(data, src + copied, chunk);
+            write_bits(data, dst + copied, chunk, value);
+            copied += chunk;
+        }
+    }
+}
+
+/// Set every bit in [start, end)
+#[inline]
+pub fn set_bits_in_range(data: &mut [u64], start: usize, end: usize) {
+    for (word, mask) in range_masks(data.len(), start, end) {
+        data[word] |= mask;
+    }
+}
+
+/// Clear every bit in [start, end)
+#[inline]
+pub fn clear_bits_in_range(data: &mut [u64], start: usize, end: usize) {
+    for (word, mask) in range_masks(data.len(), start, end) {
+        data[word] &= !mask;
+    }
+}
+
+/// Move the bits in [start, end) by `offset` positions, towards higher
+/// positions if positive, and clear the positions of the range they leave
+///
+/// Bits outside both the range and its destination are untouched.
+#[inline]
+pub fn shift_bits_range(data: &mut [u64], start: usize, end: usize, offset: isize) {
+    if start >= end || offset == 0 {
+        return;
+    }
+    let dst = start
+        .checked_add_signed(offset)
+        .expect("shift moves bits below position 0");
+    let len = end - start;
+    debug_assert!(
+        dst + len <= data.len() * U64_BIT_SIZE,
+        "shift to [{}, {}) is out of bounds for {} words",
+        dst,
+        dst + len,
+        data.len()
+    );
+    move_bits(data, start, dst, len);
+    if offset > 0 {
+        clear_bits_in_range(data, start, dst.min(end));
+    } else {
+        clear_bits_in_range(data, (dst + len).max(start), end);
+    }
+}
+
+/// (word index, mask) of every word holding bits in [start, end) of `num_words` words
+#[inline]
+fn range_masks(num_words: usize, start: usize, end: usize) -> impl Iterator<Item = (usize, u64)> {
+    debug_assert!(
+        start >= end || end <= num_words * U64_BIT_SIZE,
+        "range [{}, {}) is out of bounds for {} words",
+        start,
+        end,
+        num_words
+    );
+    let words = if start < end {
+        start / U64_BIT_SIZE..(end - 1) / U64_BIT_SIZE + 1
+    } else {
+        0..0
+    };
+    words.map(move |word| {
+        let first_bit = word * U64_BIT_SIZE;
+        // bits [low, high) of the word, high is at least 1
+        let low = start.max(first_bit) - first_bit;
+        let high = end.min(first_bit + U64_BIT_SIZE) - first_bit;
+        (
+            word,
+            (u64::MAX >> (U64_BIT_SIZE - high)) & (u64::MAX << low),
+        )
+    })
+}
+
+/// Bit vector that owns its words and knows its length in bits
+///
+/// Wraps the slice functions above for bitmaps that stand on their own, with
+/// positions checked against the length in debug builds instead of against
+/// the word count. Bits past the length are always zero. Bitmaps packed into
+/// a larger buffer, like the sections of an InfixStore's data words, keep
+/// using the slice functions, which also run over borrowed words.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BitVec {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitVec {
+    /// `len` zero bits
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: alloc::vec![0; len.div_ceil(U64_BIT_SIZE)],
+            len,
+        }
+    }
+
+    /// Take over `words` holding `len` bits
+    ///
+    /// Panics if the word count does not match `len`; set bits past `len` are cleared.
+    pub fn from_words(words: Vec<u64>, len: usize) -> Self {
+        assert_eq!(
+            words.len(),
+            len.div_ceil(U64_BIT_SIZE),
+            "{} words cannot hold exactly {} bits",
+            words.len(),
+            len
+        );
+        let mut bits = Self { words, len };
+        bits.clear_tail();
+        bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// the underlying words, bit `i` at `words[i / 64] >> (i % 64)`
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    #[inline]
+    pub fn get(&self, pos: usize) -> bool {
+        self.check(pos);
+        get_bit(&self.words, pos)
+    }
+
+    #[inline]
+    pub fn set(&mut self, pos: usize) {
+        self.check(pos);
+        set_bit(&mut self.words, pos);
+    }
+
+    #[inline]
+    pub fn clear(&mut self, pos: usize) {
+        self.check(pos);
+        clear_bit(&mut self.words, pos);
+    }
+
+    /// Number of set bits in [0, pos)
+    #[inline]
+    pub fn rank(&self, pos: usize) -> usize {
+        debug_assert!(
+            pos <= self.len,
+            "pos: {} is out of bounds for {} bits",
+            pos,
+            self.len
+        );
+        rank(&self.words, pos)
+    }
+
+    /// Position of the set bit with the given rank (0-based)
+    #[inline]
+    pub fn select(&self, rank_val: usize) -> Option<usize> {
+        select(&self.words, rank_val)
+    }
+
+    /// Position of the first set bit at or after `pos`, if any
+    #[inline]
+    pub fn next_one(&self, pos: usize) -> Option<usize> {
+        next_set_bit(&self.words, pos)
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Positions of the set bits in ascending order, see `iter_ones`
+    pub fn iter_ones(&self) -> Ones<'_> {
+        iter_ones(&self.words)
+    }
+
+    /// Copy the `len` bits at `src` to `dst`, see `move_bits`
+    #[inline]
+    pub fn move_bits(&mut self, src: usize, dst: usize, len: usize) {
+        debug_assert!(
+            src.max(dst) + len <= self.len,
+            "moving {} bits from {} to {} overruns {} bits",
+            len,
+            src,
+            dst,
+            self.len
+        );
+        move_bits(&mut self.words, src, dst, len);
+    }
+
+    /// Set every bit in [start, end)
+    pub fn set_range(&mut self, start: usize, end: usize) {
+        self.check_range(start, end);
+        set_bits_in_range(&mut self.words, start, end);
+    }
+
+    /// Clear every bit in [start, end)
+    pub fn clear_range(&mut self, start: usize, end: usize) {
+        self.check_range(start, end);
+        clear_bits_in_range(&mut self.words, start, end);
+    }
+
+    /// Move the bits in [start, end) by `offset`, see `shift_bits_range`
+    pub fn shift_range(&mut self, start: usize, end: usize, offset: isize) {
+        self.check_range(start, end);
+        debug_assert!(
+            start >= end || end.saturating_add_signed(offset) <= self.len,
+            "shifting [{}, {}) by {} overruns {} bits",
+            start,
+            end,
+            offset,
+            self.len
+        );
+        shift_bits_range(&mut self.words, start, end, offset);
+    }
+
+    /// Grow with zero bits or truncate to `len` bits
+    pub fn resize(&mut self, len: usize) {
+        self.words.resize(len.div_ceil(U64_BIT_SIZE), 0);
+        self.len = len;
+        self.clear_tail();
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.words.shrink_to_fit();
+    }
+
+    /// memory allocated for the words
+    pub fn heap_size(&self) -> usize {
+        self.words.capacity() * core::mem::size_of::<u64>()
+    }
+
+    #[inline]
+    fn check(&self, pos: usize) {
+        debug_assert!(
+            pos < self.len,
+            "pos: {} is out of bounds for {} bits",
+            pos,
+            self.len
+        );
+    }
+
+    #[inline]
+    fn check_range(&self, start: usize, end: usize) {
+        debug_assert!(
+            start >= end || end <= self.len,
+            "range [{}, {}) is out of bounds for {} bits",
+            start,
+            end,
+            self.len
+        );
+    }
+
+    // zero the bits of the last word past len
+    fn clear_tail(&mut self) {
+        let used = self.len % U64_BIT_SIZE;
+        if let (Some(last), true) = (self.words.last_mut(), used > 0) {
+            *last &= (1 << used) - 1;
+        }
+    }
+}
+
+/// Iterator over the set bits of a bitmap, see `iter_ones`
+#[derive(Debug, Clone)]
+pub struct Ones<'a> {
+    words: &'a [u64],
+    word_idx: usize,
+    // bits of words[word_idx] not returned yet
+    word: u64,
+}
+
+impl Iterator for Ones<'_> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            self.word_idx += 1;
+            self.word = *self.words.get(self.word_idx)?;
+        }
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.word_idx * U64_BIT_SIZE + bit)
+    }
+}
+
+impl core::iter::FusedIterator for Ones<'_> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -251,6 +689,39 @@ mod tests {
         assert_eq!(select_in_word(word, 1), Some(2));
         assert_eq!(select_in_word(word, 2), Some(4));
         assert_eq!(select_in_word(word, 3), None);
+        assert_eq!(select_in_word(0, 0), None);
+    }
+
+    #[test]
+    fn test_select_in_word_matches_bit_scan() {
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for i in 0..500 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // sparse, dense and random words
+            let word = match i % 3 {
+                0 => state & (state >> 17) & (state >> 31),
+                1 => state | (state >> 9),
+                _ => state,
+            };
+            let ones: Vec<usize> = (0..U64_BIT_SIZE)
+                .filter(|&bit| (word >> bit) & 1 == 1)
+                .collect();
+            for rank in 0..=ones.len() {
+                let expected = ones.get(rank).copied();
+                assert_eq!(select_in_word(word, rank), expected);
+                if let Some(pos) = expected {
+                    assert_eq!(select_in_word_portable(word, rank), pos);
+                    #[cfg(target_arch = "x86_64")]
+                    if x86::has_bmi2() {
+                        assert_eq!(unsafe { x86::select_in_word_pdep(word, rank) }, pos);
+                    }
+                }
+            }
+        }
+        assert_eq!(select_in_word(u64::MAX, 63), Some(63));
+        assert_eq!(select_in_word(1 << 63, 0), Some(63));
     }
 
     #[test]
@@ -325,4 +796,192 @@ mod tests {
         assert!(!has_bits_in_range(&data, 15, 65)); // Between bits 10 and 70
         assert!(!has_bits_in_range(&data, 75, 125)); // Between bits 70 and 130
     }
+
+    #[test]
+    fn test_next_set_bit() {
+        let mut data = vec![0u64; 4];
+        for pos in [3, 64, 65, 200] {
+            set_bit(&mut data, pos);
+        }
+        assert_eq!(next_set_bit(&data, 0), Some(3));
+        assert_eq!(next_set_bit(&data, 3), Some(3));
+        assert_eq!(next_set_bit(&data, 4), Some(64));
+        assert_eq!(next_set_bit(&data, 66), Some(200));
+        assert_eq!(next_set_bit(&data, 201), None);
+        assert_eq!(next_set_bit(&data, 256), None);
+        assert_eq!(next_set_bit(&[], 0), None);
+    }
+
+    #[test]
+    fn test_iter_ones() {
+        let mut data = vec![0u64; 5];
+        let ones = [0, 3, 63, 64, 65, 255, 319];
+        for pos in ones {
+            set_bit(&mut data, pos);
+        }
+        assert_eq!(iter_ones(&data).collect::<Vec<_>>(), ones);
+        assert_eq!(iter_ones(&data).count(), rank(&data, 320));
+        assert_eq!(
+            iter_ones(&[u64::MAX]).collect::<Vec<_>>(),
+            (0..64).collect::<Vec<_>>()
+        );
+        assert_eq!(iter_ones(&[0, 0, 1 << 7]).collect::<Vec<_>>(), vec![135]);
+        assert!(iter_ones(&[]).next().is_none());
+    }
+
+    #[test]
+    fn test_read_write_bits() {
+        let mut data = vec![0u64; 3];
+        write_bits(&mut data, 60, 10, 0b11_0110_1011);
+        assert_eq!(read_bits(&data, 60, 10), 0b11_0110_1011);
+        assert_eq!(data[0] >> 60, 0b1011);
+        assert_eq!(data[1], 0b11_0110);
+
+        write_bits(&mut data, 64, 64, u64::MAX);
+        assert_eq!(read_bits(&data, 64, 64), u64::MAX);
+        write_bits(&mut data, 100, 4, 0);
+        assert_eq!(read_bits(&data, 96, 12), 0b1111_0000_1111);
+        assert_eq!(read_bits(&data, 60, 4), 0b1011);
+        assert_eq!(read_bits(&data, 5, 0), 0);
+    }
+
+    #[test]
+    fn test_move_bits_matches_bitwise_copy() {
+        let mut seed = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for _ in 0..200 {
+            let original: Vec<u64> = (0..6).map(|_| next()).collect();
+            let len = next() as usize % 200;
+            let src = next() as usize % (384 - len);
+            let dst = next() as usize % (384 - len);
+
+            let mut expected = original.clone();
+            let bits: Vec<bool> = (0..len).map(|i| get_bit(&original, src + i)).collect();
+            for (i, &bit) in bits.iter().enumerate() {
+                if bit {
+                    set_bit(&mut expected, dst + i);
+                } else {
+                    clear_bit(&mut expected, dst + i);
+                }
+            }
+
+            let mut moved = original.clone();
+            move_bits(&mut moved, src, dst, len);
+            assert_eq!(moved, expected, "src {} dst {} len {}", src, dst, len);
+        }
+    }
+
+    #[test]
+    fn test_bit_range_operations_match_bitwise() {
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for _ in 0..300 {
+            let original: Vec<u64> = (0..4).map(|_| next()).collect();
+            let start = next() as usize % 257;
+            let end = start + next() as usize % (257 - start);
+
+            let mut set = original.clone();
+            set_bits_in_range(&mut set, start, end);
+            let mut cleared = original.clone();
+            clear_bits_in_range(&mut cleared, start, end);
+            for pos in 0..256 {
+                let inside = (start..end).contains(&pos);
+                assert_eq!(get_bit(&set, pos), inside || get_bit(&original, pos));
+                assert_eq!(get_bit(&cleared, pos), !inside && get_bit(&original, pos));
+            }
+
+            // shift within the 256 bits, either direction
+            let len = end - start;
+            let offset = (next() % 40) as isize - 20;
+            let dst = start as isize + offset;
+            if dst < 0 || dst as usize + len > 256 {
+                continue;
+            }
+            let dst = dst as usize;
+            let mut shifted = original.clone();
+            shift_bits_range(&mut shifted, start, end, offset);
+            for pos in 0..256 {
+                let expected = if (dst..dst + len).contains(&pos) {
+                    get_bit(&original, pos - dst + start)
+                } else if (start..end).contains(&pos) {
+                    offset == 0 && get_bit(&original, pos)
+                } else {
+                    get_bit(&original, pos)
+                };
+                assert_eq!(
+                    get_bit(&shifted, pos),
+                    expected,
+                    "[{}, {}) by {} at {}",
+                    start,
+                    end,
+                    offset,
+                    pos
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_bit_vec_matches_slice_functions() {
+        let mut bits = BitVec::new(130);
+        assert_eq!(bits.words().len(), 3);
+        for pos in [0, 5, 63, 64, 100, 129] {
+            bits.set(pos);
+        }
+        bits.clear(5);
+        assert!(bits.get(63) && !bits.get(5) && !bits.get(128));
+        assert_eq!(bits.count_ones(), 5);
+        assert_eq!(
+            bits.iter_ones().collect::<Vec<_>>(),
+            vec![0, 63, 64, 100, 129]
+        );
+        for pos in 0..=130 {
+            assert_eq!(bits.rank(pos), rank(bits.words(), pos));
+        }
+        for rank_val in 0..=5 {
+            assert_eq!(bits.select(rank_val), select(bits.words(), rank_val));
+        }
+        assert_eq!(bits.next_one(65), Some(100));
+
+        // shifting keeps the bits, truncating drops those past the new length
+        bits.move_bits(0, 1, 129);
+        bits.clear(0);
+        assert_eq!(bits.iter_ones().collect::<Vec<_>>(), vec![1, 64, 65, 101]);
+        bits.shift_range(64, 102, -63);
+        assert_eq!(bits.iter_ones().collect::<Vec<_>>(), vec![1, 2, 38]);
+        bits.set_range(60, 70);
+        bits.clear_range(0, 62);
+        assert_eq!(
+            bits.iter_ones().collect::<Vec<_>>(),
+            (62..70).collect::<Vec<_>>()
+        );
+        bits.set(1);
+        bits.set(64);
+        bits.resize(65);
+        assert_eq!(bits.iter_ones().collect::<Vec<_>>(), vec![1, 62, 63, 64]);
+        bits.resize(200);
+        assert_eq!((bits.len(), bits.count_ones()), (200, 4));
+
+        let bits = BitVec::from_words(vec![u64::MAX, u64::MAX], 70);
+        assert_eq!(bits.count_ones(), 70);
+        assert!(BitVec::new(0).iter_ones().next().is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of bounds for 10 bits")]
+    fn test_bit_vec_checks_length() {
+        // the word has room for bit 10, the length does not
+        BitVec::new(10).set(10);
+    }
 }
@@ -0,0 +1,217 @@
+use crate::Key;
+use crate::diva::{Diva, DivaBuilder};
+use crate::error::RangeFilterError;
+
+/// Identifier of a block in a `BlockFilterSet`, assigned in the order blocks are added
+pub type BlockId = usize;
+
+struct Block {
+    min: Key,
+    max: Key,
+    filter: Diva,
+}
+
+/// One small `Diva` per data block plus an index of the block key ranges
+///
+/// This is how an LSM tree consumes a range filter: a scan first asks which
+/// blocks may hold keys in its range and only reads those. `query` prunes with
+/// the index first, skipping every block whose [min, max] key range misses the
+/// queried range, and then asks the filters of the remaining blocks. Blocks may
+/// overlap, e.g. blocks of different L0 files.
+///
+/// # Example
+/// ```rust
+/// use range_filters::block_filter_set::BlockFilterSet;
+///
+/// let mut blocks = BlockFilterSet::new(64, 0.01).unwrap();
+/// let first = blocks.add_block(&[10, 20, 30]).unwrap();
+/// let second = blocks.add_block(&[1000, 1500, 2000]).unwrap();
+///
+/// assert_eq!(blocks.query(15, 1200), vec![first, second]);
+/// assert_eq!(blocks.query(1400, 1600), vec![second]);
+/// assert!(blocks.query(40, 900).is_empty());
+/// ```
+pub struct BlockFilterSet {
+    builder: DivaBuilder,
+    // indexed by BlockId
+    blocks: Vec<Block>,
+    // block ids sorted by the smallest key of their block
+    by_min: Vec<BlockId>,
+    // largest block max over by_min[..=i], non-decreasing
+    running_max: Vec<Key>,
+}
+
+impl BlockFilterSet {
+    /// Create an empty set whose block filters use the given `Diva` parameters
+    pub fn new(target_size: usize, fpr: f64) -> Result<Self, RangeFilterError> {
+        Self::with_builder(Diva::builder().target_size(target_size).fpr(fpr))
+    }
+
+    /// Create an empty set building every block filter with `builder`
+    pub fn with_builder(builder: DivaBuilder) -> Result<Self, RangeFilterError> {
+        builder.build()?;
+        Ok(Self {
+            builder,
+            blocks: Vec::new(),
+            by_min: Vec::new(),
+            running_max: Vec::new(),
+        })
+    }
+
+    /// Build the filter of a block from its keys, in any order
+    pub fn add_block(&mut self, keys: &[Key]) -> Result<BlockId, RangeFilterError> {
+        let (Some(&min), Some(&max)) = (keys.iter().min(), keys.iter().max()) else {
+            return Err(RangeFilterError::InvalidConfig(
+                "a block needs at least one key".to_string(),
+            ));
+        };
+        let filter = self.builder.build_with_keys(keys)?;
+
+        let id = self.blocks.len();
+        self.blocks.push(Block { min, max, filter });
+        let position = self
+            .by_min
+            .partition_point(|&other| self.blocks[other].min <= min);
+        self.by_min.insert(position, id);
+        self.rebuild_running_max(position);
+        Ok(id)
+    }
+
+    /// recompute the running maximum from `position` on
+    fn rebuild_running_max(&mut self, position: usize) {
+        self.running_max.truncate(position);
+        let mut max = self.running_max.last().copied().unwrap_or(Key::MIN);
+        for &id in &self.by_min[position..] {
+            max = max.max(self.blocks[id].max);
+            self.running_max.push(max);
+        }
+    }
+
+    /// Blocks that may contain a key in [start, end] (inclusive), ordered by
+    /// their smallest key
+    pub fn query(&self, start: Key, end: Key) -> Vec<BlockId> {
+        if start > end {
+            return Vec::new();
+        }
+        // blocks before `first` all end below start, blocks from `last` all begin after end
+        let first = self.running_max.partition_point(|&max| max < start);
+        let last = self
+            .by_min
+            .partition_point(|&id| self.blocks[id].min <= end);
+
+        self.by_min[first.min(last)..last]
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let block = &self.blocks[id];
+                block.max >= start
+                    && block
+                        .filter
+                        .range_query(start.max(block.min), end.min(block.max))
+            })
+            .collect()
+    }
+
+    /// Blocks that may contain `key`, ordered by their smallest key
+    pub fn point_query(&self, key: Key) -> Vec<BlockId> {
+        self.query(key, key)
+    }
+
+    /// The filter of a block
+    pub fn block(&self, id: BlockId) -> Option<&Diva> {
+        self.blocks.get(id).map(|block| &block.filter)
+    }
+
+    /// The smallest and largest key of a block
+    pub fn key_range(&self, id: BlockId) -> Option<(Key, Key)> {
+        self.blocks.get(id).map(|block| (block.min, block.max))
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// number of keys over all blocks
+    pub fn num_keys(&self) -> usize {
+        self.blocks
+            .iter()
+            .map(|block| block.filter.num_keys())
+            .sum()
+    }
+
+    /// memory used by the index and all block filters
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.blocks.capacity() * std::mem::size_of::<Block>()
+            + self.by_min.capacity() * std::mem::size_of::<BlockId>()
+            + self.running_max.capacity() * std::mem::size_of::<Key>()
+            + self
+                .blocks
+                .iter()
+                .map(|block| block.filter.size_in_bytes() - std::mem::size_of::<Diva>())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_query_prunes_blocks() {
+        let mut blocks = BlockFilterSet::new(32, 0.01).unwrap();
+        // ten disjoint blocks of 100 keys, added out of key order
+        let mut ids = vec![0; 10];
+        for b in [3, 7, 0, 9, 1, 5, 2, 8, 4, 6] {
+            let keys: Vec<Key> = (0..100).map(|i| b * 10_000 + i * 50).collect();
+            ids[b as usize] = blocks.add_block(&keys).unwrap();
+        }
+        assert_eq!(blocks.num_blocks(), 10);
+        assert_eq!(blocks.num_keys(), 1000);
+
+        assert_eq!(blocks.point_query(30_050), vec![ids[3]]);
+        assert_eq!(blocks.query(0, 100_000), ids);
+        assert_eq!(blocks.query(24_000, 51_000), ids[2..=5].to_vec());
+        // gaps between blocks never reach a filter
+        assert!(blocks.query(5_000, 9_999).is_empty());
+        assert!(blocks.query(200_000, Key::MAX).is_empty());
+        assert!(blocks.query(10, 5).is_empty());
+        assert_eq!(blocks.key_range(ids[4]), Some((40_000, 44_950)));
+    }
+
+    #[test]
+    fn test_overlapping_blocks() {
+        let mut rng = rand::thread_rng();
+        let mut blocks = BlockFilterSet::new(64, 0.01).unwrap();
+        let mut all_keys = Vec::new();
+        for _ in 0..20 {
+            let low: Key = rng.gen_range(0..1_000_000);
+            let keys: Vec<Key> = (0..200).map(|_| low + rng.gen_range(0..100_000)).collect();
+            let id = blocks.add_block(&keys).unwrap();
+            all_keys.extend(keys.into_iter().map(|key| (key, id)));
+        }
+
+        // a query never misses a block holding a key in its range
+        for &(key, id) in &all_keys {
+            assert!(blocks.point_query(key).contains(&id));
+            assert!(blocks.query(key.saturating_sub(5), key + 5).contains(&id));
+        }
+        let result = blocks.query(0, Key::MAX);
+        assert_eq!(result.len(), 20);
+        assert!(
+            result.windows(2).all(|w| {
+                blocks.key_range(w[0]).unwrap().0 <= blocks.key_range(w[1]).unwrap().0
+            })
+        );
+    }
+
+    #[test]
+    fn test_rejects_empty_block_and_bad_config() {
+        let mut blocks = BlockFilterSet::new(64, 0.01).unwrap();
+        assert!(blocks.add_block(&[]).is_err());
+        assert_eq!(blocks.num_blocks(), 0);
+        assert!(blocks.query(0, Key::MAX).is_empty());
+        assert!(BlockFilterSet::new(0, 0.01).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+//! Cache-line blocked Bloom filter over `u64` keys
+//!
+//! The bit array is split into 512-bit blocks aligned to 64 bytes. One hash
+//! picks the block of a key and a second one its `k` bits inside it, so a
+//! query touches exactly one cache line whatever `k` is. Confining the bits to
+//! a block makes blocks fill unevenly, so for the same false positive rate the
+//! filter is sized with about 10% more bits than a plain Bloom filter.
+//!
+//! Keys are hashed as integers, not formatted as strings like
+//! `BloomFilter` does, so it is the speed-oriented Bloom baseline.
+
+use crate::Key;
+use crate::range_filter::RangeFilter;
+use crate::utils::mix64;
+
+const BLOCK_BITS: usize = 512;
+/// extra bits over a plain Bloom filter to make up for uneven blocks
+const BLOCK_OVERHEAD: f64 = 1.1;
+
+/// 512 bits on one cache line
+#[derive(Clone, Copy, Default)]
+#[repr(C, align(64))]
+struct Block([u64; BLOCK_BITS / 64]);
+
+pub struct BlockedBloomFilter {
+    blocks: Vec<Block>,
+    num_hashes: u32,
+    fpr: f64,
+    num_keys: usize,
+    seed: u64,
+}
+
+impl BlockedBloomFilter {
+    /// Create a new Blocked Bloom Filter with the given keys and false positive rate.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new_with_keys(keys: &[Key], fpr: f64) -> Self {
+        let mut filter = Self::new_with_capacity(keys.len(), fpr);
+        for &key in keys {
+            filter.insert(key);
+        }
+        filter
+    }
+
+    /// Create an empty Blocked Bloom Filter sized for `capacity` keys.
+    ///
+    /// # Arguments
+    /// * `capacity` - Number of keys the bit array is sized for
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new_with_capacity(capacity: usize, fpr: f64) -> Self {
+        let fpr = fpr.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let capacity = capacity.max(1) as f64;
+        // m = -n ln(p) / ln(2)^2 and k = m / n ln(2) of a plain Bloom filter
+        let num_bits = -capacity * fpr.ln() / (ln2 * ln2) * BLOCK_OVERHEAD;
+        let num_blocks = (num_bits / BLOCK_BITS as f64).ceil().max(1.0) as usize;
+        let num_hashes = (num_bits / BLOCK_OVERHEAD / capacity * ln2)
+            .round()
+            .clamp(1.0, 16.0) as u32;
+        Self {
+            blocks: vec![Block::default(); num_blocks],
+            num_hashes,
+            fpr,
+            num_keys: 0,
+            seed: rand::random(),
+        }
+    }
+
+    /// Insert a key after construction.
+    pub fn insert(&mut self, key: Key) {
+        let (block, bits) = self.probe(key);
+        let block = &mut self.blocks[block].0;
+        for pos in bits {
+            block[pos / 64] |= 1 << (pos % 64);
+        }
+        self.num_keys += 1;
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist (with false positive rate `fpr`)
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        let (block, mut bits) = self.probe(key);
+        let block = &self.blocks[block].0;
+        bits.all(|pos| block[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// Probes every key in the range, like `BloomFilter::range_query`.
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        start <= end && (start..=end).any(|key| self.point_query(key))
+    }
+
+    /// the block of `key` and its bits inside the block
+    fn probe(&self, key: Key) -> (usize, impl Iterator<Item = usize> + use<>) {
+        let hash = mix64(key ^ self.seed);
+        // multiply-shift maps the hash onto the blocks without a division
+        let block = ((hash as u128 * self.blocks.len() as u128) >> 64) as usize;
+        let bit_hash = mix64(hash);
+        let (h1, h2) = (bit_hash & 0xFFFF_FFFF, (bit_hash >> 32) | 1);
+        let bits = (0..self.num_hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % BLOCK_BITS as u64) as usize);
+        (block, bits)
+    }
+
+    /// Get the configured false positive rate.
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of keys inserted into the filter, duplicates included.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of bits set per key.
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Get the memory used by the filter: the struct plus its cache-line blocks.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + std::mem::size_of_val(self.blocks.as_slice())
+    }
+
+    /// Get the memory per inserted key in bits, a key inserted twice counted
+    /// twice.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for BlockedBloomFilter {
+    fn point_query(&self, key: Key) -> bool {
+        BlockedBloomFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        BlockedBloomFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives_and_fpr() {
+        let keys: Vec<Key> = (0..20_000).map(|i| i * 7919).collect();
+        let filter = BlockedBloomFilter::new_with_keys(&keys, 0.01);
+        assert!(keys.iter().all(|&key| filter.point_query(key)));
+
+        let false_positives = (0..50_000)
+            .map(|i| i * 7919 + 13)
+            .filter(|&key| filter.point_query(key))
+            .count();
+        // 1% of 50_000 expected, allow for variance
+        assert!(
+            false_positives < 1000,
+            "{} false positives",
+            false_positives
+        );
+        assert!(
+            filter.bits_per_key() < 12.0,
+            "{} bits per key",
+            filter.bits_per_key()
+        );
+    }
+
+    #[test]
+    fn test_blocks_are_cache_lines() {
+        let filter = BlockedBloomFilter::new_with_keys(&[1, 2, 3], 0.01);
+        assert_eq!(std::mem::size_of::<Block>(), 64);
+        assert_eq!(filter.blocks.as_ptr() as usize % 64, 0);
+        assert!(filter.num_hashes() >= 1);
+    }
+
+    #[test]
+    fn test_insert_after_construction() {
+        let mut filter = BlockedBloomFilter::new_with_capacity(100, 0.001);
+        assert!(!filter.point_query(5) && !filter.range_query(0, 100));
+        filter.insert(50);
+        assert!(filter.point_query(50));
+        assert!(filter.range_query(40, 60));
+        assert!(!filter.range_query(60, 40));
+        assert_eq!(filter.num_keys(), 1);
+    }
+}
@@ -1,11 +1,17 @@
 use fastbloom::BloomFilter as FastBloomFilter;
 
 use crate::Key;
+use crate::error::RangeFilterError;
+use crate::persist::{self, FilterKind, PayloadReader, PayloadWriter};
+use crate::range_filter::RangeFilter;
+use std::io::{self, Read, Write};
 
 pub struct BloomFilter {
     filter: FastBloomFilter,
     fpr: f64,
     num_keys: usize,
+    // hasher seed, kept so a saved filter hashes keys the same way when loaded
+    seed: u128,
 }
 
 impl BloomFilter {
@@ -18,19 +24,58 @@ impl BloomFilter {
     /// # Returns
     /// A new `BloomFilter` instance containing all the provided keys
     pub fn new_with_keys(keys: &[Key], fpr: f64) -> Self {
-        let num_keys = keys.len();
+        let mut bloom = Self::new_with_capacity(keys.len(), fpr);
+        bloom.extend(keys.iter().copied());
+        bloom
+    }
 
-        let mut filter = FastBloomFilter::with_false_pos(fpr).expected_items(num_keys);
-        for &key in keys {
-            filter.insert(&key.to_string());
-        }
+    /// Create an empty Bloom Filter sized for `capacity` keys, to be filled with
+    /// `insert` or `extend`.
+    ///
+    /// The false positive rate only holds up to `capacity` keys and rises as
+    /// more are inserted.
+    ///
+    /// # Arguments
+    /// * `capacity` - Number of keys the bit array is sized for
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new_with_capacity(capacity: usize, fpr: f64) -> Self {
+        let seed: u128 = rand::random();
+        // a filter sized for no items hangs on its first insert
+        let filter = FastBloomFilter::with_false_pos(fpr)
+            .seed(&seed)
+            .expected_items(capacity.max(1));
         Self {
             filter,
             fpr,
-            num_keys,
+            num_keys: 0,
+            seed,
         }
     }
 
+    /// Create an empty Bloom Filter with the same bit array size, hashes and seed
+    /// as this one, e.g. for the next segment of an LSM tree, so the two can be
+    /// combined with `union` and `intersect`.
+    pub fn empty_like(&self) -> Self {
+        let filter = FastBloomFilter::from_vec(vec![0; self.filter.as_slice().len()])
+            .seed(&self.seed)
+            .hashes(self.filter.num_hashes());
+        Self {
+            filter,
+            fpr: self.fpr,
+            num_keys: 0,
+            seed: self.seed,
+        }
+    }
+
+    /// Insert a key after construction.
+    ///
+    /// The bit array does not grow, so inserting past the capacity the filter
+    /// was built for raises its false positive rate above `fpr`.
+    pub fn insert(&mut self, key: Key) {
+        self.filter.insert(&key.to_string());
+        self.num_keys += 1;
+    }
+
     /// Perform a point query to check if a key might exist in the filter.
     ///
     /// # Arguments
@@ -65,6 +110,66 @@ impl BloomFilter {
         false
     }
 
+    /// Range query for many ranges at once, returning one result per range in input order
+    pub fn range_query_many(&self, ranges: &[(Key, Key)]) -> Vec<bool> {
+        ranges
+            .iter()
+            .map(|&(start, end)| self.range_query(start, end))
+            .collect()
+    }
+
+    /// Create a filter holding the keys of both filters, e.g. when segments are
+    /// compacted into one.
+    ///
+    /// The key count is the sum of both counts, so keys present in both are
+    /// counted twice. Fails with `InvalidConfig` unless `other` has the same bit
+    /// array size, hashes and seed, see `empty_like`.
+    pub fn union(&self, other: &BloomFilter) -> Result<Self, RangeFilterError> {
+        self.check_compatible(other)?;
+        let mut filter = self.filter.clone();
+        filter.union(&other.filter);
+        Ok(Self {
+            filter,
+            fpr: self.fpr,
+            num_keys: self.num_keys + other.num_keys,
+            seed: self.seed,
+        })
+    }
+
+    /// Create a filter answering yes only for keys both filters may hold.
+    ///
+    /// Its false positive rate is at most the smaller of the two, and the key
+    /// count the smaller count as an upper bound. Fails with `InvalidConfig`
+    /// unless `other` has the same bit array size, hashes and seed, see
+    /// `empty_like`.
+    pub fn intersect(&self, other: &BloomFilter) -> Result<Self, RangeFilterError> {
+        self.check_compatible(other)?;
+        let mut filter = self.filter.clone();
+        filter.intersect(&other.filter);
+        Ok(Self {
+            filter,
+            fpr: self.fpr,
+            num_keys: self.num_keys.min(other.num_keys),
+            seed: self.seed,
+        })
+    }
+
+    fn check_compatible(&self, other: &BloomFilter) -> Result<(), RangeFilterError> {
+        if self.seed != other.seed
+            || self.filter.num_hashes() != other.filter.num_hashes()
+            || self.filter.num_bits() != other.filter.num_bits()
+        {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "bloom filters differ: {} bits, {} hashes vs {} bits, {} hashes, or their seeds",
+                self.filter.num_bits(),
+                self.filter.num_hashes(),
+                other.filter.num_bits(),
+                other.filter.num_hashes()
+            )));
+        }
+        Ok(())
+    }
+
     /// Get the configured false positive rate.
     ///
     /// # Returns
@@ -73,8 +178,188 @@ impl BloomFilter {
         self.fpr
     }
 
-    /// Get the number of keys inserted into the filter.
+    /// Get the number of keys inserted into the filter, duplicates included.
     pub fn num_keys(&self) -> usize {
         self.num_keys
     }
+
+    /// Get the memory used by the filter: the struct plus its bit array,
+    /// sized for the capacity it was built with.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + std::mem::size_of_val(self.filter.as_slice())
+    }
+
+    /// Get the memory per inserted key in bits, far above the optimum while
+    /// the filter is below its capacity.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+
+    /// Save the filter in the versioned binary format (see `persist`).
+    ///
+    /// The payload holds the false positive rate, key count, hasher seed,
+    /// number of hashes and the bit array.
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut payload = PayloadWriter::default();
+        payload.f64(self.fpr);
+        payload.u64(self.num_keys as u64);
+        payload.u64(self.seed as u64);
+        payload.u64((self.seed >> 64) as u64);
+        payload.u32(self.filter.num_hashes());
+        let bits = self.filter.as_slice();
+        payload.u64(bits.len() as u64);
+        for &word in bits {
+            payload.u64(word);
+        }
+        persist::write_container(writer, FilterKind::Bloom, &payload.into_bytes())
+    }
+
+    /// Load a filter saved with `save_to`, verifying its checksums.
+    pub fn load_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let (_version, payload) = persist::read_container(reader, FilterKind::Bloom)?;
+        let mut payload = PayloadReader::new(&payload);
+
+        let fpr = payload.f64()?;
+        let num_keys = payload.u64()? as usize;
+        let seed = payload.u64()? as u128 | (payload.u64()? as u128) << 64;
+        let num_hashes = payload.u32()?;
+        let num_words = payload.count(8)?;
+        let bits = (0..num_words)
+            .map(|_| payload.u64())
+            .collect::<io::Result<Vec<u64>>>()?;
+        payload.finish()?;
+        if bits.is_empty() || num_hashes == 0 {
+            return Err(persist::invalid_data("empty bloom filter"));
+        }
+
+        let filter = FastBloomFilter::from_vec(bits)
+            .seed(&seed)
+            .hashes(num_hashes);
+        Ok(Self {
+            filter,
+            fpr,
+            num_keys,
+            seed,
+        })
+    }
+
+    /// `save_to` into a new buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.save_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// `load_from` a buffer written by `to_bytes` or `save_to`
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        Self::load_from(&mut &bytes[..])
+    }
+}
+
+impl Extend<Key> for BloomFilter {
+    fn extend<I: IntoIterator<Item = Key>>(&mut self, keys: I) {
+        for key in keys {
+            self.insert(key);
+        }
+    }
+}
+
+impl RangeFilter for BloomFilter {
+    fn point_query(&self, key: Key) -> bool {
+        BloomFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        BloomFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_extend_after_construction() {
+        let mut bloom = BloomFilter::new_with_keys(&[10, 20], 0.001);
+
+        bloom.insert(110);
+        bloom.extend([300, 400]);
+        assert_eq!(bloom.num_keys(), 5);
+        for key in [10, 20, 110, 300, 400] {
+            assert!(bloom.point_query(key), "key {} should be found", key);
+        }
+        assert!(bloom.range_query(100, 120));
+    }
+
+    #[test]
+    fn test_range_query_many_matches_range_query() {
+        let bloom = BloomFilter::new_with_keys(&[10, 20, 500], 0.001);
+        let ranges = [(5, 15), (30, 10), (490, 510), (0, 0), (18, 22)];
+        let expected: Vec<bool> = ranges
+            .iter()
+            .map(|&(start, end)| bloom.range_query(start, end))
+            .collect();
+        assert_eq!(bloom.range_query_many(&ranges), expected);
+        assert!(!bloom.range_query_many(&ranges)[1]);
+        assert!(bloom.range_query_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_union_and_intersect() {
+        let mut first = BloomFilter::new_with_capacity(1000, 0.01);
+        let mut second = first.empty_like();
+        first.extend(0..600);
+        second.extend(400..1000);
+
+        let union = first.union(&second).unwrap();
+        assert_eq!(union.num_keys(), 1200);
+        assert!((0..1000).all(|key| union.point_query(key)));
+
+        let both = first.intersect(&second).unwrap();
+        assert_eq!(both.num_keys(), 600);
+        assert!((400..600).all(|key| both.point_query(key)));
+        // keys in only one filter survive as false positives of the other
+        let survivors = (0..400).filter(|&key| both.point_query(key)).count();
+        assert!(
+            survivors < 40,
+            "{} keys of the first filter only",
+            survivors
+        );
+    }
+
+    #[test]
+    fn test_union_rejects_other_configurations() {
+        let first = BloomFilter::new_with_keys(&[1, 2, 3], 0.01);
+        // same size, different seed
+        let second = BloomFilter::new_with_keys(&[4, 5, 6], 0.01);
+        let bigger = BloomFilter::new_with_capacity(100_000, 0.01);
+        for other in [&second, &bigger] {
+            assert!(matches!(
+                first.union(other),
+                Err(RangeFilterError::InvalidConfig(_))
+            ));
+            assert!(first.intersect(other).is_err());
+        }
+        assert!(first.union(&first.empty_like()).is_ok());
+    }
+
+    #[test]
+    fn test_fill_empty_filter_with_capacity() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 97).collect();
+        let mut bloom = BloomFilter::new_with_capacity(keys.len(), 0.01);
+        assert_eq!(bloom.num_keys(), 0);
+        bloom.extend(keys.iter().copied());
+
+        assert!(keys.iter().all(|&key| bloom.point_query(key)));
+        let false_positives = (0..10_000)
+            .map(|i| i * 97 + 50)
+            .filter(|&key| bloom.point_query(key))
+            .count();
+        // 1% of 10_000 expected, allow for variance
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
 }
@@ -0,0 +1,256 @@
+//! Counting Bloom filter with 4-bit counters
+//!
+//! Every key increments `k` counters instead of setting `k` bits, so it can be
+//! deleted again by decrementing them. Counters are packed 16 to a `u64`. A
+//! counter that reaches 15 sticks there: it is no longer decremented, which
+//! keeps deletes from creating false negatives at the cost of a slot that
+//! stays set.
+//!
+//! The `k` positions of a key come from one 64-bit hash split into two halves,
+//! `h1 + i * h2` (Kirsch and Mitzenmacher).
+
+use crate::Key;
+use crate::bitmap::{read_bits, write_bits};
+use crate::range_filter::RangeFilter;
+use crate::utils::mix64;
+
+const COUNTER_BITS: usize = 4;
+const MAX_COUNT: u64 = (1 << COUNTER_BITS) - 1;
+
+pub struct CountingBloomFilter {
+    counters: Vec<u64>,
+    num_counters: usize,
+    num_hashes: u32,
+    fpr: f64,
+    num_keys: usize,
+    seed: u64,
+}
+
+impl CountingBloomFilter {
+    /// Create a new Counting Bloom Filter with the given keys and false positive rate.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new_with_keys(keys: &[Key], fpr: f64) -> Self {
+        let mut filter = Self::new_with_capacity(keys.len(), fpr);
+        for &key in keys {
+            filter.insert(key);
+        }
+        filter
+    }
+
+    /// Create an empty Counting Bloom Filter sized for `capacity` keys.
+    ///
+    /// The false positive rate only holds while at most `capacity` keys are
+    /// present.
+    ///
+    /// # Arguments
+    /// * `capacity` - Number of keys the counters are sized for
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new_with_capacity(capacity: usize, fpr: f64) -> Self {
+        let fpr = fpr.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        // m = -n ln(p) / ln(2)^2 and k = m / n ln(2) of a plain Bloom filter
+        let num_counters =
+            ((-(capacity.max(1) as f64) * fpr.ln() / (ln2 * ln2)).ceil() as usize).max(64);
+        let num_hashes = ((num_counters as f64 / capacity.max(1) as f64) * ln2)
+            .round()
+            .clamp(1.0, 32.0) as u32;
+        Self {
+            counters: vec![0; (num_counters * COUNTER_BITS).div_ceil(64)],
+            num_counters,
+            num_hashes,
+            fpr,
+            num_keys: 0,
+            seed: rand::random(),
+        }
+    }
+
+    /// Insert a key, again for every copy of a key inserted more than once.
+    pub fn insert(&mut self, key: Key) {
+        for pos in self.positions(key) {
+            let count = self.counter(pos);
+            if count < MAX_COUNT {
+                self.set_counter(pos, count + 1);
+            }
+        }
+        self.num_keys += 1;
+    }
+
+    /// Delete one copy of a key, returning false if the key is definitely absent.
+    ///
+    /// Deleting a key that was never inserted but tests positive decrements
+    /// counters of other keys and can make them test negative, so only delete
+    /// keys known to be present.
+    pub fn delete(&mut self, key: Key) -> bool {
+        if !self.point_query(key) {
+            return false;
+        }
+        for pos in self.positions(key) {
+            let count = self.counter(pos);
+            if count < MAX_COUNT {
+                self.set_counter(pos, count - 1);
+            }
+        }
+        self.num_keys = self.num_keys.saturating_sub(1);
+        true
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist (with false positive rate `fpr`)
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.positions(key).all(|pos| self.counter(pos) > 0)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// Probes every key in the range, like `BloomFilter::range_query`.
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        start <= end && (start..=end).any(|key| self.point_query(key))
+    }
+
+    /// the counters of `key`, not borrowing the filter so they can be updated
+    fn positions(&self, key: Key) -> impl Iterator<Item = usize> + use<> {
+        let hash = mix64(key ^ self.seed);
+        let (h1, h2) = (hash & 0xFFFF_FFFF, (hash >> 32) | 1);
+        let num_counters = self.num_counters as u64;
+        (0..self.num_hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_counters) as usize)
+    }
+
+    #[inline]
+    fn counter(&self, pos: usize) -> u64 {
+        read_bits(&self.counters, pos * COUNTER_BITS, COUNTER_BITS)
+    }
+
+    #[inline]
+    fn set_counter(&mut self, pos: usize, count: u64) {
+        write_bits(&mut self.counters, pos * COUNTER_BITS, COUNTER_BITS, count);
+    }
+
+    /// Get the configured false positive rate.
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of keys present, duplicates included.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of counters each key increments.
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Get the memory used by the filter: the struct plus its packed 4-bit
+    /// counters.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + std::mem::size_of_val(self.counters.as_slice())
+    }
+
+    /// Get the memory per key copy currently inserted in bits, which rises
+    /// as deletes empty the filter.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for CountingBloomFilter {
+    fn point_query(&self, key: Key) -> bool {
+        CountingBloomFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        CountingBloomFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_delete_point_query() {
+        let keys: Vec<Key> = (0..2000).map(|i| i * 1009).collect();
+        let mut filter = CountingBloomFilter::new_with_keys(&keys, 0.01);
+        assert_eq!(filter.num_keys(), 2000);
+        assert!(keys.iter().all(|&key| filter.point_query(key)));
+
+        // delete every other key, the rest must stay
+        for &key in keys.iter().step_by(2) {
+            assert!(filter.delete(key));
+        }
+        assert_eq!(filter.num_keys(), 1000);
+        assert!(
+            keys.iter()
+                .skip(1)
+                .step_by(2)
+                .all(|&key| filter.point_query(key))
+        );
+        let still_present = keys
+            .iter()
+            .step_by(2)
+            .filter(|&&key| filter.point_query(key))
+            .count();
+        // deleted keys only come back as false positives
+        assert!(
+            still_present < 60,
+            "{} deleted keys still present",
+            still_present
+        );
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let keys: Vec<Key> = (0..5000).map(|i| i * 31).collect();
+        let filter = CountingBloomFilter::new_with_keys(&keys, 0.01);
+        let false_positives = (0..20_000)
+            .map(|i| i * 31 + 7)
+            .filter(|&key| filter.point_query(key))
+            .count();
+        // 1% of 20_000 expected, allow for variance
+        assert!(false_positives < 400, "{} false positives", false_positives);
+        // 4-bit counters cost four times a Bloom filter's bits
+        assert!(
+            filter.bits_per_key() < 45.0,
+            "{} bits per key",
+            filter.bits_per_key()
+        );
+    }
+
+    #[test]
+    fn test_duplicates_and_absent_keys() {
+        let mut filter = CountingBloomFilter::new_with_capacity(100, 0.001);
+        assert!(!filter.point_query(5) && !filter.delete(5));
+        filter.insert(5);
+        filter.insert(5);
+        assert!(filter.delete(5));
+        assert!(filter.point_query(5), "one copy of 5 is left");
+        assert!(filter.delete(5));
+        assert!(!filter.point_query(5));
+        assert_eq!(filter.num_keys(), 0);
+        assert!(!filter.range_query(0, 100));
+        assert!(!filter.range_query(10, 5));
+    }
+
+    #[test]
+    fn test_saturated_counters_stick() {
+        let mut filter = CountingBloomFilter::new_with_capacity(10, 0.01);
+        for _ in 0..20 {
+            filter.insert(42);
+        }
+        for _ in 0..20 {
+            assert!(filter.delete(42));
+        }
+        // past 15 copies the counters can no longer tell how many are left
+        assert!(filter.point_query(42));
+    }
+}
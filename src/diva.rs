@@ -1,13 +1,32 @@
-use rand::Rng;
-
 use crate::Key;
 use crate::U64_BITS;
-use crate::infix_store::InfixStore;
-use crate::utils::longest_common_prefix_length;
-use crate::y_fast_trie::YFastTrie;
-use std::fmt;
+use crate::diva_snapshot::DivaSnapshot;
+use crate::error::RangeFilterError;
+use crate::infix_store::{InfixStore, StoreLayout, StoreProbe};
+#[cfg(feature = "std")]
+use crate::persist::{self, FilterKind, PayloadReader, PayloadWriter};
+use crate::query_trace::{QueryKind, QueryTrace, QueryTracer};
+use crate::range_filter::RangeFilter;
+use crate::sync::{Arc, RwLock};
+use crate::utils::{
+    extract_partial_key, longest_common_prefix_length, mix64, shared_ignore_implicit_size,
+};
+use crate::y_fast_trie::{GroupSizePolicy, YFastTrie};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
 
 const BASE_IMPLICIT_SIZE: u32 = 10;
+/// upper bound for the remainder size a dense partition can pick
+const MAX_REMAINDER_SIZE: u8 = 32;
+
+/// A partition between two consecutive samples: predecessor, successor and the
+/// InfixStore of the keys in between, if any
+pub type Partition = (Key, Key, Option<Arc<RwLock<InfixStore>>>);
 
 /// Diva range filter
 ///
@@ -15,7 +34,8 @@ const BASE_IMPLICIT_SIZE: u32 = 10;
 /// * `y_fast_trie` - Y-Fast Trie
 /// * `target_size` - Target size
 /// * `fpr` - False positive rate
-/// * `remainder_size` - Remainder size
+/// * `remainder_size` - Remainder size of a full partition, each InfixStore picks
+///   its own from the key density between its samples
 ///
 /// # Example
 /// ```rust
@@ -28,26 +48,93 @@ const BASE_IMPLICIT_SIZE: u32 = 10;
 ///
 /// # Returns
 /// * `Diva` - Diva range filter
+///
+/// Use `DivaBuilder` to change the quotient size or size grade table of the
+/// InfixStores, or the group size policy of the y-fast trie.
+///
+/// The InfixStores are counting stores (see `InfixStore::new_counting`): a
+/// short remainder lets distinct keys of a partition share an infix, and
+/// deleting one of them must leave a copy for the others.
+///
+/// With the `serde` feature enabled, a built filter (samples, BST groups and
+/// all InfixStores) can be serialized and reloaded without reconstruction.
+///
+/// Which inserted keys become samples is drawn from a generator seeded at
+/// random with `std` and with a fixed seed without it, `DivaBuilder::seed`
+/// makes inserts reproducible.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Diva {
     y_fast_trie: YFastTrie,
     target_size: usize,
     fpr: f64,
     remainder_size: u8,
+    layout: StoreLayout,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tracer: Option<QueryTracer>,
+    /// state of the generator drawing the samples among inserted keys
+    #[cfg_attr(feature = "serde", serde(skip, default = "default_sample_seed"))]
+    sample_rng: u64,
+}
+
+/// seed of the sample generator when none is given
+fn default_sample_seed() -> u64 {
+    #[cfg(feature = "std")]
+    return rand::random();
+    #[cfg(not(feature = "std"))]
+    return 0x2545_F491_4F6C_DD1D;
 }
 
 impl Diva {
     pub fn new(target_size: usize, fpr: f64) -> Self {
+        Self::new_with_layout(
+            target_size,
+            fpr,
+            StoreLayout::DEFAULT,
+            GroupSizePolicy::default(),
+        )
+    }
+
+    pub fn new_with_keys(keys: &[Key], target_size: usize, fpr: f64) -> Self {
+        Self::new_with_keys_and_layout(
+            keys,
+            target_size,
+            fpr,
+            StoreLayout::DEFAULT,
+            GroupSizePolicy::default(),
+        )
+    }
+
+    /// start configuring a filter with custom parameters
+    pub fn builder() -> DivaBuilder {
+        DivaBuilder::default()
+    }
+
+    fn new_with_layout(
+        target_size: usize,
+        fpr: f64,
+        layout: StoreLayout,
+        group_size: GroupSizePolicy,
+    ) -> Self {
         let remainder_size = Self::choose_remainder_size(target_size, fpr);
         const NO_LEVELS: usize = 64;
         Self {
-            y_fast_trie: YFastTrie::new(NO_LEVELS),
+            y_fast_trie: YFastTrie::with_group_size(NO_LEVELS, group_size),
             target_size,
             fpr,
             remainder_size,
+            layout,
+            tracer: None,
+            sample_rng: default_sample_seed(),
         }
     }
 
-    pub fn new_with_keys(keys: &[Key], target_size: usize, fpr: f64) -> Self {
+    fn new_with_keys_and_layout(
+        keys: &[Key],
+        target_size: usize,
+        fpr: f64,
+        layout: StoreLayout,
+        group_size: GroupSizePolicy,
+    ) -> Self {
         let remainder_size = Self::choose_remainder_size(target_size, fpr);
         let mut sorted_keys = keys.to_vec();
         sorted_keys.sort();
@@ -64,7 +151,8 @@ impl Diva {
 
         // TODO: make this dynamic based on the key length
         const NO_LEVELS: usize = U64_BITS;
-        let mut y_fast_trie = YFastTrie::new_with_keys(&sampled_keys, NO_LEVELS);
+        let mut y_fast_trie =
+            YFastTrie::from_keys_with_group_size(&sampled_keys, NO_LEVELS, group_size);
 
         // for each pair of consecutive samples, extract infixes from intermediate keys
         for i in 0..sampled_keys.len().saturating_sub(1) {
@@ -73,7 +161,12 @@ impl Diva {
 
             // compute extraction parameters from boundary keys
             let (shared_prefix_len, redundant_bits, quotient_bits) =
-                Self::get_shared_ignore_implicit_size(&predecessor, &successor, false);
+                Self::get_shared_ignore_implicit_size_with(
+                    &predecessor,
+                    &successor,
+                    false,
+                    layout.quotient_size(),
+                );
 
             // find intermediate keys between these samples excluding the samples themselves
             let intermediate_keys: Vec<Key> = sorted_keys
@@ -81,11 +174,25 @@ impl Diva {
                 .filter(|&&k| k > predecessor && k < successor)
                 .copied()
                 .collect();
+            let remainder_size = Self::choose_partition_remainder_size(
+                predecessor,
+                successor,
+                intermediate_keys.len().max(target_size - 1),
+                layout.quotient_size(),
+                fpr,
+            );
 
             // extract infixes from intermediate keys
             let mut infixes = Vec::new();
-            // println!("Processing keys between {} and {} (shared={}, redundant={}, quotient={})",
-            //          predecessor, successor, shared_prefix_len, redundant_bits, quotient_bits);
+            trace!(
+                "partition ({}, {}): shared={} redundant={} quotient={} remainder={}",
+                predecessor,
+                successor,
+                shared_prefix_len,
+                redundant_bits,
+                quotient_bits,
+                remainder_size
+            );
             for key in intermediate_keys {
                 let infix = Self::extract_partial_key(
                     key,
@@ -94,15 +201,13 @@ impl Diva {
                     quotient_bits,
                     remainder_size,
                 );
-                // println!("Key {} ({:064b}) -> infix {} ({:064b})",
-                //          key, key, infix, infix);
                 infixes.push(infix);
             }
-            // println!("Infixes before InfixStore creation: {:?}", infixes);
 
             // create InfixStore and attach to predecessor sample
             if !infixes.is_empty() {
-                let infix_store = InfixStore::new_with_infixes(&infixes, remainder_size);
+                let infix_store =
+                    InfixStore::new_counting(&infixes, remainder_size, layout.clone());
                 y_fast_trie.set_infix_store(predecessor, infix_store);
             }
         }
@@ -112,81 +217,230 @@ impl Diva {
             target_size,
             fpr,
             remainder_size,
+            layout,
+            tracer: None,
+            sample_rng: default_sample_seed(),
         }
     }
 
     /// insert a new key into DIVA
-    pub fn insert(&mut self, key: Key) -> bool {
-        let mut rng = rand::thread_rng();
-        let chance: usize = rng.gen_range(0..self.target_size);
-        let is_sample = chance == 0;
-
-        if !is_sample && self.insert_in_infix(key) {
-            return true;
+    ///
+    /// Keys are sampled with probability 1 / target size. Keys outside the
+    /// sampled range move the first or last sample out while its partition has
+    /// room, and any key that cannot be stored as an infix becomes a sample that
+    /// splits its partition, so a filter can be built incrementally from empty.
+    pub fn insert(&mut self, key: Key) -> Result<(), RangeFilterError> {
+        let is_sample = self.next_sample_chance() == 0;
+
+        if !is_sample {
+            match self.insert_in_infix(key) {
+                Err(RangeFilterError::KeyOutOfUniverse(_)) => {
+                    if self.extend_edge_partition(key) {
+                        return Ok(());
+                    }
+                }
+                result => return result,
+            }
         }
         self.insert_as_sample(key)
     }
 
-    pub fn insert_in_infix(&mut self, key: Key) -> bool {
+    /// draw a chance in `0..target_size` from a Weyl sequence through `mix64`
+    fn next_sample_chance(&mut self) -> usize {
+        self.sample_rng = self.sample_rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        ((mix64(self.sample_rng) as u128 * self.target_size as u128) >> 64) as usize
+    }
+
+    /// insert a key as an infix of the partition it falls in
+    ///
+    /// Another insert of a key the store keeps exactly is a no-op, while a
+    /// lossy infix gets a copy per insert, as it may stand for another key.
+    ///
+    /// Fails with `KeyOutOfUniverse` if the key is not between two samples.
+    pub fn insert_in_infix(&mut self, key: Key) -> Result<(), RangeFilterError> {
         // key should be inserted as a sample if any of the boundary keys are missing
-        let (s_low, s_high) = match self
+        let (s_low, s_high) = self
             .y_fast_trie
             .predecessor(key)
             .zip(self.y_fast_trie.successor(key))
-        {
-            Some(bounds) => bounds,
-            None => return false,
-        };
+            .ok_or(RangeFilterError::KeyOutOfUniverse(key))?;
 
         // if the key to be inserted already exists as one of the samples
         if key == s_low || key == s_high {
-            return true;
+            return Ok(());
         }
 
         // extract the infix from the key
         let (shared_bits, redundant_bits, quotient_bits) =
-            Self::get_shared_ignore_implicit_size(&s_low, &s_high, false);
-
-        let infix = Self::extract_partial_key(
-            key,
-            shared_bits,
-            redundant_bits,
-            quotient_bits,
-            self.remainder_size,
-        );
-
-        match self.y_fast_trie.get_infix_store(s_low) {
+            Self::get_shared_ignore_implicit_size_with(
+                &s_low,
+                &s_high,
+                false,
+                self.layout.quotient_size(),
+            );
+
+        match self.store_for_write(s_low) {
             // attempt to insert in an existing infix store first
             Some(store) => {
-                if let Ok(mut store) = store.write() {
+                let inserted = {
+                    let mut store = store.write().map_err(|_| RangeFilterError::LockPoisoned)?;
+                    let infix = Self::extract_partial_key(
+                        key,
+                        shared_bits,
+                        redundant_bits,
+                        quotient_bits,
+                        store.remainder_size(),
+                    );
+                    // an exact infix stands for this key only, so it is there already
+                    let free_bits = Self::free_bits(s_low, s_high, self.layout.quotient_size());
+                    if store.remainder_size() as i32 >= free_bits && store.count(infix) > 0 {
+                        return Ok(());
+                    }
                     store.insert(infix)
-                } else {
-                    false
+                };
+                match inserted {
+                    // the store is at its largest size grade
+                    Err(RangeFilterError::PartitionFull) => {
+                        self.split_saturated_partition(s_low, s_high, key)
+                    }
+                    result => result,
                 }
             }
             // else, create a new store with the infix
+            // sized for a partition filled up to the target size
             None => {
-                let new_store = InfixStore::new_with_infixes(&[infix], self.remainder_size);
+                let remainder_size = Self::choose_partition_remainder_size(
+                    s_low,
+                    s_high,
+                    self.target_size - 1,
+                    self.layout.quotient_size(),
+                    self.fpr,
+                );
+                let infix = Self::extract_partial_key(
+                    key,
+                    shared_bits,
+                    redundant_bits,
+                    quotient_bits,
+                    remainder_size,
+                );
+                let new_store =
+                    InfixStore::new_counting(&[infix], remainder_size, self.layout.clone());
                 self.y_fast_trie.set_infix_store(s_low, new_store);
-                true
+                Ok(())
             }
         }
     }
 
-    fn insert_as_sample(&mut self, _key: Key) -> bool {
-        todo!();
+    /// promote a key to a sample, splitting the partition it falls in
+    fn insert_as_sample(&mut self, key: Key) -> Result<(), RangeFilterError> {
+        if self.y_fast_trie.contains(key) {
+            return Ok(());
+        }
+
+        let bounds = self
+            .y_fast_trie
+            .predecessor(key)
+            .zip(self.y_fast_trie.successor(key));
+        let key_ranges = bounds
+            .map(|(s_low, s_high)| self.partition_key_ranges(s_low, s_high))
+            .unwrap_or_default();
+
+        // the key is not a sample yet, so the trie only refuses it on a poisoned lock
+        if !self.y_fast_trie.insert(key) {
+            return Err(RangeFilterError::LockPoisoned);
+        }
+        if let Some((s_low, s_high)) = bounds {
+            self.rebuild_partition(s_low, key, &key_ranges);
+            self.rebuild_partition(key, s_high, &key_ranges);
+        }
+        Ok(())
+    }
+
+    /// split a partition whose store is full at the median of its stored keys,
+    /// then insert the key into the half it falls in
+    ///
+    /// Falls back to making the key itself a sample if the partition has no
+    /// usable median.
+    fn split_saturated_partition(
+        &mut self,
+        s_low: Key,
+        s_high: Key,
+        key: Key,
+    ) -> Result<(), RangeFilterError> {
+        let key_ranges =
+            Self::clip_key_ranges(&self.partition_key_ranges(s_low, s_high), s_low, s_high);
+        let median = key_ranges
+            .get(key_ranges.len() / 2)
+            .map(|&(low, _)| low)
+            .filter(|&median| median != key);
+
+        let Some(median) = median else {
+            return self.insert_as_sample(key);
+        };
+        if !self.y_fast_trie.insert(median) {
+            return self.insert_as_sample(key);
+        }
+        self.rebuild_partition(s_low, median, &key_ranges);
+        self.rebuild_partition(median, s_high, &key_ranges);
+
+        self.insert_in_infix(key)
+            .or_else(|_| self.insert_as_sample(key))
+    }
+
+    /// move the first or last sample out to a key beyond it, keeping the old
+    /// sample as an infix of the widened edge partition
+    ///
+    /// Returns false if the key is inside the sampled range, there is only one
+    /// sample, or the edge partition already holds target size - 1 infixes.
+    fn extend_edge_partition(&mut self, key: Key) -> bool {
+        let (Some(first), Some(last)) = (
+            self.y_fast_trie.successor(Key::MIN),
+            self.y_fast_trie.predecessor(Key::MAX),
+        ) else {
+            return false;
+        };
+
+        let (old_sample, neighbor) = if key < first {
+            let neighbor = first
+                .checked_add(1)
+                .and_then(|k| self.y_fast_trie.successor(k));
+            (first, neighbor)
+        } else if key > last {
+            let neighbor = last
+                .checked_sub(1)
+                .and_then(|k| self.y_fast_trie.predecessor(k));
+            (last, neighbor)
+        } else {
+            return false;
+        };
+        let Some(neighbor) = neighbor else {
+            return false;
+        };
+
+        let mut key_ranges =
+            self.partition_key_ranges(old_sample.min(neighbor), old_sample.max(neighbor));
+        if key_ranges.len() + 1 >= self.target_size {
+            return false;
+        }
+        key_ranges.push((old_sample, old_sample));
+
+        self.y_fast_trie.delete(old_sample);
+        self.y_fast_trie.insert(key);
+        self.rebuild_partition(key.min(neighbor), key.max(neighbor), &key_ranges);
+        true
     }
 
-    pub fn delete(&mut self, key: Key) -> bool {
+    /// delete a key from DIVA
+    ///
+    /// Fails with `KeyNotFound` if the key is neither a sample nor stored in
+    /// the partition it falls in.
+    pub fn delete(&mut self, key: Key) -> Result<(), RangeFilterError> {
         // key doesn't exist if its out of bounds
-        let (s_low, s_high) = match self
+        let (s_low, s_high) = self
             .y_fast_trie
             .predecessor(key)
             .zip(self.y_fast_trie.successor(key))
-        {
-            Some(bounds) => bounds,
-            None => return false,
-        };
+            .ok_or(RangeFilterError::KeyNotFound(key))?;
 
         // delete from trie if the key is a sample
         if key == s_low || key == s_high {
@@ -195,62 +449,154 @@ impl Diva {
         self.delete_from_infix(key, s_low, s_high)
     }
 
-    fn delete_from_infix(&mut self, key: Key, s_low: u64, s_high: u64) -> bool {
+    fn delete_from_infix(
+        &mut self,
+        key: Key,
+        s_low: u64,
+        s_high: u64,
+    ) -> Result<(), RangeFilterError> {
         // extract the infix from the key
         let (shared_bits, redundant_bits, quotient_bits) =
-            Self::get_shared_ignore_implicit_size(&s_low, &s_high, false);
+            Self::get_shared_ignore_implicit_size_with(
+                &s_low,
+                &s_high,
+                false,
+                self.layout.quotient_size(),
+            );
+
+        // delete from store if the store exists
+        let store = self
+            .store_for_write(s_low)
+            .ok_or(RangeFilterError::KeyNotFound(key))?;
+        let mut store = store.write().map_err(|_| RangeFilterError::LockPoisoned)?;
         let infix = Self::extract_partial_key(
             key,
             shared_bits,
             redundant_bits,
             quotient_bits,
-            self.remainder_size,
+            store.remainder_size(),
         );
+        // report the key rather than its infix
+        store.delete(infix).map_err(|error| match error {
+            RangeFilterError::KeyNotFound(_) => RangeFilterError::KeyNotFound(key),
+            error => error,
+        })
+    }
 
-        // delete from store if the store exists
-        match self.y_fast_trie.get_infix_store(s_low) {
-            Some(store) => {
-                if let Ok(mut store) = store.write() {
-                    store.delete(infix)
-                } else {
-                    false
-                }
-            }
-            None => false,
+    /// remove a sample key, promoting the closest stored key next to it to sample
+    ///
+    /// The promoted key is the largest key range end of the partition before the
+    /// sample or the smallest key range start of the partition after it, whichever
+    /// is closer. Both partitions around the new sample are rebuilt from their
+    /// stored key ranges. If neither partition stores anything, the two
+    /// partitions are merged. A lossy infix is promoted as the smallest or largest
+    /// key it may represent, which is kept as a sample after the key it stood
+    /// for is deleted.
+    fn delete_sample(&mut self, key: Key) -> Result<(), RangeFilterError> {
+        let predecessor = key
+            .checked_sub(1)
+            .and_then(|k| self.y_fast_trie.predecessor(k));
+        let successor = key
+            .checked_add(1)
+            .and_then(|k| self.y_fast_trie.successor(k));
+
+        let left_ranges = predecessor
+            .map(|predecessor| self.partition_key_ranges(predecessor, key))
+            .unwrap_or_default();
+        let right_ranges = successor
+            .map(|successor| self.partition_key_ranges(key, successor))
+            .unwrap_or_default();
+
+        let replacement = [
+            left_ranges.iter().map(|&(_, high)| high).max(),
+            right_ranges.iter().map(|&(low, _)| low).min(),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|&candidate| candidate.abs_diff(key));
+
+        if !self.y_fast_trie.delete(key) {
+            return Err(RangeFilterError::KeyNotFound(key));
+        }
+        if let Some(replacement) = replacement {
+            self.y_fast_trie.insert(replacement);
         }
-    }
 
-    fn delete_sample(&mut self, _key: Key) -> bool {
-        todo!();
+        // re-extract the infixes of every partition between the remaining samples
+        let key_ranges: Vec<(Key, Key)> = left_ranges.into_iter().chain(right_ranges).collect();
+        let samples: Vec<Key> = [predecessor, replacement, successor]
+            .into_iter()
+            .flatten()
+            .collect();
+        for bounds in samples.windows(2) {
+            self.rebuild_partition(bounds[0], bounds[1], &key_ranges);
+        }
+        Ok(())
     }
 
-    /// compute redundant bits after first differing bit
-    /// redundant bits are consecutive bits with opposite patterns in pred/succ
-    /// that can be reconstructed knowing the key is in this range
-    fn compute_redundant_bits(key_1: Key, key_2: Key, shared_prefix_len: u8) -> u8 {
-        if shared_prefix_len >= 63 {
-            return 0;
+    /// the store of the partition starting at a sample, about to be written in place
+    ///
+    /// A store still shared with a `DivaSnapshot` is copied first and the copy
+    /// attached to the partition, so the snapshot keeps seeing the old contents.
+    fn store_for_write(&mut self, predecessor: Key) -> Option<Arc<RwLock<InfixStore>>> {
+        let store = self.y_fast_trie.get_infix_store(predecessor)?;
+        // one reference is held by the trie, the other is the one just returned
+        if Arc::strong_count(&store) <= 2 {
+            return Some(store);
         }
+        let copy = Arc::new(RwLock::new(store.read().ok()?.clone()));
+        self.y_fast_trie
+            .attach_infix_store(predecessor, copy.clone())
+            .ok()?;
+        Some(copy)
+    }
 
-        let mut redundant_bits = 0u8;
-
-        // start after shared prefix + 1 (skip first differing bit)
-        let start_pos = shared_prefix_len + 1;
-
-        for bit_pos in start_pos..64 {
-            let shift = 63 - bit_pos;
-            let bit_1 = (key_1 >> shift) & 1;
-            let bit_2 = (key_2 >> shift) & 1;
+    /// the key ranges of the infixes stored in the partition (predecessor, successor)
+    fn partition_key_ranges(&self, predecessor: Key, successor: Key) -> Vec<(Key, Key)> {
+        self.y_fast_trie
+            .get_infix_store(predecessor)
+            .and_then(|store| {
+                store
+                    .read()
+                    .ok()
+                    .map(|store| Self::stored_key_ranges(&store, predecessor, successor))
+            })
+            .unwrap_or_default()
+    }
 
-            // redundant if pred has 0 and succ has 1 (opposite of first diff bit)
-            if bit_1 == 0 && bit_2 == 1 {
-                redundant_bits += 1;
-            } else {
-                break; // stop at first non-redundant bit
+    /// replace the store of the partition (predecessor, successor) with one
+    /// covering the parts of the key ranges that fall inside it
+    fn rebuild_partition(&mut self, predecessor: Key, successor: Key, key_ranges: &[(Key, Key)]) {
+        let clipped = Self::clip_key_ranges(key_ranges, predecessor, successor);
+        match Self::build_partition_store(
+            predecessor,
+            successor,
+            &clipped,
+            self.target_size,
+            self.fpr,
+            &self.layout,
+        ) {
+            Some(store) => self.y_fast_trie.set_infix_store(predecessor, store),
+            None => {
+                self.y_fast_trie.remove_infix_store(predecessor);
             }
         }
+    }
 
-        redundant_bits
+    /// clip key ranges to the open interval (predecessor, successor)
+    fn clip_key_ranges(
+        key_ranges: &[(Key, Key)],
+        predecessor: Key,
+        successor: Key,
+    ) -> Vec<(Key, Key)> {
+        key_ranges
+            .iter()
+            .filter_map(|&(low, high)| {
+                let low = low.max(predecessor + 1);
+                let high = high.min(successor - 1);
+                (low <= high).then_some((low, high))
+            })
+            .collect()
     }
 
     /// compute shared prefix, redundant bits, and quotient size
@@ -260,45 +606,22 @@ impl Diva {
         key_2: &Key,
         use_redundant_bits: bool,
     ) -> (u8, u8, u8) {
-        // step 1: find shared prefix length (LCP)
-        let shared = longest_common_prefix_length(*key_1, *key_2) as u8;
-
-        // step 2: compute redundant bits
-        let redundant_bits = if use_redundant_bits {
-            Self::compute_redundant_bits(*key_1, *key_2, shared)
-        } else {
-            0
-        };
-
-        // step 3: compute quotient size or aka implicit bits
-        let bits_used = shared + redundant_bits;
-
-        if bits_used >= 64 {
-            return (shared, redundant_bits, 0);
-        }
-
-        let remaining_bits = 64 - bits_used;
-
-        // try to use BASE_IMPLICIT_SIZE quotient bits
-        if remaining_bits < BASE_IMPLICIT_SIZE as u8 {
-            return (shared, redundant_bits, remaining_bits);
-        }
-
-        // extract quotient bits from both keys to check sparsity
-        // let shift = remaining_bits - BASE_IMPLICIT_SIZE as u8;
-        // let quotient_1 = (key_1 >> shift) & ((1u64 << BASE_IMPLICIT_SIZE) - 1);
-        // let quotient_2 = (key_2 >> shift) & ((1u64 << BASE_IMPLICIT_SIZE) - 1);
-
-        // TODO: check if there is a better heuristic for the quotient size
-        // add 1 bit if range is sparse (uses < 50% of quotient space)
-        // let range_size = quotient_2 - quotient_1 + 1;
-        // let quotient_bits = if 2 * range_size < (1u64 << BASE_IMPLICIT_SIZE) {
-        //     (BASE_IMPLICIT_SIZE + 1).min(remaining_bits as u32) as u8
-        // } else {
-        //     BASE_IMPLICIT_SIZE as u8
-        // };
+        Self::get_shared_ignore_implicit_size_with(
+            key_1,
+            key_2,
+            use_redundant_bits,
+            BASE_IMPLICIT_SIZE as u8,
+        )
+    }
 
-        (shared, redundant_bits, BASE_IMPLICIT_SIZE as u8)
+    /// same as `get_shared_ignore_implicit_size` with a custom base quotient size
+    pub fn get_shared_ignore_implicit_size_with(
+        key_1: &Key,
+        key_2: &Key,
+        use_redundant_bits: bool,
+        base_implicit_size: u8,
+    ) -> (u8, u8, u8) {
+        shared_ignore_implicit_size(key_1, key_2, use_redundant_bits, base_implicit_size)
     }
 
     /// extract partial key (infix) from a full key
@@ -317,23 +640,246 @@ impl Diva {
         quotient_bits: u8,
         remainder_bits: u8,
     ) -> Key {
+        extract_partial_key(
+            key,
+            shared_prefix_len,
+            redundant_bits,
+            quotient_bits,
+            remainder_bits,
+        )
+    }
+
+    /// inverse of `extract_partial_key`: the range of full keys [low, high] that
+    /// map to `infix` in the partition between `predecessor` and `successor`
+    fn infix_to_key_range(
+        infix: Key,
+        predecessor: Key,
+        successor: Key,
+        quotient_size: u8,
+        remainder_size: u8,
+    ) -> (Key, Key) {
+        let (shared_prefix_len, redundant_bits, quotient_bits) =
+            Self::get_shared_ignore_implicit_size_with(
+                &predecessor,
+                &successor,
+                false,
+                quotient_size,
+            );
         let start_bit = shared_prefix_len + redundant_bits;
+        let bits = (quotient_bits + remainder_size).min(64 - start_bit);
+        let shift = 64 - start_bit - bits;
 
-        if start_bit >= 64 {
-            return 0;
+        let prefix = if start_bit == 0 {
+            0
+        } else {
+            predecessor & !(u64::MAX >> start_bit)
+        };
+        let low = prefix | (infix << shift);
+        let high = if shift == 0 {
+            low
+        } else {
+            low | (u64::MAX >> (64 - shift))
+        };
+        (low, high)
+    }
+
+    /// the key ranges represented by every infix in a partition's store
+    fn stored_key_ranges(store: &InfixStore, predecessor: Key, successor: Key) -> Vec<(Key, Key)> {
+        store
+            .infixes()
+            .into_iter()
+            .map(|infix| {
+                Self::infix_to_key_range(
+                    infix,
+                    predecessor,
+                    successor,
+                    store.quotient_size(),
+                    store.remainder_size(),
+                )
+            })
+            .collect()
+    }
+
+    /// build a store for the partition (predecessor, successor) covering every key range
+    ///
+    /// The remainder size starts from the one the partition's density calls for,
+    /// sized for at least a full partition so later inserts keep the target FPR.
+    /// Ranges coming from lossy infixes only know their leading bits, so it is
+    /// lowered until every range maps to a single infix, or at least until the
+    /// covering infixes fit in one store, keeping a single copy of each 1-bit
+    /// infix if nothing else does. This keeps rebuilt partitions free of false
+    /// negatives.
+    fn build_partition_store(
+        predecessor: Key,
+        successor: Key,
+        key_ranges: &[(Key, Key)],
+        target_size: usize,
+        fpr: f64,
+        layout: &StoreLayout,
+    ) -> Option<InfixStore> {
+        if key_ranges.is_empty() {
+            return None;
+        }
+        let max_remainder_size = Self::choose_partition_remainder_size(
+            predecessor,
+            successor,
+            key_ranges.len().max(target_size - 1),
+            layout.quotient_size(),
+            fpr,
+        );
+
+        let (shared_prefix_len, redundant_bits, quotient_bits) =
+            Self::get_shared_ignore_implicit_size_with(
+                &predecessor,
+                &successor,
+                false,
+                layout.quotient_size(),
+            );
+        let start_bit = (shared_prefix_len + redundant_bits + quotient_bits) as i32;
+
+        // largest remainder for which every range is fully determined by its known bits
+        let fitting_remainder = key_ranges
+            .iter()
+            .map(|&(low, high)| longest_common_prefix_length(low, high) as i32 - start_bit)
+            .min()
+            .unwrap_or(max_remainder_size as i32);
+        let mut remainder_size = fitting_remainder.clamp(1, max_remainder_size.max(1) as i32) as u8;
+
+        loop {
+            let mut infixes = Vec::with_capacity(key_ranges.len());
+            for &(low, high) in key_ranges {
+                let first = Self::extract_partial_key(
+                    low,
+                    shared_prefix_len,
+                    redundant_bits,
+                    quotient_bits,
+                    remainder_size,
+                );
+                let last = Self::extract_partial_key(
+                    high,
+                    shared_prefix_len,
+                    redundant_bits,
+                    quotient_bits,
+                    remainder_size,
+                );
+                infixes.extend(first..=last);
+            }
+            // ranges sharing an infix keep one copy each, so every stored key
+            // can still be deleted on its own
+            infixes.sort_unstable();
+
+            if infixes.len() <= layout.max_capacity() {
+                return Some(InfixStore::new_counting(
+                    &infixes,
+                    remainder_size,
+                    layout.clone(),
+                ));
+            }
+            if remainder_size == 1 {
+                // ranges covering most of the partition overflow it even now,
+                // keep one copy of each infix; the at most `2 * num_quotients`
+                // 1-bit infixes fit, see `DivaBuilder::build`
+                infixes.dedup();
+                return Some(InfixStore::new_counting(
+                    &infixes,
+                    remainder_size,
+                    layout.clone(),
+                ));
+            }
+            remainder_size -= 1;
         }
+    }
 
-        let remaining_bits = 64 - start_bit;
-        let bits_to_extract = (quotient_bits + remainder_bits).min(remaining_bits);
+    /// build a filter from exact sample keys and the key ranges that fall between them
+    fn from_samples_and_ranges(
+        samples: &[Key],
+        key_ranges: &[(Key, Key)],
+        target_size: usize,
+        fpr: f64,
+        layout: StoreLayout,
+        group_size: GroupSizePolicy,
+    ) -> Self {
+        let remainder_size = Self::choose_remainder_size(target_size, fpr);
+        let mut y_fast_trie = YFastTrie::from_keys_with_group_size(samples, U64_BITS, group_size);
+
+        // clip every range to the partitions it overlaps
+        let mut partition_ranges = vec![Vec::new(); samples.len().saturating_sub(1)];
+        for &(low, high) in key_ranges {
+            let mut index = samples.partition_point(|&s| s <= low).saturating_sub(1);
+            while index + 1 < samples.len() && samples[index] < high {
+                partition_ranges[index].extend(Self::clip_key_ranges(
+                    &[(low, high)],
+                    samples[index],
+                    samples[index + 1],
+                ));
+                index += 1;
+            }
+        }
 
-        if bits_to_extract == 0 {
-            return 0;
+        for (index, ranges) in partition_ranges.iter().enumerate() {
+            let (predecessor, successor) = (samples[index], samples[index + 1]);
+            if let Some(store) = Self::build_partition_store(
+                predecessor,
+                successor,
+                ranges,
+                target_size,
+                fpr,
+                &layout,
+            ) {
+                y_fast_trie.set_infix_store(predecessor, store);
+            }
         }
 
-        let shift_amount = 64 - start_bit - bits_to_extract;
-        let extracted = (key >> shift_amount) & ((1 << bits_to_extract) - 1);
+        Self {
+            y_fast_trie,
+            target_size,
+            fpr,
+            remainder_size,
+            layout,
+            tracer: None,
+            sample_rng: default_sample_seed(),
+        }
+    }
+
+    /// sorted sample keys and the key ranges represented by every stored infix
+    fn samples_and_ranges(&self) -> (Vec<Key>, Vec<(Key, Key)>) {
+        let samples = self.sample_keys().collect();
+        let mut key_ranges = Vec::new();
+        for (predecessor, successor, store) in self.partitions() {
+            if let Some(store) = store.as_ref().and_then(|s| s.read().ok()) {
+                key_ranges.extend(Self::stored_key_ranges(&store, predecessor, successor));
+            }
+        }
+        (samples, key_ranges)
+    }
 
-        extracted
+    /// Merge two filters into one without access to the original keys
+    ///
+    /// Sample keys of both filters become samples of the merged filter, and every
+    /// stored infix is re-encoded into the partition it falls in. Partitions that
+    /// receive infixes from a coarser source partition use a shorter remainder, so
+    /// the merged filter has no false negatives but may have a higher FPR locally.
+    /// The merged filter uses the target size, FPR, store layout and group size
+    /// policy of `self`.
+    pub fn merge(&self, other: &Diva) -> Diva {
+        let (mut samples, mut key_ranges) = self.samples_and_ranges();
+        let (other_samples, other_ranges) = other.samples_and_ranges();
+
+        samples.extend(other_samples);
+        samples.sort_unstable();
+        samples.dedup();
+        key_ranges.extend(other_ranges);
+        key_ranges.sort_unstable();
+        key_ranges.dedup();
+
+        Self::from_samples_and_ranges(
+            &samples,
+            &key_ranges,
+            self.target_size,
+            self.fpr,
+            self.layout.clone(),
+            self.y_fast_trie.group_size_policy(),
+        )
     }
 
     /// get MSB (first differing bit) between predecessor and successor
@@ -352,11 +898,51 @@ impl Diva {
     /// calculate remainder size based on FPR
     /// FPR ≈ 2 / 2^remainder_size
     fn choose_remainder_size(_target_size: usize, fpr: f64) -> u8 {
-        // remainder_size = log2(2/FPR) = log2(2) + log2(1/FPR) = 1 - log2(FPR)
-        let remainder_size = (1.0 - fpr.log2()).ceil() as u8;
+        // remainder_size = log2(2/FPR), the smallest r with 2 <= FPR * 2^r
+        let remainder_size = InfixStore::remainder_size_for_fpr(2.0, fpr);
         remainder_size.max(4).min(16) // clamp between 4 and 16 bits
     }
 
+    /// remainder size for a partition holding `elem_count` keys between two samples
+    ///
+    /// Every infix stands for `2^(free_bits - r)` keys, where `free_bits` are the
+    /// key bits below the quotient, so a partition of width `W` holding `n`
+    /// infixes has a point query FPR of about `n * 2^(free_bits - r) / W`. The
+    /// smallest `r` that keeps this below the target FPR is used, so sparse
+    /// partitions get shorter remainders and dense partitions longer ones.
+    fn choose_partition_remainder_size(
+        predecessor: Key,
+        successor: Key,
+        elem_count: usize,
+        quotient_size: u8,
+        fpr: f64,
+    ) -> u8 {
+        let free_bits = Self::free_bits(predecessor, successor, quotient_size);
+        if free_bits <= 1 {
+            return 1;
+        }
+
+        // the partition's keys fall into width / 2^free_bits quotients
+        let width = successor.saturating_sub(predecessor).max(2) as f64 - 1.0;
+        let density = elem_count.max(1) as f64 * (1u128 << free_bits) as f64 / width;
+        InfixStore::remainder_size_for_fpr(density, fpr)
+            .clamp(1, free_bits.min(MAX_REMAINDER_SIZE as i32) as u8)
+    }
+
+    /// number of key bits below the quotient in the partition (predecessor, successor),
+    /// a remainder this long stores keys exactly
+    fn free_bits(predecessor: Key, successor: Key, quotient_size: u8) -> i32 {
+        let (shared_prefix_len, redundant_bits, quotient_bits) =
+            Self::get_shared_ignore_implicit_size_with(
+                &predecessor,
+                &successor,
+                false,
+                quotient_size,
+            );
+        U64_BITS as i32 - (shared_prefix_len + redundant_bits + quotient_bits) as i32
+    }
+
+    #[cfg(feature = "std")]
     pub fn pretty_print(&self) {
         print!("{}", self);
     }
@@ -367,8 +953,8 @@ impl Diva {
     where
         F: FnOnce(&InfixStore, Key, Key) -> R,
     {
-        let predecessor_infix_store = self.y_fast_trie.predecessor_infix_store(key)?;
-        let predecessor_key = self.y_fast_trie.predecessor(key)?;
+        let (predecessor_key, predecessor_infix_store) = self.y_fast_trie.floor_entry(key)?;
+        let predecessor_infix_store = predecessor_infix_store?;
         let successor_key = self.y_fast_trie.successor(key)?;
 
         let store = predecessor_infix_store.read().ok()?;
@@ -387,30 +973,147 @@ impl Diva {
     /// Point lookup: check if a key exists in the filter
     /// Returns true if key might exist (with FPR), false if definitely doesn't exist
     pub fn contains(&self, key: Key) -> bool {
+        let mut trace = QueryTrace::new(QueryKind::Point(key));
+        trace.result = self.contains_traced(key, &mut trace);
+        self.emit_trace(&trace);
+        trace.result
+    }
+
+    fn contains_traced(&self, key: Key, trace: &mut QueryTrace) -> bool {
         if self.y_fast_trie.contains(key) {
+            trace.sample_hit = true;
             return true;
         }
 
         // Query InfixStore using helper function
         self.with_infix_store_for_key(key, |store, predecessor_key, successor_key| {
-            let result =
-                store.point_query(key, predecessor_key, successor_key, self.remainder_size);
+            let mut probe = StoreProbe::default();
+            let result = store.point_query_probed(
+                key,
+                predecessor_key,
+                successor_key,
+                store.remainder_size(),
+                &mut probe,
+            );
+            trace.record_store((predecessor_key, successor_key), probe);
             result
         })
-        .unwrap_or_else(|| {
-            false
-        })
+        .unwrap_or_else(|| false)
+    }
+
+    /// Install a callback that receives a `QueryTrace` after every `contains`
+    /// and `range_query`, recording which partition was searched, how many
+    /// slots were scanned and whether the occupieds bitmap decided the answer
+    ///
+    /// Batch queries are not traced. Replaces any previously installed tracer.
+    ///
+    /// # Example
+    /// ```rust
+    /// use range_filters::Diva;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let keys: Vec<u64> = (0..1000).map(|i| i * 10).collect();
+    /// let mut diva = Diva::new_with_keys(&keys, 100, 0.01);
+    ///
+    /// let slots = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = slots.clone();
+    /// diva.set_query_tracer(move |trace| sink.lock().unwrap().push(trace.slots_scanned));
+    ///
+    /// diva.contains(505);
+    /// diva.range_query(511, 512);
+    /// assert_eq!(slots.lock().unwrap().len(), 2);
+    /// ```
+    pub fn set_query_tracer<F>(&mut self, tracer: F)
+    where
+        F: Fn(&QueryTrace) + Send + Sync + 'static,
+    {
+        self.tracer = Some(Arc::new(tracer));
+    }
+
+    /// remove the query tracer, if any
+    pub fn clear_query_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    fn emit_trace(&self, trace: &QueryTrace) {
+        if let Some(tracer) = &self.tracer {
+            tracer(trace);
+        }
+    }
+
+    /// Point lookup for many keys at once, returning one result per key in input order
+    ///
+    /// Queries are answered in sorted order so the trie lookups that find a
+    /// partition and its store are done once per partition instead of once per key.
+    pub fn contains_many(&self, keys: &[Key]) -> Vec<bool> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_unstable_by_key(|&index| keys[index]);
+
+        let mut results = vec![false; keys.len()];
+        let mut partition: Option<Partition> = None;
+        let mut previous: Option<(Key, bool)> = None;
+        for index in order {
+            let key = keys[index];
+            if let Some((_, result)) = previous.filter(|&(previous_key, _)| previous_key == key) {
+                results[index] = result;
+                continue;
+            }
+
+            // move to the partition [predecessor, successor) holding the key
+            if !partition
+                .as_ref()
+                .is_some_and(|&(predecessor, successor, _)| predecessor <= key && key < successor)
+            {
+                let floor = self.y_fast_trie.floor_entry(key);
+                let successor = key
+                    .checked_add(1)
+                    .and_then(|k| self.y_fast_trie.successor(k));
+                partition = floor
+                    .zip(successor)
+                    .map(|((predecessor, store), successor)| (predecessor, successor, store));
+            }
+
+            let result = match &partition {
+                Some((predecessor, successor, store)) => {
+                    key == *predecessor
+                        || store
+                            .as_ref()
+                            .and_then(|s| s.read().ok())
+                            .is_some_and(|store| {
+                                store.point_query(
+                                    key,
+                                    *predecessor,
+                                    *successor,
+                                    store.remainder_size(),
+                                )
+                            })
+                }
+                // only the last sample can lack a successor
+                None => self.y_fast_trie.predecessor(key) == Some(key),
+            };
+            results[index] = result;
+            previous = Some((key, result));
+        }
+        results
     }
 
     /// Range query: check if any key exists in the given range [start, end] (inclusive)
     /// Returns true if at least one key might exist in the range (with FPR), false if definitely no keys exist
     pub fn range_query(&self, start: Key, end: Key) -> bool {
+        let mut trace = QueryTrace::new(QueryKind::Range(start, end));
+        trace.result = self.range_query_traced(start, end, &mut trace);
+        self.emit_trace(&trace);
+        trace.result
+    }
+
+    fn range_query_traced(&self, start: Key, end: Key, trace: &mut QueryTrace) -> bool {
         if start > end {
             return false;
         }
 
         // Check if any samples intersect with the range
         if self.has_samples_in_range(start, end) {
+            trace.sample_hit = true;
             return true;
         }
 
@@ -424,13 +1127,17 @@ impl Diva {
                     let range_end = end.min(successor_key - 1);
 
                     if range_start <= range_end {
-                        if store.range_query(
+                        let mut probe = StoreProbe::default();
+                        let found = store.range_query_probed(
                             range_start,
                             range_end,
                             predecessor_key,
                             successor_key,
-                            self.remainder_size,
-                        ) {
+                            store.remainder_size(),
+                            &mut probe,
+                        );
+                        trace.record_store((predecessor_key, successor_key), probe);
+                        if found {
                             return (true, successor_key); // Found match, continue from successor
                         }
                     }
@@ -451,37 +1158,713 @@ impl Diva {
         false
     }
 
-    /// Get the number of samples in the Y-Fast Trie
-    pub fn sample_count(&self) -> usize {
-        self.y_fast_trie.sample_count()
+    /// Adapt to a key that the filter reports as present but the backing store
+    /// confirmed absent, so it is not reported again
+    ///
+    /// Stored infixes only hold leading key bits and cannot be lengthened on
+    /// their own, so `partition_keys(predecessor, successor)` is asked for the
+    /// keys strictly between the two samples around `key`, as found in the
+    /// backing store. The partition is rebuilt from them with the shortest
+    /// longer remainder that tells `key` apart from every stored key.
+    ///
+    /// Returns false if `key` is not a false positive, is among the returned
+    /// keys, or cannot be told apart within the largest remainder size.
+    ///
+    /// # Example
+    /// ```rust
+    /// use range_filters::Diva;
+    /// use std::collections::BTreeSet;
+    ///
+    /// let keys: BTreeSet<u64> = (0..1000).map(|i| i * 1_000_003).collect();
+    /// let mut diva = Diva::new_with_keys(&keys.iter().copied().collect::<Vec<_>>(), 64, 0.1);
+    ///
+    /// for probe in 0..100_000 {
+    ///     if !keys.contains(&probe) && diva.contains(probe) {
+    ///         diva.report_false_positive(probe, |low, high| {
+    ///             keys.range(low + 1..high).copied().collect()
+    ///         });
+    ///         assert!(!diva.contains(probe));
+    ///     }
+    /// }
+    /// ```
+    pub fn report_false_positive<F>(&mut self, key: Key, partition_keys: F) -> bool
+    where
+        F: FnOnce(Key, Key) -> Vec<Key>,
+    {
+        if self.y_fast_trie.contains(key) {
+            return false;
+        }
+        let Some(((predecessor, Some(store)), successor)) = self
+            .y_fast_trie
+            .floor_entry(key)
+            .zip(self.y_fast_trie.successor(key))
+        else {
+            return false;
+        };
+        let remainder_size = match store.read() {
+            Ok(store) if store.point_query(key, predecessor, successor, store.remainder_size()) => {
+                store.remainder_size()
+            }
+            _ => return false,
+        };
+
+        let mut keys = partition_keys(predecessor, successor);
+        keys.retain(|&k| k > predecessor && k < successor);
+        keys.sort_unstable();
+        keys.dedup();
+        if keys.binary_search(&key).is_ok() || keys.len() > self.layout.max_capacity() {
+            return false;
+        }
+        if keys.is_empty() {
+            self.y_fast_trie.remove_infix_store(predecessor);
+            return true;
+        }
+
+        let (shared_prefix_len, redundant_bits, quotient_bits) =
+            Self::get_shared_ignore_implicit_size_with(
+                &predecessor,
+                &successor,
+                false,
+                self.layout.quotient_size(),
+            );
+        let free_bits = Self::free_bits(predecessor, successor, self.layout.quotient_size());
+        let max_remainder_size = free_bits.clamp(1, MAX_REMAINDER_SIZE as i32) as u8;
+
+        for remainder_size in remainder_size + 1..=max_remainder_size {
+            let extract = |k| {
+                Self::extract_partial_key(
+                    k,
+                    shared_prefix_len,
+                    redundant_bits,
+                    quotient_bits,
+                    remainder_size,
+                )
+            };
+            let infixes: Vec<Key> = keys.iter().map(|&k| extract(k)).collect();
+            if !infixes.contains(&extract(key)) {
+                let store = InfixStore::new_counting(&infixes, remainder_size, self.layout.clone());
+                self.y_fast_trie.set_infix_store(predecessor, store);
+                return true;
+            }
+        }
+        false
     }
-}
 
-impl fmt::Display for Diva {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(
-            f,
-            "\n╔════════════════════════════════════════════════════════╗"
-        )?;
-        writeln!(
-            f,
-            "║                   DIVA RANGE FILTER                    ║"
-        )?;
-        writeln!(
-            f,
-            "╚════════════════════════════════════════════════════════╝"
-        )?;
+    /// Range query for many ranges at once, returning one result per range in input order
+    ///
+    /// A range without a sample in it lies inside a single partition. Ranges are
+    /// answered in sorted order, so consecutive ranges that fall inside the same
+    /// partition share its trie lookups and skip the sample check entirely.
+    pub fn range_query_many(&self, ranges: &[(Key, Key)]) -> Vec<bool> {
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_unstable_by_key(|&index| ranges[index]);
+
+        let mut results = vec![false; ranges.len()];
+        let mut partition: Option<Partition> = None;
+        for index in order {
+            let (start, end) = ranges[index];
+            if start > end {
+                continue;
+            }
 
-        // configuration
-        writeln!(f, "\nConfiguration:")?;
-        writeln!(f, "  Target size (T):      {}", self.target_size)?;
-        writeln!(f, "  False positive rate:  {:.4}%", self.fpr * 100.0)?;
-        writeln!(f, "  Remainder size:       {} bits", self.remainder_size)?;
+            // ranges strictly inside the current partition hold no sample
+            if !partition
+                .as_ref()
+                .is_some_and(|&(predecessor, successor, _)| predecessor < start && end < successor)
+            {
+                let Some(successor) = self.y_fast_trie.successor(start) else {
+                    continue;
+                };
+                if successor <= end {
+                    results[index] = true;
+                    continue;
+                }
+                let Some((predecessor, store)) = self.y_fast_trie.floor_entry(start) else {
+                    continue;
+                };
+                partition = Some((predecessor, successor, store));
+            }
 
-        // stats
-        writeln!(f, "\nStatistics:")?;
-        writeln!(f, "  Total keys:           {}", self.y_fast_trie.len())?;
-        writeln!(
+            if let Some((predecessor, successor, Some(store))) = &partition {
+                results[index] = store.read().ok().is_some_and(|store| {
+                    store.range_query(start, end, *predecessor, *successor, store.remainder_size())
+                });
+            }
+        }
+        results
+    }
+
+    /// Estimate the number of keys in [start, end] (inclusive)
+    ///
+    /// Counts the samples in the range plus, in every partition the range
+    /// touches, the stored infixes matching a key of the range, located with
+    /// rank/select instead of a scan. Keys sharing an infix count once and keys
+    /// just outside the range can share an infix with one inside, so the
+    /// estimate is as accurate as the filter's false positive rate allows.
+    pub fn estimate_count(&self, start: Key, end: Key) -> usize {
+        if start > end {
+            return 0;
+        }
+
+        let mut count = 0;
+        let mut current = self.y_fast_trie.predecessor(start);
+        if current == Some(start) {
+            count += 1;
+        }
+        loop {
+            let next = match current {
+                Some(sample) => sample
+                    .checked_add(1)
+                    .and_then(|key| self.y_fast_trie.successor(key)),
+                None => self.y_fast_trie.successor(start),
+            };
+
+            // infixes of the partition between the two samples
+            if let (Some(predecessor), Some(successor)) = (current, next) {
+                let range_start = start.max(predecessor + 1);
+                let range_end = end.min(successor - 1);
+                if range_start <= range_end {
+                    let store = self.y_fast_trie.get_infix_store(predecessor);
+                    if let Some(store) = store.as_ref().and_then(|store| store.read().ok()) {
+                        count += store.count_keys_in_range(
+                            range_start,
+                            range_end,
+                            predecessor,
+                            successor,
+                            store.remainder_size(),
+                        );
+                    }
+                }
+            }
+
+            match next {
+                Some(sample) if sample <= end => {
+                    count += 1;
+                    current = Some(sample);
+                }
+                _ => break,
+            }
+        }
+        count
+    }
+
+    /// Iterate over the partitions between consecutive samples in key order
+    ///
+    /// Each item is `(predecessor, successor, store)` where `store` holds the
+    /// infixes of the keys strictly between the two samples, or is `None` if
+    /// the partition is empty.
+    ///
+    /// # Example
+    /// ```rust
+    /// use range_filters::Diva;
+    /// let keys: Vec<u64> = (0..100).map(|i| i * 10).collect();
+    /// let diva = Diva::new_with_keys(&keys, 16, 0.01);
+    ///
+    /// for (predecessor, successor, store) in diva.partitions() {
+    ///     let elem_count = store.map_or(0, |s| s.read().unwrap().elem_count());
+    ///     println!("[{}, {}]: {} keys", predecessor, successor, elem_count);
+    /// }
+    /// ```
+    pub fn partitions(&self) -> impl Iterator<Item = Partition> {
+        let samples: Vec<_> = self
+            .y_fast_trie
+            .group_entries()
+            .into_iter()
+            .flatten()
+            .collect();
+        let successors: Vec<Key> = samples.iter().skip(1).map(|(key, _)| *key).collect();
+        samples
+            .into_iter()
+            .zip(successors)
+            .map(|((predecessor, store), successor)| (predecessor, successor, store))
+    }
+
+    /// Iterate over the sample keys in sorted order
+    pub fn sample_keys(&self) -> impl Iterator<Item = Key> {
+        self.y_fast_trie
+            .group_entries()
+            .into_iter()
+            .flatten()
+            .map(|(key, _)| key)
+    }
+
+    pub fn target_size(&self) -> usize {
+        self.target_size
+    }
+
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of samples in the Y-Fast Trie
+    pub fn sample_count(&self) -> usize {
+        self.y_fast_trie.sample_count()
+    }
+
+    /// Get the number of keys in the filter: sample keys plus stored infixes
+    pub fn num_keys(&self) -> usize {
+        let stored: usize = self
+            .y_fast_trie
+            .group_entries()
+            .iter()
+            .flatten()
+            .filter_map(|(_, store)| store.as_ref())
+            .filter_map(|store| store.read().ok().map(|s| s.elem_count()))
+            .sum();
+        self.y_fast_trie.len() + stored
+    }
+
+    /// Keep the occupieds of sparse partitions as sorted quotient arrays,
+    /// returning how many stores were frozen
+    ///
+    /// For filters that are done taking inserts, see
+    /// `InfixStore::freeze_occupieds`. A later insert or delete thaws the one
+    /// store it changes.
+    pub fn freeze_occupieds(&mut self) -> Result<usize, RangeFilterError> {
+        let mut frozen = 0;
+        for (_, _, store) in self.partitions() {
+            if let Some(store) = store {
+                let mut store = store.write().map_err(|_| RangeFilterError::LockPoisoned)?;
+                frozen += store.freeze_occupieds() as usize;
+            }
+        }
+        Ok(frozen)
+    }
+
+    /// memory used by the filter: samples, trie tables, BST groups and InfixStores
+    pub fn size_in_bytes(&self) -> usize {
+        core::mem::size_of::<Self>() - core::mem::size_of::<YFastTrie>()
+            + self.y_fast_trie.size_in_bytes()
+    }
+
+    /// memory per key in bits, samples counted as keys and the trie and BST
+    /// overhead spread over all of them
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys() {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+
+    /// expected point query false positive rate for an absent key drawn uniformly
+    /// between the first and last sample
+    ///
+    /// Every stored infix stands for all keys that share its leading bits, so a
+    /// partition with `n` infixes of which each covers `2^k` keys answers true
+    /// for `n * 2^k` keys; all but the `n` stored ones are false positives.
+    /// Partitions are weighted by the number of absent keys they hold.
+    pub fn expected_fpr(&self) -> f64 {
+        let mut false_positives = 0u128;
+        let mut negatives = 0u128;
+        for (predecessor, successor, store) in self.partitions() {
+            let width = (successor - predecessor - 1) as u128;
+
+            let (elem_count, covered) = match store.as_ref().and_then(|s| s.read().ok()) {
+                Some(store) => {
+                    let (low, high) = Self::infix_to_key_range(
+                        0,
+                        predecessor,
+                        successor,
+                        store.quotient_size(),
+                        store.remainder_size(),
+                    );
+                    let infix_span = (high - low) as u128 + 1;
+                    let elem_count = store.elem_count() as u128;
+                    (elem_count, (elem_count * infix_span).min(width))
+                }
+                None => (0, 0),
+            };
+            false_positives += covered.saturating_sub(elem_count);
+            negatives += width.saturating_sub(elem_count);
+        }
+
+        match negatives {
+            0 => 0.0,
+            negatives => false_positives as f64 / negatives as f64,
+        }
+    }
+
+    /// observed false positive rate over keys known to be absent from the filter
+    pub fn measure_fpr(&self, negatives: &[Key]) -> f64 {
+        if negatives.is_empty() {
+            return 0.0;
+        }
+        let false_positives = self
+            .contains_many(negatives)
+            .into_iter()
+            .filter(|&result| result)
+            .count();
+        false_positives as f64 / negatives.len() as f64
+    }
+
+    /// Write the zero-copy layout that can be memory-mapped and queried in place
+    /// through `DivaView` without rebuilding the filter
+    #[cfg(feature = "std")]
+    pub fn write_view<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let groups = self.y_fast_trie.group_entries();
+        crate::diva_view::write_layout(
+            writer,
+            self.target_size,
+            self.fpr,
+            self.remainder_size,
+            &groups,
+        )
+    }
+
+    /// Take a frozen point-in-time view of the filter
+    ///
+    /// The snapshot copies the sample keys and shares every InfixStore with the
+    /// filter. Later inserts and deletes copy a shared store before changing it
+    /// and rebuilt partitions get new stores, so the snapshot keeps answering
+    /// queries as of the moment it was taken while the filter moves on.
+    ///
+    /// # Example
+    /// ```rust
+    /// use range_filters::diva::Diva;
+    ///
+    /// let mut diva = Diva::new_with_keys(&[100, 200, 300, 400], 1024, 0.01);
+    /// let snapshot = diva.snapshot();
+    ///
+    /// diva.insert(250).unwrap();
+    /// assert!(diva.contains(250));
+    /// assert!(!snapshot.contains(250));
+    /// ```
+    pub fn snapshot(&self) -> DivaSnapshot {
+        let (samples, stores) = self
+            .y_fast_trie
+            .group_entries()
+            .into_iter()
+            .flatten()
+            .unzip();
+        DivaSnapshot::new(self.target_size, self.fpr, samples, stores)
+    }
+
+    /// Save the filter in the versioned binary format (see `persist`)
+    ///
+    /// The payload holds the parameters, the store layout, the sample keys and
+    /// the infixes of every partition, independent of how InfixStores lay out
+    /// their slots in memory. The group size policy is not saved, a loaded
+    /// filter uses the default one.
+    ///
+    /// # Example
+    /// ```rust
+    /// use range_filters::diva::Diva;
+    ///
+    /// let keys: Vec<u64> = (0..1000).map(|i| i * 10).collect();
+    /// let diva = Diva::new_with_keys(&keys, 100, 0.01);
+    ///
+    /// let mut bytes = Vec::new();
+    /// diva.save_to(&mut bytes).unwrap();
+    /// let loaded = Diva::load_from(&mut bytes.as_slice()).unwrap();
+    /// assert!(loaded.contains(500));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut payload = PayloadWriter::default();
+        payload.u64(self.target_size as u64);
+        payload.f64(self.fpr);
+        payload.u8(self.remainder_size);
+        payload.u8(self.layout.quotient_size());
+        payload.u64(self.layout.size_grades().len() as u64);
+        for &num_slots in self.layout.size_grades() {
+            payload.u16(num_slots);
+        }
+
+        let samples: Vec<Key> = self.sample_keys().collect();
+        payload.u64(samples.len() as u64);
+        for &sample in &samples {
+            payload.u64(sample);
+        }
+
+        let stores: Vec<(Key, Arc<RwLock<InfixStore>>)> = self
+            .partitions()
+            .filter_map(|(predecessor, _, store)| Some((predecessor, store?)))
+            .collect();
+        payload.u64(stores.len() as u64);
+        for (predecessor, store) in stores {
+            let store = store
+                .read()
+                .map_err(|_| io::Error::other("InfixStore lock poisoned"))?;
+            let infixes = store.infixes();
+            payload.u64(predecessor);
+            payload.u8(store.remainder_size());
+            payload.u64(infixes.len() as u64);
+            for infix in infixes {
+                payload.u64(infix);
+            }
+        }
+
+        persist::write_container(writer, FilterKind::Diva, &payload.into_bytes())
+    }
+
+    /// Load a filter saved with `save_to`, verifying its checksums and contents
+    #[cfg(feature = "std")]
+    pub fn load_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let (_version, payload) = persist::read_container(reader, FilterKind::Diva)?;
+        let mut payload = PayloadReader::new(&payload);
+
+        let target_size = payload.u64()? as usize;
+        let fpr = payload.f64()?;
+        let remainder_size = payload.u8()?;
+        let quotient_size = payload.u8()?;
+        let num_grades = payload.count(2)?;
+        let size_grades = (0..num_grades)
+            .map(|_| payload.u16())
+            .collect::<io::Result<Vec<u16>>>()?;
+        // the checks of the builder, e.g. a partition must fit the largest store
+        let layout = Diva::builder()
+            .target_size(target_size)
+            .fpr(fpr)
+            .quotient_size(quotient_size)
+            .size_grades(&size_grades)
+            .layout()
+            .map_err(|error| persist::invalid_data(&error.to_string()))?;
+
+        let num_samples = payload.count(8)?;
+        let samples = (0..num_samples)
+            .map(|_| payload.u64())
+            .collect::<io::Result<Vec<Key>>>()?;
+        if !samples.windows(2).all(|w| w[0] < w[1]) {
+            return Err(persist::invalid_data("samples are not strictly increasing"));
+        }
+        let mut y_fast_trie = YFastTrie::new_with_keys(&samples, U64_BITS);
+
+        let num_stores = payload.count(17)?;
+        for _ in 0..num_stores {
+            let predecessor = payload.u64()?;
+            let store_remainder_size = payload.u8()?;
+            let num_infixes = payload.count(8)?;
+            let infixes = (0..num_infixes)
+                .map(|_| payload.u64())
+                .collect::<io::Result<Vec<u64>>>()?;
+
+            // stores belong to every sample but the last
+            let index = samples
+                .binary_search(&predecessor)
+                .map_err(|_| persist::invalid_data("store attached to a non-sample key"))?;
+            if index + 1 == samples.len() {
+                return Err(persist::invalid_data("store attached to the last sample"));
+            }
+            let infix_size = quotient_size as u32 + store_remainder_size as u32;
+            if store_remainder_size == 0 || infix_size > U64_BITS as u32 {
+                return Err(persist::invalid_data("invalid remainder size"));
+            }
+            if infixes.len() > layout.max_capacity()
+                || !infixes.windows(2).all(|w| w[0] <= w[1])
+                || infixes
+                    .last()
+                    .is_some_and(|&infix| infix_size < 64 && infix >> infix_size != 0)
+            {
+                return Err(persist::invalid_data("invalid infixes"));
+            }
+            y_fast_trie.set_infix_store(
+                predecessor,
+                InfixStore::new_counting(&infixes, store_remainder_size, layout.clone()),
+            );
+        }
+        payload.finish()?;
+
+        Ok(Self {
+            y_fast_trie,
+            target_size,
+            fpr,
+            remainder_size,
+            layout,
+            tracer: None,
+            sample_rng: default_sample_seed(),
+        })
+    }
+}
+
+impl RangeFilter for Diva {
+    fn point_query(&self, key: Key) -> bool {
+        self.contains(key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        Diva::range_query(self, start, end)
+    }
+}
+
+/// Builder for a Diva filter with custom InfixStore parameters
+///
+/// # Arguments
+/// * `target_size` - Number of keys between consecutive samples
+/// * `fpr` - Target false positive rate, decides the remainder size
+/// * `quotient_size` - Quotient bits per InfixStore (2^quotient_size quotients)
+/// * `size_grades` - Slot counts an InfixStore grows and shrinks through
+///
+/// If no size grade table is given, the default table is scaled to the quotient size.
+///
+/// # Example
+/// ```rust
+/// use range_filters::diva::Diva;
+/// let keys: Vec<u64> = (0..10_000).map(|i| i * 10).collect();
+/// let diva = Diva::builder()
+///     .target_size(256)
+///     .fpr(0.001)
+///     .quotient_size(8)
+///     .build_with_keys(&keys)
+///     .unwrap();
+/// assert!(diva.contains(500));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DivaBuilder {
+    target_size: usize,
+    fpr: f64,
+    quotient_size: u8,
+    size_grades: Option<Vec<u16>>,
+    group_size: GroupSizePolicy,
+    seed: Option<u64>,
+}
+
+impl Default for DivaBuilder {
+    fn default() -> Self {
+        Self {
+            target_size: 1024,
+            fpr: 0.01,
+            quotient_size: BASE_IMPLICIT_SIZE as u8,
+            size_grades: None,
+            group_size: GroupSizePolicy::default(),
+            seed: None,
+        }
+    }
+}
+
+impl DivaBuilder {
+    pub fn target_size(mut self, target_size: usize) -> Self {
+        self.target_size = target_size;
+        self
+    }
+
+    pub fn fpr(mut self, fpr: f64) -> Self {
+        self.fpr = fpr;
+        self
+    }
+
+    pub fn quotient_size(mut self, quotient_size: u8) -> Self {
+        self.quotient_size = quotient_size;
+        self
+    }
+
+    pub fn size_grades(mut self, size_grades: &[u16]) -> Self {
+        self.size_grades = Some(size_grades.to_vec());
+        self
+    }
+
+    /// how many samples the y-fast trie groups together under one boundary
+    pub fn group_size(mut self, group_size: GroupSizePolicy) -> Self {
+        self.group_size = group_size;
+        self
+    }
+
+    /// seed of the generator picking which inserted keys become samples
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// seed the sample generator if a seed was given
+    fn seeded(&self, mut diva: Diva) -> Diva {
+        if let Some(seed) = self.seed {
+            diva.sample_rng = seed;
+        }
+        diva
+    }
+
+    /// validate the parameters and resolve the store layout
+    fn layout(&self) -> Result<StoreLayout, RangeFilterError> {
+        if self.target_size == 0 {
+            return Err(RangeFilterError::InvalidConfig(
+                "target size must be at least 1".to_string(),
+            ));
+        }
+        if !(self.fpr > 0.0 && self.fpr < 1.0) {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "fpr must be in (0, 1), got {}",
+                self.fpr
+            )));
+        }
+        self.group_size.validate()?;
+
+        let layout = match &self.size_grades {
+            Some(size_grades) => StoreLayout::new(self.quotient_size, size_grades)?,
+            None => StoreLayout::scaled(self.quotient_size)?,
+        };
+
+        // a partition holds up to target_size - 1 keys between its samples
+        if self.target_size - 1 > layout.max_capacity() {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "target size {} does not fit in the largest size grade ({} slots)",
+                self.target_size,
+                layout.max_capacity()
+            )));
+        }
+        // merged partitions fall back to a 1-bit remainder, which must always fit
+        if layout.max_capacity() < layout.num_quotients() * 2 {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "largest size grade needs at least {} slots for quotient size {}",
+                layout.num_quotients() * 2,
+                self.quotient_size
+            )));
+        }
+        Ok(layout)
+    }
+
+    /// build an empty filter
+    pub fn build(&self) -> Result<Diva, RangeFilterError> {
+        let layout = self.layout()?;
+        Ok(self.seeded(Diva::new_with_layout(
+            self.target_size,
+            self.fpr,
+            layout,
+            self.group_size,
+        )))
+    }
+
+    /// build a filter over the given keys
+    pub fn build_with_keys(&self, keys: &[Key]) -> Result<Diva, RangeFilterError> {
+        let layout = self.layout()?;
+        Ok(self.seeded(Diva::new_with_keys_and_layout(
+            keys,
+            self.target_size,
+            self.fpr,
+            layout,
+            self.group_size,
+        )))
+    }
+}
+
+impl fmt::Display for Diva {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "\n╔════════════════════════════════════════════════════════╗"
+        )?;
+        writeln!(
+            f,
+            "║                   DIVA RANGE FILTER                    ║"
+        )?;
+        writeln!(
+            f,
+            "╚════════════════════════════════════════════════════════╝"
+        )?;
+
+        // configuration
+        writeln!(f, "\nConfiguration:")?;
+        writeln!(f, "  Target size (T):      {}", self.target_size)?;
+        writeln!(f, "  False positive rate:  {:.4}%", self.fpr * 100.0)?;
+        writeln!(f, "  Remainder size:       {} bits", self.remainder_size)?;
+        writeln!(
+            f,
+            "  Quotient size:        {} bits",
+            self.layout.quotient_size()
+        )?;
+
+        // stats
+        writeln!(f, "\nStatistics:")?;
+        writeln!(f, "  Total keys:           {}", self.y_fast_trie.len())?;
+        writeln!(
             f,
             "  Sample count:         {}",
             self.y_fast_trie.sample_count()
@@ -781,62 +2164,447 @@ mod tests {
     #[test]
     fn test_insert_between_samples() {
         let mut diva = Diva::new_with_keys(&[1000, 5000], 1024, 0.01);
-        assert!(diva.insert(3000));
+        assert!(diva.insert(3000).is_ok());
     }
 
     #[test]
     fn test_insert_into_existing_store() {
         let mut diva = Diva::new_with_keys(&[1000, 2000, 5000], 1024, 0.01);
-        assert!(diva.insert(2500));
+        assert!(diva.insert(2500).is_ok());
     }
 
     #[test]
     fn test_insert_duplicate_sample() {
         let mut diva = Diva::new_with_keys(&[1000, 5000], 1024, 0.01);
-        assert!(diva.insert(1000));
+        assert!(diva.insert(1000).is_ok());
     }
 
     #[test]
     fn test_delete_empty_diva() {
         let mut diva = Diva::new(1024, 0.01);
-        assert!(!diva.delete(100));
+        assert_eq!(diva.delete(100), Err(RangeFilterError::KeyNotFound(100)));
     }
 
     #[test]
     fn test_delete_non_existent() {
         let mut diva = Diva::new_with_keys(&[1000, 5000], 1024, 0.01);
-        assert!(!diva.delete(3000));
+        assert_eq!(diva.delete(3000), Err(RangeFilterError::KeyNotFound(3000)));
     }
 
     #[test]
     fn test_delete_existing_infix() {
         let mut diva = Diva::new_with_keys(&[1000, 2000, 5000], 1024, 0.01);
-        assert!(diva.delete(2000));
-        assert!(!diva.delete(2000));
+        assert!(diva.delete(2000).is_ok());
+        assert_eq!(diva.delete(2000), Err(RangeFilterError::KeyNotFound(2000)));
     }
 
     #[test]
     fn test_insert_delete_roundtrip() {
         let mut diva = Diva::new_with_keys(&[1000, 10000], 1024, 0.01);
-        assert!(diva.insert(5000));
-        assert!(diva.delete(5000));
-        assert!(!diva.delete(5000));
+        assert!(diva.insert(5000).is_ok());
+        assert!(diva.delete(5000).is_ok());
+        assert_eq!(diva.delete(5000), Err(RangeFilterError::KeyNotFound(5000)));
     }
 
     #[test]
     fn test_multiple_operations() {
         let mut diva = Diva::new_with_keys(&[1000, 10000], 1024, 0.01);
-        assert!(diva.insert(2000));
-        assert!(diva.insert(3000));
-        assert!(diva.delete(2000));
-        assert!(!diva.delete(2000));
-        assert!(diva.delete(3000));
+        assert!(diva.insert(2000).is_ok());
+        assert!(diva.insert(3000).is_ok());
+        assert!(diva.delete(2000).is_ok());
+        assert_eq!(diva.delete(2000), Err(RangeFilterError::KeyNotFound(2000)));
+        assert!(diva.delete(3000).is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let keys: Vec<Key> = (0..3000).map(|i| i * 100).collect();
+        let diva = Diva::new_with_keys(&keys, 100, 0.01);
+
+        let json = serde_json::to_string(&diva).unwrap();
+        let restored: Diva = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(restored.target_size, diva.target_size);
+        assert_eq!(restored.remainder_size, diva.remainder_size);
+        assert_eq!(restored.sample_count(), diva.sample_count());
+        assert_eq!(restored.y_fast_trie.len(), diva.y_fast_trie.len());
+
+        for &key in &keys {
+            assert!(restored.contains(key), "Key {} should be found", key);
+        }
+        for key in (0..300_000).step_by(37) {
+            assert_eq!(restored.contains(key), diva.contains(key));
+            assert_eq!(
+                restored.range_query(key, key + 20),
+                diva.range_query(key, key + 20)
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_empty() {
+        let diva = Diva::new(1024, 0.01);
+        let json = serde_json::to_string(&diva).unwrap();
+        let restored: Diva = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.sample_count(), 0);
+        assert!(!restored.contains(42));
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let keys: Vec<Key> = (0..3000).map(|i| i * 100).collect();
+        let mut diva = Diva::builder()
+            .target_size(100)
+            .quotient_size(8)
+            .build_with_keys(&keys)
+            .unwrap();
+        diva.insert(150).unwrap();
+        diva.delete(200).unwrap();
+
+        let mut bytes = Vec::new();
+        diva.save_to(&mut bytes).unwrap();
+        let restored = Diva::load_from(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(restored.target_size, diva.target_size);
+        assert_eq!(restored.remainder_size, diva.remainder_size);
+        assert_eq!(restored.layout, diva.layout);
+        assert_eq!(restored.num_keys(), diva.num_keys());
+        assert!(restored.sample_keys().eq(diva.sample_keys()));
+        for key in (0..300_000).step_by(37) {
+            assert_eq!(restored.contains(key), diva.contains(key));
+            assert_eq!(
+                restored.range_query(key, key + 20),
+                diva.range_query(key, key + 20)
+            );
+        }
+
+        let mut empty = Vec::new();
+        Diva::new(1024, 0.01).save_to(&mut empty).unwrap();
+        let empty = Diva::load_from(&mut empty.as_slice()).unwrap();
+        assert_eq!(empty.sample_count(), 0);
+    }
+
+    #[test]
+    fn test_save_load_duplicate_infixes() {
+        // neighbouring keys in a wide partition truncate to the same infix
+        let mut keys: Vec<Key> = (0..50).map(|i| (1 << 40) + i).collect();
+        keys.extend([0, 1 << 62]);
+        let diva = Diva::new_with_keys(&keys, 1024, 0.01);
+
+        let mut bytes = Vec::new();
+        diva.save_to(&mut bytes).unwrap();
+        let restored = Diva::load_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(restored.num_keys(), diva.num_keys());
+        for &key in &keys {
+            assert!(restored.contains(key), "Key {} should be found", key);
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_corrupted_file() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 10).collect();
+        let mut bytes = Vec::new();
+        Diva::new_with_keys(&keys, 100, 0.01)
+            .save_to(&mut bytes)
+            .unwrap();
+
+        for position in [0, 10, 40, bytes.len() / 2, bytes.len() - 1] {
+            let mut corrupted = bytes.clone();
+            corrupted[position] ^= 0x10;
+            assert!(Diva::load_from(&mut corrupted.as_slice()).is_err());
+        }
+        assert!(Diva::load_from(&mut &bytes[..bytes.len() - 3]).is_err());
+        assert!(crate::BloomFilter::load_from(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_load_rejects_target_size_the_builder_rejects() {
+        // partitions of this filter could outgrow the largest store
+        let target_size = StoreLayout::DEFAULT.max_capacity() + 2;
+        assert!(Diva::builder().target_size(target_size).build().is_err());
+
+        let mut bytes = Vec::new();
+        Diva::new(target_size, 0.01).save_to(&mut bytes).unwrap();
+        let error = Diva::load_from(&mut bytes.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = Vec::new();
+        Diva::new(target_size - 1, 0.01)
+            .save_to(&mut bytes)
+            .unwrap();
+        assert!(Diva::load_from(&mut bytes.as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_infix_to_key_range_roundtrip() {
+        let (predecessor, successor) = (1000u64, 100_000u64);
+        let (shared, redundant, quotient) =
+            Diva::get_shared_ignore_implicit_size(&predecessor, &successor, false);
+        for key in [1001u64, 5000, 54321, 99_999] {
+            let infix = Diva::extract_partial_key(key, shared, redundant, quotient, 4);
+            let (low, high) = Diva::infix_to_key_range(infix, predecessor, successor, 10, 4);
+            assert!(
+                low <= key && key <= high,
+                "{} not in [{}, {}]",
+                key,
+                low,
+                high
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_disjoint() {
+        let keys_a: Vec<Key> = (0..3000).map(|i| i * 200).collect();
+        let keys_b: Vec<Key> = (0..3000).map(|i| i * 200 + 100).collect();
+        let diva_a = Diva::new_with_keys(&keys_a, 256, 0.01);
+        let diva_b = Diva::new_with_keys(&keys_b, 256, 0.01);
+
+        let merged = diva_a.merge(&diva_b);
+        for &key in keys_a.iter().chain(&keys_b) {
+            assert!(merged.contains(key), "Key {} should be found", key);
+            assert!(merged.range_query(key.saturating_sub(3), key + 3));
+        }
+
+        // re-encoding may cost remainder bits, but the filter must stay useful
+        let false_positives = (0..3000)
+            .map(|i| i * 200 + 50)
+            .filter(|&key| merged.contains(key))
+            .count();
+        assert!(
+            false_positives < 300,
+            "too many false positives: {}",
+            false_positives
+        );
+    }
+
+    #[test]
+    fn test_merge_overlapping() {
+        let keys_a: Vec<Key> = (0..3000).map(|i| i * 10).collect();
+        let keys_b: Vec<Key> = (1500..4500).map(|i| i * 10).collect();
+        let diva_a = Diva::new_with_keys(&keys_a, 100, 0.01);
+        let diva_b = Diva::new_with_keys(&keys_b, 300, 0.01);
+
+        let merged = diva_a.merge(&diva_b);
+        assert_eq!(merged.target_size, 100);
+        for key in (0..4500).map(|i| i * 10) {
+            assert!(merged.contains(key), "Key {} should be found", key);
+        }
+        assert!(!merged.range_query(45_001, 50_000));
+    }
+
+    #[test]
+    fn test_merge_with_empty() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 7).collect();
+        let diva = Diva::new_with_keys(&keys, 64, 0.01);
+        let empty = Diva::new(64, 0.01);
+
+        for merged in [diva.merge(&empty), empty.merge(&diva)] {
+            for &key in &keys {
+                assert!(merged.contains(key), "Key {} should be found", key);
+            }
+        }
+        assert_eq!(empty.merge(&empty).sample_count(), 0);
+    }
+
+    #[test]
+    fn test_size_in_bytes() {
+        let empty = Diva::new(1024, 0.01);
+        assert_eq!(empty.num_keys(), 0);
+        assert_eq!(empty.bits_per_key(), 0.0);
+
+        let keys: Vec<Key> = (0..50_000).map(|i| i * 17).collect();
+        let diva = Diva::new_with_keys(&keys, 1024, 0.01);
+        assert_eq!(diva.num_keys(), keys.len());
+
+        // every stored infix costs at least its remainder plus runend bit
+        let store_bytes: usize = diva
+            .y_fast_trie
+            .group_entries()
+            .iter()
+            .flatten()
+            .filter_map(|(_, store)| store.as_ref())
+            .map(|store| store.read().unwrap().size_in_bytes())
+            .sum();
+        assert!(diva.size_in_bytes() > store_bytes);
+        assert!(diva.bits_per_key() > (diva.remainder_size + 1) as f64);
+
+        let smaller = Diva::new_with_keys(&keys[..5000], 1024, 0.01);
+        assert!(smaller.size_in_bytes() < diva.size_in_bytes());
+    }
+
+    #[test]
+    fn test_builder_defaults_match_new_with_keys() {
+        let keys: Vec<Key> = (0..5000).map(|i| i * 13).collect();
+        let built = Diva::builder()
+            .target_size(128)
+            .fpr(0.01)
+            .build_with_keys(&keys)
+            .unwrap();
+        let diva = Diva::new_with_keys(&keys, 128, 0.01);
+
+        assert_eq!(built.layout, StoreLayout::DEFAULT);
+        for key in 0..70_000 {
+            assert_eq!(built.contains(key), diva.contains(key), "key {}", key);
+        }
+    }
+
+    #[test]
+    fn test_freeze_occupieds_of_sparse_partitions() {
+        // 40 keys per partition leave most of the 1024 quotients empty
+        let keys: Vec<Key> = (0..4000).map(|i| i * 1_000_003).collect();
+        let plain = Diva::new_with_keys(&keys, 40, 0.01);
+        let mut diva = Diva::new_with_keys(&keys, 40, 0.01);
+        let frozen = diva.freeze_occupieds().unwrap();
+        assert_eq!(frozen, diva.partitions().count());
+        assert!(diva.size_in_bytes() < plain.size_in_bytes());
+        for &key in &keys {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+        for start in (0..4_000_000_000u64).step_by(999_983) {
+            assert_eq!(diva.contains(start), plain.contains(start));
+            assert_eq!(
+                diva.range_query(start, start + 5000),
+                plain.range_query(start, start + 5000)
+            );
+        }
+
+        diva.insert(keys[5] + 50).unwrap();
+        assert!(diva.contains(keys[5] + 50));
+        assert!(diva.freeze_occupieds().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_builder_seed_makes_inserts_reproducible() {
+        let build = |seed| {
+            let mut diva = Diva::builder().target_size(16).seed(seed).build().unwrap();
+            for key in 0..2000 {
+                diva.insert(key * 7919).unwrap();
+            }
+            diva.sample_keys().collect::<Vec<Key>>()
+        };
+        assert_eq!(build(42), build(42));
+        assert_ne!(build(42), build(43));
+    }
+
+    #[test]
+    fn test_builder_custom_quotient_size() {
+        let keys: Vec<Key> = (0..20_000).map(|i| i * 37).collect();
+        for quotient_size in [6, 8, 12] {
+            let diva = Diva::builder()
+                .target_size(100)
+                .fpr(0.01)
+                .quotient_size(quotient_size)
+                .build_with_keys(&keys)
+                .unwrap();
+            assert_eq!(diva.layout.quotient_size(), quotient_size);
+            for &key in &keys {
+                assert!(diva.contains(key), "Key {} should be found", key);
+            }
+            assert!(diva.range_query(36, 38));
+            assert!(!diva.range_query(20_000 * 37, u64::MAX));
+        }
+    }
+
+    #[test]
+    fn test_builder_custom_size_grades() {
+        let grades: Vec<u16> = (5..=24).map(|i| i * 100).collect();
+        let keys: Vec<Key> = (0..10_000).map(|i| i * 1000).collect();
+        let diva = Diva::builder()
+            .target_size(1500)
+            .size_grades(&grades)
+            .build_with_keys(&keys)
+            .unwrap();
+
+        assert_eq!(diva.layout.size_grades(), &grades[..]);
+        for &key in &keys {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_config() {
+        assert!(Diva::builder().target_size(0).build().is_err());
+        assert!(Diva::builder().fpr(0.0).build().is_err());
+        assert!(Diva::builder().fpr(1.5).build().is_err());
+        assert!(Diva::builder().quotient_size(0).build().is_err());
+        assert!(Diva::builder().quotient_size(17).build().is_err());
+        // too small for the default target size of 1024
+        assert!(
+            Diva::builder()
+                .size_grades(&[600, 700, 800])
+                .build()
+                .is_err()
+        );
+        // not strictly increasing
+        assert!(
+            Diva::builder()
+                .size_grades(&[2048, 2048, 4096])
+                .build()
+                .is_err()
+        );
+        // cannot hold a 1-bit remainder per quotient
+        assert!(
+            Diva::builder()
+                .target_size(100)
+                .quotient_size(12)
+                .size_grades(&[2048, 4096])
+                .build()
+                .is_err()
+        );
+        assert!(
+            Diva::builder()
+                .group_size(GroupSizePolicy::Fixed(0))
+                .build()
+                .is_err()
+        );
+        assert!(Diva::builder().build().is_ok());
+    }
+
+    #[test]
+    fn test_builder_group_size() {
+        let keys: Vec<Key> = (0..20_000).map(|i| i * 7).collect();
+        let builder = Diva::builder().target_size(16);
+        let default = builder.build_with_keys(&keys).unwrap();
+        let dense = builder
+            .clone()
+            .group_size(GroupSizePolicy::Fixed(8))
+            .build_with_keys(&keys)
+            .unwrap();
+
+        assert_eq!(dense.sample_keys().count(), default.sample_keys().count());
+        // the y-fast trie counts its group boundaries as samples
+        assert!(dense.sample_count() > 4 * default.sample_count());
+        for &key in &keys {
+            assert!(dense.contains(key), "Key {} should be found", key);
+        }
+        let merged = dense.merge(&default);
+        assert_eq!(
+            merged.y_fast_trie.group_size_policy(),
+            GroupSizePolicy::Fixed(8)
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_layout() {
+        let keys_a: Vec<Key> = (0..2000).map(|i| i * 20).collect();
+        let keys_b: Vec<Key> = (0..2000).map(|i| i * 20 + 7).collect();
+        let builder = Diva::builder().target_size(100).quotient_size(8);
+        let diva_a = builder.build_with_keys(&keys_a).unwrap();
+        let diva_b = builder.build_with_keys(&keys_b).unwrap();
+
+        let merged = diva_a.merge(&diva_b);
+        assert_eq!(merged.layout, diva_a.layout);
+        for &key in keys_a.iter().chain(&keys_b) {
+            assert!(merged.contains(key), "Key {} should be found", key);
+        }
     }
 
     #[test]
     fn test_random_operations() {
         use rand::Rng;
-        use std::panic;
 
         let mut diva = Diva::new_with_keys(&[1000, 100000], 1024, 0.01);
         let mut rng = rand::thread_rng();
@@ -845,23 +2613,543 @@ mod tests {
         for _ in 0..100 {
             let key = rng.gen_range(2000..99000);
             if rng.gen_bool(0.6) {
-                let result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-                    (&mut *(&mut diva as *mut Diva)).insert(key)
-                }));
-                match result {
-                    Ok(true) => inserted.push(key),
-                    Ok(false) | Err(_) => {}
-                }
+                assert_eq!(diva.insert(key), Ok(()));
+                inserted.push(key);
             } else if !inserted.is_empty() {
                 let idx = rng.gen_range(0..inserted.len());
-                let key_to_delete = inserted[idx];
-                let delete_result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-                    (&mut *(&mut diva as *mut Diva)).delete(key_to_delete)
-                }));
-                if let Ok(true) = delete_result {
+                if diva.delete(inserted[idx]).is_ok() {
                     inserted.swap_remove(idx);
                 }
             }
         }
     }
+
+    #[test]
+    fn test_incremental_build_from_empty() {
+        use rand::Rng;
+
+        let mut diva = Diva::new(64, 0.01);
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..5000).map(|_| rng.gen_range(0..1 << 40)).collect();
+
+        for &key in &keys {
+            assert!(diva.insert(key).is_ok());
+        }
+        for &key in &keys {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+        assert!(diva.y_fast_trie.len() > 1);
+        assert!(diva.y_fast_trie.len() < keys.len() / 4);
+    }
+
+    #[test]
+    fn test_incremental_build_ascending() {
+        // every key lands beyond the last sample
+        let mut diva = Diva::new(32, 0.01);
+        let keys: Vec<Key> = (0..2000).map(|i| i * 1000).collect();
+        for &key in &keys {
+            assert!(diva.insert(key).is_ok());
+        }
+        for &key in &keys {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+        assert!(diva.y_fast_trie.len() < keys.len() / 4);
+    }
+
+    #[test]
+    fn test_insert_keeps_stores_of_inner_samples() {
+        // sample 64000 is not a y-fast group boundary
+        let keys: Vec<Key> = (0..1000).map(|i| i * 1000).collect();
+        let mut diva = Diva::new_with_keys(&keys, 64, 0.01);
+        assert!(diva.insert_in_infix(64500).is_ok());
+
+        for &key in keys.iter().chain(&[64500]) {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+    }
+
+    #[test]
+    fn test_delete_samples() {
+        let keys: Vec<Key> = (0..2000).map(|i| i * 100).collect();
+        let mut diva = Diva::new_with_keys(&keys, 64, 0.01);
+
+        // first, last and inner samples
+        for sample in [0, 199_900, 64 * 100, 128 * 100] {
+            assert!(diva.delete(sample).is_ok());
+        }
+        for &key in &keys {
+            if [0, 199_900, 6400, 12800].contains(&key) {
+                continue;
+            }
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+    }
+
+    #[test]
+    fn test_delete_sample_promotes_neighbor() {
+        let keys: Vec<Key> = (0..2000).map(|i| i * 100).collect();
+        let mut diva = Diva::new_with_keys(&keys, 64, 0.01);
+        let sample_count = diva.y_fast_trie.len();
+
+        // 6400 is a sample, 6300 and 6500 are its closest stored neighbours
+        assert!(diva.delete(6400).is_ok());
+        assert_eq!(diva.y_fast_trie.len(), sample_count);
+        assert!(!diva.y_fast_trie.contains(6400));
+        let promoted = diva.y_fast_trie.predecessor(6499).unwrap();
+        assert!((6300..6500).contains(&promoted), "promoted {}", promoted);
+
+        // first and last samples are replaced by the outermost stored keys
+        assert!(diva.delete(0).is_ok());
+        assert!(diva.delete(199_900).is_ok());
+        assert!(diva.y_fast_trie.successor(0).unwrap() <= 100);
+        assert!(diva.y_fast_trie.predecessor(Key::MAX).unwrap() >= 199_800);
+        assert_eq!(diva.y_fast_trie.len(), sample_count);
+
+        for &key in &keys {
+            if ![0, 6400, 199_900].contains(&key) {
+                assert!(diva.contains(key), "Key {} should be found", key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_delete_sample_merges_empty_partitions() {
+        let mut diva = Diva::new_with_keys(&[100, 200, 300], 1, 0.01);
+        assert!(diva.delete(200).is_ok());
+        assert!(!diva.contains(200));
+        assert_eq!(diva.y_fast_trie.len(), 2);
+        assert!(diva.y_fast_trie.get_infix_store(100).is_none());
+        assert!(diva.contains(100) && diva.contains(300));
+    }
+
+    #[test]
+    fn test_delete_every_key() {
+        use rand::seq::SliceRandom;
+
+        let mut keys: Vec<Key> = (0..3000).map(|i| i * 7919).collect();
+        let mut diva = Diva::new_with_keys(&keys, 32, 0.01);
+        keys.shuffle(&mut rand::thread_rng());
+
+        for (index, &key) in keys.iter().enumerate() {
+            assert!(diva.delete(key).is_ok(), "Key {} should be deleted", key);
+            if index % 500 == 0 {
+                for &remaining in &keys[index + 1..] {
+                    assert!(
+                        diva.contains(remaining),
+                        "Key {} should be found",
+                        remaining
+                    );
+                }
+            }
+        }
+        // lossy infixes promoted to samples leave a few approximate keys behind
+        assert!(diva.num_keys() < keys.len() / 10);
+    }
+
+    #[test]
+    fn test_saturated_partition_splits() {
+        let mut diva = Diva::builder()
+            .target_size(32)
+            .quotient_size(4)
+            .size_grades(&[8, 16, 32])
+            .build_with_keys(&[0, 1 << 20])
+            .unwrap();
+
+        let keys: Vec<Key> = (1..1000).map(|i| i * 1000).collect();
+        for &key in &keys {
+            assert!(
+                diva.insert_in_infix(key).is_ok(),
+                "Key {} should be inserted",
+                key
+            );
+        }
+
+        // partitions were split into stores of at most 32 infixes
+        assert!(diva.y_fast_trie.len() >= keys.len() / 32);
+        for &key in &keys {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+    }
+
+    #[test]
+    fn test_random_inserts_and_deletes_keep_stores_in_capacity() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        // sample deletes and merges re-encode lossy ranges covering most of a
+        // partition, which once overflowed the largest size grade
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut diva = Diva::builder()
+                .target_size(16)
+                .quotient_size(4)
+                .size_grades(&[8, 16, 32])
+                .fpr(0.1)
+                .build()
+                .unwrap();
+            let mut keys: Vec<Key> = Vec::new();
+            for _ in 0..1000 {
+                if !keys.is_empty() && rng.gen_bool(0.4) {
+                    let key = keys.swap_remove(rng.gen_range(0..keys.len()));
+                    // a key sharing its infix with a deleted one may be gone already
+                    let _ = diva.delete(key);
+                } else {
+                    let key = rng.gen_range(1..1 << 24);
+                    diva.insert(key).unwrap();
+                    keys.push(key);
+                }
+            }
+            for store in diva.partitions().filter_map(|(_, _, store)| store) {
+                assert!(store.read().unwrap().elem_count() <= 32);
+            }
+
+            let other = Diva::new_with_keys(&keys[..keys.len() / 2], 4, 0.1);
+            let merged = diva.merge(&other);
+            for &key in &keys {
+                if diva.contains(key) {
+                    assert!(merged.contains(key), "Key {} should be found", key);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_deletes_keep_keys_sharing_an_infix() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        // sparse partitions get remainders short enough for live keys to share
+        // an infix, deleting one of them must leave the others in
+        for seed in 0..20 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut diva = Diva::builder().target_size(16).seed(0).build().unwrap();
+            let mut live: Vec<Key> = Vec::new();
+            for _ in 0..1000 {
+                if !live.is_empty() && rng.gen_bool(0.25) {
+                    let key = live.swap_remove(rng.gen_range(0..live.len()));
+                    assert_eq!(diva.delete(key), Ok(()), "seed {}", seed);
+                    for &other in &live {
+                        assert!(diva.contains(other), "seed {} lost {}", seed, other);
+                    }
+                } else {
+                    let key = rng.gen_range(0..100_000);
+                    if !live.contains(&key) {
+                        diva.insert(key).unwrap();
+                        live.push(key);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_expected_fpr_matches_measured() {
+        use rand::Rng;
+        use std::collections::HashSet;
+
+        let mut rng = rand::thread_rng();
+        let keys: HashSet<Key> = (0..20_000).map(|_| rng.gen_range(0..1 << 36)).collect();
+        let keys: Vec<Key> = keys.into_iter().collect();
+        let diva = Diva::new_with_keys(&keys, 1024, 0.01);
+
+        let (first, last) = (*keys.iter().min().unwrap(), *keys.iter().max().unwrap());
+        let key_set: HashSet<Key> = keys.iter().copied().collect();
+        let negatives: Vec<Key> = (0..50_000)
+            .map(|_| rng.gen_range(first..=last))
+            .filter(|key| !key_set.contains(key))
+            .collect();
+
+        let expected = diva.expected_fpr();
+        let measured = diva.measure_fpr(&negatives);
+        assert!(
+            expected > 0.0 && expected < 0.05,
+            "expected fpr {}",
+            expected
+        );
+        assert!(
+            (measured - expected).abs() < expected / 4.0,
+            "measured {} vs expected {}",
+            measured,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_fpr_without_false_positives() {
+        // dense keys are stored exactly
+        let keys: Vec<Key> = (0..4096).collect();
+        let diva = Diva::new_with_keys(&keys, 64, 0.01);
+        assert_eq!(diva.expected_fpr(), 0.0);
+        assert_eq!(diva.measure_fpr(&[]), 0.0);
+        assert_eq!(diva.measure_fpr(&[5000, 6000]), 0.0);
+    }
+
+    #[test]
+    fn test_partitions_and_sample_keys() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 10).collect();
+        let diva = Diva::new_with_keys(&keys, 64, 0.01);
+
+        let samples: Vec<Key> = diva.sample_keys().collect();
+        assert!(samples.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(samples.first(), Some(&0));
+        assert_eq!(samples.last(), Some(&9990));
+        assert_eq!(samples.len(), diva.y_fast_trie.len());
+
+        let partitions: Vec<_> = diva.partitions().collect();
+        assert_eq!(partitions.len(), samples.len() - 1);
+        let mut stored = 0;
+        for (index, (predecessor, successor, store)) in partitions.iter().enumerate() {
+            assert_eq!(
+                (*predecessor, *successor),
+                (samples[index], samples[index + 1])
+            );
+            stored += store.as_ref().map_or(0, |s| s.read().unwrap().elem_count());
+        }
+        assert_eq!(stored + samples.len(), keys.len());
+
+        let empty = Diva::new(64, 0.01);
+        assert_eq!(empty.partitions().count(), 0);
+        assert_eq!(empty.sample_keys().count(), 0);
+    }
+
+    #[test]
+    fn test_contains_many_matches_contains() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..5000).map(|_| rng.gen_range(1000..1 << 30)).collect();
+        let diva = Diva::new_with_keys(&keys, 128, 0.01);
+
+        let mut queries: Vec<Key> = (0..5000)
+            .map(|_| rng.gen_range(0..(1 << 30) + 1000))
+            .collect();
+        queries.extend(keys.iter().step_by(7));
+        queries.extend(diva.sample_keys());
+        queries.extend([0, 999, Key::MAX, queries[0], queries[0]]);
+
+        let results = diva.contains_many(&queries);
+        assert_eq!(results.len(), queries.len());
+        for (query, result) in queries.iter().zip(results) {
+            assert_eq!(result, diva.contains(*query), "query {}", query);
+        }
+
+        assert!(diva.contains_many(&[]).is_empty());
+        assert_eq!(
+            Diva::new(64, 0.01).contains_many(&[1, 2]),
+            vec![false, false]
+        );
+    }
+
+    #[test]
+    fn test_range_query_many_matches_range_query() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..5000).map(|_| rng.gen_range(1000..1 << 30)).collect();
+        let diva = Diva::new_with_keys(&keys, 128, 0.01);
+
+        let mut ranges: Vec<(Key, Key)> = (0..3000)
+            .map(|_| {
+                let start = rng.gen_range(0..(1 << 30) + 1000);
+                let width_bits = rng.gen_range(0..24);
+                (start, start + rng.gen_range(0..1 << width_bits))
+            })
+            .collect();
+        ranges.extend(keys.iter().step_by(11).map(|&key| (key, key)));
+        ranges.extend([(500, 100), (0, 999), (0, Key::MAX), (Key::MAX, Key::MAX)]);
+
+        let results = diva.range_query_many(&ranges);
+        assert_eq!(results.len(), ranges.len());
+        for (&(start, end), result) in ranges.iter().zip(results) {
+            assert_eq!(
+                result,
+                diva.range_query(start, end),
+                "range [{}, {}]",
+                start,
+                end
+            );
+        }
+
+        assert!(diva.range_query_many(&[]).is_empty());
+        assert_eq!(Diva::new(64, 0.01).range_query_many(&[(1, 2)]), vec![false]);
+    }
+
+    #[test]
+    fn test_estimate_count() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        let mut keys: Vec<Key> = (0..20_000).map(|_| rng.gen_range(0..1 << 32)).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let diva = Diva::new_with_keys(&keys, 256, 0.001);
+
+        assert_eq!(diva.estimate_count(0, Key::MAX), diva.num_keys());
+        assert_eq!(
+            diva.estimate_count(keys[0], keys[keys.len() - 1]),
+            diva.num_keys()
+        );
+        assert_eq!(diva.estimate_count(10, 5), 0);
+        assert_eq!(Diva::new(64, 0.01).estimate_count(0, Key::MAX), 0);
+
+        for _ in 0..500 {
+            let start = rng.gen_range(0..1 << 32);
+            let width_bits = rng.gen_range(0..28);
+            let end = start + rng.gen_range(0..1 << width_bits);
+            let actual =
+                keys.partition_point(|&key| key <= end) - keys.partition_point(|&key| key < start);
+            let estimate = diva.estimate_count(start, end);
+            // shared infixes only undercount slightly, boundary infixes add a few
+            assert!(
+                estimate + actual / 50 + 2 >= actual && estimate <= actual + actual / 50 + 2,
+                "[{}, {}]: estimate {} for {} keys",
+                start,
+                end,
+                estimate,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn test_partition_remainder_size_adapts_to_density() {
+        use rand::Rng;
+
+        // a dense run of consecutive keys followed by sparse keys
+        let mut rng = rand::thread_rng();
+        let mut keys: Vec<Key> = (0..20_000).collect();
+        keys.extend((0..2000).map(|_| rng.gen_range(1 << 40..1 << 41)));
+        let diva = Diva::new_with_keys(&keys, 256, 0.01);
+
+        let remainder_sizes = |dense: bool| -> Vec<u8> {
+            diva.partitions()
+                .filter(|&(predecessor, _, _)| (predecessor < 20_000) == dense)
+                .filter_map(|(_, _, store)| store.map(|s| s.read().unwrap().remainder_size()))
+                .collect()
+        };
+        let dense = remainder_sizes(true);
+        let sparse = remainder_sizes(false);
+        let median = |sizes: &[u8]| {
+            let mut sizes = sizes.to_vec();
+            sizes.sort_unstable();
+            sizes[sizes.len() / 2]
+        };
+        assert!(
+            median(&dense) < median(&sparse),
+            "{:?} vs {:?}",
+            dense,
+            sparse
+        );
+        assert!(median(&sparse) >= 6, "{:?}", sparse);
+
+        for &key in &keys {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+        assert!(
+            diva.expected_fpr() <= 0.01,
+            "expected fpr {}",
+            diva.expected_fpr()
+        );
+    }
+
+    #[test]
+    fn test_choose_partition_remainder_size() {
+        // capped at the 7 key bits left below the quotient, which makes infixes exact
+        assert_eq!(
+            Diva::choose_partition_remainder_size(0, 1 << 16, 1023, 10, 0.01),
+            7
+        );
+        assert_eq!(
+            Diva::choose_partition_remainder_size(0, 1 << 12, 1023, 10, 0.01),
+            3
+        );
+        // a full partition over a wide range
+        let wide = Diva::choose_partition_remainder_size(0, 1 << 40, 1023, 10, 0.01);
+        assert_eq!(wide, 8);
+        // fewer keys over the same range need fewer bits
+        assert!(Diva::choose_partition_remainder_size(0, 1 << 40, 63, 10, 0.01) < wide);
+        // the remainder grows with a lower target FPR
+        assert!(Diva::choose_partition_remainder_size(0, 1 << 40, 1023, 10, 0.0001) > wide);
+    }
+
+    #[test]
+    fn test_query_tracer() {
+        use std::sync::Mutex;
+
+        let keys: Vec<Key> = (0..1000).map(|i| i * 1000).collect();
+        let mut diva = Diva::new_with_keys(&keys, 100, 0.01);
+        let traces = Arc::new(Mutex::new(Vec::new()));
+        let sink = traces.clone();
+        diva.set_query_tracer(move |trace| sink.lock().unwrap().push(*trace));
+        let last_trace = || *traces.lock().unwrap().last().unwrap();
+
+        // sample keys never reach a partition
+        assert!(diva.contains(0));
+        let trace = last_trace();
+        assert_eq!(trace.kind, QueryKind::Point(0));
+        assert!(trace.sample_hit && trace.result);
+        assert_eq!(trace.partition, None);
+
+        // stored keys are found by scanning their run
+        assert!(diva.contains(5000));
+        let trace = last_trace();
+        assert!(!trace.sample_hit && trace.result);
+        assert_eq!(trace.partition, Some((0, 100_000)));
+        assert!(trace.slots_scanned >= 1);
+        assert!(!trace.occupieds_shortcut);
+
+        assert!(diva.range_query(4990, 5010));
+        let trace = last_trace();
+        assert_eq!(trace.kind, QueryKind::Range(4990, 5010));
+        assert_eq!(trace.partition, Some((0, 100_000)));
+
+        // most absent keys land on an empty quotient
+        let shortcut = (1..100_000).find(|&key| {
+            diva.contains(key);
+            last_trace().occupieds_shortcut
+        });
+        let trace = last_trace();
+        assert!(shortcut.is_some());
+        assert!(!trace.result);
+        assert_eq!(trace.slots_scanned, 0);
+
+        let traced = traces.lock().unwrap().len();
+        diva.clear_query_tracer();
+        diva.contains(5000);
+        assert_eq!(traces.lock().unwrap().len(), traced);
+    }
+
+    #[test]
+    fn test_report_false_positive() {
+        use rand::Rng;
+        use std::collections::BTreeSet;
+
+        let mut rng = rand::thread_rng();
+        let keys: BTreeSet<Key> = (0..5000).map(|_| rng.gen_range(0..1 << 36)).collect();
+        let key_list: Vec<Key> = keys.iter().copied().collect();
+        let mut diva = Diva::new_with_keys(&key_list, 256, 0.05);
+        let backing_store = |low: Key, high: Key| keys.range(low + 1..high).copied().collect();
+
+        let false_positives: Vec<Key> = (0..20_000)
+            .map(|_| rng.gen_range(0..1 << 36))
+            .filter(|key| !keys.contains(key) && diva.contains(*key))
+            .collect();
+        assert!(!false_positives.is_empty());
+
+        for &key in &false_positives {
+            // an earlier rebuild of the same partition may have excluded it already
+            if diva.contains(key) {
+                assert!(diva.report_false_positive(key, backing_store));
+            }
+            assert!(!diva.contains(key), "Key {} should no longer match", key);
+            assert!(!diva.report_false_positive(key, backing_store));
+        }
+        for &key in &false_positives {
+            assert!(!diva.contains(key), "Key {} should stay excluded", key);
+        }
+        for &key in &key_list {
+            assert!(diva.contains(key), "Key {} should be found", key);
+            assert!(!diva.report_false_positive(key, backing_store));
+        }
+    }
 }
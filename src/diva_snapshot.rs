@@ -0,0 +1,198 @@
+use crate::Key;
+use crate::infix_store::InfixStore;
+use crate::range_filter::RangeFilter;
+use crate::sync::{Arc, RwLock};
+use alloc::vec::Vec;
+
+/// Frozen point-in-time view of a `Diva`, created by `Diva::snapshot`
+///
+/// Holds the sorted sample keys and shares the InfixStore of every partition
+/// with the filter it was taken from. The filter copies a store before writing
+/// to it while a snapshot still references it, so queries on a snapshot never
+/// observe later inserts or deletes. Cloning a snapshot is cheap, and snapshots
+/// can be sent to reader threads while a writer keeps updating the filter.
+#[derive(Debug, Clone)]
+pub struct DivaSnapshot {
+    target_size: usize,
+    fpr: f64,
+    samples: Vec<Key>,
+    // stores[i] covers the partition (samples[i], samples[i + 1])
+    stores: Vec<Option<Arc<RwLock<InfixStore>>>>,
+}
+
+impl DivaSnapshot {
+    pub(crate) fn new(
+        target_size: usize,
+        fpr: f64,
+        samples: Vec<Key>,
+        stores: Vec<Option<Arc<RwLock<InfixStore>>>>,
+    ) -> Self {
+        Self {
+            target_size,
+            fpr,
+            samples,
+            stores,
+        }
+    }
+
+    pub fn target_size(&self) -> usize {
+        self.target_size
+    }
+
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of sample keys
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// index of the sample at or before a key
+    fn predecessor_index(&self, key: Key) -> Option<usize> {
+        self.samples
+            .partition_point(|&sample| sample <= key)
+            .checked_sub(1)
+    }
+
+    /// Point lookup: check if a key exists in the snapshot
+    /// Returns true if key might exist (with FPR), false if definitely doesn't exist
+    pub fn contains(&self, key: Key) -> bool {
+        let Some(index) = self.predecessor_index(key) else {
+            return false;
+        };
+        let predecessor_key = self.samples[index];
+        if predecessor_key == key {
+            return true;
+        }
+        let Some(&successor_key) = self.samples.get(index + 1) else {
+            return false;
+        };
+        self.stores[index]
+            .as_ref()
+            .and_then(|store| store.read().ok())
+            .is_some_and(|store| {
+                store.point_query(key, predecessor_key, successor_key, store.remainder_size())
+            })
+    }
+
+    /// Range query: check if any key exists in the given range [start, end] (inclusive)
+    /// Returns true if at least one key might exist in the range (with FPR), false if definitely no keys exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end {
+            return false;
+        }
+
+        // any sample inside the range answers the query directly
+        let successor_index = self.samples.partition_point(|&sample| sample < start);
+        let Some(&successor_key) = self.samples.get(successor_index) else {
+            return false;
+        };
+        if successor_key <= end {
+            return true;
+        }
+
+        // otherwise the whole range lies strictly inside one partition
+        let Some(index) = successor_index.checked_sub(1) else {
+            return false;
+        };
+        let predecessor_key = self.samples[index];
+        self.stores[index]
+            .as_ref()
+            .and_then(|store| store.read().ok())
+            .is_some_and(|store| {
+                store.range_query(
+                    start,
+                    end,
+                    predecessor_key,
+                    successor_key,
+                    store.remainder_size(),
+                )
+            })
+    }
+}
+
+impl RangeFilter for DivaSnapshot {
+    fn point_query(&self, key: Key) -> bool {
+        self.contains(key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        DivaSnapshot::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diva::Diva;
+
+    #[test]
+    fn test_snapshot_matches_diva() {
+        let keys: Vec<Key> = (0..3000).map(|i| i * 100).collect();
+        let diva = Diva::new_with_keys(&keys, 100, 0.01);
+        let snapshot = diva.snapshot();
+
+        assert_eq!(snapshot.sample_count(), diva.sample_keys().count());
+        for key in (0..310_000).step_by(37) {
+            assert_eq!(snapshot.contains(key), diva.contains(key));
+            assert_eq!(
+                snapshot.range_query(key, key + 20),
+                diva.range_query(key, key + 20)
+            );
+        }
+    }
+
+    #[test]
+    fn test_snapshot_is_isolated_from_writes() {
+        let keys: Vec<Key> = (0..3000).map(|i| i * 100).collect();
+        let mut diva = Diva::new_with_keys(&keys, 100, 0.01);
+        let snapshot = diva.snapshot();
+
+        let new_keys: Vec<Key> = (0..3000).map(|i| i * 100 + 50).collect();
+        for &key in &new_keys {
+            diva.insert(key).unwrap();
+        }
+        for &key in keys.iter().filter(|&&key| key % 1000 == 500) {
+            diva.delete(key).unwrap();
+        }
+
+        // the snapshot answers exactly like a fresh filter over the old keys
+        let reference = Diva::new_with_keys(&keys, 100, 0.01);
+        for &key in &keys {
+            assert!(snapshot.contains(key), "Key {} should be found", key);
+        }
+        for &key in &new_keys {
+            assert_eq!(snapshot.contains(key), reference.contains(key));
+        }
+        for &key in &new_keys {
+            assert!(diva.contains(key), "Key {} should be found", key);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_of_snapshot_state() {
+        let mut diva = Diva::new_with_keys(&[1000, 2000, 5000], 1024, 0.01);
+        let first = diva.snapshot();
+        diva.insert(1500).unwrap();
+        let second = diva.snapshot();
+        diva.delete(1500).unwrap();
+
+        assert!(!first.contains(1500));
+        assert!(second.contains(1500));
+        assert!(!diva.contains(1500));
+    }
+
+    #[test]
+    fn test_snapshot_sent_to_reader_thread() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 10).collect();
+        let mut diva = Diva::new_with_keys(&keys, 100, 0.01);
+        let snapshot = diva.snapshot();
+
+        let reader = std::thread::spawn(move || keys.iter().all(|&key| snapshot.contains(key)));
+        for key in 0..1000 {
+            diva.insert(key * 10 + 5).unwrap();
+        }
+        assert!(reader.join().unwrap());
+    }
+}
@@ -0,0 +1,439 @@
+use crate::Key;
+use crate::infix_store::InfixStoreRef;
+use crate::y_fast_trie::GroupEntries;
+use std::io::{self, Write};
+
+/// "DIVAVIEW" as a little-endian word
+const VIEW_MAGIC: u64 = u64::from_le_bytes(*b"DIVAVIEW");
+const VIEW_VERSION: u64 = 1;
+const HEADER_WORDS: usize = 7;
+const DIRECTORY_ENTRY_WORDS: usize = 4;
+
+/// Zero-copy, read-only Diva over a flat word buffer
+///
+/// The layout written by `Diva::write_view` is a sequence of little-endian
+/// `u64` words, so a memory-mapped file can be queried in place without
+/// rebuilding the y-fast trie or copying any InfixStore data:
+///
+/// ```text
+/// [magic] [version] [target_size] [fpr bits] [remainder_size] [num_samples] [num_stores]
+/// [samples: num_samples sorted keys]
+/// [directory: num_stores x (sample_key, meta, data_offset, data_len)]
+/// [data: concatenated InfixStore words]
+/// ```
+///
+/// `meta` packs `elem_count | num_slots << 16 | remainder_size << 32 | quotient_size << 40`.
+/// Sample lookup is a binary search over the sorted samples instead of a trie descent.
+///
+/// # Example
+/// ```rust
+/// use range_filters::diva::Diva;
+/// use range_filters::diva_view::DivaView;
+///
+/// let keys: Vec<u64> = (0..1000).map(|i| i * 10).collect();
+/// let diva = Diva::new_with_keys(&keys, 100, 0.01);
+///
+/// let mut bytes = Vec::new();
+/// diva.write_view(&mut bytes).unwrap();
+/// let words: Vec<u64> = bytes
+///     .chunks_exact(8)
+///     .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+///     .collect();
+///
+/// let view = DivaView::from_words(&words).unwrap();
+/// assert!(view.contains(500));
+/// assert!(view.range_query(495, 505));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct DivaView<'a> {
+    target_size: usize,
+    fpr: f64,
+    remainder_size: u8,
+    samples: &'a [u64],
+    directory: &'a [u64],
+    data: &'a [u64],
+}
+
+impl<'a> DivaView<'a> {
+    /// Parse a view from raw bytes
+    ///
+    /// The buffer must be 8-byte aligned (a memory map always is) and the target
+    /// little-endian, otherwise the data cannot be borrowed in place.
+    pub fn from_bytes(bytes: &'a [u8]) -> io::Result<Self> {
+        if cfg!(target_endian = "big") {
+            return Err(invalid_data(
+                "zero-copy views require a little-endian target",
+            ));
+        }
+        // SAFETY: every bit pattern is a valid u64, and align_to only returns
+        // the middle slice for the properly aligned part of the buffer
+        let (prefix, words, suffix) = unsafe { bytes.align_to::<u64>() };
+        if !prefix.is_empty() {
+            return Err(invalid_data("buffer is not 8-byte aligned"));
+        }
+        if !suffix.is_empty() {
+            return Err(invalid_data("buffer length is not a multiple of 8 bytes"));
+        }
+        Self::from_words(words)
+    }
+
+    /// Parse a view from words, validating the header and every directory entry
+    pub fn from_words(words: &'a [u64]) -> io::Result<Self> {
+        if words.len() < HEADER_WORDS {
+            return Err(invalid_data("buffer too small for header"));
+        }
+        if words[0] != VIEW_MAGIC {
+            return Err(invalid_data("bad magic"));
+        }
+        if words[1] != VIEW_VERSION {
+            return Err(invalid_data("unsupported view version"));
+        }
+
+        let target_size = words[2] as usize;
+        let fpr = f64::from_bits(words[3]);
+        let remainder_size = words[4] as u8;
+        let num_samples = words[5] as usize;
+        let num_stores = words[6] as usize;
+
+        let samples_end = num_samples
+            .checked_add(HEADER_WORDS)
+            .filter(|&end| end <= words.len())
+            .ok_or_else(|| invalid_data("truncated samples"))?;
+        let directory_end = num_stores
+            .checked_mul(DIRECTORY_ENTRY_WORDS)
+            .and_then(|len| len.checked_add(samples_end))
+            .filter(|&end| end <= words.len())
+            .ok_or_else(|| invalid_data("truncated directory"))?;
+
+        let view = Self {
+            target_size,
+            fpr,
+            remainder_size,
+            samples: &words[HEADER_WORDS..samples_end],
+            directory: &words[samples_end..directory_end],
+            data: &words[directory_end..],
+        };
+
+        if !view.samples.windows(2).all(|w| w[0] < w[1]) {
+            return Err(invalid_data("samples are not strictly increasing"));
+        }
+        let mut prev_key = None;
+        for index in 0..num_stores {
+            let key = view.directory[index * DIRECTORY_ENTRY_WORDS];
+            if prev_key.is_some_and(|prev| prev >= key) {
+                return Err(invalid_data("directory is not sorted"));
+            }
+            prev_key = Some(key);
+            if view.samples.binary_search(&key).is_err() {
+                return Err(invalid_data("store attached to a non-sample key"));
+            }
+            view.store_at(index)
+                .ok_or_else(|| invalid_data("corrupt InfixStore entry"))?;
+        }
+
+        Ok(view)
+    }
+
+    pub fn target_size(&self) -> usize {
+        self.target_size
+    }
+
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    pub fn remainder_size(&self) -> u8 {
+        self.remainder_size
+    }
+
+    /// Get the number of sample keys
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// build the InfixStore view for a directory entry
+    fn store_at(&self, index: usize) -> Option<InfixStoreRef<'a>> {
+        let entry = &self.directory[index * DIRECTORY_ENTRY_WORDS..][..DIRECTORY_ENTRY_WORDS];
+        let meta = entry[1];
+        let offset = usize::try_from(entry[2]).ok()?;
+        let len = usize::try_from(entry[3]).ok()?;
+        let data = self.data.get(offset..offset.checked_add(len)?)?;
+        InfixStoreRef::new(
+            meta as u16,
+            (meta >> 16) as u16,
+            (meta >> 32) as u8,
+            (meta >> 40) as u8,
+            data,
+        )
+    }
+
+    /// find the InfixStore attached to a sample key
+    fn store_for_sample(&self, sample: Key) -> Option<InfixStoreRef<'a>> {
+        let num_stores = self.directory.len() / DIRECTORY_ENTRY_WORDS;
+        let (mut low, mut high) = (0, num_stores);
+        while low < high {
+            let mid = (low + high) / 2;
+            let key = self.directory[mid * DIRECTORY_ENTRY_WORDS];
+            if key == sample {
+                return self.store_at(mid);
+            } else if key < sample {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        None
+    }
+
+    fn predecessor(&self, key: Key) -> Option<Key> {
+        let index = self.samples.partition_point(|&sample| sample <= key);
+        index.checked_sub(1).map(|i| self.samples[i])
+    }
+
+    fn successor(&self, key: Key) -> Option<Key> {
+        let index = self.samples.partition_point(|&sample| sample < key);
+        self.samples.get(index).copied()
+    }
+
+    /// Point lookup: check if a key exists in the filter
+    /// Returns true if key might exist (with FPR), false if definitely doesn't exist
+    pub fn contains(&self, key: Key) -> bool {
+        if self.samples.binary_search(&key).is_ok() {
+            return true;
+        }
+
+        let (Some(predecessor_key), Some(successor_key)) =
+            (self.predecessor(key), self.successor(key))
+        else {
+            return false;
+        };
+        self.store_for_sample(predecessor_key).is_some_and(|store| {
+            store.point_query(key, predecessor_key, successor_key, store.remainder_size())
+        })
+    }
+
+    /// Range query: check if any key exists in the given range [start, end] (inclusive)
+    /// Returns true if at least one key might exist in the range (with FPR), false if definitely no keys exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end {
+            return false;
+        }
+
+        // any sample inside the range answers the query directly
+        let Some(successor_key) = self.successor(start) else {
+            return false;
+        };
+        if successor_key <= end {
+            return true;
+        }
+
+        // otherwise the whole range lies strictly inside one partition
+        let Some(predecessor_key) = self.predecessor(start) else {
+            return false;
+        };
+        self.store_for_sample(predecessor_key).is_some_and(|store| {
+            store.range_query(
+                start,
+                end,
+                predecessor_key,
+                successor_key,
+                store.remainder_size(),
+            )
+        })
+    }
+}
+
+impl crate::range_filter::RangeFilter for DivaView<'_> {
+    fn point_query(&self, key: Key) -> bool {
+        self.contains(key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        DivaView::range_query(self, start, end)
+    }
+}
+
+/// write the zero-copy layout for a set of groups (see `DivaView`)
+pub(crate) fn write_layout<W: Write>(
+    writer: &mut W,
+    target_size: usize,
+    fpr: f64,
+    remainder_size: u8,
+    groups: &[GroupEntries],
+) -> io::Result<()> {
+    let entries: Vec<_> = groups.iter().flatten().collect();
+    let stores: Vec<_> = entries
+        .iter()
+        .filter_map(|(key, store)| Some((*key, store.as_ref()?)))
+        .collect();
+
+    let header = [
+        VIEW_MAGIC,
+        VIEW_VERSION,
+        target_size as u64,
+        fpr.to_bits(),
+        remainder_size as u64,
+        entries.len() as u64,
+        stores.len() as u64,
+    ];
+    write_words(writer, &header)?;
+
+    for (key, _) in &entries {
+        write_words(writer, &[*key])?;
+    }
+
+    let mut offset = 0u64;
+    let mut guards = Vec::with_capacity(stores.len());
+    for (key, store) in &stores {
+        let store = store
+            .read()
+            .map_err(|_| io::Error::other("InfixStore lock poisoned"))?;
+        let meta = store.elem_count() as u64
+            | (store.num_slots() as u64) << 16
+            | (store.remainder_size() as u64) << 32
+            | (store.quotient_size() as u64) << 40;
+        let len = store.data().len() as u64;
+        write_words(writer, &[*key, meta, offset, len])?;
+        offset += len;
+        guards.push(store);
+    }
+
+    for store in &guards {
+        write_words(writer, &store.data())?;
+    }
+    Ok(())
+}
+
+fn write_words<W: Write>(writer: &mut W, words: &[u64]) -> io::Result<()> {
+    for word in words {
+        writer.write_all(&word.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A `DivaView` backed by a read-only memory map of a file written with `Diva::write_view`
+#[cfg(feature = "mmap")]
+pub struct MappedDiva {
+    mmap: memmap2::Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedDiva {
+    /// Memory-map a file and validate its layout once
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the map is read-only; callers must not truncate or modify the
+        // file while it is mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        DivaView::from_bytes(&mmap)?;
+        Ok(Self { mmap })
+    }
+
+    /// Borrow the mapped filter for queries
+    pub fn view(&self) -> DivaView<'_> {
+        DivaView::from_bytes(&self.mmap).expect("layout validated in open")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diva::Diva;
+
+    fn to_words(diva: &Diva) -> Vec<u64> {
+        let mut bytes = Vec::new();
+        diva.write_view(&mut bytes).unwrap();
+        bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_view_matches_diva() {
+        let keys: Vec<Key> = (0..3000).map(|i| i * 100).collect();
+        let diva = Diva::new_with_keys(&keys, 100, 0.01);
+        let words = to_words(&diva);
+        let view = DivaView::from_words(&words).unwrap();
+
+        // one sample every 100 keys plus the last key
+        assert_eq!(view.sample_count(), 31);
+        assert_eq!(view.target_size(), 100);
+
+        for &key in &keys {
+            assert!(view.contains(key), "Key {} should be found", key);
+        }
+        for key in (0..310_000).step_by(37) {
+            assert_eq!(view.contains(key), diva.contains(key), "key {}", key);
+            assert_eq!(
+                view.range_query(key, key + 50),
+                diva.range_query(key, key + 50),
+                "range starting at {}",
+                key
+            );
+        }
+        assert!(!view.range_query(500, 100));
+    }
+
+    #[test]
+    fn test_view_from_aligned_bytes() {
+        let diva = Diva::new_with_keys(&[100, 200, 300, 400, 500], 2, 0.01);
+        let words = to_words(&diva);
+        // SAFETY: viewing initialized u64 words as bytes
+        let (_, bytes, _) = unsafe { words.align_to::<u8>() };
+        let view = DivaView::from_bytes(bytes).unwrap();
+
+        for key in [100, 200, 300, 400, 500] {
+            assert!(view.contains(key));
+        }
+        assert!(!view.contains(150));
+    }
+
+    #[test]
+    fn test_view_rejects_corrupt_buffers() {
+        let diva = Diva::new_with_keys(&[100, 200, 300, 400, 500], 2, 0.01);
+        let words = to_words(&diva);
+
+        let mut bad_magic = words.clone();
+        bad_magic[0] = 0;
+        assert!(DivaView::from_words(&bad_magic).is_err());
+
+        let truncated = &words[..words.len() - 1];
+        assert!(DivaView::from_words(truncated).is_err());
+
+        assert!(DivaView::from_words(&words[..3]).is_err());
+    }
+
+    #[test]
+    fn test_empty_view() {
+        let diva = Diva::new(1024, 0.01);
+        let words = to_words(&diva);
+        let view = DivaView::from_words(&words).unwrap();
+        assert_eq!(view.sample_count(), 0);
+        assert!(!view.contains(42));
+        assert!(!view.range_query(0, u64::MAX));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mapped_diva() {
+        let keys: Vec<Key> = (0..2000).map(|i| i * 7).collect();
+        let diva = Diva::new_with_keys(&keys, 64, 0.01);
+
+        let path = std::env::temp_dir().join(format!("diva_view_{}.bin", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        diva.write_view(&mut file).unwrap();
+        drop(file);
+
+        let mapped = MappedDiva::open(&path).unwrap();
+        let view = mapped.view();
+        for &key in &keys {
+            assert!(view.contains(key));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
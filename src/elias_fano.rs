@@ -0,0 +1,318 @@
+//! Elias-Fano encoding of a sparse bitmap
+//!
+//! The positions of the `n` set bits of a bitmap of `len` bits are split into
+//! `l = floor(log2(len / n))` low bits, stored verbatim, and high bits, stored
+//! in unary as a bit vector of `n + (len >> l) + 1` bits. That is about
+//! `n * (2 + log2(len / n))` bits, far below `len` when few bits are set, e.g.
+//! the occupieds of a partition holding a handful of keys.
+//!
+//! `select` is one select over the high bits. `rank` finds the bucket of the
+//! queried position with a select-zero, done as a binary search over `rank`,
+//! and then scans the few set bits in that bucket.
+
+use crate::bitmap::{BitVec, iter_ones, read_bits, write_bits};
+use crate::rank_select::{RankSelect, RankSelectOps};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Read-only sparse bitmap with the rank/select API of `RankSelect`
+///
+/// # Example
+/// ```rust
+/// use range_filters::elias_fano::EliasFano;
+/// use range_filters::rank_select::RankSelectOps;
+///
+/// let bits = EliasFano::from_ones([3, 700, 701, 40_000], 65_536);
+/// assert_eq!(bits.rank(701), 2);
+/// assert_eq!(bits.select(3), Some(40_000));
+/// assert!(bits.get(700) && !bits.get(702));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EliasFano {
+    // u64 so a sparse bitmap may span more bits than a usize counts
+    len: u64,
+    ones: usize,
+    low_bits: u8,
+    // low_bits bits per set bit
+    lows: Vec<u64>,
+    // set bit i at (position >> low_bits) + i
+    highs: RankSelect,
+}
+
+impl EliasFano {
+    /// Encode a bitmap of `len` bits from the positions of its set bits
+    ///
+    /// Panics if the positions are not strictly increasing or not below `len`.
+    pub fn from_ones(ones: impl IntoIterator<Item = usize>, len: usize) -> Self {
+        Self::from_ones_u64(ones.into_iter().map(|pos| pos as u64), len as u64)
+    }
+
+    /// `from_ones` for a bitmap of up to 2^64 bits, also on 32-bit targets
+    ///
+    /// Only the set bits take memory, so `len` may exceed `usize::MAX`.
+    pub fn from_ones_u64(ones: impl IntoIterator<Item = u64>, len: u64) -> Self {
+        let ones: Vec<u64> = ones.into_iter().collect();
+        assert!(
+            ones.windows(2).all(|w| w[0] < w[1]) && ones.last().is_none_or(|&pos| pos < len),
+            "set bits must be strictly increasing and below {}",
+            len
+        );
+        let low_bits = Self::low_bits_for(ones.len(), len);
+        let low_mask = (1u64 << low_bits) - 1;
+
+        let mut lows = vec![0u64; (ones.len() * low_bits as usize).div_ceil(64)];
+        let mut highs = BitVec::new(ones.len() + (len >> low_bits) as usize + 1);
+        for (i, &pos) in ones.iter().enumerate() {
+            write_bits(
+                &mut lows,
+                i * low_bits as usize,
+                low_bits as usize,
+                pos & low_mask,
+            );
+            highs.set((pos >> low_bits) as usize + i);
+        }
+        Self {
+            len,
+            ones: ones.len(),
+            low_bits,
+            lows,
+            highs: RankSelect::new(highs),
+        }
+    }
+
+    /// Decode an encoding of `ones` set bits out of `len` from its parts
+    ///
+    /// Returns `None` unless the parts hold exactly `ones` strictly increasing
+    /// positions below `len`, so they can come from an untrusted file.
+    pub fn from_parts(lows: &[u64], highs: &[u64], ones: usize, len: u64) -> Option<Self> {
+        let low_bits = Self::low_bits_for(ones, len) as usize;
+        let highs_len = ones
+            .checked_add(usize::try_from(len >> low_bits).ok()?)?
+            .checked_add(1)?;
+        if lows.len() != ones.checked_mul(low_bits)?.div_ceil(64)
+            || highs.len() != highs_len.div_ceil(64)
+        {
+            return None;
+        }
+
+        let mut positions = Vec::with_capacity(ones);
+        for (i, high_pos) in iter_ones(highs).enumerate() {
+            if i == ones || high_pos >= highs_len {
+                return None;
+            }
+            let pos =
+                (((high_pos - i) as u64) << low_bits) | read_bits(lows, i * low_bits, low_bits);
+            if pos >= len || positions.last().is_some_and(|&last| last >= pos) {
+                return None;
+            }
+            positions.push(pos);
+        }
+        (positions.len() == ones).then(|| Self::from_ones_u64(positions, len))
+    }
+
+    /// The low bits and the high bits of the encoding, as `from_parts` takes them
+    pub fn parts(&self) -> (&[u64], &[u64]) {
+        (&self.lows, self.highs.bits().words())
+    }
+
+    /// low bits per set bit, about log2 of the average gap between set bits
+    ///
+    /// Without set bits every position goes to the low bits, so the high bits
+    /// stay a couple of zeros rather than one per position.
+    fn low_bits_for(ones: usize, len: u64) -> u8 {
+        (len / ones.max(1) as u64).checked_ilog2().unwrap_or(0) as u8
+    }
+
+    /// Encode the first `len` bits of `words`
+    pub fn from_words(words: &[u64], len: usize) -> Self {
+        Self::from_ones(iter_ones(words).take_while(|&pos| pos < len), len)
+    }
+
+    /// Positions of the set bits in ascending order
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.ones).map(|i| self.position(i) as usize)
+    }
+
+    /// memory used by the low and high bits
+    pub fn heap_size(&self) -> usize {
+        self.lows.capacity() * core::mem::size_of::<u64>() + self.highs.heap_size()
+    }
+
+    /// `rank` of a position of a bitmap built with `from_ones_u64`
+    pub fn rank_u64(&self, pos: u64) -> usize {
+        debug_assert!(
+            pos <= self.len,
+            "pos: {} is out of bounds for {} bits",
+            pos,
+            self.len
+        );
+        if pos >= self.len {
+            return self.ones;
+        }
+        // the bucket of pos starts after the zero closing the previous bucket
+        let bucket = (pos >> self.low_bits) as usize;
+        let mut high_pos = match bucket {
+            0 => 0,
+            _ => self.select_zero(bucket - 1) + 1,
+        };
+        let mut rank = high_pos - bucket;
+        let low = pos & ((1 << self.low_bits) - 1);
+        while high_pos < self.highs.len() && self.highs.get(high_pos) && self.low(rank) < low {
+            rank += 1;
+            high_pos += 1;
+        }
+        rank
+    }
+
+    #[inline]
+    fn low(&self, i: usize) -> u64 {
+        let low_bits = self.low_bits as usize;
+        read_bits(&self.lows, i * low_bits, low_bits)
+    }
+
+    /// position of the i-th set bit, i below the number of set bits
+    #[inline]
+    fn position(&self, i: usize) -> u64 {
+        let high_pos = self.highs.select(i).expect("fewer set bits than expected");
+        (((high_pos - i) as u64) << self.low_bits) | self.low(i)
+    }
+
+    /// position of the zero with the given rank in the high bits
+    fn select_zero(&self, rank_val: usize) -> usize {
+        // smallest pos with more than rank_val zeros in [0, pos]
+        let (mut low, mut high) = (0, self.highs.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if mid + 1 - self.highs.rank(mid + 1) > rank_val {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        low
+    }
+}
+
+impl RankSelectOps for EliasFano {
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn count_ones(&self) -> usize {
+        self.ones
+    }
+
+    fn get(&self, pos: usize) -> bool {
+        debug_assert!(
+            (pos as u64) < self.len,
+            "pos: {} is out of bounds for {} bits",
+            pos,
+            self.len
+        );
+        self.rank(pos + 1) > self.rank(pos)
+    }
+
+    fn rank(&self, pos: usize) -> usize {
+        self.rank_u64(pos as u64)
+    }
+
+    fn select(&self, rank_val: usize) -> Option<usize> {
+        (rank_val < self.ones).then(|| self.position(rank_val) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::{rank, select, set_bit};
+
+    #[test]
+    fn test_matches_plain_bitmap() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for len in [0usize, 1, 64, 1024, 5000] {
+            for one_in in [1u64, 2, 16, 300, u64::MAX] {
+                let mut words = vec![0u64; len.div_ceil(64)];
+                for pos in 0..len {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    if (state >> 20).is_multiple_of(one_in) {
+                        set_bit(&mut words, pos);
+                    }
+                }
+                let bits = EliasFano::from_words(&words, len);
+                assert_eq!(bits.count_ones(), rank(&words, len));
+                assert!(bits.iter_ones().eq(iter_ones(&words)));
+                for pos in 0..=len {
+                    assert_eq!(bits.rank(pos), rank(&words, pos), "len {} pos {}", len, pos);
+                }
+                for pos in 0..len {
+                    assert_eq!(bits.get(pos), crate::bitmap::get_bit(&words, pos));
+                }
+                for rank_val in 0..=bits.count_ones() {
+                    assert_eq!(bits.select(rank_val), select(&words, rank_val));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_bitmap_is_small() {
+        let bits = EliasFano::from_ones([5, 600, 900], 1024);
+        // 128 bytes as a plain bitmap
+        assert!(bits.heap_size() < 64, "{} bytes", bits.heap_size());
+        assert_eq!(bits.iter_ones().collect::<Vec<_>>(), vec![5, 600, 900]);
+    }
+
+    #[test]
+    fn test_parts_roundtrip_and_validation() {
+        let bits = EliasFano::from_ones([0, 7, 8, 1000, 4095], 4096);
+        let (lows, highs) = bits.parts();
+        assert_eq!(
+            EliasFano::from_parts(lows, highs, 5, 4096),
+            Some(bits.clone())
+        );
+        assert_eq!(
+            EliasFano::from_parts(&[], &[0], 0, 100),
+            Some(EliasFano::from_ones([], 100))
+        );
+
+        // wrong counts, lengths and out of range positions
+        assert!(EliasFano::from_parts(lows, highs, 4, 4096).is_none());
+        assert!(EliasFano::from_parts(lows, highs, 5, 4095).is_none());
+        assert!(EliasFano::from_parts(&lows[1..], highs, 5, 4096).is_none());
+        let mut extra_one = highs.to_vec();
+        extra_one[0] |= 1 << 13;
+        assert!(EliasFano::from_parts(lows, &extra_one, 5, 4096).is_none());
+    }
+
+    #[test]
+    fn test_positions_beyond_u32() {
+        // wider than a 32-bit usize counts, only the set bits take memory
+        let len = 1u64 << 40;
+        let ones = [7, u32::MAX as u64 + 1, 1 << 39, len - 1];
+        let bits = EliasFano::from_ones_u64(ones, len);
+        assert!(bits.heap_size() < 256, "{} bytes", bits.heap_size());
+        for (rank_val, &pos) in ones.iter().enumerate() {
+            assert_eq!(bits.rank_u64(pos), rank_val);
+            assert_eq!(bits.rank_u64(pos + 1), rank_val + 1);
+        }
+        assert_eq!(bits.rank_u64(len), ones.len());
+
+        let empty = EliasFano::from_ones_u64([], len);
+        assert!(empty.heap_size() < 64, "{} bytes", empty.heap_size());
+        assert_eq!(empty.rank_u64(len / 2), 0);
+
+        let (lows, highs) = bits.parts();
+        assert_eq!(
+            EliasFano::from_parts(lows, highs, 4, len),
+            Some(bits.clone())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "strictly increasing")]
+    fn test_rejects_unsorted_ones() {
+        EliasFano::from_ones([5, 3], 10);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::Key;
+use alloc::string::String;
+use core::fmt;
+
+/// Errors returned by filter updates and configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeFilterError {
+    /// the key has no sample on one of its sides, so no partition covers it
+    KeyOutOfUniverse(Key),
+    /// the InfixStore is full at its largest size grade
+    PartitionFull,
+    /// the key (or infix, for an InfixStore) is not stored
+    KeyNotFound(Key),
+    /// the filter or store configuration is not usable
+    InvalidConfig(String),
+    /// a lock around an InfixStore was poisoned by a panicking writer
+    LockPoisoned,
+    /// the occupieds and runends bitmaps of an InfixStore disagree
+    CorruptedStore,
+}
+
+impl fmt::Display for RangeFilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeFilterError::KeyOutOfUniverse(key) => {
+                write!(f, "key {} is outside the sampled range", key)
+            }
+            RangeFilterError::PartitionFull => write!(f, "infix store is full"),
+            RangeFilterError::KeyNotFound(key) => write!(f, "key {} not found", key),
+            RangeFilterError::InvalidConfig(reason) => {
+                write!(f, "invalid configuration: {}", reason)
+            }
+            RangeFilterError::LockPoisoned => write!(f, "infix store lock poisoned"),
+            RangeFilterError::CorruptedStore => {
+                write!(f, "occupied bit set but no runend found")
+            }
+        }
+    }
+}
+
+impl core::error::Error for RangeFilterError {}
+
+/// A broken invariant of an InfixStore, found by `InfixStore::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    /// `data` is not as long as the store shape requires
+    DataLength { expected: usize, actual: usize },
+    /// more elements than slots at the current size grade
+    ElemCountOverflow { elem_count: usize, num_slots: usize },
+    /// the number of runends differs from the number of occupied quotients
+    RunendCount { occupieds: usize, runends: usize },
+    /// the runs do not fill exactly the first `elem_count` slots
+    RunsLength { elem_count: usize, covered: usize },
+    /// the remainder at `slot` is smaller than the one before it in the run of `quotient`
+    UnsortedRun { quotient: usize, slot: usize },
+    /// the popcounts cached in the first data word are stale
+    StalePopcounts,
+    /// a rank directory does not match its bitmap
+    StaleRankDirectory,
+    /// a tombstone is set on a slot past the last element
+    StrayTombstone { slot: usize },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::DataLength { expected, actual } => {
+                write!(f, "data has {} words, expected {}", actual, expected)
+            }
+            InvariantViolation::ElemCountOverflow {
+                elem_count,
+                num_slots,
+            } => write!(f, "{} elements in {} slots", elem_count, num_slots),
+            InvariantViolation::RunendCount { occupieds, runends } => write!(
+                f,
+                "{} occupied quotients but {} runends",
+                occupieds, runends
+            ),
+            InvariantViolation::RunsLength {
+                elem_count,
+                covered,
+            } => write!(
+                f,
+                "runs cover {} slots, elem_count is {}",
+                covered, elem_count
+            ),
+            InvariantViolation::UnsortedRun { quotient, slot } => {
+                write!(
+                    f,
+                    "run of quotient {} is unsorted at slot {}",
+                    quotient, slot
+                )
+            }
+            InvariantViolation::StalePopcounts => write!(f, "cached popcounts are stale"),
+            InvariantViolation::StaleRankDirectory => write!(f, "rank directory is stale"),
+            InvariantViolation::StrayTombstone { slot } => {
+                write!(f, "tombstone set on unused slot {}", slot)
+            }
+        }
+    }
+}
+
+impl core::error::Error for InvariantViolation {}
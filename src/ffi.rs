@@ -0,0 +1,273 @@
+//! C interface to `Diva`
+//!
+//! `include/range_filters.h` declares these functions (regenerate it with
+//! `cbindgen --config cbindgen.toml --output include/range_filters.h`). A
+//! filter is an opaque `Diva *` owned by the caller: create it with
+//! `diva_new_with_keys` or `diva_deserialize` and release it with `diva_free`.
+//! Buffers returned by `diva_serialize` are released with `diva_buffer_free`.
+//! A panic inside any of these functions aborts the process.
+//!
+//! Build the library to link against with
+//! `cargo rustc --lib --release --features ffi --crate-type staticlib` (or
+//! `cdylib`).
+
+use crate::Key;
+use crate::diva::Diva;
+use crate::error::RangeFilterError;
+use std::ptr;
+use std::slice;
+
+/// Result of a fallible call, mirroring `RangeFilterError`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivaStatus {
+    Ok = 0,
+    KeyOutOfUniverse = 1,
+    PartitionFull = 2,
+    KeyNotFound = 3,
+    InvalidConfig = 4,
+    LockPoisoned = 5,
+    CorruptedStore = 6,
+    NullPointer = 7,
+}
+
+impl From<Result<(), RangeFilterError>> for DivaStatus {
+    fn from(result: Result<(), RangeFilterError>) -> Self {
+        match result {
+            Ok(()) => DivaStatus::Ok,
+            Err(RangeFilterError::KeyOutOfUniverse(_)) => DivaStatus::KeyOutOfUniverse,
+            Err(RangeFilterError::PartitionFull) => DivaStatus::PartitionFull,
+            Err(RangeFilterError::KeyNotFound(_)) => DivaStatus::KeyNotFound,
+            Err(RangeFilterError::InvalidConfig(_)) => DivaStatus::InvalidConfig,
+            Err(RangeFilterError::LockPoisoned) => DivaStatus::LockPoisoned,
+            Err(RangeFilterError::CorruptedStore) => DivaStatus::CorruptedStore,
+        }
+    }
+}
+
+/// Bytes allocated by Rust and handed to C, see `diva_serialize`
+#[repr(C)]
+#[derive(Debug)]
+pub struct DivaBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl DivaBuffer {
+    const EMPTY: DivaBuffer = DivaBuffer {
+        data: ptr::null_mut(),
+        len: 0,
+    };
+}
+
+/// view `len` elements at `data` as a slice, accepting null for an empty slice
+unsafe fn slice_from_raw<'a, T>(data: *const T, len: usize) -> Option<&'a [T]> {
+    match (data.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        // SAFETY: the caller guarantees `data` points to `len` readable elements
+        (false, _) => Some(unsafe { slice::from_raw_parts(data, len) }),
+    }
+}
+
+/// Build a filter over `len` keys at `keys`, in any order
+///
+/// Returns null if `keys` is null with a non-zero `len` or if `target_size`
+/// or `fpr` are invalid.
+///
+/// # Safety
+/// `keys` must point to `len` readable `uint64_t` values or be null when `len` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_new_with_keys(
+    keys: *const Key,
+    len: usize,
+    target_size: usize,
+    fpr: f64,
+) -> *mut Diva {
+    // SAFETY: forwarded from the caller
+    let Some(keys) = (unsafe { slice_from_raw(keys, len) }) else {
+        return ptr::null_mut();
+    };
+    match Diva::builder()
+        .target_size(target_size)
+        .fpr(fpr)
+        .build_with_keys(keys)
+    {
+        Ok(diva) => Box::into_raw(Box::new(diva)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Release a filter. Null is ignored.
+///
+/// # Safety
+/// `diva` must come from `diva_new_with_keys` or `diva_deserialize` and not be
+/// used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_free(diva: *mut Diva) {
+    if !diva.is_null() {
+        // SAFETY: the pointer was created by Box::into_raw and is freed once
+        drop(unsafe { Box::from_raw(diva) });
+    }
+}
+
+/// Point query; false for a null filter
+///
+/// # Safety
+/// `diva` must be a live filter or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_contains(diva: *const Diva, key: Key) -> bool {
+    // SAFETY: the caller guarantees a live filter or null
+    unsafe { diva.as_ref() }.is_some_and(|diva| diva.contains(key))
+}
+
+/// Range query over [start, end] inclusive; false for a null filter
+///
+/// # Safety
+/// `diva` must be a live filter or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_range_query(diva: *const Diva, start: Key, end: Key) -> bool {
+    // SAFETY: the caller guarantees a live filter or null
+    unsafe { diva.as_ref() }.is_some_and(|diva| diva.range_query(start, end))
+}
+
+/// # Safety
+/// `diva` must be a live filter or null, not used concurrently from other threads.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_insert(diva: *mut Diva, key: Key) -> DivaStatus {
+    // SAFETY: the caller guarantees a live, exclusively borrowed filter or null
+    match unsafe { diva.as_mut() } {
+        Some(diva) => diva.insert(key).into(),
+        None => DivaStatus::NullPointer,
+    }
+}
+
+/// # Safety
+/// `diva` must be a live filter or null, not used concurrently from other threads.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_delete(diva: *mut Diva, key: Key) -> DivaStatus {
+    // SAFETY: the caller guarantees a live, exclusively borrowed filter or null
+    match unsafe { diva.as_mut() } {
+        Some(diva) => diva.delete(key).into(),
+        None => DivaStatus::NullPointer,
+    }
+}
+
+/// Number of keys in the filter; 0 for a null filter
+///
+/// # Safety
+/// `diva` must be a live filter or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_num_keys(diva: *const Diva) -> usize {
+    // SAFETY: the caller guarantees a live filter or null
+    unsafe { diva.as_ref() }.map_or(0, Diva::num_keys)
+}
+
+/// Serialize a filter in the `Diva::save_to` format
+///
+/// Returns an empty buffer (null `data`) for a null filter. Release the buffer
+/// with `diva_buffer_free`.
+///
+/// # Safety
+/// `diva` must be a live filter or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_serialize(diva: *const Diva) -> DivaBuffer {
+    // SAFETY: the caller guarantees a live filter or null
+    let Some(diva) = (unsafe { diva.as_ref() }) else {
+        return DivaBuffer::EMPTY;
+    };
+    let mut bytes = Vec::new();
+    if diva.save_to(&mut bytes).is_err() {
+        return DivaBuffer::EMPTY;
+    }
+    let bytes = Box::into_raw(bytes.into_boxed_slice());
+    DivaBuffer {
+        len: bytes.len(),
+        data: bytes.cast(),
+    }
+}
+
+/// Release a buffer returned by `diva_serialize`
+///
+/// # Safety
+/// `buffer` must come from `diva_serialize` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_buffer_free(buffer: DivaBuffer) {
+    if !buffer.data.is_null() {
+        let bytes = ptr::slice_from_raw_parts_mut(buffer.data, buffer.len);
+        // SAFETY: the buffer was created from a boxed slice of this length
+        drop(unsafe { Box::from_raw(bytes) });
+    }
+}
+
+/// Open a filter from `len` bytes written by `diva_serialize` or `Diva::save_to`
+///
+/// Returns null if the bytes are not a valid filter.
+///
+/// # Safety
+/// `data` must point to `len` readable bytes or be null when `len` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn diva_deserialize(data: *const u8, len: usize) -> *mut Diva {
+    // SAFETY: forwarded from the caller
+    let Some(mut bytes) = (unsafe { slice_from_raw(data, len) }) else {
+        return ptr::null_mut();
+    };
+    match Diva::load_from(&mut bytes) {
+        Ok(diva) => Box::into_raw(Box::new(diva)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffi_roundtrip() {
+        let keys: Vec<Key> = (0..2000).map(|i| i * 50).collect();
+        unsafe {
+            let diva = diva_new_with_keys(keys.as_ptr(), keys.len(), 128, 0.01);
+            assert!(!diva.is_null());
+            assert_eq!(diva_num_keys(diva), keys.len());
+            assert!(diva_contains(diva, 500));
+            assert!(diva_range_query(diva, 490, 510));
+
+            assert_eq!(diva_insert(diva, 525), DivaStatus::Ok);
+            assert_eq!(diva_delete(diva, 525), DivaStatus::Ok);
+            assert_eq!(diva_delete(diva, 525), DivaStatus::KeyNotFound);
+
+            let buffer = diva_serialize(diva);
+            assert!(!buffer.data.is_null());
+            let loaded = diva_deserialize(buffer.data, buffer.len);
+            diva_buffer_free(buffer);
+            assert!(!loaded.is_null());
+            for key in (0..100_000).step_by(7) {
+                assert_eq!(diva_contains(loaded, key), diva_contains(diva, key));
+            }
+
+            diva_free(loaded);
+            diva_free(diva);
+        }
+    }
+
+    #[test]
+    fn test_ffi_rejects_bad_input() {
+        unsafe {
+            assert!(diva_new_with_keys(ptr::null(), 3, 128, 0.01).is_null());
+            assert!(diva_new_with_keys(ptr::null(), 0, 0, 0.01).is_null());
+            assert!(diva_deserialize(b"garbage".as_ptr(), 7).is_null());
+
+            assert!(!diva_contains(ptr::null(), 1));
+            assert_eq!(diva_insert(ptr::null_mut(), 1), DivaStatus::NullPointer);
+            let buffer = diva_serialize(ptr::null());
+            assert!(buffer.data.is_null());
+            diva_buffer_free(buffer);
+            diva_free(ptr::null_mut());
+
+            let empty = diva_new_with_keys(ptr::null(), 0, 128, 0.01);
+            assert!(!empty.is_null());
+            assert_eq!(diva_num_keys(empty), 0);
+            diva_free(empty);
+        }
+    }
+}
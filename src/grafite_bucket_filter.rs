@@ -0,0 +1,186 @@
+//! Bucketing heuristic range filter from the Grafite paper
+//!
+//! The key range `[min, max]` is cut into buckets of `bucket_size` keys and
+//! only the set of non-empty buckets is kept, Elias-Fano encoded. A query asks
+//! whether any bucket it overlaps is non-empty, which is two ranks. Unlike
+//! `GrafiteFilter` there is no hashing and no false positive bound: the
+//! bucket size is chosen so that about an `epsilon` fraction of the buckets is
+//! non-empty when keys are spread evenly, and clustered keys or wide queries
+//! do worse.
+
+use crate::Key;
+use crate::elias_fano::EliasFano;
+use crate::range_filter::RangeFilter;
+use crate::rank_select::RankSelectOps;
+
+pub struct GrafiteBucketFilter {
+    // non-empty buckets, bucket b holds [min + b * bucket_size, min + (b + 1) * bucket_size)
+    buckets: EliasFano,
+    min: Key,
+    max: Key,
+    bucket_size: u64,
+    epsilon: f64,
+    num_keys: usize,
+}
+
+impl GrafiteBucketFilter {
+    /// Create a new bucketing filter with the given keys and epsilon.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `epsilon` - Target fraction of non-empty buckets (e.g., 0.01 for 1%),
+    ///   the point query false positive rate for evenly spread keys
+    pub fn new_with_keys(keys: &[Key], epsilon: f64) -> Self {
+        let num_keys = keys.len();
+        let min = keys.iter().copied().min().unwrap_or(0);
+        let max = keys.iter().copied().max().unwrap_or(0);
+
+        // n non-empty buckets out of span / bucket_size
+        let span = (max - min) as f64 + 1.0;
+        let mut bucket_size = (epsilon * span / num_keys.max(1) as f64).max(1.0) as u64;
+        if max - min == Key::MAX {
+            // 2^64 buckets of one key cannot be counted
+            bucket_size = bucket_size.max(2);
+        }
+        let num_buckets = ((max - min) / bucket_size + 1) as usize;
+
+        let mut ids: Vec<usize> = keys
+            .iter()
+            .map(|&key| ((key - min) / bucket_size) as usize)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        Self {
+            buckets: EliasFano::from_ones(ids, num_buckets),
+            min,
+            max,
+            bucket_size,
+            epsilon,
+            num_keys,
+        }
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the bucket of the key is non-empty
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.range_query(key, key)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// # Returns
+    /// * `true` if a bucket overlapping the range is non-empty
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end || self.num_keys == 0 || end < self.min || start > self.max {
+            return false;
+        }
+        let first = (start.max(self.min) - self.min) / self.bucket_size;
+        let last = (end.min(self.max) - self.min) / self.bucket_size;
+        self.buckets.rank(last as usize + 1) > self.buckets.rank(first as usize)
+    }
+
+    /// Get the configured epsilon.
+    pub fn fpr(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Get the number of keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of keys per bucket.
+    pub fn bucket_size(&self) -> u64 {
+        self.bucket_size
+    }
+
+    /// Get the memory used by the filter: the struct plus the Elias-Fano
+    /// encoded ids of the non-empty buckets.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.buckets.heap_size()
+    }
+
+    /// Get the memory per key in bits, about `2 + log2(1 / epsilon)` when the
+    /// keys are evenly spread and fewer when several share a bucket.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for GrafiteBucketFilter {
+    fn point_query(&self, key: Key) -> bool {
+        GrafiteBucketFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        GrafiteBucketFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_no_false_negatives_and_fpr() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..10_000).map(|_| rng.gen_range(0..1u64 << 40)).collect();
+        let filter = GrafiteBucketFilter::new_with_keys(&keys, 0.01);
+        for &key in &keys {
+            assert!(filter.point_query(key));
+            assert!(filter.range_query(key.saturating_sub(100), key));
+        }
+
+        let false_positives = (0..20_000)
+            .map(|_| rng.gen_range(0..1u64 << 40))
+            .filter(|&key| filter.point_query(key))
+            .count();
+        // 1% of 20_000 expected for uniform keys, allow for variance
+        assert!(false_positives < 400, "{} false positives", false_positives);
+        assert!(
+            filter.bits_per_key() < 12.0,
+            "{} bits per key",
+            filter.bits_per_key()
+        );
+    }
+
+    #[test]
+    fn test_bucket_edges() {
+        let filter = GrafiteBucketFilter::new_with_keys(&[1000, 1999, 5000, 1 << 20], 1e-9);
+        assert_eq!(filter.bucket_size(), 1);
+        assert!(filter.range_query(0, 1000));
+        assert!(filter.range_query(1999, 4000));
+        assert!(!filter.range_query(2000, 4999));
+        assert!(!filter.range_query(0, 999));
+        assert!(filter.range_query(1 << 20, Key::MAX));
+        assert!(!filter.range_query(5001, (1 << 20) - 1));
+        assert!(!filter.range_query(10, 5));
+
+        let full = GrafiteBucketFilter::new_with_keys(&[0, Key::MAX], 1e-30);
+        assert_eq!(full.bucket_size(), 2);
+        assert!(full.point_query(Key::MAX) && full.point_query(0));
+        assert!(!full.range_query(2, Key::MAX - 2));
+    }
+
+    #[test]
+    fn test_empty_and_coarse_buckets() {
+        let empty = GrafiteBucketFilter::new_with_keys(&[], 0.01);
+        assert!(!empty.point_query(0) && !empty.range_query(0, Key::MAX));
+        assert_eq!(empty.bits_per_key(), 0.0);
+
+        // buckets of 45 keys, a query anywhere near a key is positive
+        let coarse = GrafiteBucketFilter::new_with_keys(&[0, 100], 0.9);
+        assert!(coarse.bucket_size() >= 45);
+        assert!(coarse.point_query(40));
+        assert!(!coarse.range_query(101, 200));
+    }
+}
@@ -1,13 +1,139 @@
-use grafite::{PairwiseIndependentHasher, RangeFilter};
+//! Grafite range filter
+//!
+//! Keys are hashed into a reduced universe of `r = n * L * floor(1 / epsilon)`
+//! codes, `L` the maximum query range, and the sorted distinct codes are kept
+//! Elias-Fano encoded. The hash `h(x) = (q(x / r) + x) mod r` shifts every
+//! block of `r` consecutive keys by a pairwise-independent offset
+//! `q(y) = ((a * y + b) mod p) mod r`, `p` a prime above `r`, so a range
+//! within a block keeps its order and maps to one circular interval of codes.
+//! A range query asks whether a code falls in the interval of each block it
+//! overlaps, and a range of another `n` keys hits one with probability about
+//! `n * L / r`, which is `epsilon`.
+//!
+//! The hash parameters are drawn by the filter itself, so `save_to` writes
+//! them along with the codes and a loaded filter answers like the saved one.
 
 use crate::Key;
+use crate::elias_fano::EliasFano;
+use crate::error::RangeFilterError;
+use crate::persist::{self, FilterKind, PayloadReader, PayloadWriter};
+use crate::range_filter::RangeFilter;
+use crate::rank_select::RankSelectOps;
+use crate::utils::mix64;
+use std::io::{self, Read, Write};
+
+/// maximum query range length the hasher is tuned for, unless configured
+const DEFAULT_MAX_QUERY_RANGE: u64 = 1_000_000;
+
+/// largest prime below 2^64, the reduced universe has to stay under it
+const LARGEST_U64_PRIME: u64 = u64::MAX - 58;
 
 pub struct GrafiteFilter {
-    filter: RangeFilter,
+    // sorted distinct codes of the keys
+    codes: EliasFano,
+    hasher: LocalHasher,
     epsilon: f64,
+    max_query_range: u64,
     num_keys: usize,
 }
 
+/// Order-preserving hash of the keys into the reduced universe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LocalHasher {
+    slope: u64,
+    intercept: u64,
+    prime: u64,
+    reduced_universe_size: u64,
+}
+
+impl LocalHasher {
+    /// draw the offsets of a hash into `reduced_universe_size` codes
+    fn random(reduced_universe_size: u64, rng: &mut u64) -> Self {
+        let prime = loop {
+            let candidate = reduced_universe_size
+                + 1
+                + next_below(rng, LARGEST_U64_PRIME - reduced_universe_size);
+            if is_prime(candidate) {
+                break candidate;
+            }
+        };
+        Self {
+            slope: 1 + next_below(rng, prime - 1),
+            intercept: next_below(rng, prime),
+            prime,
+            reduced_universe_size,
+        }
+    }
+
+    /// offset of the block of keys `block * r..(block + 1) * r`
+    fn offset(&self, block: u64) -> u64 {
+        let linear =
+            (self.slope as u128 * block as u128 + self.intercept as u128) % self.prime as u128;
+        (linear % self.reduced_universe_size as u128) as u64
+    }
+
+    fn hash(&self, key: Key) -> u64 {
+        let r = self.reduced_universe_size;
+        let code = self.offset(key / r) as u128 + (key % r) as u128;
+        (code % r as u128) as u64
+    }
+
+    /// parameters read back from a file, checked so queries cannot panic
+    fn is_valid(&self) -> bool {
+        self.reduced_universe_size > 0
+            && self.prime > self.reduced_universe_size
+            && is_prime(self.prime)
+            && (1..self.prime).contains(&self.slope)
+            && self.intercept < self.prime
+    }
+}
+
+/// next value below `bound` of a Weyl sequence mixed through `mix64`
+fn next_below(rng: &mut u64, bound: u64) -> u64 {
+    *rng = rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    ((mix64(*rng) as u128 * bound as u128) >> 64) as u64
+}
+
+/// Miller-Rabin with the first twelve primes as bases, exact for every u64
+fn is_prime(n: u64) -> bool {
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+    if n < 2 {
+        return false;
+    }
+    if let Some(&base) = BASES.iter().find(|&&base| n.is_multiple_of(base)) {
+        return n == base;
+    }
+
+    let mul_mod = |a: u64, b: u64| (a as u128 * b as u128 % n as u128) as u64;
+    let shift = (n - 1).trailing_zeros();
+    let odd = (n - 1) >> shift;
+    BASES.iter().all(|&base| {
+        let (mut x, mut power, mut exp) = (1, base, odd);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                x = mul_mod(x, power);
+            }
+            power = mul_mod(power, power);
+            exp >>= 1;
+        }
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        (1..shift).any(|_| {
+            x = mul_mod(x, x);
+            x == n - 1
+        })
+    })
+}
+
+/// `n * max_query_range * floor(1 / epsilon)` codes, if that stays below the largest u64 prime
+fn reduced_universe_size(num_keys: usize, epsilon: f64, max_query_range: u64) -> Option<u64> {
+    let size = (num_keys.max(1) as u64)
+        .checked_mul(max_query_range)?
+        .checked_mul((1.0 / epsilon).floor() as u64)?;
+    (size < LARGEST_U64_PRIME).then_some(size)
+}
+
 impl GrafiteFilter {
     /// Create a new Grafite Range Filter with the given keys and epsilon (false positive rate).
     ///
@@ -17,20 +143,38 @@ impl GrafiteFilter {
     ///
     /// # Returns
     /// A new `GrafiteFilter` instance containing all the provided keys
+    ///
+    /// The false positive rate holds for ranges up to 1_000_000 keys wide, use
+    /// `GrafiteFilter::builder` to tune the filter for another width or to
+    /// seed its hash parameters.
     pub fn new_with_keys(keys: &[Key], epsilon: f64) -> Self {
-        let num_keys = keys.len();
-
-        const MAX_QUERY_RANGE: u64 = 1_000_000;
+        Self::builder()
+            .epsilon(epsilon)
+            .build_with_keys(keys)
+            .expect("Invalid parameters for the Grafite hasher")
+    }
 
-        let hasher = PairwiseIndependentHasher::new(num_keys, epsilon, MAX_QUERY_RANGE)
-            .expect("Invalid parameters for PairwiseIndependentHasher");
+    /// start configuring a filter with a custom maximum query range
+    pub fn builder() -> GrafiteFilterBuilder {
+        GrafiteFilterBuilder::default()
+    }
 
-        let filter = RangeFilter::new(keys.iter().copied(), hasher);
+    fn new_with_hasher(
+        keys: &[Key],
+        hasher: LocalHasher,
+        epsilon: f64,
+        max_query_range: u64,
+    ) -> Self {
+        let mut codes: Vec<u64> = keys.iter().map(|&key| hasher.hash(key)).collect();
+        codes.sort_unstable();
+        codes.dedup();
 
         Self {
-            filter,
+            codes: EliasFano::from_ones_u64(codes, hasher.reduced_universe_size),
+            hasher,
             epsilon,
-            num_keys,
+            max_query_range,
+            num_keys: keys.len(),
         }
     }
 
@@ -43,7 +187,7 @@ impl GrafiteFilter {
     /// * `true` if the key might exist (with false positive rate `epsilon`)
     /// * `false` if the key definitely does not exist
     pub fn point_query(&self, key: Key) -> bool {
-        self.filter.query(key..=key)
+        self.range_query(key, key)
     }
 
     /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
@@ -56,10 +200,43 @@ impl GrafiteFilter {
     /// * `true` if at least one key in the range might exist (with false positive rate `epsilon`)
     /// * `false` if no keys in the range exist
     pub fn range_query(&self, start: Key, end: Key) -> bool {
-        if start > end {
+        if start > end || self.codes.count_ones() == 0 {
             return false;
         }
-        self.filter.query(start..=end)
+        let r = self.hasher.reduced_universe_size;
+        if end - start >= r - 1 {
+            // covers a whole block, every code
+            return true;
+        }
+
+        let end_block_start = end - end % r;
+        if start >= end_block_start {
+            return self.has_code_between(self.hasher.hash(start), self.hasher.hash(end));
+        }
+        // the range crosses into the next block, which has another offset
+        self.has_code_between(
+            self.hasher.hash(start),
+            self.hasher.hash(end_block_start - 1),
+        ) || self.has_code_between(self.hasher.hash(end_block_start), self.hasher.hash(end))
+    }
+
+    /// whether a code lies in [from, to], wrapping around the reduced universe if from > to
+    fn has_code_between(&self, from: u64, to: u64) -> bool {
+        let below_from = self.codes.rank_u64(from);
+        let up_to = self.codes.rank_u64(to + 1);
+        if from <= to {
+            up_to > below_from
+        } else {
+            up_to > 0 || below_from < self.codes.count_ones()
+        }
+    }
+
+    /// Range query for many ranges at once, returning one result per range in input order
+    pub fn range_query_many(&self, ranges: &[(Key, Key)]) -> Vec<bool> {
+        ranges
+            .iter()
+            .map(|&(start, end)| self.range_query(start, end))
+            .collect()
     }
 
     /// Get the configured false positive rate (epsilon).
@@ -70,8 +247,366 @@ impl GrafiteFilter {
         self.epsilon
     }
 
+    /// Get the widest range the false positive rate holds for.
+    pub fn max_query_range(&self) -> u64 {
+        self.max_query_range
+    }
+
     /// Get the number of keys inserted into the filter.
     pub fn num_keys(&self) -> usize {
         self.num_keys
     }
+
+    /// Get the memory used by the filter: the struct plus the Elias-Fano
+    /// codes, about `2 + log2(max_query_range / epsilon)` bits per key.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.codes.heap_size()
+    }
+
+    /// Get the memory per key in bits. Duplicate keys share one code, so
+    /// they lower it below the per-code cost.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl GrafiteFilter {
+    /// Save the filter in the versioned binary format (see `persist`).
+    ///
+    /// The payload holds epsilon, the maximum query range, the key count, the
+    /// hash parameters and the low and high bits of the Elias-Fano codes, so
+    /// it takes about as much space as the filter in memory and no keys.
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut payload = PayloadWriter::default();
+        payload.f64(self.epsilon);
+        payload.u64(self.max_query_range);
+        payload.u64(self.num_keys as u64);
+        payload.u64(self.hasher.slope);
+        payload.u64(self.hasher.intercept);
+        payload.u64(self.hasher.prime);
+        payload.u64(self.hasher.reduced_universe_size);
+        payload.u64(self.codes.count_ones() as u64);
+        let (lows, highs) = self.codes.parts();
+        for words in [lows, highs] {
+            payload.u64(words.len() as u64);
+            for &word in words {
+                payload.u64(word);
+            }
+        }
+        persist::write_container(writer, FilterKind::Grafite, &payload.into_bytes())
+    }
+
+    /// Load a filter saved with `save_to`, verifying its checksums.
+    ///
+    /// The loaded filter has the hash parameters and codes of the saved one
+    /// and gives the same answers.
+    pub fn load_from<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let (_version, payload) = persist::read_container(reader, FilterKind::Grafite)?;
+        let mut payload = PayloadReader::new(&payload);
+
+        let epsilon = payload.f64()?;
+        let max_query_range = payload.u64()?;
+        let num_keys = payload.u64()? as usize;
+        let hasher = LocalHasher {
+            slope: payload.u64()?,
+            intercept: payload.u64()?,
+            prime: payload.u64()?,
+            reduced_universe_size: payload.u64()?,
+        };
+        let num_codes = payload.u64()? as usize;
+        let mut words = || {
+            let count = payload.count(8)?;
+            (0..count)
+                .map(|_| payload.u64())
+                .collect::<io::Result<Vec<u64>>>()
+        };
+        let (lows, highs) = (words()?, words()?);
+        payload.finish()?;
+        if !hasher.is_valid() {
+            return Err(persist::invalid_data("invalid grafite hash parameters"));
+        }
+        let codes = EliasFano::from_parts(&lows, &highs, num_codes, hasher.reduced_universe_size)
+            .ok_or_else(|| persist::invalid_data("invalid grafite codes"))?;
+
+        Ok(Self {
+            codes,
+            hasher,
+            epsilon,
+            max_query_range,
+            num_keys,
+        })
+    }
+
+    /// `save_to` into a new buffer
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.save_to(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    /// `load_from` a buffer written by `to_bytes` or `save_to`
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        Self::load_from(&mut &bytes[..])
+    }
+}
+
+/// Configures a `GrafiteFilter` beyond `new_with_keys`
+///
+/// Grafite hashes keys so that ranges up to `max_query_range` keys wide keep
+/// the false positive rate `epsilon`; wider ranges are still answered without
+/// false negatives but lose the bound. Every key costs about
+/// `2 + log2(max_query_range / epsilon)` bits, so a workload of narrow ranges
+/// should not pay for the default width of 1_000_000.
+///
+/// # Example
+/// ```rust
+/// use range_filters::GrafiteFilter;
+/// let keys: Vec<u64> = (0..10_000).map(|i| i * 10).collect();
+/// let filter = GrafiteFilter::builder()
+///     .epsilon(0.001)
+///     .max_query_range(64)
+///     .build_with_keys(&keys)
+///     .unwrap();
+/// assert!(filter.range_query(495, 505));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GrafiteFilterBuilder {
+    epsilon: f64,
+    max_query_range: u64,
+    seed: Option<u64>,
+}
+
+impl Default for GrafiteFilterBuilder {
+    fn default() -> Self {
+        Self {
+            epsilon: 0.01,
+            max_query_range: DEFAULT_MAX_QUERY_RANGE,
+            seed: None,
+        }
+    }
+}
+
+impl GrafiteFilterBuilder {
+    pub fn epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    /// widest range, in keys, the false positive rate should hold for
+    pub fn max_query_range(mut self, max_query_range: u64) -> Self {
+        self.max_query_range = max_query_range;
+        self
+    }
+
+    /// seed of the generator drawing the hash parameters, random by default
+    ///
+    /// Filters built from the same keys with the same seed and settings are
+    /// identical, down to their `to_bytes` encodings and false positives.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// build a filter over the given keys
+    pub fn build_with_keys(&self, keys: &[Key]) -> Result<GrafiteFilter, RangeFilterError> {
+        if !(self.epsilon > 0.0 && self.epsilon < 1.0) {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "epsilon must be in (0, 1), got {}",
+                self.epsilon
+            )));
+        }
+        if self.max_query_range == 0 {
+            return Err(RangeFilterError::InvalidConfig(
+                "max query range must be at least 1".to_string(),
+            ));
+        }
+        // the hashed universe of n * max_query_range / epsilon codes must fit in a u64
+        let Some(reduced_universe_size) =
+            reduced_universe_size(keys.len(), self.epsilon, self.max_query_range)
+        else {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "max query range {} is too wide for {} keys at epsilon {}",
+                self.max_query_range,
+                keys.len(),
+                self.epsilon
+            )));
+        };
+        let mut rng = self.seed.unwrap_or_else(rand::random);
+        let hasher = LocalHasher::random(reduced_universe_size, &mut rng);
+        Ok(GrafiteFilter::new_with_hasher(
+            keys,
+            hasher,
+            self.epsilon,
+            self.max_query_range,
+        ))
+    }
+}
+
+impl RangeFilter for GrafiteFilter {
+    fn point_query(&self, key: Key) -> bool {
+        GrafiteFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        GrafiteFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_narrow_and_wide_query_ranges() {
+        let keys: Vec<Key> = (0..5000).map(|i| i * 100_003).collect();
+        let narrow = GrafiteFilter::builder()
+            .max_query_range(16)
+            .build_with_keys(&keys)
+            .unwrap();
+        let wide = GrafiteFilter::builder()
+            .max_query_range(1 << 24)
+            .build_with_keys(&keys)
+            .unwrap();
+        assert_eq!(narrow.max_query_range(), 16);
+        assert_eq!(
+            GrafiteFilter::new_with_keys(&keys, 0.01).max_query_range(),
+            DEFAULT_MAX_QUERY_RANGE
+        );
+        // log2(2^24 / 16) = 20 bits more per key
+        assert!(
+            wide.bits_per_key() - narrow.bits_per_key() > 19.0,
+            "{} vs {} bits per key",
+            wide.bits_per_key(),
+            narrow.bits_per_key()
+        );
+
+        for &key in &keys {
+            assert!(narrow.range_query(key.saturating_sub(15), key));
+            assert!(wide.range_query(key.saturating_sub(1 << 23), key + (1 << 23)));
+        }
+    }
+
+    #[test]
+    fn test_no_false_negatives_across_blocks() {
+        // 12 codes, so the ranges around the keys cross many block boundaries
+        let keys = [3, 17, 40, 41, 1000, Key::MAX];
+        let filter = GrafiteFilter::builder()
+            .epsilon(0.5)
+            .max_query_range(1)
+            .build_with_keys(&keys)
+            .unwrap();
+        for &key in &keys {
+            for width in 0..30 {
+                for before in 0..=width {
+                    let start = key.saturating_sub(before);
+                    let end = start.saturating_add(width);
+                    if (start..=end).contains(&key) {
+                        assert!(filter.range_query(start, end), "[{}, {}]", start, end);
+                    }
+                }
+            }
+        }
+        assert!(filter.range_query(0, Key::MAX));
+
+        let empty = GrafiteFilter::new_with_keys(&[], 0.01);
+        assert!(!empty.point_query(0) && !empty.range_query(0, Key::MAX));
+    }
+
+    #[test]
+    fn test_is_prime() {
+        let primes: Vec<u64> = (0..30).filter(|&n| is_prime(n)).collect();
+        assert_eq!(primes, vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        // Carmichael numbers and a strong pseudoprime to bases 2, 3, 5 and 7
+        assert!(!is_prime(561) && !is_prime(41_041) && !is_prime(3_215_031_751));
+        assert!(is_prime(LARGEST_U64_PRIME) && !is_prime(u64::MAX));
+        assert!((LARGEST_U64_PRIME + 1..=u64::MAX).all(|n| !is_prime(n)));
+    }
+
+    #[test]
+    fn test_range_query_many_matches_range_query() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 997).collect();
+        let filter = GrafiteFilter::new_with_keys(&keys, 0.01);
+        let ranges: Vec<(Key, Key)> = (0..500)
+            .map(|i| (i * 1993, i * 1993 + i % 50))
+            .chain([(100, 50), (0, Key::MAX)])
+            .collect();
+        let expected: Vec<bool> = ranges
+            .iter()
+            .map(|&(start, end)| filter.range_query(start, end))
+            .collect();
+        assert_eq!(filter.range_query_many(&ranges), expected);
+        assert!(filter.range_query_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_configurations() {
+        let keys = [1, 2, 3];
+        for epsilon in [0.0, 1.0, -0.5, f64::NAN] {
+            let result = GrafiteFilter::builder()
+                .epsilon(epsilon)
+                .build_with_keys(&keys);
+            assert!(
+                matches!(result, Err(RangeFilterError::InvalidConfig(_))),
+                "epsilon {}",
+                epsilon
+            );
+        }
+        assert!(
+            GrafiteFilter::builder()
+                .max_query_range(0)
+                .build_with_keys(&keys)
+                .is_err()
+        );
+        assert!(
+            GrafiteFilter::builder()
+                .epsilon(1e-6)
+                .max_query_range(u64::MAX / 2)
+                .build_with_keys(&keys)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_universe_wider_than_u32() {
+        // 100 keys at the default range and epsilon hash into 10^10 codes,
+        // more than a 32-bit usize counts
+        let keys: Vec<Key> = (0..100).map(|i| i * 1_000_003).collect();
+        let filter = GrafiteFilter::new_with_keys(&keys, 0.01);
+        assert!(filter.hasher.reduced_universe_size > u32::MAX as u64);
+        for &key in &keys {
+            assert!(filter.point_query(key));
+        }
+        assert!(
+            filter.size_in_bytes() < 1024,
+            "{} bytes",
+            filter.size_in_bytes()
+        );
+
+        let loaded = GrafiteFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert!(keys.iter().all(|&key| loaded.point_query(key)));
+
+        // no codes, and no bitmap over the whole reduced universe either
+        let empty = GrafiteFilter::new_with_keys(&[], 0.01);
+        assert!(
+            empty.size_in_bytes() < 256,
+            "{} bytes",
+            empty.size_in_bytes()
+        );
+    }
+
+    #[test]
+    fn test_save_load_keeps_max_query_range() {
+        let filter = GrafiteFilter::builder()
+            .max_query_range(100)
+            .build_with_keys(&[5, 500, 50_000])
+            .unwrap();
+        let loaded = GrafiteFilter::from_bytes(&filter.to_bytes()).unwrap();
+        assert_eq!(loaded.max_query_range(), 100);
+        assert_eq!(loaded.size_in_bytes(), filter.size_in_bytes());
+        assert!(loaded.range_query(450, 549));
+    }
 }
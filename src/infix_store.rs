@@ -1,14 +1,34 @@
 use crate::U64_BITS;
-use crate::bitmap::{clear_bit, get_bit, set_bit, rank, rank_cached, select_cached};
-use std::fmt;
+use crate::bitmap::{
+    BitVec, Ones, clear_bit, clear_bits_in_range, get_bit, iter_ones, move_bits, next_set_bit,
+    rank, rank_cached, read_bits, select, select_cached, set_bit, shift_bits_range, write_bits,
+};
+use crate::elias_fano::EliasFano;
+use crate::error::{InvariantViolation, RangeFilterError};
+use crate::rank_directory::RankDirectory;
+use crate::rank_select::CompactBitmap;
+use crate::run_scan;
+use crate::utils::{extract_partial_key, shared_ignore_implicit_size};
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
-const TARGET_SIZE: u16 = 1024;
 const QUOTIENT_SIZE: u8 = 10;
 // const LOAD_FACTOR: f64 = 0.95;
 const SIZE_GRADE_COUNT: usize = 31;
-const CACHE_BOUNDARY: u16 = TARGET_SIZE / 2;
 // const DEFAULT_SIZE_GRADE: u8 = 14;
 
+/// largest supported quotient size (2^16 quotients per store)
+pub const MAX_QUOTIENT_SIZE: u8 = 16;
+
+/// largest supported remainder size; a remainder size of 0 stores quotients only
+pub const MAX_REMAINDER_SIZE: u8 = 64;
+
 // precomputed number of slots for each size grade
 // size grades 0-30
 // grade 14 is neutral - 1024 slots
@@ -17,17 +37,306 @@ const SCALED_SIZES: [u16; 31] = [
     1258, 1325, 1395, 1469, 1547, 1629, 1715, 1806, 1901, 2002, 2108, 2219, 2326,
 ];
 
+/// Shape shared by all InfixStores of a filter
+///
+/// `quotient_size` fixes the number of quotients (and occupieds bits) per store,
+/// and `size_grades` lists the slot counts a store can grow and shrink through.
+/// A custom table is shared by the stores of a layout and freed with the last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreLayout {
+    quotient_size: u8,
+    // None for the default table
+    size_grades: Option<Arc<[u16]>>,
+}
+
+impl StoreLayout {
+    /// the layout used by `InfixStore::new_with_infixes`: 10 quotient bits and 31 size grades
+    pub const DEFAULT: StoreLayout = StoreLayout {
+        quotient_size: QUOTIENT_SIZE,
+        size_grades: None,
+    };
+
+    /// Create a layout, validating the quotient size and size grade table
+    ///
+    /// The table must be non-empty, strictly increasing, non-zero and have at most 256 grades.
+    pub fn new(quotient_size: u8, size_grades: &[u16]) -> Result<Self, RangeFilterError> {
+        if quotient_size == 0 || quotient_size > MAX_QUOTIENT_SIZE {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "quotient size must be in 1..={}, got {}",
+                MAX_QUOTIENT_SIZE, quotient_size
+            )));
+        }
+        if size_grades.is_empty() || size_grades.len() > u8::MAX as usize + 1 {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "size grade table must have 1..=256 grades, got {}",
+                size_grades.len()
+            )));
+        }
+        if size_grades.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(RangeFilterError::InvalidConfig(
+                "size grade table must be strictly increasing".to_string(),
+            ));
+        }
+        if size_grades[0] == 0 {
+            return Err(RangeFilterError::InvalidConfig(
+                "size grades must have at least one slot".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            quotient_size,
+            size_grades: (size_grades != SCALED_SIZES).then(|| size_grades.into()),
+        })
+    }
+
+    /// Layout with the default size grade table scaled to 2^quotient_size quotients
+    pub fn scaled(quotient_size: u8) -> Result<Self, RangeFilterError> {
+        if quotient_size == 0 || quotient_size > MAX_QUOTIENT_SIZE {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "quotient size must be in 1..={}, got {}",
+                MAX_QUOTIENT_SIZE, quotient_size
+            )));
+        }
+        let mut size_grades = Vec::with_capacity(SIZE_GRADE_COUNT);
+        for num_slots in SCALED_SIZES {
+            let scaled = ((num_slots as u64) << quotient_size).div_ceil(1 << QUOTIENT_SIZE);
+            if scaled > u16::MAX as u64 {
+                break;
+            }
+            size_grades.push(scaled as u16);
+        }
+        size_grades.dedup();
+        Self::new(quotient_size, &size_grades)
+    }
+
+    pub fn quotient_size(&self) -> u8 {
+        self.quotient_size
+    }
+
+    /// number of quotients (occupieds bits) per store
+    pub fn num_quotients(&self) -> usize {
+        1 << self.quotient_size
+    }
+
+    pub fn size_grades(&self) -> &[u16] {
+        self.size_grades.as_deref().unwrap_or(&SCALED_SIZES)
+    }
+
+    /// maximum number of elements a store can hold at the largest size grade
+    pub fn max_capacity(&self) -> usize {
+        let size_grades = self.size_grades();
+        size_grades[size_grades.len() - 1] as usize
+    }
+}
+
+impl Default for StoreLayout {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for StoreLayout {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.quotient_size, self.size_grades()).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StoreLayout {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (quotient_size, size_grades): (u8, Vec<u16>) =
+            serde::Deserialize::deserialize(deserializer)?;
+        StoreLayout::new(quotient_size, &size_grades).map_err(serde::de::Error::custom)
+    }
+}
+
+/// number of words in the occupieds bitmap
+fn occupieds_words(quotient_size: u8) -> usize {
+    (1usize << quotient_size).div_ceil(U64_BITS)
+}
+
+/// bit position of the cached popcounts, half the quotients rounded down to a word
+fn cache_boundary(quotient_size: u8) -> usize {
+    ((1usize << quotient_size) / 2) & !(U64_BITS - 1)
+}
+
+/// cached popcount boundary for the runends, clamped to the runends bitmap
+fn runends_cache_boundary(num_slots: u16, quotient_size: u8) -> usize {
+    cache_boundary(quotient_size).min((num_slots as usize).div_ceil(U64_BITS) * U64_BITS)
+}
+
+/// get memory layout offsets for a given number of slots
+fn get_offsets(num_slots: u16, quotient_size: u8) -> (usize, usize, usize) {
+    let occupieds_start = 1;
+    let occupieds_words = occupieds_words(quotient_size);
+    let runends_start = occupieds_start + occupieds_words;
+    let runends_words = (num_slots as usize).div_ceil(U64_BITS);
+    let slots_start = runends_start + runends_words;
+
+    (occupieds_start, runends_start, slots_start)
+}
+
+/// layout offsets of a store whose occupieds are kept outside the data words,
+/// see `InfixStore::freeze_occupieds`
+fn get_offsets_without_occupieds(num_slots: u16) -> (usize, usize, usize) {
+    let runends_words = (num_slots as usize).div_ceil(U64_BITS);
+    (1, 1, 1 + runends_words)
+}
+
+/// number of frozen occupied quotients below `quotient`
+#[inline]
+fn sparse_rank(sparse: &[u16], quotient: usize) -> usize {
+    sparse.partition_point(|&occupied| (occupied as usize) < quotient)
+}
+
+/// total number of data words used by a store of the given shape
+fn data_words(num_slots: u16, quotient_size: u8, remainder_size: u8) -> usize {
+    let (_, _, slots_start) = get_offsets(num_slots, quotient_size);
+    slots_start + (num_slots as usize * remainder_size as usize).div_ceil(U64_BITS)
+}
+
+/// mask of the low `remainder_size` bits, all ones for 64-bit remainders
+#[inline]
+fn remainder_mask(remainder_size: u8) -> u64 {
+    u64::MAX
+        .checked_shr((U64_BITS - remainder_size as usize) as u32)
+        .unwrap_or(0)
+}
+
+/// quotient part of an infix, 0 when the remainder takes all 64 bits
+#[inline]
+fn infix_quotient(infix: u64, remainder_size: u8) -> u64 {
+    infix.checked_shr(remainder_size as u32).unwrap_or(0)
+}
+
+/// infix made of a quotient and a remainder of `remainder_size` bits
+#[inline]
+fn join_infix(quotient: u64, remainder: u64, remainder_size: u8) -> u64 {
+    quotient.checked_shl(remainder_size as u32).unwrap_or(0) | remainder
+}
+
 /// Memory layout of data:
-/// [popcounts: 64 bits] [occupieds: TARGET_SIZE bits]
+/// [popcounts: 64 bits] [occupieds: 2^quotient_size bits]
 /// [runends: num_slots bits] [slots: num_slots * remainder_size bits]
 /// popcounts: 32 bits for occupieds and 32 bits for runends
-#[derive(Debug, Default)]
+///
+/// Next to `data` the store keeps a rank directory over the occupieds and the
+/// runends, so rank and select cost one count lookup plus a 256-bit block
+/// scan. The directories are derived state, rebuilt instead of serialized.
+///
+/// A counting store (see `new_counting`) keeps one slot per inserted copy of
+/// an infix, so an infix inserted n times needs n deletes to disappear.
+///
+/// With lazy deletes (see `set_lazy_delete`) a delete only marks its slot in
+/// a tombstone bitmap; queries skip marked slots and `compact` reclaims them.
+///
+/// A store that no longer changes can keep its occupieds as a sorted array of
+/// quotients instead, see `freeze_occupieds`; `data` then leaves the occupieds
+/// words out.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(try_from = "StoredInfixStore")
+)]
 pub struct InfixStore {
     elem_count: u16,
     size_grade: u8, // decides the number of slots in the infix store
     remainder_size: u8,
-    quotient_size: u8,
+    layout: StoreLayout,
+    data: Vec<u64>,
+    // duplicate inserts add another slot instead of being collapsed
+    counting: bool,
+    // one bit per slot marking lazily deleted slots, None when deletes shift
+    tombstones: Option<BitVec>,
+    // occupied quotients of a frozen store, None when the bitmap is in `data`
+    sparse_occupieds: Option<Box<[u16]>>,
+    occupieds_ranks: RankDirectory,
+    runends_ranks: RankDirectory,
+}
+
+/// Saves the plain data words, frozen occupieds included, and tombstones as
+/// their words; the rank directories are derived state and left out
+#[cfg(feature = "serde")]
+impl serde::Serialize for InfixStore {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("InfixStore", 7)?;
+        state.serialize_field("elem_count", &self.elem_count)?;
+        state.serialize_field("size_grade", &self.size_grade)?;
+        state.serialize_field("remainder_size", &self.remainder_size)?;
+        state.serialize_field("layout", &self.layout)?;
+        state.serialize_field("data", &*self.data())?;
+        state.serialize_field("counting", &self.counting)?;
+        state.serialize_field("tombstones", &self.tombstones.as_ref().map(BitVec::words))?;
+        state.end()
+    }
+}
+
+/// serialized fields of an InfixStore, see `InfixStore::rebuild_rank_directories`
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct StoredInfixStore {
+    elem_count: u16,
+    size_grade: u8,
+    remainder_size: u8,
+    layout: StoreLayout,
     data: Vec<u64>,
+    #[serde(default)]
+    counting: bool,
+    #[serde(default)]
+    tombstones: Option<Vec<u64>>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<StoredInfixStore> for InfixStore {
+    type Error = alloc::string::String;
+
+    fn try_from(stored: StoredInfixStore) -> Result<Self, Self::Error> {
+        let num_slots = *stored
+            .layout
+            .size_grades()
+            .get(stored.size_grade as usize)
+            .ok_or("size grade out of range")?;
+        if stored.remainder_size > MAX_REMAINDER_SIZE
+            || stored.data.len()
+                != data_words(
+                    num_slots,
+                    stored.layout.quotient_size,
+                    stored.remainder_size,
+                )
+            || stored.elem_count > num_slots
+            || stored
+                .tombstones
+                .as_ref()
+                .is_some_and(|bits| bits.len() != (num_slots as usize).div_ceil(U64_BITS))
+        {
+            return Err(format!(
+                "InfixStore data of {} words does not match its metadata",
+                stored.data.len()
+            ));
+        }
+        let mut store = Self {
+            elem_count: stored.elem_count,
+            size_grade: stored.size_grade,
+            remainder_size: stored.remainder_size,
+            layout: stored.layout,
+            data: stored.data,
+            counting: stored.counting,
+            tombstones: stored
+                .tombstones
+                .map(|words| BitVec::from_words(words, num_slots as usize)),
+            sparse_occupieds: None,
+            occupieds_ranks: RankDirectory::default(),
+            runends_ranks: RankDirectory::default(),
+        };
+        store.rebuild_rank_directories();
+        store
+            .validate()
+            .map_err(|violation| violation.to_string())?;
+        Ok(store)
+    }
 }
 
 impl InfixStore {
@@ -37,54 +346,130 @@ impl InfixStore {
     /// * `infixes` - Sorted list of extracted partial keys (quotient|remainder)
     /// * `remainder_size` - Number of bits for remainder part
     pub fn new_with_infixes(infixes: &[u64], remainder_size: u8) -> Self {
+        Self::new_with_layout(infixes, remainder_size, StoreLayout::DEFAULT)
+    }
+
+    /// Create a new InfixStore from sorted extracted infixes with a custom layout
+    ///
+    /// # Arguments
+    /// * `infixes` - Sorted list of extracted partial keys (quotient|remainder)
+    /// * `remainder_size` - Number of bits for remainder part
+    /// * `layout` - Quotient size and size grade table of the store
+    pub fn new_with_layout(infixes: &[u64], remainder_size: u8, layout: StoreLayout) -> Self {
         // step 1: determine size_grade based on number of elements
-        let size_grade = Self::choose_size_grade(infixes.len());
-        let num_slots = SCALED_SIZES[size_grade as usize];
+        let size_grade = Self::choose_size_grade(layout.size_grades(), infixes.len());
+        let num_slots = layout.size_grades()[size_grade as usize];
 
         // step 2: calculate total data size needed
-        // [popcounts: 64 bits] [occupieds: TARGET_SIZE bits]
+        // [popcounts: 64 bits] [occupieds: 2^quotient_size bits]
         // [runends: num_slots bits] [slots: num_slots * remainder_size bits]
-        let popcounts_words = 1;
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
-        let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
-        let slots_bits = num_slots as usize * remainder_size as usize;
-        let slots_words = (slots_bits + U64_BITS - 1) / U64_BITS;
-
-        let total_words = popcounts_words + occupieds_words + runends_words + slots_words;
+        let total_words = data_words(num_slots, layout.quotient_size, remainder_size);
         let mut data = vec![0u64; total_words];
-        // let quotient_size = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS - 1;
-        // println!("quotient_size: {}", quotient_size);
 
-        if infixes.is_empty() {
-            return Self {
-                elem_count: 0,
-                size_grade,
+        // step 3: load infixes in the infix store
+        if !infixes.is_empty() {
+            Self::load_infixes_to_store(
+                &mut data,
+                infixes,
+                layout.quotient_size,
                 remainder_size,
-                quotient_size: QUOTIENT_SIZE,
-                data,
-            };
+                num_slots,
+            );
         }
 
-        // step 3: load infixes in the infix store
-        Self::load_infixes_to_store(&mut data, infixes, QUOTIENT_SIZE, remainder_size, num_slots);
-
-        Self {
+        let mut store = Self {
             elem_count: infixes.len() as u16,
             size_grade,
             remainder_size,
-            quotient_size: QUOTIENT_SIZE,
+            layout,
             data,
+            counting: false,
+            tombstones: None,
+            sparse_occupieds: None,
+            occupieds_ranks: RankDirectory::default(),
+            runends_ranks: RankDirectory::default(),
+        };
+        store.rebuild_rank_directories();
+        store
+    }
+
+    /// Create an empty InfixStore sized for `infix_count` infixes, with the
+    /// remainder size that gives point queries a false positive rate of about `fpr`
+    ///
+    /// Uses the default layout and assumes the infixes spread evenly over its
+    /// quotients, see `remainder_size_for_fpr`.
+    pub fn new_with_fpr(infix_count: usize, fpr: f64) -> Result<Self, RangeFilterError> {
+        if !(fpr > 0.0 && fpr < 1.0) {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "fpr must be in (0, 1), got {}",
+                fpr
+            )));
         }
+        let layout = StoreLayout::DEFAULT;
+        let density = infix_count as f64 / layout.num_quotients() as f64;
+        let mut store = Self::new_with_layout(
+            &[],
+            Self::remainder_size_for_fpr(density, fpr),
+            layout.clone(),
+        );
+        let size_grade = Self::choose_size_grade(layout.size_grades(), infix_count);
+        if size_grade > store.size_grade {
+            store.resize_to(size_grade);
+        }
+        Ok(store)
+    }
+
+    /// Smallest remainder size keeping the point query FPR of a store at `fpr`
+    ///
+    /// `density` is the number of stored infixes per quotient the queried keys
+    /// fall into. A query meets `density` remainders of its quotient on
+    /// average, each matching with probability `2^-remainder_size`, so the FPR
+    /// is about `density / 2^remainder_size`. The result is in 0..=64.
+    pub fn remainder_size_for_fpr(density: f64, fpr: f64) -> u8 {
+        // smallest r with density <= fpr * 2^r, doubling instead of log2 to stay no_std
+        let mut reach = fpr;
+        for remainder_size in 0..MAX_REMAINDER_SIZE {
+            if density <= reach {
+                return remainder_size;
+            }
+            reach *= 2.0;
+        }
+        MAX_REMAINDER_SIZE
+    }
+
+    /// Create a counting InfixStore from sorted extracted infixes, which may
+    /// repeat
+    ///
+    /// Every insert of an infix adds a copy and every delete removes one, so
+    /// the store behaves like a multiset; `count` reports the copies.
+    pub fn new_counting(infixes: &[u64], remainder_size: u8, layout: StoreLayout) -> Self {
+        let mut store = Self::new_with_layout(infixes, remainder_size, layout);
+        store.counting = true;
+        store
+    }
+
+    /// recompute the occupieds and runends rank directories from the bitmaps,
+    /// frozen occupieds answering rank queries themselves
+    fn rebuild_rank_directories(&mut self) {
+        let num_quotients = self.layout.num_quotients();
+        let num_slots = self.num_slots();
+        let (occupieds_start, runends_start, slots_start) = self.get_offsets();
+        self.occupieds_ranks = match self.sparse_occupieds {
+            Some(_) => RankDirectory::default(),
+            None => RankDirectory::build(&self.data[occupieds_start..runends_start], num_quotients),
+        };
+        self.runends_ranks =
+            RankDirectory::build(&self.data[runends_start..slots_start], num_slots);
     }
 
     /// choose appropriate size_grade based on number of elements
-    fn choose_size_grade(num_elements: usize) -> u8 {
-        for grade in 0..SIZE_GRADE_COUNT {
-            if SCALED_SIZES[grade] >= num_elements as u16 {
+    fn choose_size_grade(size_grades: &[u16], num_elements: usize) -> u8 {
+        for (grade, &num_slots) in size_grades.iter().enumerate() {
+            if num_slots as usize >= num_elements {
                 return grade as u8;
             }
         }
-        (SIZE_GRADE_COUNT - 1) as u8
+        (size_grades.len() - 1) as u8
     }
 
     /// load sorted infixes into the infix store
@@ -96,7 +481,7 @@ impl InfixStore {
         num_slots: u16,
     ) {
         let occupieds_start = 1;
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
+        let occupieds_words = occupieds_words(quotient_size);
         let runends_start = occupieds_start + occupieds_words;
         let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
         let slots_start = runends_start + runends_words;
@@ -133,38 +518,36 @@ impl InfixStore {
             set_bit(runends_slice, slot_pos - 1);
         }
 
-        Self::compute_popcounts(data, occupieds_start, runends_start, num_slots);
+        Self::compute_popcounts(
+            data,
+            occupieds_start,
+            runends_start,
+            num_slots,
+            quotient_size,
+        );
     }
 
     /// Split infix into quotient and remainder
+    ///
+    /// Handles every remainder size in 0..=64: with 0 the infix is all
+    /// quotient, with 64 the quotient is always 0.
     fn split_infix(infix: u64, quotient_size: u8, remainder_size: u8) -> (u64, u64) {
         // extract remainder (bottom remainder_size bits)
-        let remainder = infix & ((1 << remainder_size) - 1);
+        let remainder = infix & remainder_mask(remainder_size);
 
-        let quotient = (infix >> remainder_size) & ((1 << (quotient_size)) - 1);
+        let quotient = infix_quotient(infix, remainder_size) & ((1 << quotient_size) - 1);
         (quotient, remainder)
     }
 
     /// Write a remainder value to a specific slot
     fn write_slot(slots_slice: &mut [u64], slot_index: usize, remainder: u64, remainder_size: u8) {
-        let bit_pos = slot_index * remainder_size as usize;
-        let word_index = bit_pos / U64_BITS;
-        let bit_offset = bit_pos % U64_BITS;
-
-        // clear the bits first
-        let mask = ((1u64 << remainder_size) - 1) << bit_offset;
-        slots_slice[word_index] &= !mask;
-
-        // write the remainder
-        slots_slice[word_index] |= (remainder & ((1u64 << remainder_size) - 1)) << bit_offset;
-
-        // handle overflow to next word if needed
-        if bit_offset + remainder_size as usize > U64_BITS {
-            let overflow_bits = (bit_offset + remainder_size as usize) - U64_BITS;
-            let overflow_mask = (1u64 << overflow_bits) - 1;
-            slots_slice[word_index + 1] &= !overflow_mask;
-            slots_slice[word_index + 1] |= remainder >> (remainder_size as usize - overflow_bits);
-        }
+        let remainder_size = remainder_size as usize;
+        write_bits(
+            slots_slice,
+            slot_index * remainder_size,
+            remainder_size,
+            remainder,
+        );
     }
 
     /// Compute and store popcounts for first half. Optimization for rank queries
@@ -173,11 +556,12 @@ impl InfixStore {
         occupieds_start: usize,
         runends_start: usize,
         num_slots: u16,
+        quotient_size: u8,
     ) {
-        let occupieds_half = TARGET_SIZE as usize / 2;
-        let runends_half = CACHE_BOUNDARY as usize;
+        let occupieds_half = cache_boundary(quotient_size);
+        let runends_half = runends_cache_boundary(num_slots, quotient_size);
 
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
+        let occupieds_words = occupieds_words(quotient_size);
         let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
 
         let occupieds_slice = &data[occupieds_start..occupieds_start + occupieds_words];
@@ -191,21 +575,30 @@ impl InfixStore {
     }
 
     /// insert a key into the infix store
-    pub fn insert(&mut self, infix: u64) -> bool {
-        let mut num_slots = SCALED_SIZES[self.size_grade as usize];
+    ///
+    /// Fails with `PartitionFull` if the store is full at its largest size grade.
+    /// Frozen occupieds are thawed first.
+    pub fn insert(&mut self, infix: u64) -> Result<(), RangeFilterError> {
+        self.thaw_occupieds();
+        let mut num_slots = self.layout.size_grades()[self.size_grade as usize];
+
+        // reclaim lazily deleted slots before growing
+        if self.elem_count >= num_slots && self.tombstone_count() > 0 {
+            self.compact();
+            num_slots = self.layout.size_grades()[self.size_grade as usize];
+        }
 
         // check if we have enough space and resize if possible
         if self.elem_count >= num_slots {
             if !self.resize_up() {
-                return false;
+                return Err(RangeFilterError::PartitionFull);
             }
-            num_slots = SCALED_SIZES[self.size_grade as usize];
+            num_slots = self.layout.size_grades()[self.size_grade as usize];
         }
 
-        let (quotient, remainder) = Self::split_infix(infix, self.quotient_size, self.remainder_size);
-        let (occupieds_start, runends_start, slots_start) = self.get_offsets();
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
-        let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
+        let (quotient, remainder) =
+            Self::split_infix(infix, self.layout.quotient_size, self.remainder_size);
+        let (_, _, slots_start) = self.get_offsets();
         let slots_words =
             (num_slots as usize * self.remainder_size as usize + U64_BITS - 1) / U64_BITS;
 
@@ -221,29 +614,32 @@ impl InfixStore {
                 .map(|x| x + 1)
                 .unwrap_or(0)
         };
-        let run_end = self.select_runends_cached(run_index).unwrap_or(self.elem_count as usize);
+        let run_end = self
+            .select_runends_cached(run_index)
+            .unwrap_or(self.elem_count as usize);
 
-        let insert_pos;
-        if is_new_quotient {
-            insert_pos = run_start;
+        let insert_pos = if is_new_quotient {
+            run_start
         } else {
-            // assume insertion position at the end to begin with
-            // if a run with a greater remainder is found, update position
-            let mut found_pos = run_end + 1;
-            for i in run_start..=run_end {
-                let val = self.read_slot(i);
-                // the key already exists
-                if val == remainder {
-                    return true;
-                }
-
-                if val > remainder {
-                    found_pos = i;
-                    break;
+            // binary search the sorted run for the copies of this remainder
+            let view = self.view();
+            let probe = &mut StoreProbe::default();
+            let first = view.search_run(run_start, run_end + 1, remainder, probe);
+            let mut slot = first;
+            while slot <= run_end && view.read_slot(slot) == remainder {
+                // a lazily deleted copy is revived instead of adding a slot
+                if view.is_tombstone(slot) {
+                    self.set_tombstone(slot, false);
+                    return Ok(());
                 }
+                slot += 1;
             }
-            insert_pos = found_pos;
-        }
+            // the key already exists, a counting store adds a copy after it
+            if slot > first && !self.counting {
+                return Ok(());
+            }
+            slot
+        };
 
         // shift the slots and runends to make room
         if self.elem_count > 0 {
@@ -255,41 +651,36 @@ impl InfixStore {
         let slots_slice = &mut self.data[slots_start..slots_start + slots_words];
         Self::write_slot(slots_slice, insert_pos, remainder, self.remainder_size);
 
-        let runends_slice = &mut self.data[runends_start..runends_start + runends_words];
         // set both runend and occupieds bits if new quotient
         if is_new_quotient {
-            set_bit(runends_slice, insert_pos);
-            let occupieds_slice =
-                &mut self.data[occupieds_start..occupieds_start + occupieds_words];
-            set_bit(occupieds_slice, quotient as usize);
+            self.set_runend(insert_pos, true);
+            self.set_occupied(quotient as usize, true);
         } else {
             // if inserted after the old run_end, clear and set new run_end
-            if insert_pos >= run_end {
-                clear_bit(runends_slice, run_end);
-                set_bit(runends_slice, insert_pos);
+            if insert_pos > run_end {
+                self.set_runend(run_end, false);
+                self.set_runend(insert_pos, true);
             }
         }
         // increment element count
         self.elem_count += 1;
-
-        // recompute cache
-        Self::compute_popcounts(&mut self.data, occupieds_start, runends_start, num_slots);
-        true
+        Ok(())
     }
 
     /// delete a key from the infix store
-    pub fn delete(&mut self, infix: u64) -> bool {
+    ///
+    /// With lazy deletes the slot is only marked as a tombstone, see
+    /// `set_lazy_delete`. Fails with `KeyNotFound` if the infix is not stored.
+    /// Frozen occupieds are thawed first.
+    pub fn delete(&mut self, infix: u64) -> Result<(), RangeFilterError> {
+        self.thaw_occupieds();
         // check if the quotient exists
-        let (quotient, remainder) = Self::split_infix(infix, self.quotient_size, self.remainder_size);
+        let (quotient, remainder) =
+            Self::split_infix(infix, self.layout.quotient_size, self.remainder_size);
         if !self.is_occupied(quotient as usize) {
-            return false;
+            return Err(RangeFilterError::KeyNotFound(infix));
         }
 
-        let mut num_slots = SCALED_SIZES[self.size_grade as usize];
-        let (occupieds_start, runends_start, _) = self.get_offsets();
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
-        let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
-
         // find the run index
         let run_index = self.rank_occupieds_cached(quotient as usize);
 
@@ -302,33 +693,30 @@ impl InfixStore {
                 .map(|x| x + 1)
                 .unwrap_or(0)
         };
-        let run_end =
-            self.select_runends_cached(run_index).expect("panic: occupied bit set but no runend found.");
+        let run_end = self
+            .select_runends_cached(run_index)
+            .ok_or(RangeFilterError::CorruptedStore)?;
 
         // find the slot position to be deleted
-        let mut del_pos = None;
-        for i in run_start..=run_end {
-            if self.read_slot(i) == remainder {
-                del_pos = Some(i);
-                break;
-            }
-        }
+        let del_pos =
+            self.view()
+                .find_live_slot(run_start, run_end, remainder, &mut StoreProbe::default());
         let pos = match del_pos {
             Some(p) => p,
-            None => return false,
+            None => return Err(RangeFilterError::KeyNotFound(infix)),
         };
-
-        let runends_slice = &mut self.data[runends_start..runends_start + runends_words];
+        if self.tombstones.is_some() {
+            self.set_tombstone(pos, true);
+            return Ok(());
+        }
 
         let is_last_item_in_run = run_start == run_end;
         if is_last_item_in_run {
             // if only item remaining in the run, remove the quotient as well
-            let occupieds_slice =
-                &mut self.data[occupieds_start..occupieds_start + occupieds_words];
-            clear_bit(occupieds_slice, quotient as usize);
+            self.set_occupied(quotient as usize, false);
         } else if pos == run_end {
             // if last item of a multi-item run, mark previous item as the new run_end
-            set_bit(runends_slice, pos - 1);
+            self.set_runend(pos - 1, true);
         }
 
         // shift slots and runends to the left and delete the remainder
@@ -340,171 +728,901 @@ impl InfixStore {
 
         // check if we can size down a grade
         if self.size_grade > 0 {
-            let prev_size_grade = SCALED_SIZES[(self.size_grade - 1) as usize];
+            let prev_size_grade = self.layout.size_grades()[(self.size_grade - 1) as usize];
             if self.elem_count <= prev_size_grade / 2 {
                 self.resize_down();
-                num_slots = SCALED_SIZES[self.size_grade as usize];
             }
         }
+        Ok(())
+    }
 
-        // recompute cache
-        Self::compute_popcounts(&mut self.data, occupieds_start, runends_start, num_slots);
-        true
+    /// Delete every infix in [start_infix, end_infix] (inclusive), returning
+    /// how many were removed
+    ///
+    /// The removed infixes occupy one contiguous block of slots, so the runs
+    /// they cut are patched first and the rest of the store is compacted with
+    /// a single shift, instead of one shift per `delete`. Tombstones in the
+    /// block are reclaimed along the way, also with lazy deletes. Frozen
+    /// occupieds are thawed first.
+    pub fn delete_range(&mut self, start_infix: u64, end_infix: u64) -> usize {
+        if start_infix > end_infix {
+            return 0;
+        }
+        self.thaw_occupieds();
+        let view = self.view();
+        let first = view.lower_bound(start_infix);
+        let last = match end_infix.checked_add(1) {
+            Some(next_infix) => view.lower_bound(next_infix),
+            None => self.elem_count(),
+        };
+        if first == last {
+            return 0;
+        }
+        let dead = view.tombstones_in(first, last);
+
+        // every run overlapping the removed slots belongs to a quotient in this range
+        let num_quotients = self.layout.num_quotients();
+        let (start_quotient, _) =
+            Self::split_infix(start_infix, self.layout.quotient_size, self.remainder_size);
+        let end_quotient =
+            (infix_quotient(end_infix, self.remainder_size) as usize).min(num_quotients - 1);
+        let mut emptied = Vec::new();
+        let mut tail_cut = false;
+        let mut quotient = view.next_occupied(start_quotient as usize);
+        while quotient <= end_quotient {
+            if let Some((run_start, run_end)) = view.get_run_bounds(quotient) {
+                if run_start >= first && run_end < last {
+                    emptied.push(quotient);
+                } else if run_start < first && run_end >= first && run_end < last {
+                    // the run keeps its head, which now ends right before the removed block
+                    tail_cut = true;
+                }
+            }
+            quotient = view.next_occupied(quotient + 1);
+        }
+
+        if tail_cut {
+            self.set_runend(first - 1, true);
+        }
+        for quotient in emptied {
+            self.set_occupied(quotient, false);
+        }
+
+        // move everything after the removed block down and clear the vacated runends
+        let removed = last - first;
+        let elem_count = self.elem_count();
+        let remainder_size = self.remainder_size as usize;
+        move_bits(
+            self.slots_slice_mut(),
+            last * remainder_size,
+            first * remainder_size,
+            (elem_count - last) * remainder_size,
+        );
+        let runends_slice = self.runends_slice_mut();
+        move_bits(runends_slice, last, first, elem_count - last);
+        clear_bits_in_range(runends_slice, elem_count - removed, elem_count);
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.move_bits(last, first, elem_count - last);
+            tombstones.clear_range(elem_count - removed, elem_count);
+        }
+        self.elem_count -= removed as u16;
+
+        // the compaction moved many runend bits across the cached boundaries
+        let num_slots = self.num_slots() as u16;
+        let (occupieds_start, runends_start, _) = self.get_offsets();
+        Self::compute_popcounts(
+            &mut self.data,
+            occupieds_start,
+            runends_start,
+            num_slots,
+            self.layout.quotient_size,
+        );
+        self.rebuild_rank_directories();
+
+        // size down as far as the remaining elements allow
+        while self.size_grade > 0
+            && self.elem_count <= self.layout.size_grades()[(self.size_grade - 1) as usize] / 2
+        {
+            self.resize_down();
+        }
+        removed - dead
+    }
+
+    /// Switch lazy deletes on or off
+    ///
+    /// With lazy deletes `delete` marks the slot as a tombstone instead of
+    /// shifting every later slot. Queries skip tombstones, and their slots are
+    /// reclaimed by `compact` or once an insert finds the store full.
+    /// Switching lazy deletes off compacts the store.
+    pub fn set_lazy_delete(&mut self, enabled: bool) {
+        if enabled && self.tombstones.is_none() {
+            self.tombstones = Some(BitVec::new(self.num_slots()));
+        } else if !enabled && self.tombstones.is_some() {
+            self.compact();
+            self.tombstones = None;
+        }
     }
 
+    pub fn is_lazy_delete(&self) -> bool {
+        self.tombstones.is_some()
+    }
+
+    /// number of lazily deleted slots waiting to be reclaimed
+    pub fn tombstone_count(&self) -> usize {
+        self.tombstones.as_ref().map_or(0, BitVec::count_ones)
+    }
+
+    /// Reclaim the slots of lazily deleted infixes
+    ///
+    /// Rebuilds the store from its live infixes in one pass, which may also
+    /// move it to a smaller size grade.
+    pub fn compact(&mut self) {
+        if self.tombstone_count() == 0 {
+            return;
+        }
+        let infixes = self.infixes();
+        let mut store = Self::new_with_layout(&infixes, self.remainder_size, self.layout.clone());
+        store.counting = self.counting;
+        store.set_lazy_delete(true);
+        *self = store;
+    }
+
+    /// mark or unmark a slot as lazily deleted
+    fn set_tombstone(&mut self, slot_pos: usize, dead: bool) {
+        if let Some(tombstones) = &mut self.tombstones {
+            if dead {
+                tombstones.set(slot_pos);
+            } else {
+                tombstones.clear(slot_pos);
+            }
+        }
+    }
+
+    /// Move the store to another size grade within its buffer
+    ///
+    /// Only the runends in front of the slots change size, so the slots are
+    /// moved inside `data` and the words past the stored elements are zeroed.
+    /// Growing reallocates only beyond the current capacity, and shrinking
+    /// keeps the capacity for the next grow (see `shrink_to_fit`).
     fn resize_to(&mut self, new_size_grade: u8) {
-        let new_num_slots = SCALED_SIZES[new_size_grade as usize];
-
-        // initialize new data vector
-        let popcounts_words = 1;
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
-        let new_runends_words = (new_num_slots as usize + U64_BITS - 1) / U64_BITS;
-        let new_slots_words =
-            (new_num_slots as usize * self.remainder_size as usize + U64_BITS - 1) / U64_BITS;
-        let total_words = popcounts_words + occupieds_words + new_runends_words + new_slots_words;
-        let mut new_data = vec![0u64; total_words];
-
-        // calculate new offsets
-        let occupieds_start = popcounts_words;
-        let runends_start = occupieds_start + occupieds_words;
+        let new_num_slots = self.layout.size_grades()[new_size_grade as usize];
+        let (occupieds_start, runends_start, old_slots_start) = self.get_offsets();
+        let new_runends_words = (new_num_slots as usize).div_ceil(U64_BITS);
         let new_slots_start = runends_start + new_runends_words;
+        let total_words = data_words(
+            new_num_slots,
+            self.layout.quotient_size,
+            self.remainder_size,
+        );
+
+        // words holding the runends and slots of stored elements
+        let valid_runends_words = (self.elem_count as usize).div_ceil(U64_BITS);
+        let valid_slots_words =
+            (self.elem_count as usize * self.remainder_size as usize).div_ceil(U64_BITS);
+
+        if total_words > self.data.len() {
+            self.data.reserve_exact(total_words - self.data.len());
+            self.data.resize(total_words, 0);
+        }
+        self.data.copy_within(
+            old_slots_start..old_slots_start + valid_slots_words,
+            new_slots_start,
+        );
+        self.data.truncate(total_words);
+        self.data[runends_start + valid_runends_words..new_slots_start].fill(0);
+        self.data[new_slots_start + valid_slots_words..].fill(0);
+
+        self.size_grade = new_size_grade;
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.resize(new_num_slots as usize);
+        }
+
+        // recompute cache
+        Self::compute_popcounts(
+            &mut self.data,
+            occupieds_start,
+            runends_start,
+            new_num_slots,
+            self.layout.quotient_size,
+        );
+        self.rebuild_rank_directories();
+    }
+
+    fn resize_up(&mut self) -> bool {
+        // fail if already at max size
+        if self.size_grade as usize >= self.layout.size_grades().len() - 1 {
+            return false;
+        }
+        self.resize_to(self.size_grade + 1);
+        true
+    }
+
+    fn resize_down(&mut self) -> bool {
+        // fail if already at the min size
+        if self.size_grade == 0 {
+            return false;
+        }
+        self.resize_to(self.size_grade - 1);
+        true
+    }
+
+    /// shift all slots from start_pos to the right by 1 (for insertion)
+    ///
+    /// The slots are one contiguous bit region, so this moves it in 64-bit
+    /// chunks instead of slot by slot.
+    fn shift_slots_right(&mut self, start_pos: usize) {
+        let remainder_size = self.remainder_size as usize;
+        let elem_count = self.elem_count as usize;
+        let slots_slice = self.slots_slice_mut();
+        move_bits(
+            slots_slice,
+            start_pos * remainder_size,
+            (start_pos + 1) * remainder_size,
+            (elem_count - start_pos) * remainder_size,
+        );
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.shift_range(start_pos, elem_count, 1);
+        }
+    }
+
+    /// shift all runend bits from start_pos to the right by 1 (for insertion)
+    fn shift_runends_right(&mut self, start_pos: usize) {
+        let elem_count = self.elem_count as usize;
+        let (_, half_pos) = self.cache_boundaries();
+        // the bit just below the cache boundary moves above it
+        let leaving =
+            start_pos < half_pos && half_pos <= elem_count && self.is_runend(half_pos - 1);
+        let (_, runends_start, slots_start) = self.get_offsets();
+        self.runends_ranks.shift_right(
+            &self.data[runends_start..slots_start],
+            start_pos,
+            elem_count,
+        );
+        shift_bits_range(self.runends_slice_mut(), start_pos, elem_count, 1);
+        if leaving {
+            self.adjust_popcounts(0, -1);
+        }
+    }
+
+    /// shift all slots to the left by 1 (after deletion)
+    fn shift_slots_left(&mut self, start_pos: usize) {
+        let remainder_size = self.remainder_size as usize;
+        let elem_count = self.elem_count as usize;
+        let slots_slice = self.slots_slice_mut();
+        move_bits(
+            slots_slice,
+            (start_pos + 1) * remainder_size,
+            start_pos * remainder_size,
+            (elem_count - start_pos - 1) * remainder_size,
+        );
+        if let Some(tombstones) = &mut self.tombstones {
+            // cleared first, the range is empty when the last slot goes
+            tombstones.clear(start_pos);
+            tombstones.shift_range(start_pos + 1, elem_count, -1);
+        }
+    }
+
+    /// shift all runend bits to the left by 1 (after deletion)
+    fn shift_runends_left(&mut self, start_pos: usize) {
+        let elem_count = self.elem_count as usize;
+        let (_, half_pos) = self.cache_boundaries();
+        // the bit at start_pos is overwritten and the bit at the cache boundary moves below it
+        let mut delta = 0;
+        if start_pos < half_pos {
+            delta -= self.is_runend(start_pos) as i32;
+            if half_pos < elem_count {
+                delta += self.is_runend(half_pos) as i32;
+            }
+        }
+        let (_, runends_start, slots_start) = self.get_offsets();
+        self.runends_ranks.shift_left(
+            &self.data[runends_start..slots_start],
+            start_pos,
+            elem_count,
+        );
+        let runends_slice = self.runends_slice_mut();
+        clear_bit(runends_slice, start_pos);
+        shift_bits_range(runends_slice, start_pos + 1, elem_count, -1);
+        self.adjust_popcounts(0, delta);
+    }
+
+    /// set or clear the occupieds bit of `quotient`, keeping the cached popcount in sync
+    fn set_occupied(&mut self, quotient: usize, value: bool) {
+        if self.is_occupied(quotient) == value {
+            return;
+        }
+        let (occupieds_start, _, _) = self.get_offsets();
+        let occupieds_words = occupieds_words(self.layout.quotient_size);
+        let occupieds_slice = &mut self.data[occupieds_start..occupieds_start + occupieds_words];
+        if value {
+            set_bit(occupieds_slice, quotient);
+        } else {
+            clear_bit(occupieds_slice, quotient);
+        }
+        self.occupieds_ranks.update(quotient, value);
+        if quotient < self.cache_boundaries().0 {
+            self.adjust_popcounts(if value { 1 } else { -1 }, 0);
+        }
+    }
+
+    /// set or clear the runend bit at `pos`, keeping the cached popcount in sync
+    fn set_runend(&mut self, pos: usize, value: bool) {
+        if self.is_runend(pos) == value {
+            return;
+        }
+        let runends_slice = self.runends_slice_mut();
+        if value {
+            set_bit(runends_slice, pos);
+        } else {
+            clear_bit(runends_slice, pos);
+        }
+        self.runends_ranks.update(pos, value);
+        if pos < self.cache_boundaries().1 {
+            self.adjust_popcounts(0, if value { 1 } else { -1 });
+        }
+    }
+
+    /// positions of the occupieds and runends popcounts cached in word 0
+    fn cache_boundaries(&self) -> (usize, usize) {
+        let quotient_size = self.layout.quotient_size;
+        (
+            cache_boundary(quotient_size),
+            runends_cache_boundary(self.num_slots() as u16, quotient_size),
+        )
+    }
+
+    /// add to the popcounts cached in word 0: occupieds in the high half, runends in the low half
+    fn adjust_popcounts(&mut self, occupieds_delta: i32, runends_delta: i32) {
+        let occupieds = ((self.data[0] >> 32) as u32).wrapping_add_signed(occupieds_delta);
+        let runends = (self.data[0] as u32).wrapping_add_signed(runends_delta);
+        self.data[0] = ((occupieds as u64) << 32) | runends as u64;
+    }
+
+    /// the runends bitmap of the current size grade
+    fn runends_slice_mut(&mut self) -> &mut [u64] {
+        let num_slots = self.num_slots();
+        let (_, runends_start, _) = self.get_offsets();
+        let runends_words = num_slots.div_ceil(U64_BITS);
+        &mut self.data[runends_start..runends_start + runends_words]
+    }
+
+    /// the packed remainder slots of the current size grade
+    fn slots_slice_mut(&mut self) -> &mut [u64] {
+        let num_slots = self.num_slots();
+        let (_, _, slots_start) = self.get_offsets();
+        let slots_words = (num_slots * self.remainder_size as usize).div_ceil(U64_BITS);
+        &mut self.data[slots_start..slots_start + slots_words]
+    }
+
+    /// get memory layout offsets
+    fn get_offsets(&self) -> (usize, usize, usize) {
+        match self.sparse_occupieds {
+            Some(_) => get_offsets_without_occupieds(self.num_slots() as u16),
+            None => get_offsets(self.num_slots() as u16, self.layout.quotient_size),
+        }
+    }
+
+    /// Keep the occupieds as a sorted array of quotients if that is smaller
+    /// than the bitmap and its rank directory, returning whether it is
+    ///
+    /// The array is the container a Roaring bitmap uses for sparse chunks, two
+    /// bytes per occupied quotient, so a sparse partition's 2^quotient_size
+    /// occupieds bits shrink to a few bytes, which `size_in_bytes` reflects.
+    /// For stores that are done changing: queries give the same answers,
+    /// testing and ranking quotients with a binary search over the array
+    /// instead of a word lookup, and the next insert or delete thaws the store
+    /// back to the bitmap.
+    pub fn freeze_occupieds(&mut self) -> bool {
+        if self.sparse_occupieds.is_some() {
+            return true;
+        }
+        let (occupieds_start, runends_start, _) = self.get_offsets();
+        let occupieds = &self.data[occupieds_start..runends_start];
+        let saved = core::mem::size_of_val(occupieds) + self.occupieds_ranks.heap_size();
+        if rank(occupieds, self.layout.num_quotients()) * core::mem::size_of::<u16>() >= saved {
+            return false;
+        }
+        // quotients have at most MAX_QUOTIENT_SIZE = 16 bits
+        let sparse = iter_ones(occupieds)
+            .map(|quotient| quotient as u16)
+            .collect();
+        self.data.drain(occupieds_start..runends_start);
+        self.data.shrink_to_fit();
+        self.sparse_occupieds = Some(sparse);
+        self.rebuild_rank_directories();
+        true
+    }
+
+    /// Put frozen occupieds back into the data words, see `freeze_occupieds`
+    pub fn thaw_occupieds(&mut self) {
+        if self.sparse_occupieds.is_none() {
+            return;
+        }
+        self.data = self.data().into_owned();
+        self.sparse_occupieds = None;
+        self.rebuild_rank_directories();
+    }
+
+    /// whether the occupieds are kept as an array, see `freeze_occupieds`
+    pub fn is_occupieds_frozen(&self) -> bool {
+        self.sparse_occupieds.is_some()
+    }
+
+    /// borrow a read-only view of this store for queries
+    #[inline]
+    pub fn view(&self) -> InfixStoreRef<'_> {
+        InfixStoreRef {
+            elem_count: self.elem_count,
+            num_slots: self.layout.size_grades()[self.size_grade as usize],
+            remainder_size: self.remainder_size,
+            quotient_size: self.layout.quotient_size,
+            data: &self.data,
+            ranks: (!self.runends_ranks.is_empty())
+                .then_some((&self.occupieds_ranks, &self.runends_ranks)),
+            tombstones: self.tombstones.as_ref().map(BitVec::words),
+            sparse_occupieds: self.sparse_occupieds.as_deref(),
+        }
+    }
+
+    /// check if a quotient bit is set in occupieds
+    pub fn is_occupied(&self, quotient: usize) -> bool {
+        self.view().is_occupied(quotient)
+    }
+
+    /// check if a slot position has runend bit set
+    pub fn is_runend(&self, slot_pos: usize) -> bool {
+        self.view().is_runend(slot_pos)
+    }
+
+    /// read remainder value from a specific slot
+    pub fn read_slot(&self, slot_index: usize) -> u64 {
+        self.view().read_slot(slot_index)
+    }
+
+    pub fn elem_count(&self) -> usize {
+        self.elem_count as usize
+    }
+
+    pub fn size_grade(&self) -> u8 {
+        self.size_grade
+    }
+
+    pub fn remainder_size(&self) -> u8 {
+        self.remainder_size
+    }
+
+    pub fn num_slots(&self) -> usize {
+        self.layout.size_grades()[self.size_grade as usize] as usize
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pretty_print(&self) {
+        print!("{}", self);
+    }
+
+    pub fn quotient_size(&self) -> u8 {
+        self.layout.quotient_size
+    }
+
+    pub fn layout(&self) -> StoreLayout {
+        self.layout.clone()
+    }
+
+    /// whether duplicate inserts are kept as copies, see `new_counting`
+    pub fn is_counting(&self) -> bool {
+        self.counting
+    }
+
+    /// collect the stored infixes (quotient | remainder) in slot order
+    pub(crate) fn infixes(&self) -> Vec<u64> {
+        self.iter()
+            .map(|(quotient, remainder)| join_infix(quotient, remainder, self.remainder_size))
+            .collect()
+    }
+
+    /// Iterate over the stored `(quotient, remainder)` pairs in slot order,
+    /// which is ascending infix order
+    pub fn iter(&self) -> InfixIter<'_> {
+        self.view().iter()
+    }
 
-        // old offsets to read from
-        let old_num_slots = SCALED_SIZES[self.size_grade as usize];
-        let old_runends_words = (old_num_slots as usize + U64_BITS - 1) / U64_BITS;
-        let old_slots_start = runends_start + old_runends_words;
+    /// Iterate over the `(quotient, remainder)` pairs of one quotient's run;
+    /// empty if the quotient is not occupied
+    pub fn iter_run(&self, quotient: usize) -> InfixIter<'_> {
+        self.view().iter_run(quotient)
+    }
 
-        // copy popcounts and occupied (fixed data)
-        let fixed_region_len = runends_start;
-        new_data[0..fixed_region_len].copy_from_slice(&self.data[0..fixed_region_len]);
+    /// Iterate over the occupied quotients in ascending order
+    pub fn occupied_quotients(&self) -> OccupiedQuotients<'_> {
+        self.view().occupied_quotients()
+    }
+
+    /// Iterate over the slots ending a run in ascending order, one per
+    /// occupied quotient
+    pub fn runend_slots(&self) -> Ones<'_> {
+        self.view().runend_slots()
+    }
 
-        // copy runends containing data
-        let valid_runends_words = (self.elem_count as usize + U64_BITS - 1) / U64_BITS;
-        if valid_runends_words > 0 {
-            new_data[runends_start..runends_start + valid_runends_words]
-                .copy_from_slice(&self.data[runends_start..runends_start + valid_runends_words]);
+    /// Copy of the occupieds bitmap in its smallest read-only encoding, see
+    /// `InfixStoreRef::compact_occupieds`
+    pub fn compact_occupieds(&self) -> CompactBitmap {
+        self.view().compact_occupieds()
+    }
+
+    /// Split into a store holding the quotients below `quotient` and one
+    /// holding the quotients from `quotient` on
+    ///
+    /// Both stores keep this store's remainder size, layout and infix values,
+    /// sized for their own element count. Used when a partition overflows and
+    /// a new boundary sample splits its keys in two.
+    pub fn split_at_quotient(&self, quotient: usize) -> (InfixStore, InfixStore) {
+        let infixes = self.infixes();
+        let split = infixes.partition_point(|&infix| {
+            (infix_quotient(infix, self.remainder_size) as usize) < quotient
+        });
+        let mut halves = (
+            Self::new_with_layout(&infixes[..split], self.remainder_size, self.layout.clone()),
+            Self::new_with_layout(&infixes[split..], self.remainder_size, self.layout.clone()),
+        );
+        for half in [&mut halves.0, &mut halves.1] {
+            half.counting = self.counting;
+            half.set_lazy_delete(self.is_lazy_delete());
         }
+        halves
+    }
+
+    /// Check the structural invariants of the store
+    ///
+    /// Verifies that every occupied quotient has exactly one run, that the
+    /// runs fill the first `elem_count` slots with sorted remainders, and that
+    /// the cached popcounts, rank directories and tombstones agree with the
+    /// bitmaps. Deserialized stores are validated before use.
+    pub fn validate(&self) -> Result<(), InvariantViolation> {
+        if self.sparse_occupieds.is_some() {
+            let mut thawed = self.clone();
+            thawed.thaw_occupieds();
+            return thawed.validate();
+        }
+        let num_slots = self.num_slots();
+        let expected = data_words(
+            num_slots as u16,
+            self.layout.quotient_size,
+            self.remainder_size,
+        );
+        if self.data.len() != expected {
+            return Err(InvariantViolation::DataLength {
+                expected,
+                actual: self.data.len(),
+            });
+        }
+        let elem_count = self.elem_count();
+        if elem_count > num_slots {
+            return Err(InvariantViolation::ElemCountOverflow {
+                elem_count,
+                num_slots,
+            });
+        }
+
+        let (occupieds_start, runends_start, slots_start) = self.get_offsets();
+        let occupieds_slice = &self.data[occupieds_start..runends_start];
+        let runends_slice = &self.data[runends_start..slots_start];
+        let occupieds = rank(occupieds_slice, self.layout.num_quotients());
+        let runends = rank(runends_slice, num_slots);
+        if occupieds != runends {
+            return Err(InvariantViolation::RunendCount { occupieds, runends });
+        }
+        // the last runend closes the last run, so it sits right before the free slots
+        let covered = match runends {
+            0 => 0,
+            _ => select(runends_slice, runends - 1).map_or(0, |pos| pos + 1),
+        };
+        if covered != elem_count {
+            return Err(InvariantViolation::RunsLength {
+                elem_count,
+                covered,
+            });
+        }
+
+        let view = self.view();
+        let mut run_start = 0;
+        for (quotient, run_end) in view.occupied_quotients().zip(view.runend_slots()) {
+            for slot in run_start + 1..=run_end {
+                if view.read_slot(slot) < view.read_slot(slot - 1) {
+                    return Err(InvariantViolation::UnsortedRun { quotient, slot });
+                }
+            }
+            run_start = run_end + 1;
+        }
+
+        let quotient_size = self.layout.quotient_size;
+        let popcounts = (
+            rank(occupieds_slice, cache_boundary(quotient_size)) as u32,
+            rank(
+                runends_slice,
+                runends_cache_boundary(num_slots as u16, quotient_size),
+            ) as u32,
+        );
+        if popcounts != (view.get_popcount_occupieds(), view.get_popcount_runends()) {
+            return Err(InvariantViolation::StalePopcounts);
+        }
+        let num_quotients = self.layout.num_quotients();
+        if self.occupieds_ranks != RankDirectory::build(occupieds_slice, num_quotients)
+            || self.runends_ranks != RankDirectory::build(runends_slice, num_slots)
+        {
+            return Err(InvariantViolation::StaleRankDirectory);
+        }
+
+        if let Some(slot) = self
+            .tombstones
+            .as_ref()
+            .and_then(|bits| bits.next_one(elem_count))
+        {
+            return Err(InvariantViolation::StrayTombstone { slot });
+        }
+        Ok(())
+    }
+
+    /// Release the buffer capacity kept after shrinking to a smaller size grade
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.shrink_to_fit();
+        }
+    }
 
-        // copy slots containing data
-        let valid_slots_bits = self.elem_count as usize * self.remainder_size as usize;
-        let valid_slots_words = (valid_slots_bits + U64_BITS - 1) / U64_BITS;
-        if valid_slots_words > 0 {
-            new_data[new_slots_start..new_slots_start + valid_slots_words]
-                .copy_from_slice(&self.data[old_slots_start..old_slots_start + valid_slots_words]);
+    /// memory used by this store: the struct plus its allocated data words,
+    /// rank directories, tombstones and frozen occupieds
+    pub fn size_in_bytes(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self.data.capacity() * core::mem::size_of::<u64>()
+            + self.occupieds_ranks.heap_size()
+            + self.runends_ranks.heap_size()
+            + self.tombstones.as_ref().map_or(0, BitVec::heap_size)
+            + self
+                .sparse_occupieds
+                .as_ref()
+                .map_or(0, |sparse| core::mem::size_of_val(&**sparse))
+    }
+
+    /// raw data words: [popcounts] [occupieds] [runends] [slots]
+    ///
+    /// Borrowed, unless frozen occupieds have to be decoded back into place.
+    pub fn data(&self) -> Cow<'_, [u64]> {
+        let Some(sparse) = &self.sparse_occupieds else {
+            return Cow::Borrowed(&self.data);
+        };
+        let mut occupieds = vec![0u64; occupieds_words(self.layout.quotient_size)];
+        for &quotient in sparse.iter() {
+            set_bit(&mut occupieds, quotient as usize);
         }
+        let mut data = Vec::with_capacity(self.data.len() + occupieds.len());
+        data.push(self.data[0]);
+        data.extend(occupieds);
+        data.extend_from_slice(&self.data[1..]);
+        Cow::Owned(data)
+    }
+
+    /// Point query: check if a key exists in this InfixStore
+    ///
+    /// # Arguments
+    /// * `query_key` - The key to search for
+    /// * `predecessor_key` - The predecessor sample key
+    /// * `successor_key` - The successor sample key
+    /// * `remainder_size` - Number of bits in remainder
+    pub fn point_query(
+        &self,
+        query_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+    ) -> bool {
+        self.view()
+            .point_query(query_key, predecessor_key, successor_key, remainder_size)
+    }
+
+    /// `point_query` that also records the work done in `probe`
+    pub fn point_query_probed(
+        &self,
+        query_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+        probe: &mut StoreProbe,
+    ) -> bool {
+        self.view().point_query_probed(
+            query_key,
+            predecessor_key,
+            successor_key,
+            remainder_size,
+            probe,
+        )
+    }
+
+    /// Range query: check if any key exists in the given range [start_key, end_key] (inclusive)
+    ///
+    /// # Arguments
+    /// * `start_key` - Start of the query range
+    /// * `end_key` - End of the query range
+    /// * `predecessor_key` - The predecessor sample key
+    /// * `successor_key` - The successor sample key
+    /// * `remainder_size` - Number of bits in remainder
+    pub fn range_query(
+        &self,
+        start_key: u64,
+        end_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+    ) -> bool {
+        self.view().range_query(
+            start_key,
+            end_key,
+            predecessor_key,
+            successor_key,
+            remainder_size,
+        )
+    }
 
-        self.data = new_data;
-        self.size_grade = new_size_grade;
+    /// `range_query` that also records the work done in `probe`
+    pub fn range_query_probed(
+        &self,
+        start_key: u64,
+        end_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+        probe: &mut StoreProbe,
+    ) -> bool {
+        self.view().range_query_probed(
+            start_key,
+            end_key,
+            predecessor_key,
+            successor_key,
+            remainder_size,
+            probe,
+        )
+    }
 
-        // recompute cache
-        Self::compute_popcounts(&mut self.data, occupieds_start, runends_start, new_num_slots);
+    #[inline]
+    /// Number of stored infixes in [start_infix, end_infix] (inclusive)
+    pub fn count_in_range(&self, start_infix: u64, end_infix: u64) -> usize {
+        self.view().count_in_range(start_infix, end_infix)
     }
 
-    fn resize_up(&mut self) -> bool {
-        // fail if already at max size
-        if self.size_grade as usize >= SIZE_GRADE_COUNT - 1 {
-            return false;
-        }
-        self.resize_to(self.size_grade + 1);
-        true
+    /// Number of stored copies of `infix`, at most 1 unless the store is counting
+    pub fn count(&self, infix: u64) -> usize {
+        self.count_in_range(infix, infix)
     }
 
-    fn resize_down(&mut self) -> bool {
-        // fail if already at the min size
-        if self.size_grade == 0 {
-            return false;
-        }
-        self.resize_to(self.size_grade - 1);
-        true
+    /// Number of stored infixes matching a key in [start_key, end_key] (inclusive)
+    pub fn count_keys_in_range(
+        &self,
+        start_key: u64,
+        end_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+    ) -> usize {
+        self.view().count_keys_in_range(
+            start_key,
+            end_key,
+            predecessor_key,
+            successor_key,
+            remainder_size,
+        )
     }
 
-    /// shift all slots from start_pos to the right by 1 (for insertion)
-    fn shift_slots_right(&mut self, start_pos: usize) {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
-        let (_, _, slots_start) = self.get_offsets();
-        let slots_words =
-            (num_slots as usize * self.remainder_size as usize + U64_BITS - 1) / U64_BITS;
+    pub fn rank_occupieds_cached(&self, quotient: usize) -> usize {
+        self.view().rank_occupieds_cached(quotient)
+    }
 
-        for i in (start_pos..self.elem_count as usize).rev() {
-            let value = self.read_slot(i);
-            let slots_slice = &mut self.data[slots_start..slots_start + slots_words];
-            Self::write_slot(slots_slice, i + 1, value, self.remainder_size);
-        }
+    #[inline]
+    pub fn select_runends_cached(&self, rank_val: usize) -> Option<usize> {
+        self.view().select_runends_cached(rank_val)
     }
+}
 
-    /// shift all runend bits from start_pos to the right by 1 (for insertion)
-    fn shift_runends_right(&mut self, start_pos: usize) {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
-        let (_, runends_start, _) = self.get_offsets();
-        let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
-        let runends_slice = &mut self.data[runends_start..runends_start + runends_words];
+/// Work done by one InfixStore query, filled in by the `_probed` queries
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StoreProbe {
+    /// number of remainder slots read while scanning runs
+    pub slots_scanned: usize,
+    /// the answer came from the occupieds bitmap without reading any slot
+    pub occupieds_shortcut: bool,
+}
 
-        for i in (start_pos..self.elem_count as usize).rev() {
-            let bit_value = get_bit(runends_slice, i);
-            if bit_value {
-                set_bit(runends_slice, i + 1);
-            } else {
-                clear_bit(runends_slice, i + 1);
-            }
+/// Borrowed, read-only view of an InfixStore
+///
+/// Holds the store metadata plus a reference to its data words, so the same
+/// query code runs over an owned `InfixStore` or over words borrowed from a
+/// memory-mapped file (see `DivaView`). Views of an owned store also borrow its
+/// rank directories; views over raw words fall back to the popcounts cached at
+/// the midpoint of the bitmaps.
+#[derive(Debug, Clone, Copy)]
+pub struct InfixStoreRef<'a> {
+    elem_count: u16,
+    num_slots: u16,
+    remainder_size: u8,
+    quotient_size: u8,
+    data: &'a [u64],
+    // occupieds and runends rank directories
+    ranks: Option<(&'a RankDirectory, &'a RankDirectory)>,
+    // lazily deleted slots of a store with lazy deletes
+    tombstones: Option<&'a [u64]>,
+    // occupieds of a frozen store, which `data` leaves out
+    sparse_occupieds: Option<&'a [u16]>,
+}
+
+impl<'a> InfixStoreRef<'a> {
+    /// Create a view over raw data words, validating that the metadata matches the data length
+    pub fn new(
+        elem_count: u16,
+        num_slots: u16,
+        remainder_size: u8,
+        quotient_size: u8,
+        data: &'a [u64],
+    ) -> Option<Self> {
+        if num_slots == 0
+            || remainder_size > MAX_REMAINDER_SIZE
+            || quotient_size == 0
+            || quotient_size > MAX_QUOTIENT_SIZE
+            || elem_count > num_slots
+            || data.len() != data_words(num_slots, quotient_size, remainder_size)
+        {
+            return None;
         }
-        clear_bit(runends_slice, start_pos);
+        Some(Self {
+            elem_count,
+            num_slots,
+            remainder_size,
+            quotient_size,
+            data,
+            ranks: None,
+            tombstones: None,
+            sparse_occupieds: None,
+        })
     }
 
-    /// shift all slots to the left by 1 (after deletion)
-    fn shift_slots_left(&mut self, start_pos: usize) {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
-        let (_, _, slots_start) = self.get_offsets();
-        let slots_words =
-            (num_slots as usize * self.remainder_size as usize + U64_BITS - 1) / U64_BITS;
+    pub fn elem_count(&self) -> usize {
+        self.elem_count as usize
+    }
 
-        for i in start_pos..(self.elem_count as usize - 1) {
-            let value = self.read_slot(i + 1);
-            let slots_slice = &mut self.data[slots_start..slots_start + slots_words];
-            Self::write_slot(slots_slice, i, value, self.remainder_size);
-        }
+    pub fn remainder_size(&self) -> u8 {
+        self.remainder_size
     }
 
-    /// shift all runend bits to the left by 1 (after deletion)
-    fn shift_runends_left(&mut self, start_pos: usize) {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
-        let (_, runends_start, _) = self.get_offsets();
-        let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
-        let runends_slice = &mut self.data[runends_start..runends_start + runends_words];
+    pub fn num_slots(&self) -> usize {
+        self.num_slots as usize
+    }
 
-        for i in start_pos..(self.elem_count as usize - 1) {
-            let bit_value = get_bit(runends_slice, i + 1);
-            if bit_value {
-                set_bit(runends_slice, i);
-            } else {
-                clear_bit(runends_slice, i);
-            }
-        }
-        clear_bit(runends_slice, self.elem_count as usize - 1);
+    pub fn quotient_size(&self) -> u8 {
+        self.quotient_size
     }
 
     /// get memory layout offsets
+    #[inline]
     fn get_offsets(&self) -> (usize, usize, usize) {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
-        let occupieds_start = 1;
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
-        let runends_start = occupieds_start + occupieds_words;
-        let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
-        let slots_start = runends_start + runends_words;
-
-        (occupieds_start, runends_start, slots_start)
+        match self.sparse_occupieds {
+            Some(_) => get_offsets_without_occupieds(self.num_slots),
+            None => get_offsets(self.num_slots, self.quotient_size),
+        }
     }
 
     /// check if a quotient bit is set in occupieds
     pub fn is_occupied(&self, quotient: usize) -> bool {
+        if let Some(sparse) = self.sparse_occupieds {
+            return u16::try_from(quotient)
+                .is_ok_and(|quotient| sparse.binary_search(&quotient).is_ok());
+        }
         let (occupieds_start, _, _) = self.get_offsets();
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
+        let occupieds_words = occupieds_words(self.quotient_size);
         let occupieds_slice = &self.data[occupieds_start..occupieds_start + occupieds_words];
         get_bit(occupieds_slice, quotient)
     }
 
     /// check if a slot position has runend bit set
     pub fn is_runend(&self, slot_pos: usize) -> bool {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
+        let num_slots = self.num_slots;
         let (_, runends_start, _) = self.get_offsets();
         let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
         let runends_slice = &self.data[runends_start..runends_start + runends_words];
@@ -513,48 +1631,119 @@ impl InfixStore {
 
     /// read remainder value from a specific slot
     pub fn read_slot(&self, slot_index: usize) -> u64 {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
+        let num_slots = self.num_slots;
         let (_, _, slots_start) = self.get_offsets();
         let slots_words =
             (num_slots as usize * self.remainder_size as usize + U64_BITS - 1) / U64_BITS;
         let slots_slice = &self.data[slots_start..slots_start + slots_words];
 
-        let bit_pos = slot_index * self.remainder_size as usize;
-        let word_index = bit_pos / U64_BITS;
-        let bit_offset = bit_pos % U64_BITS;
+        let remainder_size = self.remainder_size as usize;
+        read_bits(slots_slice, slot_index * remainder_size, remainder_size)
+    }
+
+    /// check if a slot was lazily deleted
+    pub fn is_tombstone(&self, slot_pos: usize) -> bool {
+        self.tombstones.is_some_and(|bits| get_bit(bits, slot_pos))
+    }
 
-        let mut result =
-            (slots_slice[word_index] >> bit_offset) & ((1u64 << self.remainder_size) - 1);
+    /// number of lazily deleted slots in [start, end)
+    fn tombstones_in(&self, start: usize, end: usize) -> usize {
+        self.tombstones
+            .map_or(0, |bits| rank(bits, end) - rank(bits, start))
+    }
 
-        // handle overflow from next word if needed
-        if bit_offset + self.remainder_size as usize > U64_BITS {
-            let overflow_bits = (bit_offset + self.remainder_size as usize) - U64_BITS;
-            let overflow_mask = (1u64 << overflow_bits) - 1;
-            let overflow_value = slots_slice[word_index + 1] & overflow_mask;
-            result |= overflow_value << (self.remainder_size as usize - overflow_bits);
+    /// Iterate over the stored `(quotient, remainder)` pairs in slot order,
+    /// which is ascending infix order
+    pub fn iter(&self) -> InfixIter<'a> {
+        let mut occupieds = self.occupied_quotients();
+        InfixIter {
+            store: *self,
+            slot: 0,
+            end: self.elem_count(),
+            quotient: occupieds.next().unwrap_or(1 << self.quotient_size),
+            occupieds,
         }
+    }
 
-        result
+    /// Iterate over the `(quotient, remainder)` pairs of one quotient's run;
+    /// empty if the quotient is not occupied
+    pub fn iter_run(&self, quotient: usize) -> InfixIter<'a> {
+        let bounds = if quotient < 1 << self.quotient_size && self.is_occupied(quotient) {
+            self.get_run_bounds(quotient)
+        } else {
+            None
+        };
+        let (slot, end) = bounds.map_or((0, 0), |(run_start, run_end)| (run_start, run_end + 1));
+        InfixIter {
+            store: *self,
+            slot,
+            end,
+            quotient,
+            // the iterator stops at the end of the run
+            occupieds: OccupiedQuotients(Occupieds::Plain(iter_ones(&[]))),
+        }
     }
 
-    pub fn elem_count(&self) -> usize {
-        self.elem_count as usize
+    /// Iterate over the occupied quotients in ascending order
+    pub fn occupied_quotients(&self) -> OccupiedQuotients<'a> {
+        match self.sparse_occupieds {
+            Some(sparse) => OccupiedQuotients(Occupieds::Sparse(sparse.iter())),
+            None => {
+                let (occupieds_start, runends_start, _) = self.get_offsets();
+                let plain = iter_ones(&self.data[occupieds_start..runends_start]);
+                OccupiedQuotients(Occupieds::Plain(plain))
+            }
+        }
     }
 
-    pub fn size_grade(&self) -> u8 {
-        self.size_grade
+    /// Iterate over the slots ending a run in ascending order, one per
+    /// occupied quotient
+    pub fn runend_slots(&self) -> Ones<'a> {
+        let (_, runends_start, slots_start) = self.get_offsets();
+        iter_ones(&self.data[runends_start..slots_start])
     }
 
-    pub fn remainder_size(&self) -> u8 {
-        self.remainder_size
+    /// Copy of the occupieds bitmap in its smallest read-only encoding
+    ///
+    /// A sparse partition's occupieds come out Elias-Fano encoded, in a few
+    /// bytes instead of 2^quotient_size bits, with the same rank/select
+    /// answers. The store itself keeps the plain bitmap, which inserts and
+    /// deletes update in place and memory-mapped filters read directly, unless
+    /// it was frozen with `InfixStore::freeze_occupieds`.
+    pub fn compact_occupieds(&self) -> CompactBitmap {
+        if let Some(sparse) = self.sparse_occupieds {
+            let ones = sparse.iter().map(|&quotient| quotient as usize);
+            return CompactBitmap::Sparse(EliasFano::from_ones(ones, 1 << self.quotient_size));
+        }
+        let (occupieds_start, runends_start, _) = self.get_offsets();
+        CompactBitmap::from_words(
+            &self.data[occupieds_start..runends_start],
+            1 << self.quotient_size,
+        )
     }
 
-    pub fn num_slots(&self) -> usize {
-        SCALED_SIZES[self.size_grade as usize] as usize
+    /// first occupied quotient at or after `quotient`, or the number of quotients
+    fn next_occupied(&self, quotient: usize) -> usize {
+        if let Some(sparse) = self.sparse_occupieds {
+            return sparse
+                .get(sparse_rank(sparse, quotient))
+                .map_or(1 << self.quotient_size, |&quotient| quotient as usize);
+        }
+        let (occupieds_start, _, _) = self.get_offsets();
+        let occupieds_words = occupieds_words(self.quotient_size);
+        let occupieds_slice = &self.data[occupieds_start..occupieds_start + occupieds_words];
+        next_set_bit(occupieds_slice, quotient).unwrap_or(1 << self.quotient_size)
     }
 
-    pub fn pretty_print(&self) {
-        print!("{}", self);
+    /// whether any quotient in [start, end) is occupied
+    fn has_occupied_in(&self, start: usize, end: usize) -> bool {
+        if let Some(sparse) = self.sparse_occupieds {
+            return sparse_rank(sparse, end) > sparse_rank(sparse, start);
+        }
+        let (occupieds_start, _, _) = self.get_offsets();
+        let occupieds_words = occupieds_words(self.quotient_size);
+        let occupieds_slice = &self.data[occupieds_start..occupieds_start + occupieds_words];
+        crate::has_bits_in_range(occupieds_slice, start, end)
     }
 
     /// Function to convert a key to infix using consistent extraction logic
@@ -566,12 +1755,14 @@ impl InfixStore {
         successor_key: u64,
         remainder_size: u8,
     ) -> u64 {
-        use crate::diva::Diva;
-
-        let (shared_prefix_len, redundant_bits, quotient_bits) =
-            Diva::get_shared_ignore_implicit_size(&predecessor_key, &successor_key, false);
+        let (shared_prefix_len, redundant_bits, quotient_bits) = shared_ignore_implicit_size(
+            &predecessor_key,
+            &successor_key,
+            false,
+            self.quotient_size,
+        );
 
-        Diva::extract_partial_key(
+        extract_partial_key(
             key,
             shared_prefix_len,
             redundant_bits,
@@ -593,24 +1784,49 @@ impl InfixStore {
         predecessor_key: u64,
         successor_key: u64,
         remainder_size: u8,
+    ) -> bool {
+        self.point_query_probed(
+            query_key,
+            predecessor_key,
+            successor_key,
+            remainder_size,
+            &mut StoreProbe::default(),
+        )
+    }
+
+    /// `point_query` that also records the work done in `probe`
+    pub fn point_query_probed(
+        &self,
+        query_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+        probe: &mut StoreProbe,
     ) -> bool {
         let infix =
             self.convert_key_to_infix(query_key, predecessor_key, successor_key, remainder_size);
 
-        let (quotient, remainder) = Self::split_infix(infix, self.quotient_size, self.remainder_size);
+        let (quotient, remainder) =
+            InfixStore::split_infix(infix, self.quotient_size, self.remainder_size);
 
         // Check if quotient exists in occupieds bitmap
         if !self.is_occupied(quotient as usize) {
+            probe.occupieds_shortcut = true;
             return false;
         }
 
         // Find the run for this quotient and scan for exact remainder match
-        let result = self.find_remainder_in_run(quotient as usize, remainder);
+        let result = self.find_remainder_in_run(quotient as usize, remainder, probe);
         result
     }
 
     /// Find a specific remainder value within a quotient's run
-    fn find_remainder_in_run(&self, quotient: usize, target_remainder: u64) -> bool {
+    fn find_remainder_in_run(
+        &self,
+        quotient: usize,
+        target_remainder: u64,
+        probe: &mut StoreProbe,
+    ) -> bool {
         let (run_start, run_end) = match self.get_run_bounds(quotient) {
             Some(bounds) => bounds,
             None => {
@@ -618,14 +1834,54 @@ impl InfixStore {
             }
         };
 
-        for pos in run_start..=run_end {
-            let remainder = self.read_slot(pos);
-            if remainder == target_remainder {
-                return true;
+        self.find_live_slot(run_start, run_end, target_remainder, probe)
+            .is_some()
+    }
+
+    /// First slot in [run_start, run_end] holding `remainder` that is not a tombstone
+    fn find_live_slot(
+        &self,
+        run_start: usize,
+        run_end: usize,
+        remainder: u64,
+        probe: &mut StoreProbe,
+    ) -> Option<usize> {
+        let mut slot = self.search_run(run_start, run_end + 1, remainder, probe);
+        while slot <= run_end {
+            probe.slots_scanned += 1;
+            if self.read_slot(slot) != remainder {
+                break;
+            }
+            if !self.is_tombstone(slot) {
+                return Some(slot);
             }
+            slot += 1;
         }
+        None
+    }
 
-        false
+    /// First slot in [start, end) whose remainder is at least `remainder`, or `end`
+    ///
+    /// The slots must lie within one run, where remainders are sorted, so this
+    /// is a binary search reading about log2(end - start) slots.
+    fn search_run(
+        &self,
+        start: usize,
+        end: usize,
+        remainder: u64,
+        probe: &mut StoreProbe,
+    ) -> usize {
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let mid = (low + high) / 2;
+            probe.slots_scanned += 1;
+            if self.read_slot(mid) < remainder {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
     }
 
     /// Range query: check if any key exists in the given range [start_key, end_key] (inclusive)
@@ -643,6 +1899,26 @@ impl InfixStore {
         predecessor_key: u64,
         successor_key: u64,
         remainder_size: u8,
+    ) -> bool {
+        self.range_query_probed(
+            start_key,
+            end_key,
+            predecessor_key,
+            successor_key,
+            remainder_size,
+            &mut StoreProbe::default(),
+        )
+    }
+
+    /// `range_query` that also records the work done in `probe`
+    pub fn range_query_probed(
+        &self,
+        start_key: u64,
+        end_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+        probe: &mut StoreProbe,
     ) -> bool {
         if start_key > end_key {
             return false;
@@ -655,8 +1931,10 @@ impl InfixStore {
             self.convert_key_to_infix(end_key, predecessor_key, successor_key, remainder_size);
 
         // Split infixes into quotients and remainders
-        let (start_quotient, start_remainder) = Self::split_infix(start_infix, self.quotient_size, remainder_size);
-        let (end_quotient, end_remainder) = Self::split_infix(end_infix, self.quotient_size, remainder_size);
+        let (start_quotient, start_remainder) =
+            InfixStore::split_infix(start_infix, self.quotient_size, remainder_size);
+        let (end_quotient, end_remainder) =
+            InfixStore::split_infix(end_infix, self.quotient_size, remainder_size);
 
         // Handle the two main cases
         if start_quotient == end_quotient {
@@ -665,6 +1943,7 @@ impl InfixStore {
                 start_quotient as usize,
                 start_remainder,
                 end_remainder,
+                probe,
             )
         } else {
             // Case 2: Range spans multiple quotients
@@ -673,6 +1952,7 @@ impl InfixStore {
                 start_remainder,
                 end_quotient as usize,
                 end_remainder,
+                probe,
             )
         }
     }
@@ -683,15 +1963,17 @@ impl InfixStore {
         quotient: usize,
         start_remainder: u64,
         end_remainder: u64,
+        probe: &mut StoreProbe,
     ) -> bool {
         // Check if quotient exists
         if !self.is_occupied(quotient) {
-            // println!("    Quotient {} not occupied", quotient);
+            trace!("quotient {} not occupied", quotient);
+            probe.occupieds_shortcut = true;
             return false;
         }
 
         // Find the run and scan for any remainder in the range [start_remainder, end_remainder]
-        self.scan_run_for_range(quotient, start_remainder, end_remainder)
+        self.scan_run_for_range(quotient, start_remainder, end_remainder, probe)
     }
 
     /// Handle range query when endpoints map to different quotients
@@ -701,30 +1983,33 @@ impl InfixStore {
         start_remainder: u64,
         end_quotient: usize,
         end_remainder: u64,
+        probe: &mut StoreProbe,
     ) -> bool {
         // Check for any occupied quotients strictly between start_quotient and end_quotient
-        if start_quotient + 1 < end_quotient {
-            let (occupieds_start, _, _) = self.get_offsets();
-            let occupieds_words = (TARGET_SIZE as usize + crate::U64_BITS - 1) / crate::U64_BITS;
-            let occupieds_slice = &self.data[occupieds_start..occupieds_start + occupieds_words];
+        if start_quotient + 1 < end_quotient
+            && self.has_occupied_in(start_quotient + 1, end_quotient)
+        {
+            probe.occupieds_shortcut = true;
+            return true; // All remainders in intermediate quotients are within range
+        }
 
-            if crate::has_bits_in_range(occupieds_slice, start_quotient + 1, end_quotient) {
-                return true; // All remainders in intermediate quotients are within range
-            }
+        let start_occupied = self.is_occupied(start_quotient);
+        let end_occupied = self.is_occupied(end_quotient);
+        if !start_occupied && !end_occupied {
+            probe.occupieds_shortcut = true;
+            return false;
         }
 
         // Check start quotient for remainders >= start_remainder
-        if self.is_occupied(start_quotient) {
-            if self.scan_run_from_remainder(start_quotient, start_remainder, true) {
-                return true;
-            }
+        if start_occupied
+            && self.scan_run_from_remainder(start_quotient, start_remainder, true, probe)
+        {
+            return true;
         }
 
         // Check end quotient for remainders <= end_remainder
-        if self.is_occupied(end_quotient) {
-            if self.scan_run_from_remainder(end_quotient, end_remainder, false) {
-                return true;
-            }
+        if end_occupied && self.scan_run_from_remainder(end_quotient, end_remainder, false, probe) {
+            return true;
         }
 
         false
@@ -736,33 +2021,22 @@ impl InfixStore {
         quotient: usize,
         start_remainder: u64,
         end_remainder: u64,
+        probe: &mut StoreProbe,
     ) -> bool {
         let (run_start, run_end) = match self.get_run_bounds(quotient) {
             Some(bounds) => bounds,
             None => return false,
         };
 
-        let mut pos = run_end;
-        loop {
-            let remainder = self.read_slot(pos);
-
-            // Check if remainder is in our target range
-            if remainder >= start_remainder && remainder <= end_remainder {
-                return true;
-            }
-
-            if remainder < start_remainder {
-                return false;
-            }
-
-            // Continue to previous position if we haven't reached the start
-            if pos == run_start {
-                break;
-            }
-            pos -= 1;
-        }
-
-        false
+        // Scan from the largest remainder down, stopping below start_remainder
+        self.scan_run(
+            run_start,
+            run_end,
+            start_remainder,
+            end_remainder,
+            true,
+            probe,
+        )
     }
 
     /// Scan a quotient's run for remainders >= threshold (if ascending) or <= threshold (if !ascending)
@@ -772,6 +2046,7 @@ impl InfixStore {
         quotient: usize,
         threshold_remainder: u64,
         ascending: bool,
+        probe: &mut StoreProbe,
     ) -> bool {
         let (run_start, run_end) = match self.get_run_bounds(quotient) {
             Some(bounds) => bounds,
@@ -780,34 +2055,150 @@ impl InfixStore {
 
         if ascending {
             // For ascending queries (>= threshold), scan left-to-right
-            for pos in run_start..=run_end {
-                let remainder = self.read_slot(pos);
-
-                if remainder >= threshold_remainder {
-                    return true;
-                }
-            }
+            self.scan_run(
+                run_start,
+                run_end,
+                threshold_remainder,
+                u64::MAX,
+                false,
+                probe,
+            )
         } else {
             // For descending queries (<= threshold), scan right-to-left
-            let mut pos = run_end;
-            loop {
-                let remainder = self.read_slot(pos);
+            self.scan_run(run_start, run_end, 0, threshold_remainder, true, probe)
+        }
+    }
 
-                if remainder <= threshold_remainder {
+    /// Whether any remainder in slots [run_start, run_end] lies in [low, high]
+    ///
+    /// Unpacks the run `run_scan::CHUNK` slots at a time, from the back if
+    /// `from_back`, and compares each chunk with `run_scan::any_in_range`.
+    /// Remainders in a run are sorted, so the scan stops once a chunk has
+    /// passed the range.
+    fn scan_run(
+        &self,
+        run_start: usize,
+        run_end: usize,
+        low: u64,
+        high: u64,
+        from_back: bool,
+        probe: &mut StoreProbe,
+    ) -> bool {
+        let slots = self.slots();
+        let mut chunk = [0u64; run_scan::CHUNK];
+        let mut remaining = run_end + 1 - run_start;
+        while remaining > 0 {
+            let count = remaining.min(run_scan::CHUNK);
+            let first = if from_back {
+                run_start + remaining - count
+            } else {
+                run_end + 1 - remaining
+            };
+            remaining -= count;
+
+            run_scan::unpack(slots, first, count, self.remainder_size, &mut chunk);
+            probe.slots_scanned += count;
+            let values = &chunk[..count];
+            if run_scan::any_in_range(values, low, high) {
+                // a hit only counts if some matching slot was not lazily deleted
+                let dead = self
+                    .tombstones
+                    .map_or(0, |bits| read_bits(bits, first, count));
+                if dead == 0
+                    || values
+                        .iter()
+                        .enumerate()
+                        .any(|(i, &value)| (dead >> i) & 1 == 0 && low <= value && value <= high)
+                {
                     return true;
                 }
-
-                // Continue to previous position if we haven't reached the start
-                if pos == run_start {
-                    break;
-                }
-                pos -= 1;
+            }
+            let passed = if from_back {
+                values[0] < low
+            } else {
+                values[count - 1] > high
+            };
+            if passed {
+                return false;
             }
         }
-
         false
     }
 
+    /// the bit-packed remainder slots
+    fn slots(&self) -> &'a [u64] {
+        let (_, _, slots_start) = self.get_offsets();
+        let slots_words =
+            (self.num_slots as usize * self.remainder_size as usize).div_ceil(U64_BITS);
+        &self.data[slots_start..slots_start + slots_words]
+    }
+
+    /// Number of stored infixes in [start_infix, end_infix] (inclusive)
+    ///
+    /// Runs are stored back to back in quotient order, so the count is the
+    /// difference of two slot positions found by rank/select on the bitmaps
+    /// and a binary search within one run each.
+    pub fn count_in_range(&self, start_infix: u64, end_infix: u64) -> usize {
+        if start_infix > end_infix {
+            return 0;
+        }
+        let end = match end_infix.checked_add(1) {
+            Some(next_infix) => self.lower_bound(next_infix),
+            None => self.elem_count(),
+        };
+        let start = self.lower_bound(start_infix);
+        end - start - self.tombstones_in(start, end)
+    }
+
+    /// Number of stored infixes matching a key in [start_key, end_key] (inclusive)
+    pub fn count_keys_in_range(
+        &self,
+        start_key: u64,
+        end_key: u64,
+        predecessor_key: u64,
+        successor_key: u64,
+        remainder_size: u8,
+    ) -> usize {
+        if start_key > end_key {
+            return 0;
+        }
+        let to_infix = |key| {
+            let infix =
+                self.convert_key_to_infix(key, predecessor_key, successor_key, remainder_size);
+            let (quotient, remainder) =
+                InfixStore::split_infix(infix, self.quotient_size, remainder_size);
+            join_infix(quotient, remainder, remainder_size)
+        };
+        self.count_in_range(to_infix(start_key), to_infix(end_key))
+    }
+
+    /// number of slots holding an infix smaller than `infix`, tombstones included
+    fn lower_bound(&self, infix: u64) -> usize {
+        let quotient = infix_quotient(infix, self.remainder_size);
+        if quotient >= 1 << self.quotient_size {
+            return self.elem_count();
+        }
+        let quotient = quotient as usize;
+        let remainder = infix & remainder_mask(self.remainder_size);
+
+        // the runs of all smaller quotients fill the slots before this quotient's run
+        let rank = self.rank_occupieds_cached(quotient);
+        let run_start = match rank {
+            0 => 0,
+            _ => self
+                .select_runends_cached(rank - 1)
+                .map_or(self.elem_count(), |pos| pos + 1),
+        };
+        if !self.is_occupied(quotient) {
+            return run_start;
+        }
+        let run_end = self
+            .select_runends_cached(rank)
+            .map_or(self.elem_count(), |pos| pos + 1);
+
+        self.search_run(run_start, run_end, remainder, &mut StoreProbe::default())
+    }
+
     /// Get the start and end positions of a quotient's run
     fn get_run_bounds(&self, quotient: usize) -> Option<(usize, usize)> {
         // Get the rank of this quotient (how many quotients before it)
@@ -819,11 +2210,11 @@ impl InfixStore {
             None => return None,
         };
 
-        // Find the start of this run by looking backwards
-        let mut run_start = run_end;
-        while run_start > 0 && !self.is_runend(run_start - 1) {
-            run_start -= 1;
-        }
+        // The run starts right after the previous run's end
+        let run_start = match rank_result {
+            0 => 0,
+            _ => self.select_runends_cached(rank_result - 1)? + 1,
+        };
 
         Some((run_start, run_end))
     }
@@ -842,29 +2233,106 @@ impl InfixStore {
 
     #[inline]
     pub fn rank_occupieds_cached(&self, quotient: usize) -> usize {
+        if let Some(sparse) = self.sparse_occupieds {
+            return sparse_rank(sparse, quotient);
+        }
         let (occupieds_start, _, _) = self.get_offsets();
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
+        let occupieds_words = occupieds_words(self.quotient_size);
         let occupieds_slice = &self.data[occupieds_start..occupieds_start + occupieds_words];
+        if let Some((occupieds_ranks, _)) = self.ranks {
+            return occupieds_ranks.rank(occupieds_slice, quotient);
+        }
         let cached_popcount = self.get_popcount_occupieds() as usize;
-        let half_pos = CACHE_BOUNDARY as usize;
+        let half_pos = cache_boundary(self.quotient_size);
         rank_cached(occupieds_slice, quotient, half_pos, cached_popcount)
     }
 
     #[inline]
     pub fn select_runends_cached(&self, rank_val: usize) -> Option<usize> {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
+        let num_slots = self.num_slots;
         let (_, runends_start, _) = self.get_offsets();
         let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
         let runends_slice = &self.data[runends_start..runends_start + runends_words];
+        if let Some((_, runends_ranks)) = self.ranks {
+            return runends_ranks.select(runends_slice, rank_val);
+        }
         let cached_popcount = self.get_popcount_runends() as usize;
-        let half_pos = CACHE_BOUNDARY as usize;
+        let half_pos = runends_cache_boundary(self.num_slots, self.quotient_size);
         select_cached(runends_slice, rank_val, half_pos, cached_popcount)
     }
 }
 
+/// Iterator over `(quotient, remainder)` pairs, see `InfixStoreRef::iter`
+#[derive(Debug, Clone)]
+pub struct InfixIter<'a> {
+    store: InfixStoreRef<'a>,
+    slot: usize,
+    // one past the last slot to yield
+    end: usize,
+    // quotient owning `slot`
+    quotient: usize,
+    // occupied quotients after `quotient`
+    occupieds: OccupiedQuotients<'a>,
+}
+
+impl Iterator for InfixIter<'_> {
+    type Item = (u64, u64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.slot < self.end {
+            let slot = self.slot;
+            let quotient = self.quotient;
+            if self.store.is_runend(slot) {
+                self.quotient = self.occupieds.next().unwrap_or(quotient);
+            }
+            self.slot += 1;
+            if !self.store.is_tombstone(slot) {
+                return Some((quotient as u64, self.store.read_slot(slot)));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end.saturating_sub(self.slot);
+        let len = len - self.store.tombstones_in(self.slot, self.slot + len);
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for InfixIter<'_> {}
+
+impl core::iter::FusedIterator for InfixIter<'_> {}
+
+/// Iterator over the occupied quotients of a store, see
+/// `InfixStoreRef::occupied_quotients`
+#[derive(Debug, Clone)]
+pub struct OccupiedQuotients<'a>(Occupieds<'a>);
+
+#[derive(Debug, Clone)]
+enum Occupieds<'a> {
+    Plain(Ones<'a>),
+    // occupied quotients of a frozen store
+    Sparse(core::slice::Iter<'a, u16>),
+}
+
+impl Iterator for OccupiedQuotients<'_> {
+    type Item = usize;
+
+    #[inline]
+    fn next(&mut self) -> Option<usize> {
+        match &mut self.0 {
+            Occupieds::Plain(ones) => ones.next(),
+            Occupieds::Sparse(quotients) => quotients.next().map(|&quotient| quotient as usize),
+        }
+    }
+}
+
+impl core::iter::FusedIterator for OccupiedQuotients<'_> {}
+
 impl fmt::Display for InfixStore {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let num_slots = SCALED_SIZES[self.size_grade as usize];
+        let num_slots = self.layout.size_grades()[self.size_grade as usize];
 
         writeln!(f, "*** InfixStore ***")?;
         writeln!(f, "elem_count: {}", self.elem_count)?;
@@ -874,19 +2342,14 @@ impl fmt::Display for InfixStore {
         writeln!(f)?;
 
         writeln!(f, "popcounts: 0x{:016x}", self.data[0])?;
-        let occupieds_popcount = (self.data[0] & 0xFFFFFFFF) as u32;
-        let runends_popcount = (self.data[0] >> 32) as u32;
+        let occupieds_popcount = (self.data[0] >> 32) as u32;
+        let runends_popcount = (self.data[0] & 0xFFFFFFFF) as u32;
         writeln!(f, "  occupieds_popcount: {}", occupieds_popcount)?;
         writeln!(f, "  runends_popcount: {}", runends_popcount)?;
         writeln!(f)?;
 
         writeln!(f, "occupieds bitmap (showing set quotients):")?;
-        let mut occupied_quotients = Vec::new();
-        for q in 0..TARGET_SIZE as usize {
-            if self.is_occupied(q) {
-                occupied_quotients.push(q);
-            }
-        }
+        let occupied_quotients: Vec<usize> = self.occupied_quotients().collect();
         if occupied_quotients.is_empty() {
             writeln!(f, "  (none)")?;
         } else {
@@ -900,13 +2363,8 @@ impl fmt::Display for InfixStore {
         }
         writeln!(f)?;
 
-        writeln!(f, "runends bitmap (showing runend positions):")?;
-        let mut runend_positions = Vec::new();
-        for pos in 0..num_slots as usize {
-            if self.is_runend(pos) {
-                runend_positions.push(pos);
-            }
-        }
+        writeln!(f, "runends bitmap (showing runend positions):")?;
+        let runend_positions: Vec<usize> = self.runend_slots().collect();
         if runend_positions.is_empty() {
             writeln!(f, "  (none)")?;
         } else {
@@ -941,6 +2399,148 @@ impl fmt::Display for InfixStore {
     }
 }
 
+/// Machine-readable exports of the layout, for visualizers and debugging
+///
+/// `Display` is meant for humans; these describe the same state in formats
+/// other tools parse. Only the first `elem_count` slots are exported.
+impl InfixStore {
+    /// JSON object with the store metadata, the set occupieds quotients, the
+    /// set runends positions, the lazily deleted slots, the run of every
+    /// occupied quotient as `{"quotient", "start", "end"}` (inclusive slots)
+    /// and the remainder of every used slot
+    ///
+    /// All values are JSON integers; remainders wider than 53 bits do not
+    /// survive a round trip through JavaScript numbers.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Graphviz DOT digraph of the run structure: one node per occupied
+    /// quotient pointing at a record node with the slots of its run, and the
+    /// runs chained in slot order
+    ///
+    /// Render with e.g. `dot -Tsvg store.dot -o store.svg`.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        self.write_dot(&mut out)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// (quotient, first slot, last slot) of every run, in slot order
+    fn runs(&self) -> Vec<(usize, usize, usize)> {
+        let mut run_start = 0;
+        self.occupied_quotients()
+            .zip(self.runend_slots())
+            .map(|(quotient, run_end)| {
+                let run = (quotient, run_start, run_end);
+                run_start = run_end + 1;
+                run
+            })
+            .collect()
+    }
+
+    fn write_json(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        fn write_list(
+            out: &mut impl fmt::Write,
+            values: impl Iterator<Item = impl fmt::Display>,
+        ) -> fmt::Result {
+            out.write_char('[')?;
+            for (i, value) in values.enumerate() {
+                if i > 0 {
+                    out.write_char(',')?;
+                }
+                write!(out, "{}", value)?;
+            }
+            out.write_char(']')
+        }
+
+        let num_slots = self.num_slots();
+        write!(
+            out,
+            "{{\"elem_count\":{},\"num_slots\":{},\"size_grade\":{},\"quotient_size\":{},\
+             \"remainder_size\":{},\"counting\":{},\"lazy_delete\":{},",
+            self.elem_count,
+            num_slots,
+            self.size_grade,
+            self.quotient_size(),
+            self.remainder_size,
+            self.counting,
+            self.is_lazy_delete()
+        )?;
+        out.write_str("\"occupieds\":")?;
+        write_list(out, self.occupied_quotients())?;
+        out.write_str(",\"runends\":")?;
+        write_list(out, self.runend_slots())?;
+        out.write_str(",\"tombstones\":")?;
+        write_list(out, self.tombstones.iter().flat_map(BitVec::iter_ones))?;
+        out.write_str(",\"runs\":")?;
+        write_list(
+            out,
+            self.runs().into_iter().map(|(quotient, start, end)| {
+                format!(
+                    "{{\"quotient\":{},\"start\":{},\"end\":{}}}",
+                    quotient, start, end
+                )
+            }),
+        )?;
+        out.write_str(",\"slots\":")?;
+        write_list(
+            out,
+            (0..self.elem_count as usize).map(|slot| self.read_slot(slot)),
+        )?;
+        out.write_char('}')
+    }
+
+    fn write_dot(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        let view = self.view();
+        writeln!(out, "digraph infix_store {{")?;
+        writeln!(out, "  rankdir=LR;")?;
+        writeln!(
+            out,
+            "  label=\"{} of {} slots, {}-bit quotients, {}-bit remainders\";",
+            self.elem_count,
+            self.num_slots(),
+            self.quotient_size(),
+            self.remainder_size
+        )?;
+        writeln!(out, "  node [shape=record];")?;
+
+        let runs = self.runs();
+        for &(quotient, start, end) in &runs {
+            writeln!(
+                out,
+                "  q{} [shape=ellipse, label=\"q {}\"];",
+                quotient, quotient
+            )?;
+            write!(out, "  run{} [label=\"", quotient)?;
+            for slot in start..=end {
+                if slot > start {
+                    out.write_char('|')?;
+                }
+                write!(out, "<s{}> {}: {}", slot, slot, self.read_slot(slot))?;
+                if view.is_tombstone(slot) {
+                    out.write_str(" (deleted)")?;
+                }
+            }
+            writeln!(out, "\"];")?;
+            writeln!(out, "  q{} -> run{};", quotient, quotient)?;
+        }
+        // runs are stored back to back in quotient order
+        for pair in runs.windows(2) {
+            writeln!(
+                out,
+                "  run{} -> run{} [style=dotted];",
+                pair[0].0, pair[1].0
+            )?;
+        }
+        writeln!(out, "}}")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1080,12 +2680,402 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_boundary_remainder_sizes() {
+        for remainder_size in [0u8, 1, 63, 64] {
+            let mask = remainder_mask(remainder_size);
+            assert_eq!(mask.count_ones(), remainder_size as u32);
+
+            // slot codec: neighbouring slots survive writes that straddle words
+            let mut slots = vec![0u64; 4 * 64];
+            for slot in 0..64 {
+                InfixStore::write_slot(&mut slots, slot, u64::MAX, remainder_size);
+            }
+            InfixStore::write_slot(&mut slots, 3, 0, remainder_size);
+            InfixStore::write_slot(&mut slots, 5, 0x5555_5555_5555_5555, remainder_size);
+            let mut data = vec![0u64; data_words(64, QUOTIENT_SIZE, remainder_size)];
+            let (_, _, slots_start) = get_offsets(64, QUOTIENT_SIZE);
+            let slots_len = data.len() - slots_start;
+            data[slots_start..].copy_from_slice(&slots[..slots_len]);
+            let view = InfixStoreRef::new(0, 64, remainder_size, QUOTIENT_SIZE, &data).unwrap();
+            for slot in 0..64 {
+                let expected = match slot {
+                    3 => 0,
+                    5 => 0x5555_5555_5555_5555 & mask,
+                    _ => mask,
+                };
+                assert_eq!(view.read_slot(slot), expected);
+            }
+
+            // split and join round-trip
+            for infix in [
+                0,
+                1,
+                mask,
+                join_infix(0x3FF, mask, remainder_size),
+                u64::MAX,
+            ] {
+                let (quotient, remainder) =
+                    InfixStore::split_infix(infix, QUOTIENT_SIZE, remainder_size);
+                assert_eq!(remainder, infix & mask);
+                assert_eq!(quotient, infix_quotient(infix, remainder_size) & 0x3FF);
+                if infix_quotient(infix, remainder_size) < 1 << QUOTIENT_SIZE {
+                    assert_eq!(join_infix(quotient, remainder, remainder_size), infix);
+                }
+            }
+
+            // a store of distinct infixes survives inserts and deletes
+            let mut infixes: Vec<u64> = (0..60u64)
+                .map(|i| {
+                    let quotient = i * 17 % 1024;
+                    let remainder = i.wrapping_mul(0x9E37_79B9_7F4A_7C15) & mask;
+                    join_infix(quotient, remainder, remainder_size)
+                })
+                .collect();
+            infixes.sort_unstable();
+            infixes.dedup();
+            let (initial, inserted) = infixes.split_at(infixes.len() / 2);
+            let mut store = InfixStore::new_with_infixes(initial, remainder_size);
+            for &infix in inserted {
+                store.insert(infix).unwrap();
+            }
+            assert_eq!(store.infixes(), infixes);
+            verify_cache_integrity(&store);
+            for &infix in &infixes {
+                assert_eq!(store.view().count_in_range(infix, infix), 1);
+            }
+            for &infix in initial {
+                store.delete(infix).unwrap();
+            }
+            assert_eq!(store.infixes(), inserted);
+            verify_cache_integrity(&store);
+        }
+    }
+
+    #[test]
+    fn test_compact_occupieds() {
+        use crate::rank_select::RankSelectOps;
+
+        // three quotients of 1024 encode sparsely, every other quotient densely
+        let sparse = InfixStore::new_with_infixes(&[(7 << 8) | 1, (300 << 8) | 2, 1000 << 8], 8);
+        let dense_infixes: Vec<u64> = (0..512).map(|q| (q * 2) << 8).collect();
+        let dense = InfixStore::new_with_infixes(&dense_infixes, 8);
+        for (store, is_sparse) in [(&sparse, true), (&dense, false)] {
+            let occupieds = store.compact_occupieds();
+            assert_eq!(occupieds.is_sparse(), is_sparse);
+            assert_eq!(occupieds.len(), 1024);
+            for quotient in 0..1024 {
+                assert_eq!(occupieds.get(quotient), store.is_occupied(quotient));
+                assert_eq!(
+                    occupieds.rank(quotient),
+                    store.rank_occupieds_cached(quotient)
+                );
+            }
+            assert!(
+                store
+                    .occupied_quotients()
+                    .enumerate()
+                    .all(|(rank_val, quotient)| occupieds.select(rank_val) == Some(quotient))
+            );
+        }
+        assert!(sparse.compact_occupieds().heap_size() < 1024 / 8);
+    }
+
+    #[test]
+    fn test_frozen_occupieds_answer_like_plain_ones() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        // keys of the partition (0, MAX) keep their top 10 + 8 bits as infixes
+        let mut infixes: Vec<u64> = (0..12).map(|_| next() >> 46).collect();
+        infixes.sort_unstable();
+        let plain = InfixStore::new_with_infixes(&infixes, 8);
+        let mut frozen = plain.clone();
+        assert!(frozen.freeze_occupieds() && frozen.is_occupieds_frozen());
+        assert!(frozen.size_in_bytes() < plain.size_in_bytes());
+        assert_eq!(frozen.data(), plain.data());
+        assert!(frozen.validate().is_ok());
+        assert!(frozen.iter().eq(plain.iter()));
+        assert!(frozen.occupied_quotients().eq(plain.occupied_quotients()));
+        for quotient in 0..1024 {
+            assert_eq!(frozen.is_occupied(quotient), plain.is_occupied(quotient));
+            assert_eq!(
+                frozen.rank_occupieds_cached(quotient),
+                plain.rank_occupieds_cached(quotient)
+            );
+        }
+        for _ in 0..2000 {
+            let start = next() >> 46 << 46;
+            let end = start.saturating_add((next() % 3000) << 46);
+            assert_eq!(
+                frozen.range_query(start, end, 0, u64::MAX, 8),
+                plain.range_query(start, end, 0, u64::MAX, 8),
+                "[{}, {}]",
+                start,
+                end
+            );
+            assert_eq!(
+                frozen.point_query(start, 0, u64::MAX, 8),
+                plain.point_query(start, 0, u64::MAX, 8)
+            );
+        }
+
+        // the next insert thaws the store
+        frozen.insert(infixes[0] + 1).unwrap();
+        assert!(!frozen.is_occupieds_frozen());
+        assert!(frozen.validate().is_ok());
+        assert_eq!(frozen.elem_count(), 13);
+        assert_eq!(frozen.count(infixes[0] + 1), 1);
+
+        // dense occupieds stay plain
+        let dense_infixes: Vec<u64> = (0..512).map(|q| (q * 2) << 8).collect();
+        let mut dense = InfixStore::new_with_infixes(&dense_infixes, 8);
+        assert!(!dense.freeze_occupieds() && !dense.is_occupieds_frozen());
+    }
+
+    #[test]
+    fn test_json_and_dot_export() {
+        let infixes = [(3u64 << 8) | 7, (3 << 8) | 9, (10 << 8) | 1];
+        let mut store = InfixStore::new_with_infixes(&infixes, 8);
+        store.set_lazy_delete(true);
+        store.delete((3 << 8) | 9).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&store.to_json()).unwrap();
+        assert_eq!(json["elem_count"], 3);
+        assert_eq!(json["num_slots"], store.num_slots());
+        assert_eq!(json["remainder_size"], 8);
+        assert_eq!(json["lazy_delete"], true);
+        assert_eq!(json["occupieds"], serde_json::json!([3, 10]));
+        assert_eq!(json["runends"], serde_json::json!([1, 2]));
+        assert_eq!(json["tombstones"], serde_json::json!([1]));
+        assert_eq!(
+            json["runs"],
+            serde_json::json!([
+                {"quotient": 3, "start": 0, "end": 1},
+                {"quotient": 10, "start": 2, "end": 2}
+            ])
+        );
+        assert_eq!(json["slots"], serde_json::json!([7, 9, 1]));
+
+        let dot = store.to_dot();
+        assert!(dot.starts_with("digraph infix_store {"));
+        assert!(dot.contains("run3 [label=\"<s0> 0: 7|<s1> 1: 9 (deleted)\"];"));
+        assert!(dot.contains("q10 -> run10;"));
+        assert!(dot.contains("run3 -> run10 [style=dotted];"));
+
+        let empty = InfixStore::new_with_infixes(&[], 8);
+        let json: serde_json::Value = serde_json::from_str(&empty.to_json()).unwrap();
+        assert_eq!(json["runs"], serde_json::json!([]));
+        assert!(!empty.to_dot().contains("->"));
+    }
+
+    #[test]
+    fn test_long_run_binary_search() {
+        // one run of 1000 even remainders under quotient 5
+        let remainder_size = 12;
+        let infixes: Vec<u64> = (0..1000).map(|i| (5 << remainder_size) | (i * 2)).collect();
+        let mut store = InfixStore::new_with_infixes(&infixes, remainder_size);
+        let view = store.view();
+        for remainder in 0..2000u64 {
+            let mut probe = StoreProbe::default();
+            let found = view.find_remainder_in_run(5, remainder, &mut probe);
+            assert_eq!(found, remainder.is_multiple_of(2));
+            // about log2(1000) reads instead of a scan of the run
+            assert!(probe.slots_scanned <= 12, "{} slots", probe.slots_scanned);
+        }
+
+        // odd remainders land between their neighbours
+        for remainder in [1999u64, 1, 1001] {
+            store.insert((5 << remainder_size) | remainder).unwrap();
+        }
+        store.insert((5 << remainder_size) | 1000).unwrap();
+        let mut expected = infixes.clone();
+        expected.extend([1999u64, 1, 1001].map(|r| (5 << remainder_size) | r));
+        expected.sort_unstable();
+        assert_eq!(store.infixes(), expected);
+        verify_cache_integrity(&store);
+
+        store.delete((5 << remainder_size) | 1001).unwrap();
+        store.delete((5 << remainder_size) | 1002).unwrap();
+        assert!(store.delete((5 << remainder_size) | 1003).is_err());
+        assert_eq!(
+            store.count_in_range(5 << remainder_size, (6 << remainder_size) - 1),
+            1001
+        );
+        verify_cache_integrity(&store);
+    }
+
+    #[test]
+    fn test_counting_store_keeps_duplicates() {
+        let infixes = [(5u64 << 8) | 7, (5 << 8) | 7, (9 << 8) | 1];
+        let mut plain = InfixStore::new_with_infixes(&[], 8);
+        let mut counting = InfixStore::new_counting(&infixes, 8, StoreLayout::DEFAULT);
+        assert!(counting.is_counting() && !plain.is_counting());
+        assert_eq!(counting.count((5 << 8) | 7), 2);
+
+        for _ in 0..3 {
+            plain.insert((5 << 8) | 7).unwrap();
+            counting.insert((5 << 8) | 7).unwrap();
+        }
+        counting.insert((5 << 8) | 3).unwrap();
+        counting.insert((5 << 8) | 200).unwrap();
+        assert_eq!(plain.count((5 << 8) | 7), 1);
+        assert_eq!(counting.count((5 << 8) | 7), 5);
+        assert_eq!(counting.count_in_range(5 << 8, (6 << 8) - 1), 7);
+        assert!(counting.infixes().is_sorted());
+        verify_cache_integrity(&counting);
+
+        // the infix disappears only after as many deletes as inserts
+        for remaining in (0..5).rev() {
+            counting.delete((5 << 8) | 7).unwrap();
+            assert_eq!(counting.count((5 << 8) | 7), remaining);
+            verify_cache_integrity(&counting);
+        }
+        assert!(counting.delete((5 << 8) | 7).is_err());
+        assert_eq!(
+            counting.infixes(),
+            vec![(5 << 8) | 3, (5 << 8) | 200, (9 << 8) | 1]
+        );
+
+        // copies spanning a resize and a split stay counted
+        for _ in 0..600 {
+            counting.insert((9 << 8) | 1).unwrap();
+        }
+        assert_eq!(counting.count((9 << 8) | 1), 601);
+        verify_cache_integrity(&counting);
+        let (lower, upper) = counting.split_at_quotient(9);
+        assert!(lower.is_counting() && upper.is_counting());
+        assert_eq!(upper.count((9 << 8) | 1), 601);
+        assert_eq!(lower.elem_count(), 2);
+    }
+
+    #[test]
+    fn test_lazy_delete_matches_eager_delete() {
+        let mut lazy = InfixStore::new_with_infixes(&[], 8);
+        lazy.set_lazy_delete(true);
+        let mut eager = InfixStore::new_with_infixes(&[], 8);
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for step in 0..6000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // few quotients, so runs are long and hold several tombstones
+            let infix = ((state % 40) << 8) | ((state >> 32) % 64);
+            if (state >> 20) % 4 < 2 {
+                assert_eq!(lazy.insert(infix).is_ok(), eager.insert(infix).is_ok());
+            } else {
+                assert_eq!(lazy.delete(infix).is_ok(), eager.delete(infix).is_ok());
+            }
+            if step % 500 == 0 {
+                assert_eq!(lazy.infixes(), eager.infixes());
+                assert_eq!(lazy.iter().len(), eager.elem_count());
+                for quotient in 0..40u64 {
+                    let (low, high) = (quotient << 8, (quotient << 8) | 0x7F);
+                    assert_eq!(
+                        lazy.count_in_range(low, high),
+                        eager.count_in_range(low, high)
+                    );
+                    let (view, probe) = (lazy.view(), &mut StoreProbe::default());
+                    for remainder in [0u64, 17, 128, 255] {
+                        let quotient = quotient as usize;
+                        let found = view.is_occupied(quotient)
+                            && view.find_remainder_in_run(quotient, remainder, probe);
+                        assert_eq!(
+                            found,
+                            eager.count(((quotient as u64) << 8) | remainder) == 1
+                        );
+                    }
+                }
+                verify_cache_integrity(&lazy);
+            }
+        }
+        assert!(lazy.tombstone_count() > 0);
+        assert_eq!(
+            lazy.elem_count(),
+            eager.elem_count() + lazy.tombstone_count()
+        );
+
+        // deleting a range reclaims the tombstones inside it
+        let before = lazy.tombstone_count();
+        assert_eq!(
+            lazy.delete_range(0, 10 << 8),
+            eager.delete_range(0, 10 << 8)
+        );
+        assert!(lazy.tombstone_count() < before);
+        assert_eq!(lazy.infixes(), eager.infixes());
+
+        lazy.compact();
+        assert_eq!(lazy.tombstone_count(), 0);
+        assert!(lazy.is_lazy_delete());
+        assert_eq!(lazy.elem_count(), eager.elem_count());
+        assert_eq!(lazy.infixes(), eager.infixes());
+        verify_cache_integrity(&lazy);
+
+        // switching lazy deletes off compacts
+        let infix = lazy.infixes()[0];
+        lazy.delete(infix).unwrap();
+        lazy.set_lazy_delete(false);
+        assert!(!lazy.is_lazy_delete());
+        assert_eq!(lazy.count(infix), 0);
+        assert_eq!(lazy.elem_count(), eager.elem_count() - 1);
+    }
+
+    #[test]
+    fn test_new_with_fpr() {
+        assert_eq!(InfixStore::remainder_size_for_fpr(1.0, 0.01), 7);
+        assert_eq!(InfixStore::remainder_size_for_fpr(0.5, 0.25), 1);
+        assert_eq!(InfixStore::remainder_size_for_fpr(0.1, 0.5), 0);
+        assert_eq!(InfixStore::remainder_size_for_fpr(0.0, 0.01), 0);
+        assert_eq!(InfixStore::remainder_size_for_fpr(1e6, 1e-20), 64);
+        // denser stores need longer remainders for the same target
+        assert!(
+            InfixStore::remainder_size_for_fpr(2.0, 0.01)
+                > InfixStore::remainder_size_for_fpr(0.5, 0.01)
+        );
+
+        let mut store = InfixStore::new_with_fpr(2000, 0.001).unwrap();
+        assert_eq!(store.remainder_size(), 11);
+        assert_eq!(store.elem_count(), 0);
+        assert!(store.num_slots() >= 2000);
+        verify_cache_integrity(&store);
+
+        // 32-bit keys keep their top 21 bits as infix; absent keys hit at about the target rate
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next_key = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state >> 32
+        };
+        let mut keys: Vec<u64> = (0..2000).map(|_| next_key()).collect();
+        keys.sort_unstable();
+        let grade = store.size_grade();
+        for &key in &keys {
+            store.insert(key >> 11).unwrap();
+        }
+        assert_eq!(store.size_grade(), grade);
+        let queries = 200_000;
+        let hits = (0..queries)
+            .map(|_| next_key())
+            .filter(|key| keys.binary_search(key).is_err() && store.count(key >> 11) > 0)
+            .count();
+        let fpr = hits as f64 / queries as f64;
+        assert!(fpr > 0.0 && fpr < 0.002, "fpr {}", fpr);
+
+        assert!(InfixStore::new_with_fpr(10, 0.0).is_err());
+        assert!(InfixStore::new_with_fpr(10, 1.0).is_err());
+    }
+
     #[test]
     fn test_insert_middle_of_run() {
         let infixes = vec![(100u64 << 8) | 10, (100u64 << 8) | 30];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        store.insert((100u64 << 8) | 20);
+        store.insert((100u64 << 8) | 20).unwrap();
 
         assert_eq!(store.elem_count, 3);
         assert_eq!(store.read_slot(0), 10);
@@ -1099,7 +3089,7 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10, (100u64 << 8) | 20];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        store.insert((100u64 << 8) | 30);
+        store.insert((100u64 << 8) | 30).unwrap();
 
         assert_eq!(store.elem_count, 3);
         assert_eq!(store.read_slot(0), 10);
@@ -1114,7 +3104,7 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 20, (100u64 << 8) | 30];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        store.insert((100u64 << 8) | 10);
+        store.insert((100u64 << 8) | 10).unwrap();
 
         assert_eq!(store.elem_count, 3);
         assert_eq!(store.read_slot(0), 10);
@@ -1128,7 +3118,7 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10, (200u64 << 8) | 20];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        store.insert((150u64 << 8) | 15);
+        store.insert((150u64 << 8) | 15).unwrap();
 
         assert_eq!(store.elem_count, 3);
         assert!(store.is_occupied(100));
@@ -1144,8 +3134,8 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        assert!(store.insert((100u64 << 8) | 10));
-        assert!(store.insert((100u64 << 8) | 10));
+        assert!(store.insert((100u64 << 8) | 10).is_ok());
+        assert!(store.insert((100u64 << 8) | 10).is_ok());
 
         assert_eq!(store.elem_count, 1);
         assert_eq!(store.read_slot(0), 10);
@@ -1156,10 +3146,11 @@ mod tests {
         let infixes: Vec<u64> = vec![];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        store.insert((0u64 << 8) | 0);
-        store.insert((1023u64 << 8) | 255);
-        store.insert((0u64 << 8) | 255);
-        store.insert((1023u64 << 8) | 0);
+        // first and last quotient, smallest and largest remainder
+        store.insert(0).unwrap();
+        store.insert((1023u64 << 8) | 255).unwrap();
+        store.insert(255).unwrap();
+        store.insert(1023u64 << 8).unwrap();
 
         assert_eq!(store.elem_count, 4);
         assert!(store.is_occupied(0));
@@ -1174,7 +3165,7 @@ mod tests {
         let initial_size_grade = store.size_grade();
 
         for i in 0..500 {
-            store.insert((100u64 << 8) | i);
+            store.insert((100u64 << 8) | i).unwrap();
         }
 
         assert_eq!(store.elem_count, 500);
@@ -1186,7 +3177,7 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10, (100u64 << 8) | 20, (100u64 << 8) | 30];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        assert!(store.delete((100u64 << 8) | 20));
+        assert!(store.delete((100u64 << 8) | 20).is_ok());
 
         assert_eq!(store.elem_count, 2);
         assert_eq!(store.read_slot(0), 10);
@@ -1199,7 +3190,7 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10, (100u64 << 8) | 20, (100u64 << 8) | 30];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        assert!(store.delete((100u64 << 8) | 30));
+        assert!(store.delete((100u64 << 8) | 30).is_ok());
 
         assert_eq!(store.elem_count, 2);
         assert_eq!(store.read_slot(0), 10);
@@ -1213,7 +3204,7 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10, (100u64 << 8) | 20, (100u64 << 8) | 30];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        assert!(store.delete((100u64 << 8) | 10));
+        assert!(store.delete((100u64 << 8) | 10).is_ok());
 
         assert_eq!(store.elem_count, 2);
         assert_eq!(store.read_slot(0), 20);
@@ -1226,7 +3217,7 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10, (200u64 << 8) | 20];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        assert!(store.delete((100u64 << 8) | 10));
+        assert!(store.delete((100u64 << 8) | 10).is_ok());
 
         assert_eq!(store.elem_count, 1);
         assert!(!store.is_occupied(100));
@@ -1240,8 +3231,14 @@ mod tests {
         let infixes = vec![(100u64 << 8) | 10, (100u64 << 8) | 20];
         let mut store = InfixStore::new_with_infixes(&infixes, 8);
 
-        assert!(!store.delete((100u64 << 8) | 30));
-        assert!(!store.delete((200u64 << 8) | 10));
+        assert_eq!(
+            store.delete((100u64 << 8) | 30),
+            Err(RangeFilterError::KeyNotFound((100u64 << 8) | 30))
+        );
+        assert_eq!(
+            store.delete((200u64 << 8) | 10),
+            Err(RangeFilterError::KeyNotFound((200u64 << 8) | 10))
+        );
 
         assert_eq!(store.elem_count, 2);
     }
@@ -1252,34 +3249,507 @@ mod tests {
         let mut store = InfixStore::new_with_infixes(&infixes, 10);
 
         for i in 0..600 {
-            store.insert((100u64 << 10) | i);
+            store.insert((100u64 << 10) | i).unwrap();
         }
 
         let size_after_insert = store.size_grade();
 
         for i in 0..500 {
-            store.delete((100u64 << 10) | i);
+            store.delete((100u64 << 10) | i).unwrap();
         }
 
         assert_eq!(store.elem_count, 100);
         assert!(store.size_grade() < size_after_insert);
     }
 
+    #[test]
+    fn test_insert_before_last_in_run() {
+        // quotient 5 has run [1, 3], inserting 2 lands before the run end
+        let mut store =
+            InfixStore::new_with_infixes(&[(5 << 8) | 1, (5 << 8) | 3, (9 << 8) | 7], 8);
+        assert!(store.insert((5 << 8) | 2).is_ok());
+
+        assert_eq!(
+            store.infixes(),
+            vec![(5 << 8) | 1, (5 << 8) | 2, (5 << 8) | 3, (9 << 8) | 7]
+        );
+        assert!(!store.is_runend(0));
+        assert!(!store.is_runend(1));
+        assert!(store.is_runend(2));
+        assert!(store.is_runend(3));
+        verify_cache_integrity(&store);
+    }
+
+    #[test]
+    fn test_chunked_run_scans_match_reference() {
+        // runs of up to 40 remainders, longer than a scan chunk
+        for remainder_size in [5u8, 8, 13] {
+            let mut infixes = Vec::new();
+            for quotient in [3u64, 4, 100, 511] {
+                let len = quotient % 41 + 1;
+                let step = (1u64 << remainder_size) / (len + 1);
+                infixes.extend((1..=len).map(|i| (quotient << remainder_size) | (i * step)));
+            }
+            let store = InfixStore::new_with_infixes(&infixes, remainder_size);
+            let view = store.view();
+            let run = |quotient: u64| {
+                infixes
+                    .iter()
+                    .filter(move |&&infix| infix >> remainder_size == quotient)
+                    .map(move |&infix| infix & ((1 << remainder_size) - 1))
+            };
+
+            let mut probe = StoreProbe::default();
+            for quotient in [3u64, 4, 100, 511] {
+                for remainder in 0..1u64 << remainder_size {
+                    let q = quotient as usize;
+                    assert_eq!(
+                        view.find_remainder_in_run(q, remainder, &mut probe),
+                        run(quotient).any(|r| r == remainder)
+                    );
+                    assert_eq!(
+                        view.scan_run_from_remainder(q, remainder, true, &mut probe),
+                        run(quotient).any(|r| r >= remainder)
+                    );
+                    assert_eq!(
+                        view.scan_run_from_remainder(q, remainder, false, &mut probe),
+                        run(quotient).any(|r| r <= remainder)
+                    );
+                    let end = remainder + 3;
+                    assert_eq!(
+                        view.scan_run_for_range(q, remainder, end, &mut probe),
+                        run(quotient).any(|r| r >= remainder && r <= end)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_iter_and_iter_run() {
+        let empty = InfixStore::new_with_infixes(&[], 8);
+        assert_eq!(empty.iter().next(), None);
+        assert_eq!(empty.iter_run(0).len(), 0);
+
+        let mut store = InfixStore::new_with_infixes(&[4, (5 << 8) | 1, (5 << 8) | 3], 8);
+        store.insert((1023 << 8) | 255).unwrap();
+        store.insert((5 << 8) | 2).unwrap();
+        store.delete(4).unwrap();
+
+        let pairs: Vec<(u64, u64)> = store.iter().collect();
+        assert_eq!(pairs, vec![(5, 1), (5, 2), (5, 3), (1023, 255)]);
+        assert_eq!(store.iter().len(), store.elem_count());
+
+        assert_eq!(
+            store.iter_run(5).collect::<Vec<_>>(),
+            vec![(5, 1), (5, 2), (5, 3)]
+        );
+        assert_eq!(store.iter_run(1023).collect::<Vec<_>>(), vec![(1023, 255)]);
+        assert_eq!(store.iter_run(0).next(), None);
+        assert_eq!(store.iter_run(6).next(), None);
+        assert_eq!(store.iter_run(1 << 20).next(), None);
+        assert_eq!(
+            store.occupied_quotients().collect::<Vec<_>>(),
+            vec![5, 1023]
+        );
+        assert_eq!(store.runend_slots().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(
+            empty.occupied_quotients().count() + empty.runend_slots().count(),
+            0
+        );
+
+        // a run per quotient, read back through both iterators after resizes
+        let infixes: Vec<u64> = (0..600u64).map(|i| ((i % 300) << 8) | (i / 300)).collect();
+        let mut sorted = infixes.clone();
+        sorted.sort_unstable();
+        let mut store = InfixStore::new_with_infixes(&[], 8);
+        for &infix in &infixes {
+            store.insert(infix).unwrap();
+        }
+        let mut from_runs = Vec::new();
+        for quotient in 0..1024 {
+            from_runs.extend(store.iter_run(quotient).map(|(q, r)| (q << 8) | r));
+        }
+        let from_iter: Vec<u64> = store.iter().map(|(q, r)| (q << 8) | r).collect();
+        assert_eq!(from_iter, sorted);
+        assert_eq!(from_runs, sorted);
+    }
+
+    #[test]
+    fn test_split_at_quotient() {
+        let infixes: Vec<u64> = (0..500u64).map(|i| ((i * 2) << 8) | (i % 256)).collect();
+        let store = InfixStore::new_with_infixes(&infixes, 8);
+
+        let (lower, upper) = store.split_at_quotient(401);
+        assert_eq!(lower.elem_count() + upper.elem_count(), store.elem_count());
+        assert_eq!(lower.infixes(), infixes[..201]);
+        assert_eq!(upper.infixes(), infixes[201..]);
+        assert!(lower.size_grade() <= store.size_grade());
+        assert!(lower.iter().all(|(quotient, _)| quotient < 401));
+        assert!(upper.iter().all(|(quotient, _)| quotient >= 401));
+        assert!(upper.is_occupied(402) && !upper.is_occupied(400));
+        verify_cache_integrity(&lower);
+        verify_cache_integrity(&upper);
+
+        let (lower, upper) = store.split_at_quotient(0);
+        assert_eq!(lower.elem_count(), 0);
+        assert_eq!(upper.infixes(), infixes);
+        let (lower, upper) = store.split_at_quotient(1 << 20);
+        assert_eq!(lower.infixes(), infixes);
+        assert_eq!(upper.elem_count(), 0);
+    }
+
+    #[test]
+    fn test_popcounts_track_every_mutation() {
+        // small quotients and grades so runs cross both cache boundaries and the store resizes
+        let layout = StoreLayout::new(8, &[64, 128, 256]).unwrap();
+        let mut store = InfixStore::new_with_layout(&[], 6, layout);
+        let mut expected = alloc::collections::BTreeSet::new();
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for step in 0..4000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // mostly inserts while growing to the largest grade, then mostly deletes
+            let insert = state.is_multiple_of(4) == (step >= 2000);
+            if insert && expected.len() < 256 {
+                let infix = state % (1 << 14);
+                store.insert(infix).unwrap();
+                expected.insert(infix);
+            } else if !expected.is_empty() {
+                let infix = *expected
+                    .iter()
+                    .nth(state as usize % expected.len())
+                    .unwrap();
+                store.delete(infix).unwrap();
+                expected.remove(&infix);
+            }
+            verify_cache_integrity(&store);
+        }
+        assert_eq!(store.infixes(), expected.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_count_in_range() {
+        let infixes: Vec<u64> = (0..700u64)
+            .map(|i| ((i * 3 % 1024) << 8) | (i * 37 % 256))
+            .collect();
+        let mut sorted = infixes.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let mut store = InfixStore::new_with_infixes(&[], 8);
+        for &infix in &infixes {
+            store.insert(infix).unwrap();
+        }
+
+        let reference = |start: u64, end: u64| {
+            sorted
+                .iter()
+                .filter(|&&infix| start <= infix && infix <= end)
+                .count()
+        };
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for _ in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let start = state % (1 << 18);
+            let end = start + (state >> 40) % 5000;
+            assert_eq!(store.count_in_range(start, end), reference(start, end));
+        }
+        for &infix in &sorted {
+            assert_eq!(store.count_in_range(infix, infix), 1);
+        }
+        assert_eq!(store.count_in_range(0, u64::MAX), sorted.len());
+        assert_eq!(store.count_in_range(10, 9), 0);
+        assert_eq!(store.count_in_range(1 << 18, u64::MAX), 0);
+        assert_eq!(
+            InfixStore::new_with_infixes(&[], 8).count_in_range(0, u64::MAX),
+            0
+        );
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let mut store = InfixStore::new_with_infixes(
+            &[
+                (2 << 8) | 9,
+                (5 << 8) | 1,
+                (5 << 8) | 2,
+                (5 << 8) | 3,
+                (7 << 8) | 4,
+                (9 << 8) | 1,
+            ],
+            8,
+        );
+        // cuts the tail of quotient 5, empties 7 and keeps 9
+        assert_eq!(store.delete_range((5 << 8) | 2, (8 << 8) | 255), 3);
+        assert_eq!(
+            store.infixes(),
+            vec![(2 << 8) | 9, (5 << 8) | 1, (9 << 8) | 1]
+        );
+        assert!(!store.is_occupied(7));
+        assert!(store.is_runend(1));
+        verify_cache_integrity(&store);
+
+        // cuts the head of a run
+        assert_eq!(store.delete_range(0, (5 << 8) | 1), 2);
+        assert_eq!(store.infixes(), vec![(9 << 8) | 1]);
+        assert_eq!(store.delete_range(10, 5), 0);
+        assert_eq!(store.delete_range((9 << 8) | 2, u64::MAX), 0);
+        assert_eq!(store.delete_range(0, u64::MAX), 1);
+        assert_eq!(store.elem_count(), 0);
+        verify_cache_integrity(&store);
+    }
+
+    #[test]
+    fn test_delete_range_matches_repeated_delete() {
+        let infixes: Vec<u64> = (0..1500u64)
+            .map(|i| ((i * 7 % 1024) << 8) | (i * 13 % 256))
+            .collect();
+        let mut sorted = infixes.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        for _ in 0..40 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let start = state % (1 << 18);
+            let end = start + (state >> 32) % (1 << 17);
+
+            let mut store = InfixStore::new_with_infixes(&sorted, 8);
+            let removed = store.delete_range(start, end);
+            let expected: Vec<u64> = sorted
+                .iter()
+                .copied()
+                .filter(|&infix| infix < start || infix > end)
+                .collect();
+            assert_eq!(removed, sorted.len() - expected.len());
+            assert_eq!(store.infixes(), expected);
+            verify_cache_integrity(&store);
+
+            let mut deleted = InfixStore::new_with_infixes(&sorted, 8);
+            for &infix in sorted
+                .iter()
+                .filter(|&&infix| start <= infix && infix <= end)
+            {
+                deleted.delete(infix).unwrap();
+            }
+            assert_eq!(store.size_grade(), deleted.size_grade());
+            // slots past elem_count keep stale remainders, so compare the metadata words
+            let (_, _, slots_start) = store.get_offsets();
+            assert_eq!(store.data()[..slots_start], deleted.data()[..slots_start]);
+            assert_eq!(store.infixes(), deleted.infixes());
+            for &infix in &expected {
+                assert!(store.view().count_in_range(infix, infix) == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_layout_resizes_through_grades() {
+        let layout = StoreLayout::new(8, &[100, 200, 400]).unwrap();
+        let mut store = InfixStore::new_with_layout(&[], 6, layout);
+        assert_eq!(store.num_slots(), 100);
+
+        // 256 quotients x 64 remainders, spread over every quotient
+        let infixes: Vec<u64> = (0..350u64).map(|i| ((i % 256) << 6) | (i / 256)).collect();
+        for &infix in &infixes {
+            assert!(store.insert(infix).is_ok());
+        }
+        assert_eq!(store.num_slots(), 400);
+        verify_cache_integrity(&store);
+        for &infix in &infixes {
+            let (quotient, remainder) = InfixStore::split_infix(infix, 8, 6);
+            let mut probe = StoreProbe::default();
+            assert!(
+                store
+                    .view()
+                    .find_remainder_in_run(quotient as usize, remainder, &mut probe)
+            );
+        }
+
+        // a full store at the largest grade rejects further inserts
+        for i in 350..400u64 {
+            assert!(store.insert(((i % 256) << 6) | (i / 256)).is_ok());
+        }
+        assert_eq!(
+            store.insert((255 << 6) | 63),
+            Err(RangeFilterError::PartitionFull)
+        );
+
+        for &infix in &infixes {
+            assert!(store.delete(infix).is_ok());
+        }
+        assert_eq!(store.num_slots(), 100);
+        verify_cache_integrity(&store);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rebuilds_rank_directories() {
+        let infixes: Vec<u64> = (0..900u64).map(|i| ((i % 1024) << 8) | (i / 7)).collect();
+        let store = InfixStore::new_with_infixes(&infixes, 8);
+        let json = serde_json::to_string(&store).unwrap();
+        let restored: InfixStore = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.infixes(), infixes);
+        verify_cache_integrity(&restored);
+        assert!(restored.view().ranks.is_some());
+
+        // data that does not match the metadata is rejected instead of panicking later
+        let padded = json.replacen("\"data\":[", "\"data\":[1,", 1);
+        assert_ne!(padded, json);
+        assert!(serde_json::from_str::<InfixStore>(&padded).is_err());
+
+        // counting mode survives the round trip, and older data defaults to plain
+        let counting = InfixStore::new_counting(&infixes, 8, StoreLayout::DEFAULT);
+        let json = serde_json::to_string(&counting).unwrap();
+        assert!(
+            serde_json::from_str::<InfixStore>(&json)
+                .unwrap()
+                .is_counting()
+        );
+        let legacy = json.replacen(",\"counting\":true", "", 1);
+        assert_ne!(legacy, json);
+        assert!(
+            !serde_json::from_str::<InfixStore>(&legacy)
+                .unwrap()
+                .is_counting()
+        );
+
+        // tombstones are kept, so lazily deleted infixes stay deleted
+        let mut lazy = InfixStore::new_with_infixes(&infixes, 8);
+        lazy.set_lazy_delete(true);
+        lazy.delete(infixes[3]).unwrap();
+        let json = serde_json::to_string(&lazy).unwrap();
+        let restored: InfixStore = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.tombstone_count(), 1);
+        assert_eq!(restored.infixes(), lazy.infixes());
+
+        // frozen occupieds are saved in place, and load as a plain store
+        let mut frozen = InfixStore::new_with_infixes(&[(7 << 8) | 1, 1000 << 8], 8);
+        let plain_json = serde_json::to_string(&frozen).unwrap();
+        assert!(frozen.freeze_occupieds());
+        assert_eq!(serde_json::to_string(&frozen).unwrap(), plain_json);
+    }
+
+    #[test]
+    fn test_size_in_bytes_tracks_resizes() {
+        let mut store = InfixStore::new_with_infixes(&[], 8);
+        let empty_size = store.size_in_bytes();
+        assert_eq!(
+            empty_size,
+            std::mem::size_of::<InfixStore>()
+                + store.data().len() * 8
+                + store.occupieds_ranks.heap_size()
+                + store.runends_ranks.heap_size()
+        );
+
+        for i in 0..1500u64 {
+            store.insert(((i % 1024) << 8) | (i / 1024)).unwrap();
+        }
+        assert!(store.size_in_bytes() > empty_size);
+    }
+
+    #[test]
+    fn test_resize_reuses_buffer() {
+        let infixes: Vec<u64> = (0..2000u64)
+            .map(|i| ((i % 1024) << 8) | (i / 1024))
+            .collect();
+        let mut store = InfixStore::new_with_infixes(&[], 8);
+        for &infix in &infixes {
+            store.insert(infix).unwrap();
+        }
+        let grown_grade = store.size_grade();
+        let grown = (store.data().as_ptr(), store.data.capacity());
+
+        // shrinking keeps the buffer, growing back into it does not reallocate
+        for &infix in &infixes[600..] {
+            store.delete(infix).unwrap();
+        }
+        assert!(store.size_grade() < grown_grade);
+        assert_eq!((store.data().as_ptr(), store.data.capacity()), grown);
+        assert_eq!(store.infixes(), infixes[..600]);
+        verify_cache_integrity(&store);
+        for &infix in &infixes[600..] {
+            store.insert(infix).unwrap();
+        }
+        assert_eq!(store.size_grade(), grown_grade);
+        assert_eq!(store.data().as_ptr(), grown.0);
+        let mut sorted = infixes.clone();
+        sorted.sort_unstable();
+        assert_eq!(store.infixes(), sorted);
+        verify_cache_integrity(&store);
+
+        for &infix in &infixes[100..] {
+            store.delete(infix).unwrap();
+        }
+        store.shrink_to_fit();
+        assert_eq!(store.data.capacity(), store.data().len());
+        assert_eq!(store.infixes(), infixes[..100]);
+        verify_cache_integrity(&store);
+    }
+
+    #[test]
+    fn test_store_layout_validation() {
+        assert!(StoreLayout::new(0, &[100]).is_err());
+        assert!(StoreLayout::new(MAX_QUOTIENT_SIZE + 1, &[100]).is_err());
+        assert!(StoreLayout::new(10, &[]).is_err());
+        assert!(StoreLayout::new(10, &[0, 100]).is_err());
+        assert!(StoreLayout::new(10, &[200, 100]).is_err());
+
+        // stores of a layout share its table, equal tables compare equal
+        let a = StoreLayout::new(10, &[300, 600]).unwrap();
+        let store = InfixStore::new_with_layout(&[], 4, a.clone());
+        assert!(std::ptr::eq(a.size_grades(), store.layout.size_grades()));
+        assert_eq!(a, StoreLayout::new(10, &[300, 600]).unwrap());
+        assert_eq!(
+            StoreLayout::new(10, &SCALED_SIZES).unwrap(),
+            StoreLayout::DEFAULT
+        );
+        assert_eq!(
+            StoreLayout::scaled(QUOTIENT_SIZE).unwrap(),
+            StoreLayout::DEFAULT
+        );
+    }
+
+    #[test]
+    fn test_custom_size_grades_are_freed_with_their_stores() {
+        let layout = StoreLayout::new(8, &[300, 600]).unwrap();
+        let table = Arc::downgrade(layout.size_grades.as_ref().unwrap());
+        let stores: Vec<InfixStore> = (0..10)
+            .map(|i| InfixStore::new_with_layout(&[i], 4, layout.clone()))
+            .collect();
+        assert_eq!(table.strong_count(), 11);
+
+        drop(layout);
+        drop(stores);
+        // nothing outlives the last store, so loaded tables do not pile up
+        assert!(table.upgrade().is_none());
+        assert!(StoreLayout::DEFAULT.size_grades.is_none());
+    }
+
     // Helper function to verify cache integrity
     fn verify_cache_integrity(store: &InfixStore) {
-        let cached_occupieds = store.get_popcount_occupieds();
-        let cached_runends = store.get_popcount_runends();
+        let cached_occupieds = store.view().get_popcount_occupieds();
+        let cached_runends = store.view().get_popcount_runends();
 
         let (occupieds_start, runends_start, _) = store.get_offsets();
-        let occupieds_words = (TARGET_SIZE as usize + U64_BITS - 1) / U64_BITS;
-        let num_slots = SCALED_SIZES[store.size_grade() as usize];
-        let runends_words = (num_slots as usize + U64_BITS - 1) / U64_BITS;
+        let occupieds_words = occupieds_words(store.quotient_size());
+        let num_slots = store.num_slots();
+        let runends_words = num_slots.div_ceil(U64_BITS);
 
         let occupieds_slice = &store.data[occupieds_start..occupieds_start + occupieds_words];
         let runends_slice = &store.data[runends_start..runends_start + runends_words];
 
-        let expected_occupieds = rank(occupieds_slice, CACHE_BOUNDARY as usize) as u32;
-        let expected_runends = rank(runends_slice, CACHE_BOUNDARY as usize) as u32;
+        let expected_occupieds =
+            rank(occupieds_slice, cache_boundary(store.quotient_size())) as u32;
+        let expected_runends = rank(
+            runends_slice,
+            runends_cache_boundary(num_slots as u16, store.quotient_size()),
+        ) as u32;
 
         assert_eq!(
             cached_occupieds, expected_occupieds,
@@ -1291,6 +3761,86 @@ mod tests {
             "Runends cache mismatch: cached={}, expected={}, elem_count={}, size_grade={}",
             cached_runends, expected_runends, store.elem_count, store.size_grade
         );
+
+        let mut rebuilt = store.clone();
+        rebuilt.rebuild_rank_directories();
+        assert_eq!(store.occupieds_ranks, rebuilt.occupieds_ranks);
+        assert_eq!(store.runends_ranks, rebuilt.runends_ranks);
+        assert_eq!(store.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_violations() {
+        let infixes: Vec<u64> = (0..300u64).map(|i| ((i * 3) << 8) | (i % 7)).collect();
+        let store = InfixStore::new_with_infixes(&infixes, 8);
+        assert_eq!(store.validate(), Ok(()));
+        let (occupieds_start, _, _) = store.get_offsets();
+
+        let mut broken = store.clone();
+        broken.elem_count += 1;
+        assert_eq!(
+            broken.validate(),
+            Err(InvariantViolation::RunsLength {
+                elem_count: 301,
+                covered: 300
+            })
+        );
+
+        // an occupied quotient without a run
+        let mut broken = store.clone();
+        set_bit(&mut broken.data[occupieds_start..], 1);
+        assert_eq!(
+            broken.validate(),
+            Err(InvariantViolation::RunendCount {
+                occupieds: 301,
+                runends: 300
+            })
+        );
+
+        // two remainders of one run swapped
+        let mut broken = store.clone();
+        let two = InfixStore::new_with_infixes(&[5 << 8, (5 << 8) | 1], 8);
+        let mut data = two.data().to_vec();
+        let slots = &mut data[two.get_offsets().2..];
+        InfixStore::write_slot(slots, 0, 1, 8);
+        InfixStore::write_slot(slots, 1, 0, 8);
+        broken.data = data;
+        broken.size_grade = two.size_grade;
+        broken.elem_count = 2;
+        broken.rebuild_rank_directories();
+        assert_eq!(
+            broken.validate(),
+            Err(InvariantViolation::UnsortedRun {
+                quotient: 5,
+                slot: 1
+            })
+        );
+
+        let mut broken = store.clone();
+        broken.data[0] += 1;
+        assert_eq!(broken.validate(), Err(InvariantViolation::StalePopcounts));
+
+        let mut broken = store.clone();
+        broken.runends_ranks = RankDirectory::default();
+        assert_eq!(
+            broken.validate(),
+            Err(InvariantViolation::StaleRankDirectory)
+        );
+
+        let mut broken = store.clone();
+        broken.set_lazy_delete(true);
+        broken.set_tombstone(310, true);
+        assert_eq!(
+            broken.validate(),
+            Err(InvariantViolation::StrayTombstone { slot: 310 })
+        );
+
+        let mut broken = store.clone();
+        broken.data.pop();
+        assert!(matches!(
+            broken.validate(),
+            Err(InvariantViolation::DataLength { .. })
+        ));
     }
 
     #[test]
@@ -1300,13 +3850,13 @@ mod tests {
 
         // insert items with single quotient and verify cache after each
         for i in 0..50 {
-            store.insert((100u64 << 8) | i);
+            store.insert((100u64 << 8) | i).unwrap();
             verify_cache_integrity(&store);
         }
 
         // insert items with different quotients and verify
         for q in 50..70 {
-            store.insert((q << 8) | 100);
+            store.insert((q << 8) | 100).unwrap();
             verify_cache_integrity(&store);
         }
     }
@@ -1319,7 +3869,7 @@ mod tests {
 
         // delete items one by one and verify cache
         for i in 0..50 {
-            store.delete((50u64 << 8) | i);
+            store.delete((50u64 << 8) | i).unwrap();
             verify_cache_integrity(&store);
         }
     }
@@ -1330,13 +3880,13 @@ mod tests {
 
         // insert quotients around the 512 cache boundary
         for q in 480..540 {
-            store.insert((q << 8) | 50);
+            store.insert((q << 8) | 50).unwrap();
             verify_cache_integrity(&store);
         }
 
         // delete quotients around boundary
         for q in 490..520 {
-            store.delete((q << 8) | 50);
+            store.delete((q << 8) | 50).unwrap();
             verify_cache_integrity(&store);
         }
     }
@@ -1348,8 +3898,9 @@ mod tests {
 
         // insert enough to trigger resize up
         for i in 0..700 {
-            store.insert((100u64 << 8) | i);
-            if i % 50 == 0 {  // verify periodically to save time
+            store.insert((100u64 << 8) | i).unwrap();
+            if i % 50 == 0 {
+                // verify periodically to save time
                 verify_cache_integrity(&store);
             }
         }
@@ -1364,8 +3915,9 @@ mod tests {
 
         // delete enough to trigger resize down
         for i in 0..500 {
-            store.delete((100u64 << 8) | i);
-            if i % 50 == 0 {  // verify periodically
+            store.delete((100u64 << 8) | i).unwrap();
+            if i % 50 == 0 {
+                // verify periodically
                 verify_cache_integrity(&store);
             }
         }
@@ -1384,12 +3936,12 @@ mod tests {
 
         // perform operations and verify
         for i in 0..50 {
-            store.delete((100u64 << 8) | i);
+            store.delete((100u64 << 8) | i).unwrap();
         }
         verify_cache_integrity(&store);
 
         for i in 0..30 {
-            store.insert((100u64 << 8) | i);
+            store.insert((100u64 << 8) | i).unwrap();
         }
         verify_cache_integrity(&store);
     }
@@ -1397,7 +3949,8 @@ mod tests {
     #[test]
     fn test_cache_with_large_size_grade() {
         // test with large size grade where cache covers less than half
-        let infixes: Vec<u64> = (0..1200).map(|i| (100u64 << 8) | i).collect();
+        let mut infixes: Vec<u64> = (0..1200).map(|i| (100u64 << 8) | i).collect();
+        infixes.sort_unstable();
         let mut store = InfixStore::new_with_infixes(&infixes, 10);
 
         // verify cache for large store
@@ -1406,7 +3959,7 @@ mod tests {
 
         // perform operations
         for i in 0..200 {
-            store.delete((100u64 << 8) | i);
+            store.delete((100u64 << 8) | i).unwrap();
         }
         verify_cache_integrity(&store);
     }
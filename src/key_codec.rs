@@ -0,0 +1,159 @@
+use crate::Key;
+use alloc::vec::Vec;
+
+const SIGN_BIT: u64 = 1 << 63;
+
+/// Order-preserving mapping between a key type and the `u64` keys used by the filters
+///
+/// `a < b` holds exactly when `a.encode() < b.encode()`, so range queries over
+/// encoded keys answer the same question as over the original keys.
+///
+/// # Example
+/// ```rust
+/// use range_filters::{Diva, KeyCodec, RangeFilter};
+///
+/// let keys: Vec<i64> = vec![-500, -20, 0, 15, 300];
+/// let diva = Diva::new_with_keys(&i64::encode_all(&keys), 1024, 0.01);
+///
+/// assert!(diva.range_query_typed(-30i64, -10i64));
+/// assert!(diva.point_query_typed(15i64));
+/// ```
+pub trait KeyCodec: Copy {
+    fn encode(self) -> Key;
+
+    fn decode(key: Key) -> Self;
+
+    /// encode a slice of keys, e.g. to build a filter from them
+    fn encode_all(keys: &[Self]) -> Vec<Key> {
+        keys.iter().map(|&key| key.encode()).collect()
+    }
+}
+
+impl KeyCodec for u64 {
+    fn encode(self) -> Key {
+        self
+    }
+
+    fn decode(key: Key) -> Self {
+        key
+    }
+}
+
+impl KeyCodec for u32 {
+    fn encode(self) -> Key {
+        self as Key
+    }
+
+    fn decode(key: Key) -> Self {
+        key as u32
+    }
+}
+
+/// flipping the sign bit moves negative values below positive ones
+impl KeyCodec for i64 {
+    fn encode(self) -> Key {
+        (self as u64) ^ SIGN_BIT
+    }
+
+    fn decode(key: Key) -> Self {
+        (key ^ SIGN_BIT) as i64
+    }
+}
+
+impl KeyCodec for i32 {
+    fn encode(self) -> Key {
+        (self as i64).encode()
+    }
+
+    fn decode(key: Key) -> Self {
+        i64::decode(key) as i32
+    }
+}
+
+/// Positive values get the sign bit set, negative values have all bits flipped
+/// so larger magnitudes sort lower. The order matches `f64::total_cmp`: -0.0
+/// sorts before 0.0 and NaNs sort beyond the infinities by sign.
+impl KeyCodec for f64 {
+    fn encode(self) -> Key {
+        let bits = self.to_bits();
+        if bits & SIGN_BIT == 0 {
+            bits | SIGN_BIT
+        } else {
+            !bits
+        }
+    }
+
+    fn decode(key: Key) -> Self {
+        let bits = if key & SIGN_BIT != 0 {
+            key & !SIGN_BIT
+        } else {
+            !key
+        };
+        f64::from_bits(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_order<K: KeyCodec + PartialEq + std::fmt::Debug>(sorted: &[K]) {
+        for window in sorted.windows(2) {
+            assert!(
+                window[0].encode() < window[1].encode(),
+                "{:?} should encode below {:?}",
+                window[0],
+                window[1]
+            );
+        }
+        for &key in sorted {
+            assert_eq!(K::decode(key.encode()), key);
+        }
+    }
+
+    #[test]
+    fn test_unsigned() {
+        check_order(&[0u64, 1, 1 << 40, u64::MAX]);
+        check_order(&[0u32, 7, u32::MAX]);
+    }
+
+    #[test]
+    fn test_signed() {
+        check_order(&[i64::MIN, -1 << 40, -1, 0, 1, 1 << 40, i64::MAX]);
+        check_order(&[i32::MIN, -5, 0, 5, i32::MAX]);
+        assert_eq!(0i64.encode(), SIGN_BIT);
+        assert_eq!(0i32.encode(), 0i64.encode());
+    }
+
+    #[test]
+    fn test_f64() {
+        check_order(&[
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            -0.0,
+            0.0,
+            f64::MIN_POSITIVE,
+            1.0,
+            1.5,
+            f64::MAX,
+            f64::INFINITY,
+        ]);
+        assert!(f64::decode(f64::NAN.encode()).is_nan());
+        assert!(f64::NAN.encode() > f64::INFINITY.encode());
+        assert!((-f64::NAN).encode() < f64::NEG_INFINITY.encode());
+    }
+
+    #[test]
+    fn test_matches_total_cmp() {
+        use rand::Rng;
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..10_000 {
+            let a = f64::from_bits(rng.r#gen());
+            let b = f64::from_bits(rng.r#gen());
+            assert_eq!(a.total_cmp(&b), a.encode().cmp(&b.encode()));
+        }
+    }
+}
@@ -1,22 +1,169 @@
+//! Range filters over `u64` keys.
+//!
+//! With the default `std` feature disabled, the crate is `no_std` + `alloc` and
+//! exposes the building blocks: `bitmap`, `infix_store`, `key_codec`,
+//! `range_filter` and `utils`. The `alloc` feature adds `Diva`, its snapshots
+//! and the tries, whose locks and level tables then come from `spin` and
+//! `hashbrown`, see `sync`. The other filters, persistence and `DivaView` need
+//! `std`.
+//!
+//! The crate builds for `wasm32-unknown-unknown`: randomness comes from the
+//! browser through `getrandom`'s `js` backend and `ShardedDiva` builds its
+//! shards sequentially there. The `wasm` feature adds a wasm-bindgen wrapper,
+//! see `wasm::WasmDiva`. The `ffi` feature exports a C interface to `Diva`,
+//! see `ffi`. The `python` feature builds the PyO3 extension module
+//! `range_filters` exposing `Diva`, `GrafiteFilter` and `BloomFilter`.
+//!
+//! The `log` feature emits `trace`-level events through the `log` crate from
+//! the query and build paths. Without it the events compile to nothing.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+/// `log::trace!` with the `log` feature, nothing without it
+macro_rules! trace {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        log::trace!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+#[cfg(feature = "std")]
+pub mod arf;
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub mod binary_search_tree;
 pub mod bitmap;
+#[cfg(feature = "std")]
+pub mod block_filter_set;
+#[cfg(feature = "std")]
+pub mod blocked_bloom_filter;
+#[cfg(feature = "std")]
 pub mod bloom_filter;
+#[cfg(feature = "std")]
+pub mod counting_bloom_filter;
+#[cfg(feature = "std")]
 pub mod data_gen;
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub mod diva;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod diva_snapshot;
+#[cfg(feature = "std")]
+pub mod diva_view;
+pub mod elias_fano;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+pub mod grafite_bucket_filter;
+#[cfg(feature = "std")]
 pub mod grafite_filter;
 pub mod infix_store;
+pub mod key_codec;
+#[cfg(feature = "std")]
+pub mod memento;
+#[cfg(feature = "std")]
+pub mod persist;
+#[cfg(feature = "std")]
+pub mod prefix_bloom_filter;
+#[cfg(feature = "std")]
+pub mod proteus;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod query_trace;
+#[cfg(feature = "std")]
+pub mod quotient_filter;
+pub mod range_filter;
+mod rank_directory;
+pub mod rank_select;
+#[cfg(feature = "std")]
+pub mod rencoder;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod rep_list;
+#[cfg(feature = "std")]
+pub mod rocksdb_filter;
+#[cfg(feature = "std")]
+pub mod rosetta_filter;
+mod run_scan;
+#[cfg(feature = "std")]
+pub mod sharded_diva;
+#[cfg(feature = "std")]
+pub mod snarf;
+#[cfg(feature = "std")]
+pub mod surf;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub mod sync;
 pub mod utils;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub mod x_fast_trie;
+#[cfg(any(feature = "std", feature = "alloc"))]
 pub mod y_fast_trie;
 
-pub use binary_search_tree::BinarySearchTreeGroup;
-pub use bitmap::{get_bit, rank, select, set_bit, has_bits_in_range};
+#[cfg(feature = "std")]
+pub use arf::Arf;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use binary_search_tree::{BinarySearchTreeGroup, BstIter};
+pub use bitmap::{BitVec, get_bit, has_bits_in_range, iter_ones, rank, select, set_bit};
+#[cfg(feature = "std")]
+pub use block_filter_set::{BlockFilterSet, BlockId};
+#[cfg(feature = "std")]
+pub use blocked_bloom_filter::BlockedBloomFilter;
+#[cfg(feature = "std")]
 pub use bloom_filter::BloomFilter;
-pub use diva::Diva;
-pub use grafite_filter::GrafiteFilter;
-pub use infix_store::InfixStore;
-pub use x_fast_trie::{RepNode, XFastLevel, XFastTrie, XFastValue};
-pub use y_fast_trie::YFastTrie;
+#[cfg(feature = "std")]
+pub use counting_bloom_filter::CountingBloomFilter;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use diva::{Diva, DivaBuilder, Partition};
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use diva_snapshot::DivaSnapshot;
+#[cfg(feature = "std")]
+pub use diva_view::DivaView;
+pub use elias_fano::EliasFano;
+pub use error::{InvariantViolation, RangeFilterError};
+#[cfg(feature = "std")]
+pub use grafite_bucket_filter::GrafiteBucketFilter;
+#[cfg(feature = "std")]
+pub use grafite_filter::{GrafiteFilter, GrafiteFilterBuilder};
+pub use infix_store::{
+    InfixIter, InfixStore, InfixStoreRef, OccupiedQuotients, StoreLayout, StoreProbe,
+};
+pub use key_codec::KeyCodec;
+#[cfg(feature = "std")]
+pub use memento::MementoFilter;
+#[cfg(feature = "std")]
+pub use prefix_bloom_filter::PrefixBloomFilter;
+#[cfg(feature = "std")]
+pub use proteus::{Proteus, ProteusConfig};
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use query_trace::{QueryKind, QueryTrace};
+#[cfg(feature = "std")]
+pub use quotient_filter::QuotientFilter;
+pub use range_filter::RangeFilter;
+pub use rank_select::{CompactBitmap, RankSelect, RankSelectOps};
+#[cfg(feature = "std")]
+pub use rencoder::REncoder;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use rep_list::{RepIter, RepList, RepNode, RepPayload, SharedRep};
+#[cfg(feature = "std")]
+pub use rocksdb_filter::DivaFilterPolicy;
+#[cfg(feature = "std")]
+pub use rosetta_filter::RosettaFilter;
+#[cfg(feature = "std")]
+pub use sharded_diva::ShardedDiva;
+#[cfg(feature = "std")]
+pub use snarf::Snarf;
+#[cfg(feature = "std")]
+pub use surf::{SuffixMode, Surf};
+#[cfg(feature = "wasm")]
+pub use wasm::WasmDiva;
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use x_fast_trie::{XFastIter, XFastLevel, XFastStats, XFastTrie, XFastValue};
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub use y_fast_trie::{GroupSizePolicy, YFastIter, YFastStats, YFastTrie};
 
 pub type Key = u64;
 pub const U64_BITS: usize = 64;
@@ -0,0 +1,502 @@
+//! Memento filter, a dynamic range filter over RSQF stores
+//!
+//! A key is split into a prefix, its high bits, and a memento, its low
+//! `memento_bits` bits. The prefix is hashed into a store, a quotient and a
+//! fingerprint, and the key is kept as the infix
+//! `quotient | fingerprint | memento` of a counting `InfixStore`. The keys of
+//! one prefix share quotient and fingerprint, so their mementos sit sorted in
+//! one run and a query over a part of the prefix is a single `count_in_range`.
+//!
+//! A range of up to `2^memento_bits` keys covers at most two prefixes, so its
+//! false positive rate is that of two fingerprint probes whatever its length;
+//! the fingerprints are sized from `fpr` to match. Wider ranges probe every
+//! prefix they cover. Keys are inserted and deleted one at a time, each store
+//! growing and shrinking through the size grades of its layout.
+//!
+//! All keys of a prefix hash to the same bucket. A bucket starts as one store
+//! and splits a full store in two at its median infix, so a prefix holds any
+//! number of keys in consecutive stores of its bucket, as dense key sets need.
+
+use crate::Key;
+use crate::error::RangeFilterError;
+use crate::infix_store::{InfixStore, MAX_REMAINDER_SIZE, StoreLayout};
+use crate::range_filter::RangeFilter;
+use crate::utils::mix64;
+
+/// prefixes a wide range query probes before it answers positive unchecked
+const MAX_PREFIX_PROBES: u64 = 1024;
+
+pub struct MementoFilter {
+    buckets: Vec<Bucket>,
+    memento_bits: u8,
+    fingerprint_bits: u8,
+    fpr: f64,
+    num_keys: usize,
+    seed: u64,
+}
+
+impl MementoFilter {
+    /// Create a new Memento filter with the given keys and false positive rate.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `max_range` - Length of the longest range queries the rate holds for
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    ///
+    /// Fails with `InvalidConfig` under the same conditions as `new_with_capacity`.
+    pub fn new_with_keys(keys: &[Key], max_range: u64, fpr: f64) -> Result<Self, RangeFilterError> {
+        let memento_bits = Self::memento_bits_for(max_range)?;
+        let mut prefixes: Vec<Key> = keys.iter().map(|&key| key >> memento_bits).collect();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+        let mut filter = Self::new_with_capacity(prefixes.len(), max_range, fpr)?;
+
+        let mut located: Vec<(usize, u64)> = keys.iter().map(|&key| filter.locate(key)).collect();
+        located.sort_unstable();
+        for (index, bucket) in filter.buckets.iter_mut().enumerate() {
+            let start = located.partition_point(|&(bucket, _)| bucket < index);
+            let end = located.partition_point(|&(bucket, _)| bucket <= index);
+            let infixes: Vec<u64> = located[start..end]
+                .iter()
+                .map(|&(_, infix)| infix)
+                .collect();
+            *bucket = Bucket::new_with_infixes(&infixes, bucket.remainder_size);
+        }
+        filter.num_keys = keys.len();
+        Ok(filter)
+    }
+
+    /// Create an empty Memento filter sized for keys with `capacity` distinct prefixes.
+    ///
+    /// The false positive rate only holds while the keys have at most
+    /// `capacity` distinct prefixes. Fails with `InvalidConfig` if `fpr` is
+    /// not in (0, 1), `max_range` is 0, or the memento and fingerprint do not
+    /// fit an infix.
+    ///
+    /// # Arguments
+    /// * `capacity` - Number of prefixes, `key >> memento_bits`, the filter is sized for
+    /// * `max_range` - Length of the longest range queries the rate holds for
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new_with_capacity(
+        capacity: usize,
+        max_range: u64,
+        fpr: f64,
+    ) -> Result<Self, RangeFilterError> {
+        if !(fpr > 0.0 && fpr < 1.0) {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "fpr must be in (0, 1), got {}",
+                fpr
+            )));
+        }
+        let layout = StoreLayout::DEFAULT;
+        let memento_bits = Self::memento_bits_for(max_range)?;
+        // at most one prefix per quotient; the keys of a prefix share its quotient
+        // and fingerprint, so a probe only meets the fingerprints of other
+        // prefixes however many keys each prefix has
+        let num_buckets = capacity.div_ceil(layout.num_quotients()).max(1);
+        let density = capacity as f64 / (num_buckets * layout.num_quotients()) as f64;
+
+        // a range of up to 2^m keys probes two prefixes
+        let fingerprint_bits = InfixStore::remainder_size_for_fpr(density, fpr / 2.0).max(1);
+        let remainder_size = memento_bits as u32 + fingerprint_bits as u32;
+        if remainder_size + layout.quotient_size() as u32 > MAX_REMAINDER_SIZE as u32 {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "{} memento bits and {} fingerprint bits do not fit a {} bit quotient infix",
+                memento_bits,
+                fingerprint_bits,
+                layout.quotient_size()
+            )));
+        }
+
+        let buckets = (0..num_buckets)
+            .map(|_| Bucket::new_with_infixes(&[], remainder_size as u8))
+            .collect();
+        Ok(Self {
+            buckets,
+            memento_bits,
+            fingerprint_bits,
+            fpr,
+            num_keys: 0,
+            seed: rand::random(),
+        })
+    }
+
+    /// smallest m with max_range <= 2^m
+    fn memento_bits_for(max_range: u64) -> Result<u8, RangeFilterError> {
+        if max_range == 0 {
+            return Err(RangeFilterError::InvalidConfig(
+                "max_range must be at least 1".to_string(),
+            ));
+        }
+        Ok((Key::BITS - (max_range - 1).leading_zeros()) as u8)
+    }
+
+    /// Insert a key, again for every copy of a key inserted more than once.
+    ///
+    /// A full store splits in two, so inserts do not run out of room.
+    pub fn insert(&mut self, key: Key) -> Result<(), RangeFilterError> {
+        let (bucket, infix) = self.locate(key);
+        self.buckets[bucket].insert(infix)?;
+        self.num_keys += 1;
+        Ok(())
+    }
+
+    /// Delete one copy of a key.
+    ///
+    /// Fails with `KeyNotFound` if the key is definitely absent. Deleting a
+    /// key that was never inserted but tests positive removes the key it
+    /// collides with, so only delete keys known to be present.
+    pub fn delete(&mut self, key: Key) -> Result<(), RangeFilterError> {
+        let (bucket, infix) = self.locate(key);
+        self.buckets[bucket]
+            .delete(infix)
+            .map_err(|_| RangeFilterError::KeyNotFound(key))?;
+        self.num_keys -= 1;
+        Ok(())
+    }
+
+    /// bucket of the prefix of `key` and the infix of `key` in it
+    fn locate(&self, key: Key) -> (usize, u64) {
+        let (bucket, base) = self.prefix_slot(key >> self.memento_bits);
+        (bucket, base | (key & self.memento_mask()))
+    }
+
+    /// bucket of a prefix and its infix with a zero memento
+    fn prefix_slot(&self, prefix: u64) -> (usize, u64) {
+        let hash = mix64(prefix ^ self.seed);
+        // multiply-shift on the high bits, the quotient and fingerprint come from the low ones
+        let bucket = ((hash as u128 * self.buckets.len() as u128) >> 64) as usize;
+        let quotient_size = StoreLayout::DEFAULT.quotient_size();
+        let quotient = hash & ((1 << quotient_size) - 1);
+        let fingerprint = (hash >> quotient_size) & ((1 << self.fingerprint_bits) - 1);
+        let remainder_size = self.memento_bits + self.fingerprint_bits;
+        let infix = (quotient << remainder_size) | (fingerprint << self.memento_bits);
+        (bucket, infix)
+    }
+
+    fn memento_mask(&self) -> u64 {
+        (1 << self.memento_bits) - 1
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist (with false positive rate `fpr`)
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        let (bucket, infix) = self.locate(key);
+        self.buckets[bucket].count_in_range(infix, infix) > 0
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// # Returns
+    /// * `true` if at least one key in the range might exist
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end || self.num_keys == 0 {
+            return false;
+        }
+        let (first, last) = (start >> self.memento_bits, end >> self.memento_bits);
+        if last - first >= MAX_PREFIX_PROBES {
+            return true;
+        }
+        let mask = self.memento_mask();
+        (first..=last).any(|prefix| {
+            let low = if prefix == first { start & mask } else { 0 };
+            let high = if prefix == last { end & mask } else { mask };
+            let (bucket, base) = self.prefix_slot(prefix);
+            self.buckets[bucket].count_in_range(base | low, base | high) > 0
+        })
+    }
+
+    /// Get the configured false positive rate.
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of keys in the filter, duplicates included.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of low key bits kept exactly as the memento.
+    pub fn memento_bits(&self) -> u8 {
+        self.memento_bits
+    }
+
+    /// Get the number of prefix fingerprint bits.
+    pub fn fingerprint_bits(&self) -> u8 {
+        self.fingerprint_bits
+    }
+
+    /// Get the number of RSQF stores over all buckets.
+    pub fn num_stores(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.stores.len()).sum()
+    }
+
+    /// Get the memory used by the filter: the bucket table plus the allocated
+    /// slots of every bucket's RSQF stores.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .buckets
+                .iter()
+                .map(Bucket::size_in_bytes)
+                .sum::<usize>()
+    }
+
+    /// Get the memory per stored key in bits, a key counted from its insert
+    /// until its delete.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+/// Stores of the prefixes hashed to one bucket
+///
+/// The stores hold consecutive parts of the bucket's infixes in ascending
+/// order, copies of one infix possibly spread over neighbouring stores. An
+/// infix goes to the last store starting at or below it, a full store splits
+/// at its median infix and a store emptied by deletes is dropped.
+struct Bucket {
+    // (smallest infix, store), the first store starting at infix 0
+    stores: Vec<(u64, InfixStore)>,
+    remainder_size: u8,
+}
+
+impl Bucket {
+    /// stores over sorted infixes, filled to three quarters to leave room for inserts
+    fn new_with_infixes(infixes: &[u64], remainder_size: u8) -> Self {
+        let layout = StoreLayout::DEFAULT;
+        let chunk_len = layout.max_capacity() * 3 / 4;
+        let mut stores: Vec<(u64, InfixStore)> = infixes
+            .chunks(chunk_len)
+            .map(|chunk| {
+                (
+                    chunk[0],
+                    InfixStore::new_counting(chunk, remainder_size, layout.clone()),
+                )
+            })
+            .collect();
+        match stores.first_mut() {
+            Some((first, _)) => *first = 0,
+            None => stores.push((0, InfixStore::new_counting(&[], remainder_size, layout))),
+        }
+        Self {
+            stores,
+            remainder_size,
+        }
+    }
+
+    /// index of the last store starting at or below `infix`
+    fn store_of(&self, infix: u64) -> usize {
+        self.stores.partition_point(|&(first, _)| first <= infix) - 1
+    }
+
+    fn insert(&mut self, infix: u64) -> Result<(), RangeFilterError> {
+        loop {
+            let index = self.store_of(infix);
+            match self.stores[index].1.insert(infix) {
+                Err(RangeFilterError::PartitionFull) => self.split(index),
+                result => return result,
+            }
+        }
+    }
+
+    /// split a full store at its median infix
+    fn split(&mut self, index: usize) {
+        let infixes = self.stores[index].1.infixes();
+        let mid = infixes.len() / 2;
+        let layout = StoreLayout::DEFAULT;
+        let low = InfixStore::new_counting(&infixes[..mid], self.remainder_size, layout.clone());
+        let high = InfixStore::new_counting(&infixes[mid..], self.remainder_size, layout);
+        self.stores[index].1 = low;
+        self.stores.insert(index + 1, (infixes[mid], high));
+    }
+
+    fn delete(&mut self, infix: u64) -> Result<(), RangeFilterError> {
+        // copies of `infix` may also sit in the stores before the one it maps to
+        for index in (0..=self.store_of(infix)).rev() {
+            let (first, store) = &mut self.stores[index];
+            if store.delete(infix).is_ok() {
+                if store.elem_count() == 0 && self.stores.len() > 1 {
+                    self.stores.remove(index);
+                    self.stores[0].0 = 0;
+                }
+                return Ok(());
+            }
+            if *first < infix {
+                break;
+            }
+        }
+        Err(RangeFilterError::KeyNotFound(infix))
+    }
+
+    /// number of stored infixes in [start, end]
+    fn count_in_range(&self, start: u64, end: u64) -> usize {
+        // the first store that can hold `start`, copies of it may reach back
+        let from = self
+            .stores
+            .partition_point(|&(first, _)| first < start)
+            .saturating_sub(1);
+        self.stores[from..=self.store_of(end)]
+            .iter()
+            .map(|(_, store)| store.count_in_range(start, end))
+            .sum()
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.stores.capacity() * std::mem::size_of::<(u64, InfixStore)>()
+            + self
+                .stores
+                .iter()
+                .map(|(_, store)| store.size_in_bytes() - std::mem::size_of::<InfixStore>())
+                .sum::<usize>()
+    }
+}
+
+impl RangeFilter for MementoFilter {
+    fn point_query(&self, key: Key) -> bool {
+        MementoFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        MementoFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_no_false_negatives_and_fpr() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..20_000).map(|_| rng.gen_range(0..1u64 << 40)).collect();
+        let set: BTreeSet<Key> = keys.iter().copied().collect();
+        let filter = MementoFilter::new_with_keys(&keys, 1 << 10, 0.01).unwrap();
+        assert_eq!(filter.memento_bits(), 10);
+        for &key in &keys {
+            assert!(filter.point_query(key));
+            assert!(filter.range_query(key.saturating_sub(1000), key));
+        }
+
+        // the rate holds for short and max_range long queries alike
+        for len in [1, 1 << 10] {
+            let (mut empty, mut false_positives) = (0, 0);
+            for _ in 0..20_000 {
+                let start = rng.gen_range(0..1u64 << 40);
+                let end = start + len - 1;
+                if set.range(start..=end).next().is_none() {
+                    empty += 1;
+                    false_positives += filter.range_query(start, end) as usize;
+                }
+            }
+            // 1% expected, allow for variance
+            assert!(
+                false_positives * 50 < empty,
+                "{} false positives in {} empty ranges of {}",
+                false_positives,
+                empty,
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let mut filter = MementoFilter::new_with_capacity(1000, 256, 0.001).unwrap();
+        assert!(!filter.range_query(0, Key::MAX - 1));
+        for key in [5, 5, 300, 301, Key::MAX] {
+            filter.insert(key).unwrap();
+        }
+        assert_eq!(filter.num_keys(), 5);
+        assert!(filter.range_query(256, 300) && filter.point_query(Key::MAX));
+        assert!(!filter.range_query(6, 255));
+
+        // one copy of 5 is left after the first delete
+        filter.delete(5).unwrap();
+        assert!(filter.point_query(5));
+        filter.delete(5).unwrap();
+        assert!(!filter.point_query(5));
+        assert!(matches!(
+            filter.delete(5),
+            Err(RangeFilterError::KeyNotFound(5))
+        ));
+        filter.delete(300).unwrap();
+        assert!(!filter.range_query(0, 300) && filter.range_query(0, 301));
+        assert_eq!(filter.num_keys(), 2);
+    }
+
+    #[test]
+    fn test_dense_and_sequential_keys() {
+        // one prefix of 5000 keys, more than a store holds
+        let keys: Vec<Key> = (0..5000).collect();
+        let filter = MementoFilter::new_with_keys(&keys, 1 << 16, 0.01).unwrap();
+        assert!(filter.num_stores() > 1);
+        assert!(keys.iter().all(|&key| filter.point_query(key)));
+        assert!(filter.range_query(4999, 10_000) && !filter.range_query(5000, 65_535));
+
+        let keys: Vec<Key> = (0..1_000_000).collect();
+        let filter = MementoFilter::new_with_keys(&keys, 1024, 0.01).unwrap();
+        assert_eq!(filter.num_keys(), 1_000_000);
+        assert!(keys.iter().step_by(7).all(|&key| filter.point_query(key)));
+        assert!(filter.range_query(999_999, 2_000_000));
+        // 977 prefixes, the fingerprints are sized for them and not for 10^6 keys
+        let (mut empty, mut false_positives) = (0, 0);
+        for start in (1_000_000..50_000_000).step_by(4099) {
+            empty += 1;
+            false_positives += filter.range_query(start, start + 1023) as usize;
+        }
+        assert!(
+            false_positives * 50 < empty,
+            "{} of {}",
+            false_positives,
+            empty
+        );
+
+        // inserted one by one, full stores split, then deletes empty them again
+        let mut filter = MementoFilter::new_with_capacity(100, 1 << 20, 0.01).unwrap();
+        for key in (0..20_000).rev() {
+            filter.insert(key).unwrap();
+            filter.insert(key / 2).unwrap();
+        }
+        assert!(filter.num_stores() > 10);
+        assert!((0..20_000).all(|key| filter.point_query(key)));
+        for key in 0..20_000 {
+            filter.delete(key).unwrap();
+            filter.delete(key / 2).unwrap();
+        }
+        assert_eq!((filter.num_keys(), filter.num_stores()), (0, 1));
+        assert!(!filter.range_query(0, 1 << 20));
+    }
+
+    #[test]
+    fn test_wide_ranges_and_configs() {
+        let filter = MementoFilter::new_with_keys(&[1 << 20], 16, 0.01).unwrap();
+        // covers 2^16 prefixes of 16 keys, too many to probe
+        assert!(filter.range_query(0, 1 << 20));
+        // covers 63 prefixes, all probed
+        assert!(!filter.range_query(0, 1000));
+        assert!(filter.range_query((1 << 20) - 1000, 1 << 20));
+
+        let single = MementoFilter::new_with_capacity(10, 1, 0.01).unwrap();
+        assert_eq!(single.memento_bits(), 0);
+        assert!(matches!(
+            MementoFilter::new_with_capacity(10, 0, 0.01),
+            Err(RangeFilterError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            MementoFilter::new_with_capacity(10, 1 << 50, 0.001),
+            Err(RangeFilterError::InvalidConfig(_))
+        ));
+        assert!(MementoFilter::new_with_capacity(10, 16, 1.0).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use std::io::{self, Read, Write};
+
+/// "RNGFLTR" followed by a zero byte
+const MAGIC: [u8; 8] = *b"RNGFLTR\0";
+/// magic, format version, filter kind and payload length
+const HEADER_LEN: usize = 24;
+
+/// Version of the container and of every payload written by this release
+///
+/// Bumped whenever a payload changes. Readers reject versions newer than the
+/// one they were built with and keep decoding every older version.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Filter stored in a saved file, so a Bloom filter cannot be loaded as a Diva
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    Diva = 1,
+    Bloom = 2,
+    Grafite = 3,
+}
+
+/// Stable binary format shared by `save_to` / `load_from` of every filter
+///
+/// All integers are little-endian:
+///
+/// ```text
+/// [magic: 8 bytes] [format version: u32] [filter kind: u32] [payload length: u64]
+/// [header checksum: u32]
+/// [payload: payload length bytes]
+/// [payload checksum: u32]
+/// ```
+///
+/// Both checksums are CRC-32 (IEEE), the header one over the 24 bytes before it.
+/// Payloads describe the logical content of a filter (e.g. the samples and
+/// infixes of a Diva) rather than in-memory buffers, so internal layout changes
+/// do not invalidate saved files.
+pub(crate) fn write_container<W: Write>(
+    writer: &mut W,
+    kind: FilterKind,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&(kind as u32).to_le_bytes());
+    header.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+
+    writer.write_all(&header)?;
+    writer.write_all(&crc32(&header).to_le_bytes())?;
+    writer.write_all(payload)?;
+    writer.write_all(&crc32(payload).to_le_bytes())
+}
+
+/// read and verify a container of the expected kind, returning the format
+/// version it was written with and its payload
+pub(crate) fn read_container<R: Read>(
+    reader: &mut R,
+    kind: FilterKind,
+) -> io::Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    let header_checksum = read_u32(reader)?;
+
+    if header[..8] != MAGIC {
+        return Err(invalid_data("bad magic"));
+    }
+    if crc32(&header) != header_checksum {
+        return Err(invalid_data("header checksum mismatch"));
+    }
+    let mut fields = PayloadReader::new(&header[8..]);
+    let version = fields.u32()?;
+    let stored_kind = fields.u32()?;
+    let payload_len = fields.u64()?;
+
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(invalid_data(&format!(
+            "unsupported format version {} (this release reads up to {})",
+            version, FORMAT_VERSION
+        )));
+    }
+    if stored_kind != kind as u32 {
+        return Err(invalid_data(&format!(
+            "expected filter kind {}, found {}",
+            kind as u32, stored_kind
+        )));
+    }
+
+    // read through take() so a corrupted length cannot trigger a huge allocation
+    let mut payload = Vec::new();
+    reader.take(payload_len).read_to_end(&mut payload)?;
+    if payload.len() as u64 != payload_len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated payload",
+        ));
+    }
+    if crc32(&payload) != read_u32(reader)? {
+        return Err(invalid_data("payload checksum mismatch"));
+    }
+    Ok((version, payload))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// little-endian payload encoder
+#[derive(Default)]
+pub(crate) struct PayloadWriter {
+    bytes: Vec<u8>,
+}
+
+impl PayloadWriter {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// little-endian payload decoder, failing with `InvalidData` past the end
+pub(crate) struct PayloadReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PayloadReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let (head, rest) = self
+            .bytes
+            .split_first_chunk::<N>()
+            .ok_or_else(|| invalid_data("payload too short"))?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> io::Result<u16> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    pub(crate) fn f64(&mut self) -> io::Result<f64> {
+        self.u64().map(f64::from_bits)
+    }
+
+    /// a count of items that are at least `item_len` bytes each, checked
+    /// against the remaining payload before anything is allocated for them
+    pub(crate) fn count(&mut self, item_len: usize) -> io::Result<usize> {
+        let len = self.u64()?;
+        if len.saturating_mul(item_len as u64) > self.bytes.len() as u64 {
+            return Err(invalid_data("length exceeds payload"));
+        }
+        Ok(len as usize)
+    }
+
+    /// fail if anything is left over
+    pub(crate) fn finish(self) -> io::Result<()> {
+        if !self.bytes.is_empty() {
+            return Err(invalid_data("trailing bytes after payload"));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// CRC-32 (IEEE 802.3, reflected polynomial) lookup table
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in bytes {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_container_roundtrip() {
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, FilterKind::Diva, b"payload").unwrap();
+        assert_eq!(bytes.len(), HEADER_LEN + 4 + 7 + 4);
+
+        let (version, payload) = read_container(&mut bytes.as_slice(), FilterKind::Diva).unwrap();
+        assert_eq!(version, FORMAT_VERSION);
+        assert_eq!(payload, b"payload");
+    }
+
+    #[test]
+    fn test_container_rejects_bad_input() {
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, FilterKind::Diva, b"payload").unwrap();
+        let read = |bytes: &[u8]| read_container(&mut &bytes[..], FilterKind::Diva);
+
+        assert!(read_container(&mut bytes.as_slice(), FilterKind::Bloom).is_err());
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read(&bytes[..HEADER_LEN]).is_err());
+
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] ^= 1;
+        assert!(read(&bad_magic).is_err());
+
+        let mut bad_payload = bytes.clone();
+        bad_payload[HEADER_LEN + 4] ^= 1;
+        assert!(read(&bad_payload).is_err());
+
+        // a newer version with a valid header checksum is still refused
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let checksum = crc32(&newer[..HEADER_LEN]);
+        newer[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&checksum.to_le_bytes());
+        let error = read(&newer).unwrap_err();
+        assert!(error.to_string().contains("unsupported format version"));
+    }
+
+    #[test]
+    fn test_payload_reader() {
+        let mut writer = PayloadWriter::default();
+        writer.u8(7);
+        writer.u16(300);
+        writer.f64(0.25);
+        writer.u64(2);
+        let bytes = writer.into_bytes();
+
+        let mut reader = PayloadReader::new(&bytes);
+        assert_eq!(reader.u8().unwrap(), 7);
+        assert_eq!(reader.u16().unwrap(), 300);
+        assert_eq!(reader.f64().unwrap(), 0.25);
+        // two 8-byte items do not fit in the empty rest of the payload
+        assert!(reader.count(8).is_err());
+        assert!(PayloadReader::new(&bytes).finish().is_err());
+    }
+
+    #[test]
+    fn test_bloom_save_load_roundtrip() {
+        use crate::BloomFilter;
+
+        let keys: Vec<u64> = (0..1000).map(|i| i * 7).collect();
+        let bloom = BloomFilter::new_with_keys(&keys, 0.01);
+        let mut bytes = Vec::new();
+        bloom.save_to(&mut bytes).unwrap();
+
+        let loaded = BloomFilter::load_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.num_keys(), bloom.num_keys());
+        assert_eq!(loaded.fpr(), bloom.fpr());
+        for key in 0..10_000 {
+            assert_eq!(loaded.point_query(key), bloom.point_query(key));
+        }
+        assert!(crate::Diva::load_from(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_grafite_save_load_roundtrip() {
+        use crate::{BloomFilter, GrafiteFilter};
+
+        let keys: Vec<u64> = (0..1000).map(|i| i * 7919).rev().collect();
+        let grafite = GrafiteFilter::new_with_keys(&keys, 0.01);
+        let bytes = grafite.to_bytes();
+
+        let loaded = GrafiteFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.num_keys(), grafite.num_keys());
+        assert_eq!(loaded.fpr(), grafite.fpr());
+        assert_eq!(loaded.size_in_bytes(), grafite.size_in_bytes());
+        // the same hash parameters and codes, so the same false positives
+        for start in (0..8_000_000).step_by(997) {
+            assert_eq!(
+                loaded.range_query(start, start + 500),
+                grafite.range_query(start, start + 500)
+            );
+        }
+        assert!(keys.iter().all(|&key| loaded.point_query(key)));
+        // the codes and not the keys are saved
+        assert!(bytes.len() < keys.len() * 8, "{} bytes", bytes.len());
+        assert!(BloomFilter::from_bytes(&bytes).is_err());
+        assert!(GrafiteFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let bloom = BloomFilter::new_with_keys(&keys, 0.01);
+        let reloaded = BloomFilter::from_bytes(&bloom.to_bytes()).unwrap();
+        assert!(keys.iter().all(|&key| reloaded.point_query(key)));
+        assert!(GrafiteFilter::from_bytes(&bloom.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_grafite_bytes_are_reproducible() {
+        use crate::GrafiteFilter;
+
+        let keys: Vec<u64> = (0..500).map(|i| (i * 104_729) % 65_536).collect();
+        let mut shuffled = keys.clone();
+        shuffled.reverse();
+        let build = |keys: &[u64], seed| {
+            GrafiteFilter::builder()
+                .seed(seed)
+                .build_with_keys(keys)
+                .unwrap()
+        };
+
+        let first = build(&keys, 7);
+        let second = build(&shuffled, 7);
+        assert_eq!(first.to_bytes(), second.to_bytes());
+        assert!((0..20_000).all(|key| first.point_query(key) == second.point_query(key)));
+        assert_ne!(build(&keys, 8).to_bytes(), first.to_bytes());
+        let bytes = first.to_bytes();
+        assert_eq!(GrafiteFilter::from_bytes(&bytes).unwrap().to_bytes(), bytes);
+    }
+}
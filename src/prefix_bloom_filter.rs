@@ -0,0 +1,236 @@
+//! RocksDB-style prefix Bloom filter
+//!
+//! A Bloom filter over the fixed-length prefixes of the keys, their top
+//! `prefix_bits` bits, as RocksDB builds with a fixed prefix extractor. A range
+//! inside one prefix costs a single probe whatever its length; a range over
+//! several prefixes cannot use the filter and is answered with "maybe", as
+//! RocksDB does for iterators leaving the prefix of their seek key.
+//!
+//! With whole-key filtering, RocksDB's default, the keys themselves go into a
+//! second Bloom filter that answers point queries on their own.
+
+use crate::Key;
+use crate::U64_BITS;
+use crate::bloom_filter::BloomFilter;
+use crate::range_filter::RangeFilter;
+
+pub struct PrefixBloomFilter {
+    prefixes: BloomFilter,
+    whole_keys: Option<BloomFilter>,
+    prefix_bits: u32,
+    fpr: f64,
+    num_keys: usize,
+}
+
+impl PrefixBloomFilter {
+    /// Create a new Prefix Bloom Filter over the `prefix_bits` top bits of the keys.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `prefix_bits` - Length of the prefixes, 1 up to 64
+    /// * `fpr` - Target false positive rate of a prefix probe (e.g., 0.01 for 1%)
+    ///
+    /// Panics if `prefix_bits` is 0 or above 64.
+    pub fn new_with_keys(keys: &[Key], prefix_bits: u32, fpr: f64) -> Self {
+        Self::build(keys, prefix_bits, fpr, false)
+    }
+
+    /// Create a new Prefix Bloom Filter that also filters whole keys, so point
+    /// queries are as precise as a `BloomFilter` over the keys.
+    ///
+    /// Panics if `prefix_bits` is 0 or above 64.
+    pub fn new_with_whole_keys(keys: &[Key], prefix_bits: u32, fpr: f64) -> Self {
+        Self::build(keys, prefix_bits, fpr, true)
+    }
+
+    fn build(keys: &[Key], prefix_bits: u32, fpr: f64, whole_key_filtering: bool) -> Self {
+        assert!(
+            (1..=U64_BITS as u32).contains(&prefix_bits),
+            "prefix_bits must be between 1 and {}, got {}",
+            U64_BITS,
+            prefix_bits
+        );
+        let shift = U64_BITS as u32 - prefix_bits;
+        let mut prefixes: Vec<Key> = keys.iter().map(|&key| key >> shift).collect();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        Self {
+            prefixes: BloomFilter::new_with_keys(&prefixes, fpr),
+            whole_keys: whole_key_filtering.then(|| BloomFilter::new_with_keys(keys, fpr)),
+            prefix_bits,
+            fpr,
+            num_keys: keys.len(),
+        }
+    }
+
+    /// prefix of a key, right-aligned
+    fn prefix(&self, key: Key) -> Key {
+        key >> (U64_BITS as u32 - self.prefix_bits)
+    }
+
+    /// Insert a key after construction.
+    ///
+    /// The bit arrays do not grow, so inserting keys with new prefixes raises
+    /// the false positive rate above `fpr`.
+    pub fn insert(&mut self, key: Key) {
+        self.prefixes.insert(self.prefix(key));
+        if let Some(whole_keys) = &mut self.whole_keys {
+            whole_keys.insert(key);
+        }
+        self.num_keys += 1;
+    }
+
+    /// Check if any key might start with the given prefix, right-aligned
+    /// like `key >> (64 - prefix_bits)`.
+    pub fn prefix_query(&self, prefix: Key) -> bool {
+        self.num_keys > 0 && self.prefixes.point_query(prefix)
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist, with false positive rate `fpr` under
+    ///   whole-key filtering and that of its prefix otherwise
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        match &self.whole_keys {
+            Some(whole_keys) => self.num_keys > 0 && whole_keys.point_query(key),
+            None => self.prefix_query(self.prefix(key)),
+        }
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// # Returns
+    /// * `true` if at least one key in the range might exist, always when the
+    ///   range covers more than one prefix
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end || self.num_keys == 0 {
+            return false;
+        }
+        if start == end {
+            return self.point_query(start);
+        }
+        let prefix = self.prefix(start);
+        prefix != self.prefix(end) || self.prefix_query(prefix)
+    }
+
+    /// Get the configured false positive rate of a probe.
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the length of the indexed prefixes in bits.
+    pub fn prefix_bits(&self) -> u32 {
+        self.prefix_bits
+    }
+
+    /// Check whether whole keys are filtered as well.
+    pub fn is_whole_key_filtering(&self) -> bool {
+        self.whole_keys.is_some()
+    }
+
+    /// Get the memory used by the filter: the struct plus the bit arrays of
+    /// the prefix filter and, if kept, the whole key filter.
+    pub fn size_in_bytes(&self) -> usize {
+        let bit_array =
+            |bloom: &BloomFilter| bloom.size_in_bytes() - std::mem::size_of::<BloomFilter>();
+        std::mem::size_of::<Self>()
+            + bit_array(&self.prefixes)
+            + self.whole_keys.as_ref().map_or(0, bit_array)
+    }
+
+    /// Get the memory per key in bits, every insert counted although keys
+    /// sharing a prefix set the same prefix bits.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for PrefixBloomFilter {
+    fn point_query(&self, key: Key) -> bool {
+        PrefixBloomFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        PrefixBloomFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_no_false_negatives_and_fpr() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..10_000).map(|_| rng.gen_range(0..Key::MAX)).collect();
+        // 2^24 prefixes of 2^40 keys each
+        let filter = PrefixBloomFilter::new_with_keys(&keys, 24, 0.01);
+        for &key in &keys {
+            assert!(filter.point_query(key));
+            assert!(filter.range_query(key & !0xFFFF, key | 0xFFFF));
+            assert!(filter.prefix_query(key >> 40));
+        }
+
+        let prefixes: HashSet<Key> = keys.iter().map(|&key| key >> 40).collect();
+        let false_positives = (0..20_000)
+            .map(|_| rng.gen_range(0..1u64 << 24))
+            .filter(|prefix| !prefixes.contains(prefix))
+            .filter(|&prefix| filter.range_query(prefix << 40, (prefix << 40) + 1000))
+            .count();
+        // 1% of about 20_000 expected, allow for variance
+        assert!(false_positives < 400, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_ranges_across_prefixes_are_maybe() {
+        let filter = PrefixBloomFilter::new_with_keys(&[5 << 56], 8, 1e-6);
+        assert!(filter.range_query(5 << 56, (5 << 56) + 100));
+        assert!(!filter.range_query(6 << 56, (7 << 56) - 1));
+        // [6 << 56, 7 << 56] reaches into the next prefix
+        assert!(filter.range_query(6 << 56, 7 << 56));
+        assert!(!filter.range_query(10, 5));
+
+        let empty = PrefixBloomFilter::new_with_keys(&[], 8, 0.01);
+        assert!(!empty.range_query(0, Key::MAX) && !empty.prefix_query(0));
+        assert_eq!(empty.bits_per_key(), 0.0);
+    }
+
+    #[test]
+    fn test_whole_key_filtering_and_insert() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 2).collect();
+        let prefix_only = PrefixBloomFilter::new_with_keys(&keys, 32, 1e-6);
+        let whole = PrefixBloomFilter::new_with_whole_keys(&keys, 32, 1e-6);
+        assert!(whole.is_whole_key_filtering() && !prefix_only.is_whole_key_filtering());
+        // all keys share prefix 0, only the whole keys tell 3 apart
+        assert!(prefix_only.point_query(3));
+        assert!(!whole.point_query(3) && whole.point_query(4));
+        assert!(whole.range_query(3, 4));
+        assert!(whole.size_in_bytes() > prefix_only.size_in_bytes());
+
+        let mut filter = PrefixBloomFilter::new_with_whole_keys(&[], 16, 0.01);
+        filter.insert(Key::MAX);
+        assert!(filter.point_query(Key::MAX) && filter.prefix_query(0xFFFF));
+        assert!(filter.range_query(Key::MAX - 10, Key::MAX));
+        assert_eq!(filter.num_keys(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "prefix_bits must be between 1 and 64")]
+    fn test_rejects_zero_prefix_bits() {
+        PrefixBloomFilter::new_with_keys(&[1], 0, 0.01);
+    }
+}
@@ -0,0 +1,517 @@
+//! Proteus: a prefix trie and a prefix Bloom filter tuned to a query sample
+//!
+//! The filter keeps the distinct `trie_depth`-bit prefixes of the keys exactly,
+//! Elias-Fano encoded, and inserts the `bloom_prefix_len`-bit prefixes into a
+//! Bloom filter. A range query first asks the trie for a prefix inside the
+//! range and then probes the Bloom filter for every longer prefix of the range
+//! below a prefix the trie found. Either part can be left out: depth 0 drops
+//! the trie, prefix length 0 the Bloom filter.
+//!
+//! Which prefix lengths work best depends on the keys and on the queries, so
+//! `ProteusConfig::model` scores every pair of lengths on a sample of empty
+//! queries with the contextual prefix FPR (CPFPR) model of the paper: a query
+//! is a false positive if the trie finds a prefix and then either a Bloom
+//! prefix of the range is the prefix of a real key or one of the probes of
+//! the range answers "maybe". The pair with the lowest expected rate under the
+//! memory budget wins.
+//!
+//! Ranges needing more than 4096 Bloom probes are answered with "maybe".
+
+use fastbloom::BloomFilter as FastBloomFilter;
+
+use crate::Key;
+use crate::U64_BITS;
+use crate::elias_fano::EliasFano;
+use crate::range_filter::RangeFilter;
+use crate::rank_select::RankSelectOps;
+
+/// probes past which a range query gives up and answers "maybe"
+const MAX_BLOOM_PROBES: u64 = 1 << 12;
+/// the trie is a bitmap over 2^trie_depth prefixes, which must fit in a usize
+const MAX_TRIE_DEPTH: u32 = 56;
+/// prefix lengths `ProteusConfig::model` tries
+const MODEL_STEP: u32 = 4;
+
+/// Prefix lengths, in bits, of the two parts of a `Proteus` filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProteusConfig {
+    /// length of the prefixes kept exactly, 0 for no trie
+    pub trie_depth: u32,
+    /// length of the prefixes in the Bloom filter, 0 for no Bloom filter
+    pub bloom_prefix_len: u32,
+}
+
+impl Default for ProteusConfig {
+    /// a plain Bloom filter over the keys
+    fn default() -> Self {
+        Self {
+            trie_depth: 0,
+            bloom_prefix_len: U64_BITS as u32,
+        }
+    }
+}
+
+impl ProteusConfig {
+    /// Pick the prefix lengths with the lowest modeled false positive rate on
+    /// the empty ranges of `queries`, within `bits_per_key` bits per key.
+    ///
+    /// Lengths are tried in steps of 4 bits. Without an empty sample query
+    /// there is nothing to score and the default, a plain Bloom filter, is
+    /// returned.
+    pub fn model(keys: &[Key], bits_per_key: f64, queries: &[(Key, Key)]) -> Self {
+        Self::model_with_fpr(keys, bits_per_key, queries).0
+    }
+
+    /// `model` and the expected false positive rate of the chosen lengths
+    pub fn model_with_fpr(keys: &[Key], bits_per_key: f64, queries: &[(Key, Key)]) -> (Self, f64) {
+        let mut sorted_keys = keys.to_vec();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+        let empty: Vec<(Key, Key)> = queries
+            .iter()
+            .copied()
+            .filter(|&(start, end)| start <= end && !any_key_in(&sorted_keys, start, end))
+            .collect();
+        if sorted_keys.is_empty() || empty.is_empty() {
+            return (Self::default(), 0.0);
+        }
+
+        let num_keys = sorted_keys.len() as f64;
+        let budget = bits_per_key * num_keys;
+        let mut best = (Self::default(), f64::INFINITY);
+        for trie_depth in (0..=MAX_TRIE_DEPTH).step_by(MODEL_STEP as usize) {
+            let trie_bits = trie_bits(distinct_prefixes(&sorted_keys, trie_depth), trie_depth);
+            if trie_bits > budget {
+                break;
+            }
+            let bloom_lens =
+                (trie_depth + MODEL_STEP..=U64_BITS as u32).step_by(MODEL_STEP as usize);
+            for bloom_prefix_len in std::iter::once(0).chain(bloom_lens) {
+                if trie_depth == 0 && bloom_prefix_len == 0 {
+                    continue;
+                }
+                let config = Self {
+                    trie_depth,
+                    bloom_prefix_len,
+                };
+                // false positive rate of one Bloom probe with the optimal number of hashes
+                let bloom_fpr = match bloom_prefix_len {
+                    0 => 1.0,
+                    _ => {
+                        let prefixes = distinct_prefixes(&sorted_keys, bloom_prefix_len) as f64;
+                        let ln2 = std::f64::consts::LN_2;
+                        (-(budget - trie_bits) / prefixes * ln2 * ln2).exp()
+                    }
+                };
+                let expected = empty
+                    .iter()
+                    .map(|&(start, end)| config.query_fpr(&sorted_keys, bloom_fpr, start, end))
+                    .sum::<f64>()
+                    / empty.len() as f64;
+                if expected < best.1 {
+                    best = (config, expected);
+                }
+            }
+        }
+        best
+    }
+
+    /// chance that the empty range [start, end] tests positive
+    fn query_fpr(self, sorted_keys: &[Key], bloom_fpr: f64, start: Key, end: Key) -> f64 {
+        let (trie_depth, bloom_len) = (self.trie_depth, self.bloom_prefix_len);
+        let (low, high) = widen(start, end, trie_depth);
+        if !any_key_in(sorted_keys, low, high) {
+            return 0.0;
+        }
+        if bloom_len == 0 {
+            return 1.0;
+        }
+        let (low, high) = widen(start, end, bloom_len);
+        if any_key_in(sorted_keys, low, high) {
+            // a Bloom prefix of the range is the prefix of a key
+            return 1.0;
+        }
+
+        // Bloom prefixes of the range below the trie prefixes of the keys
+        let mut probes = 0;
+        let mut index = sorted_keys.partition_point(|&key| key < widen(start, end, trie_depth).0);
+        while let Some(&key) = sorted_keys.get(index) {
+            let (segment_low, segment_high) = widen(key, key, trie_depth);
+            if segment_low > end {
+                break;
+            }
+            probes += prefix(end.min(segment_high), bloom_len)
+                - prefix(start.max(segment_low), bloom_len)
+                + 1;
+            if probes > MAX_BLOOM_PROBES {
+                return 1.0;
+            }
+            index += sorted_keys[index..].partition_point(|&key| key <= segment_high);
+        }
+        1.0 - (1.0 - bloom_fpr).powi(probes as i32)
+    }
+}
+
+/// the `len`-bit prefix of `key`
+fn prefix(key: Key, len: u32) -> Key {
+    match len {
+        0 => 0,
+        len => key >> (U64_BITS as u32 - len),
+    }
+}
+
+/// [start, end] widened to whole `len`-bit prefixes
+fn widen(start: Key, end: Key, len: u32) -> (Key, Key) {
+    let mask = match len {
+        64 => 0,
+        len => Key::MAX >> len,
+    };
+    (start & !mask, end | mask)
+}
+
+fn any_key_in(sorted_keys: &[Key], start: Key, end: Key) -> bool {
+    let index = sorted_keys.partition_point(|&key| key < start);
+    sorted_keys.get(index).is_some_and(|&key| key <= end)
+}
+
+fn distinct_prefixes(sorted_keys: &[Key], len: u32) -> usize {
+    let mut count = 0;
+    let mut last = None;
+    for &key in sorted_keys {
+        if last != Some(prefix(key, len)) {
+            count += 1;
+            last = Some(prefix(key, len));
+        }
+    }
+    count
+}
+
+/// Elias-Fano size of `prefixes` distinct `depth`-bit prefixes
+fn trie_bits(prefixes: usize, depth: u32) -> f64 {
+    match depth {
+        0 => 0.0,
+        _ => {
+            let prefixes = prefixes.max(1) as f64;
+            prefixes * (2.0 + (2f64.powi(depth as i32) / prefixes).log2().max(0.0))
+        }
+    }
+}
+
+pub struct Proteus {
+    // set bit p for every trie_depth-bit prefix p of a key
+    trie: Option<EliasFano>,
+    bloom: Option<FastBloomFilter>,
+    config: ProteusConfig,
+    num_keys: usize,
+}
+
+impl Proteus {
+    /// Create a new Proteus filter with the prefix lengths `ProteusConfig::model`
+    /// picks for `sample_queries`.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `bits_per_key` - Memory budget of the trie and Bloom filter together
+    /// * `sample_queries` - Ranges like the ones the filter will be asked, the
+    ///   empty ones are used to tune it
+    pub fn new_with_keys(keys: &[Key], bits_per_key: f64, sample_queries: &[(Key, Key)]) -> Self {
+        let config = ProteusConfig::model(keys, bits_per_key, sample_queries);
+        Self::new_with_config(keys, bits_per_key, config)
+    }
+
+    /// Create a new Proteus filter with fixed prefix lengths.
+    ///
+    /// The Bloom filter gets whatever is left of `bits_per_key` after the trie,
+    /// at least 64 bits.
+    ///
+    /// Panics if the trie is deeper than 56 bits, or if the Bloom prefixes are
+    /// not longer than the trie ones or longer than 64 bits.
+    pub fn new_with_config(keys: &[Key], bits_per_key: f64, config: ProteusConfig) -> Self {
+        let ProteusConfig {
+            trie_depth,
+            bloom_prefix_len,
+        } = config;
+        assert!(
+            trie_depth <= MAX_TRIE_DEPTH,
+            "trie depth must be at most {}, got {}",
+            MAX_TRIE_DEPTH,
+            trie_depth
+        );
+        assert!(
+            bloom_prefix_len == 0
+                || (trie_depth < bloom_prefix_len && bloom_prefix_len <= U64_BITS as u32),
+            "bloom prefixes must be 0 or between {} and {} bits, got {}",
+            trie_depth + 1,
+            U64_BITS,
+            bloom_prefix_len
+        );
+        let mut sorted_keys = keys.to_vec();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+
+        let trie = (trie_depth > 0).then(|| {
+            let mut prefixes: Vec<usize> = sorted_keys
+                .iter()
+                .map(|&key| prefix(key, trie_depth) as usize)
+                .collect();
+            prefixes.dedup();
+            EliasFano::from_ones(prefixes, 1 << trie_depth)
+        });
+        let bloom = (bloom_prefix_len > 0).then(|| {
+            let trie_bytes = trie.as_ref().map_or(0, EliasFano::heap_size);
+            let budget = (bits_per_key * sorted_keys.len() as f64) as usize;
+            let num_bits = budget.saturating_sub(trie_bytes * 8).max(64);
+            let mut prefixes: Vec<Key> = sorted_keys
+                .iter()
+                .map(|&key| prefix(key, bloom_prefix_len))
+                .collect();
+            prefixes.dedup();
+            let mut bloom = FastBloomFilter::with_num_bits(num_bits)
+                .seed(&rand::random())
+                .expected_items(prefixes.len().max(1));
+            for prefix in &prefixes {
+                bloom.insert(prefix);
+            }
+            bloom
+        });
+
+        Self {
+            trie,
+            bloom,
+            config,
+            num_keys: sorted_keys.len(),
+        }
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.range_query(key, key)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// # Returns
+    /// * `true` if at least one key in the range might exist
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end || self.num_keys == 0 {
+            return false;
+        }
+        let ProteusConfig {
+            trie_depth,
+            bloom_prefix_len,
+        } = self.config;
+        let Some(bloom) = &self.bloom else {
+            return self.trie_has_prefix_in(start, end);
+        };
+
+        // the parts of the range below a trie prefix, the whole range without a trie
+        let mut segments = Vec::new();
+        match &self.trie {
+            None => segments.push((start, end)),
+            Some(trie) => {
+                let first = trie.rank(prefix(start, trie_depth) as usize);
+                let last = trie.rank(prefix(end, trie_depth) as usize + 1);
+                for rank in first..last {
+                    let found = trie
+                        .select(rank)
+                        .expect("rank below the number of prefixes");
+                    let low = (found as Key) << (U64_BITS as u32 - trie_depth);
+                    let high = low | (Key::MAX >> trie_depth);
+                    segments.push((start.max(low), end.min(high)));
+                }
+            }
+        }
+
+        let mut probes = 0;
+        for &(low, high) in &segments {
+            probes += prefix(high, bloom_prefix_len) - prefix(low, bloom_prefix_len) + 1;
+            if probes > MAX_BLOOM_PROBES {
+                return true;
+            }
+        }
+        segments.iter().any(|&(low, high)| {
+            (prefix(low, bloom_prefix_len)..=prefix(high, bloom_prefix_len))
+                .any(|bloom_prefix| bloom.contains(&bloom_prefix))
+        })
+    }
+
+    /// whether a trie prefix lies in [start, end], true without a trie
+    fn trie_has_prefix_in(&self, start: Key, end: Key) -> bool {
+        self.trie.as_ref().is_none_or(|trie| {
+            let depth = self.config.trie_depth;
+            trie.rank(prefix(end, depth) as usize + 1) > trie.rank(prefix(start, depth) as usize)
+        })
+    }
+
+    /// Get the prefix lengths of the filter.
+    pub fn config(&self) -> ProteusConfig {
+        self.config
+    }
+
+    /// Get the number of distinct keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the memory used by the filter: the struct, the Elias-Fano prefix
+    /// trie and the Bloom filter bits, either of which the tuned design may leave
+    /// out.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.trie.as_ref().map_or(0, EliasFano::heap_size)
+            + self
+                .bloom
+                .as_ref()
+                .map_or(0, |bloom| std::mem::size_of_val(bloom.as_slice()))
+    }
+
+    /// Get the memory per distinct key in bits.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for Proteus {
+    fn point_query(&self, key: Key) -> bool {
+        Proteus::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        Proteus::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    fn random_keys(count: usize) -> Vec<Key> {
+        let mut rng = rand::thread_rng();
+        (0..count).map(|_| rng.gen_range(0..Key::MAX)).collect()
+    }
+
+    fn empty_ranges(keys: &BTreeSet<Key>, width: Key, count: usize) -> Vec<(Key, Key)> {
+        let mut rng = rand::thread_rng();
+        let mut ranges = Vec::new();
+        while ranges.len() < count {
+            let start = rng.gen_range(0..Key::MAX - width);
+            if keys.range(start..=start + width).next().is_none() {
+                ranges.push((start, start + width));
+            }
+        }
+        ranges
+    }
+
+    fn false_positive_rate(filter: &Proteus, ranges: &[(Key, Key)]) -> f64 {
+        let positives = ranges
+            .iter()
+            .filter(|&&(start, end)| filter.range_query(start, end))
+            .count();
+        positives as f64 / ranges.len() as f64
+    }
+
+    #[test]
+    fn test_no_false_negatives_for_every_shape() {
+        let keys = random_keys(2000);
+        let mut rng = rand::thread_rng();
+        let configs = [
+            ProteusConfig::default(),
+            ProteusConfig {
+                trie_depth: 16,
+                bloom_prefix_len: 0,
+            },
+            ProteusConfig {
+                trie_depth: 12,
+                bloom_prefix_len: 40,
+            },
+            ProteusConfig {
+                trie_depth: 24,
+                bloom_prefix_len: 64,
+            },
+        ];
+        for config in configs {
+            let filter = Proteus::new_with_config(&keys, 12.0, config);
+            assert_eq!(filter.config(), config);
+            for &key in &keys {
+                assert!(filter.point_query(key), "{:?} lost {}", config, key);
+                let width = rng.gen_range(0..1u64 << 40);
+                assert!(filter.range_query(key.saturating_sub(width), key.saturating_add(3)));
+            }
+            assert!(!filter.range_query(10, 5));
+        }
+    }
+
+    #[test]
+    fn test_model_tunes_for_the_workload() {
+        let keys = random_keys(5000);
+        let set: BTreeSet<Key> = keys.iter().copied().collect();
+
+        for width in [0, 1 << 20, 1 << 36] {
+            let sample = empty_ranges(&set, width, 300);
+            let (config, expected) = ProteusConfig::model_with_fpr(&keys, 12.0, &sample);
+            let filter = Proteus::new_with_config(&keys, 12.0, config);
+            let measured = false_positive_rate(&filter, &empty_ranges(&set, width, 2000));
+            assert!(
+                measured < 0.05 && expected < 0.05,
+                "width {}: {:?} expected {} measured {}",
+                width,
+                config,
+                expected,
+                measured
+            );
+            if width == 1 << 36 {
+                // Bloom prefixes past 40 bits would take over 4096 probes per query
+                assert!(config.bloom_prefix_len <= 40, "{:?}", config);
+            }
+            assert!(
+                filter.bits_per_key() < 16.0,
+                "{:?}: {} bits per key",
+                config,
+                filter.bits_per_key()
+            );
+        }
+    }
+
+    #[test]
+    fn test_model_without_empty_queries() {
+        let keys = [10, 20, 30];
+        assert_eq!(
+            ProteusConfig::model(&keys, 10.0, &[]),
+            ProteusConfig::default()
+        );
+        // every sample range holds a key
+        assert_eq!(
+            ProteusConfig::model(&keys, 10.0, &[(5, 15), (30, 30)]),
+            ProteusConfig::default()
+        );
+        let filter = Proteus::new_with_keys(&keys, 10.0, &[]);
+        assert!(filter.point_query(20));
+
+        let empty = Proteus::new_with_keys(&[], 10.0, &[(1, 2)]);
+        assert!(!empty.range_query(0, Key::MAX));
+        assert_eq!(empty.bits_per_key(), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "bloom prefixes must be 0 or between 17 and 64 bits")]
+    fn test_rejects_bloom_prefixes_inside_the_trie() {
+        Proteus::new_with_config(
+            &[1],
+            10.0,
+            ProteusConfig {
+                trie_depth: 16,
+                bloom_prefix_len: 8,
+            },
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Python bindings, built as the `range_filters` extension module
+//!
+//! Build a wheel with `maturin build --release` (see `pyproject.toml`). Keys
+//! are Python ints in `[0, 2^64)`; batch queries take lists of keys or of
+//! `(start, end)` tuples and release the GIL while they run.
+
+use crate::Key;
+use crate::bloom_filter::BloomFilter;
+use crate::diva::Diva;
+use crate::error::RangeFilterError;
+use crate::grafite_filter::GrafiteFilter;
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+impl From<RangeFilterError> for PyErr {
+    fn from(error: RangeFilterError) -> Self {
+        match error {
+            RangeFilterError::KeyNotFound(_) => PyKeyError::new_err(error.to_string()),
+            RangeFilterError::InvalidConfig(_) | RangeFilterError::KeyOutOfUniverse(_) => {
+                PyValueError::new_err(error.to_string())
+            }
+            _ => PyRuntimeError::new_err(error.to_string()),
+        }
+    }
+}
+
+/// reject a false positive rate the baseline filters would panic on
+fn check_fpr(fpr: f64) -> PyResult<()> {
+    if fpr > 0.0 && fpr < 1.0 {
+        Ok(())
+    } else {
+        Err(PyValueError::new_err(format!(
+            "false positive rate must be in (0, 1), got {}",
+            fpr
+        )))
+    }
+}
+
+/// Dynamic range filter, `Diva(keys, target_size=1024, fpr=0.01)`
+#[pyclass(name = "Diva", module = "range_filters")]
+pub struct PyDiva {
+    inner: Diva,
+}
+
+#[pymethods]
+impl PyDiva {
+    #[new]
+    #[pyo3(signature = (keys, target_size = 1024, fpr = 0.01))]
+    fn new(py: Python<'_>, keys: Vec<Key>, target_size: usize, fpr: f64) -> PyResult<Self> {
+        let inner = py.allow_threads(|| {
+            Diva::builder()
+                .target_size(target_size)
+                .fpr(fpr)
+                .build_with_keys(&keys)
+        })?;
+        Ok(Self { inner })
+    }
+
+    /// Open a filter written by `to_bytes` or `Diva::save_to`
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        let inner =
+            Diva::load_from(&mut &bytes[..]).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut bytes = Vec::new();
+        self.inner
+            .save_to(&mut bytes)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    fn insert(&mut self, key: Key) -> PyResult<()> {
+        Ok(self.inner.insert(key)?)
+    }
+
+    fn delete(&mut self, key: Key) -> PyResult<()> {
+        Ok(self.inner.delete(key)?)
+    }
+
+    fn contains(&self, key: Key) -> bool {
+        self.inner.contains(key)
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.contains(key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        self.inner.range_query(start, end)
+    }
+
+    fn contains_many(&self, py: Python<'_>, keys: Vec<Key>) -> Vec<bool> {
+        py.allow_threads(|| self.inner.contains_many(&keys))
+    }
+
+    fn range_query_many(&self, py: Python<'_>, ranges: Vec<(Key, Key)>) -> Vec<bool> {
+        py.allow_threads(|| self.inner.range_query_many(&ranges))
+    }
+
+    /// observed false positive rate over keys known to be absent
+    fn measure_fpr(&self, py: Python<'_>, negatives: Vec<Key>) -> f64 {
+        py.allow_threads(|| self.inner.measure_fpr(&negatives))
+    }
+
+    fn expected_fpr(&self) -> f64 {
+        self.inner.expected_fpr()
+    }
+
+    #[getter]
+    fn num_keys(&self) -> usize {
+        self.inner.num_keys()
+    }
+
+    #[getter]
+    fn size_in_bytes(&self) -> usize {
+        self.inner.size_in_bytes()
+    }
+
+    #[getter]
+    fn bits_per_key(&self) -> f64 {
+        self.inner.bits_per_key()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.num_keys()
+    }
+}
+
+/// Static Grafite range filter, `GrafiteFilter(keys, epsilon=0.01)`
+#[pyclass(name = "GrafiteFilter", module = "range_filters")]
+pub struct PyGrafiteFilter {
+    inner: GrafiteFilter,
+}
+
+#[pymethods]
+impl PyGrafiteFilter {
+    #[new]
+    #[pyo3(signature = (keys, epsilon = 0.01))]
+    fn new(py: Python<'_>, keys: Vec<Key>, epsilon: f64) -> PyResult<Self> {
+        check_fpr(epsilon)?;
+        let inner = py.allow_threads(|| GrafiteFilter::new_with_keys(&keys, epsilon));
+        Ok(Self { inner })
+    }
+
+    fn contains(&self, key: Key) -> bool {
+        self.inner.point_query(key)
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.point_query(key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        self.inner.range_query(start, end)
+    }
+
+    fn range_query_many(&self, py: Python<'_>, ranges: Vec<(Key, Key)>) -> Vec<bool> {
+        py.allow_threads(|| self.inner.range_query_many(&ranges))
+    }
+
+    #[getter]
+    fn fpr(&self) -> f64 {
+        self.inner.fpr()
+    }
+
+    #[getter]
+    fn num_keys(&self) -> usize {
+        self.inner.num_keys()
+    }
+
+    #[getter]
+    fn size_in_bytes(&self) -> usize {
+        self.inner.size_in_bytes()
+    }
+
+    #[getter]
+    fn bits_per_key(&self) -> f64 {
+        self.inner.bits_per_key()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.num_keys()
+    }
+}
+
+/// Bloom filter baseline answering ranges key by key, `BloomFilter(keys, fpr=0.01)`
+#[pyclass(name = "BloomFilter", module = "range_filters")]
+pub struct PyBloomFilter {
+    inner: BloomFilter,
+}
+
+#[pymethods]
+impl PyBloomFilter {
+    #[new]
+    #[pyo3(signature = (keys, fpr = 0.01))]
+    fn new(py: Python<'_>, keys: Vec<Key>, fpr: f64) -> PyResult<Self> {
+        check_fpr(fpr)?;
+        let inner = py.allow_threads(|| BloomFilter::new_with_keys(&keys, fpr));
+        Ok(Self { inner })
+    }
+
+    /// Open a filter written by `to_bytes` or `BloomFilter::save_to`
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<Self> {
+        let inner = BloomFilter::load_from(&mut &bytes[..])
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self { inner })
+    }
+
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut bytes = Vec::new();
+        self.inner
+            .save_to(&mut bytes)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    fn contains(&self, key: Key) -> bool {
+        self.inner.point_query(key)
+    }
+
+    fn __contains__(&self, key: Key) -> bool {
+        self.inner.point_query(key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        self.inner.range_query(start, end)
+    }
+
+    fn range_query_many(&self, py: Python<'_>, ranges: Vec<(Key, Key)>) -> Vec<bool> {
+        py.allow_threads(|| self.inner.range_query_many(&ranges))
+    }
+
+    #[getter]
+    fn fpr(&self) -> f64 {
+        self.inner.fpr()
+    }
+
+    #[getter]
+    fn num_keys(&self) -> usize {
+        self.inner.num_keys()
+    }
+
+    #[getter]
+    fn size_in_bytes(&self) -> usize {
+        self.inner.size_in_bytes()
+    }
+
+    #[getter]
+    fn bits_per_key(&self) -> f64 {
+        self.inner.bits_per_key()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.num_keys()
+    }
+}
+
+#[pymodule]
+fn range_filters(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyDiva>()?;
+    module.add_class::<PyGrafiteFilter>()?;
+    module.add_class::<PyBloomFilter>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::py_run;
+
+    #[test]
+    fn test_python_module() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "range_filters").unwrap();
+            range_filters(&module).unwrap();
+            py_run!(
+                py,
+                module,
+                r#"
+keys = list(range(0, 100_000, 100))
+for cls, args in [(module.Diva, (128, 0.01)), (module.GrafiteFilter, ()), (module.BloomFilter, ())]:
+    f = cls(keys, *args)
+    assert len(f) == f.num_keys == len(keys)
+    assert 500 in f and f.contains(500)
+    assert f.range_query(450, 550)
+    assert f.range_query_many([(450, 550), (600, 700)])[0]
+    assert f.bits_per_key > 0
+
+diva = module.Diva(keys)
+diva.insert(150)
+assert diva.contains_many([150, 200]) == [True, True]
+diva.delete(150)
+try:
+    diva.delete(150)
+    raise AssertionError("expected KeyError")
+except KeyError:
+    pass
+loaded = module.Diva.from_bytes(diva.to_bytes())
+assert loaded.num_keys == diva.num_keys
+try:
+    module.BloomFilter(keys, fpr=1.5)
+    raise AssertionError("expected ValueError")
+except ValueError:
+    pass
+"#
+            );
+        });
+    }
+}
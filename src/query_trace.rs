@@ -0,0 +1,52 @@
+use crate::Key;
+use crate::infix_store::StoreProbe;
+use crate::sync::Arc;
+
+/// Callback receiving the trace of every traced query, see `Diva::set_query_tracer`
+pub type QueryTracer = Arc<dyn Fn(&QueryTrace) + Send + Sync>;
+
+/// The query a trace belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    Point(Key),
+    Range(Key, Key),
+}
+
+/// What one Diva query did to find its answer
+///
+/// A query answered by a sample key never reaches a partition. Otherwise
+/// `partition` names the samples around the InfixStore that was searched,
+/// `slots_scanned` counts the remainder slots read from its runs, and
+/// `occupieds_shortcut` tells whether the occupieds bitmap alone decided the
+/// answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryTrace {
+    pub kind: QueryKind,
+    /// answered by a sample key inside the queried range
+    pub sample_hit: bool,
+    /// (predecessor, successor) samples of the partition whose store was searched
+    pub partition: Option<(Key, Key)>,
+    pub slots_scanned: usize,
+    pub occupieds_shortcut: bool,
+    pub result: bool,
+}
+
+impl QueryTrace {
+    pub(crate) fn new(kind: QueryKind) -> Self {
+        Self {
+            kind,
+            sample_hit: false,
+            partition: None,
+            slots_scanned: 0,
+            occupieds_shortcut: false,
+            result: false,
+        }
+    }
+
+    /// record the work of a store query in `partition`
+    pub(crate) fn record_store(&mut self, partition: (Key, Key), probe: StoreProbe) {
+        self.partition = Some(partition);
+        self.slots_scanned += probe.slots_scanned;
+        self.occupieds_shortcut = probe.occupieds_shortcut;
+    }
+}
@@ -0,0 +1,324 @@
+//! Counting quotient filter over hashable items
+//!
+//! The RSQF blocks Diva keeps its infixes in, used as a filter of their own. An
+//! item is hashed to 64 bits: the top `store_bits` pick one of `2^store_bits`
+//! counting `InfixStore`s and the next `quotient_size + remainder_size` bits
+//! are its infix there, the quotient selecting the run and the remainder kept
+//! as the fingerprint. Any number of stores gives any capacity, each store
+//! growing and shrinking through the size grades of its layout.
+//!
+//! `resize` doubles the stores by moving one fingerprint bit from the
+//! remainder to the store index, as quotient filters expand without the
+//! original items: every store splits in two by the top bit of its infixes.
+//! The capacity doubles and the false positive rate with it, since each
+//! remainder is one bit shorter.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+use crate::error::RangeFilterError;
+use crate::infix_store::{InfixStore, StoreLayout};
+
+pub struct QuotientFilter {
+    stores: Vec<InfixStore>,
+    // log2 of the number of stores
+    store_bits: u8,
+    remainder_size: u8,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl QuotientFilter {
+    /// Create an empty quotient filter sized for `capacity` items at the
+    /// given false positive rate.
+    ///
+    /// Fails with `InvalidConfig` if `fpr` is not in (0, 1).
+    ///
+    /// # Arguments
+    /// * `capacity` - Number of items the filter holds before it resizes
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new(capacity: usize, fpr: f64) -> Result<Self, RangeFilterError> {
+        if !(fpr > 0.0 && fpr < 1.0) {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "fpr must be in (0, 1), got {}",
+                fpr
+            )));
+        }
+        let store_bits = Self::store_bits_for(capacity);
+        let num_quotients = StoreLayout::DEFAULT.num_quotients() << store_bits;
+        let density = capacity as f64 / num_quotients as f64;
+        let remainder_size = InfixStore::remainder_size_for_fpr(density, fpr).max(1);
+        Self::new_with_remainder_size(capacity, remainder_size)
+    }
+
+    /// Create an empty quotient filter sized for `capacity` items, keeping
+    /// `remainder_size` bits of every fingerprint.
+    ///
+    /// At full capacity the false positive rate is about `2^-remainder_size`.
+    /// Fails with `InvalidConfig` if the remainder is empty or the store
+    /// index, quotient and remainder take more than 64 hash bits.
+    pub fn new_with_remainder_size(
+        capacity: usize,
+        remainder_size: u8,
+    ) -> Result<Self, RangeFilterError> {
+        let layout = StoreLayout::DEFAULT;
+        let store_bits = Self::store_bits_for(capacity);
+        let hash_bits = store_bits as u32 + layout.quotient_size() as u32 + remainder_size as u32;
+        if remainder_size == 0 || hash_bits > u64::BITS {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "remainder size must be in 1..={}, got {}",
+                u64::BITS - store_bits as u32 - layout.quotient_size() as u32,
+                remainder_size
+            )));
+        }
+        Ok(Self {
+            stores: (0..1usize << store_bits)
+                .map(|_| InfixStore::new_counting(&[], remainder_size, layout.clone()))
+                .collect(),
+            store_bits,
+            remainder_size,
+            len: 0,
+            hasher: RandomState::new(),
+        })
+    }
+
+    /// fewest store bits giving every item a quotient of its own
+    fn store_bits_for(capacity: usize) -> u8 {
+        let num_stores = capacity
+            .div_ceil(StoreLayout::DEFAULT.num_quotients())
+            .max(1);
+        num_stores.next_power_of_two().trailing_zeros() as u8
+    }
+
+    /// Insert an item, again for every copy of an item inserted more than once.
+    ///
+    /// Resizes the filter once it holds `capacity` items or the store of the
+    /// item is full. Fails with `PartitionFull` only when the remainders are
+    /// down to one bit and cannot give up another.
+    pub fn insert<T: Hash + ?Sized>(&mut self, item: &T) -> Result<(), RangeFilterError> {
+        let hash = self.hasher.hash_one(item);
+        if self.len >= self.capacity() && self.remainder_size > 1 {
+            self.resize()?;
+        }
+        loop {
+            let (store, infix) = self.locate(hash);
+            match self.stores[store].insert(infix) {
+                Err(RangeFilterError::PartitionFull) if self.remainder_size > 1 => self.resize()?,
+                result => {
+                    result?;
+                    self.len += 1;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Delete one copy of an item, returning false if the item is definitely absent.
+    ///
+    /// Deleting an item that was never inserted but tests positive removes
+    /// the item it collides with, so only delete items known to be present.
+    pub fn delete<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        let (store, infix) = self.locate(self.hasher.hash_one(item));
+        if self.stores[store].delete(infix).is_err() {
+            return false;
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// Check if an item might be in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the item might exist (with false positive rate `fpr`)
+    /// * `false` if the item definitely does not exist
+    pub fn contains<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.count(item) > 0
+    }
+
+    /// Number of stored copies of an item, or of the items sharing its fingerprint.
+    pub fn count<T: Hash + ?Sized>(&self, item: &T) -> usize {
+        let (store, infix) = self.locate(self.hasher.hash_one(item));
+        self.stores[store].count(infix)
+    }
+
+    /// store of a hash and the infix of the hash in it
+    fn locate(&self, hash: u64) -> (usize, u64) {
+        let store = hash
+            .checked_shr(u64::BITS - self.store_bits as u32)
+            .unwrap_or(0);
+        let infix_size = StoreLayout::DEFAULT.quotient_size() + self.remainder_size;
+        let infix = (hash << self.store_bits) >> (u64::BITS - infix_size as u32);
+        (store as usize, infix)
+    }
+
+    /// Double the capacity, moving one bit of every remainder to the store index.
+    ///
+    /// Doubles the false positive rate as well. Fails with `InvalidConfig` if
+    /// the remainders are down to one bit.
+    pub fn resize(&mut self) -> Result<(), RangeFilterError> {
+        if self.remainder_size <= 1 {
+            return Err(RangeFilterError::InvalidConfig(
+                "cannot resize a quotient filter with 1-bit remainders".to_string(),
+            ));
+        }
+        let layout = StoreLayout::DEFAULT;
+        let remainder_size = self.remainder_size - 1;
+        // the old infixes, one bit longer than the new ones
+        let split_bit = layout.quotient_size() + remainder_size;
+        let mut stores = Vec::with_capacity(self.stores.len() * 2);
+        for store in &self.stores {
+            let (mut low, mut high) = (Vec::new(), Vec::new());
+            for (quotient, remainder) in store.iter() {
+                let infix = (quotient << self.remainder_size) | remainder;
+                let half = if infix >> split_bit == 0 {
+                    &mut low
+                } else {
+                    &mut high
+                };
+                half.push(infix & ((1 << split_bit) - 1));
+            }
+            stores.push(InfixStore::new_counting(
+                &low,
+                remainder_size,
+                StoreLayout::DEFAULT,
+            ));
+            stores.push(InfixStore::new_counting(
+                &high,
+                remainder_size,
+                StoreLayout::DEFAULT,
+            ));
+        }
+        self.stores = stores;
+        self.store_bits += 1;
+        self.remainder_size = remainder_size;
+        Ok(())
+    }
+
+    /// Get the number of items in the filter, duplicates included.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the filter holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of items the filter holds before it resizes, one per quotient.
+    pub fn capacity(&self) -> usize {
+        self.stores.len() * StoreLayout::DEFAULT.num_quotients()
+    }
+
+    /// Get the number of fingerprint bits kept per item.
+    pub fn remainder_size(&self) -> u8 {
+        self.remainder_size
+    }
+
+    /// Get the number of RSQF stores.
+    pub fn num_stores(&self) -> usize {
+        self.stores.len()
+    }
+
+    /// Get the expected false positive rate at the current load.
+    pub fn fpr(&self) -> f64 {
+        let density = self.len as f64 / self.capacity() as f64;
+        density / 2f64.powi(self.remainder_size as i32)
+    }
+
+    /// Get the memory used by the filter: the struct plus the allocated slots
+    /// of every InfixStore, empty ones included.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .stores
+                .iter()
+                .map(InfixStore::size_in_bytes)
+                .sum::<usize>()
+    }
+
+    /// Get the memory per stored item in bits, which falls as the stores
+    /// fill up and jumps when they resize.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.len {
+            0 => 0.0,
+            len => (self.size_in_bytes() * 8) as f64 / len as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives_and_fpr() {
+        let mut filter = QuotientFilter::new(20_000, 0.01).unwrap();
+        for i in 0..20_000u64 {
+            filter.insert(&i).unwrap();
+        }
+        assert_eq!(filter.len(), 20_000);
+        assert!((0..20_000u64).all(|i| filter.contains(&i)));
+
+        let false_positives = (20_000..70_000u64).filter(|i| filter.contains(i)).count();
+        // 1% of 50_000 expected, allow for variance
+        assert!(
+            false_positives < 1000,
+            "{} false positives",
+            false_positives
+        );
+        assert!(filter.fpr() <= 0.01);
+    }
+
+    #[test]
+    fn test_counts_and_deletes_arbitrary_items() {
+        let mut filter = QuotientFilter::new(100, 0.001).unwrap();
+        assert!(filter.is_empty() && !filter.contains("apple"));
+        filter.insert("apple").unwrap();
+        filter.insert("apple").unwrap();
+        filter.insert(&(1u8, "pear")).unwrap();
+        assert_eq!(filter.count("apple"), 2);
+        assert!(filter.contains(&(1u8, "pear")));
+
+        assert!(filter.delete("apple"));
+        assert_eq!(filter.count("apple"), 1);
+        assert!(filter.delete("apple"));
+        assert!(!filter.contains("apple") && !filter.delete("apple"));
+        assert_eq!(filter.len(), 1);
+    }
+
+    #[test]
+    fn test_resize_keeps_items() {
+        let mut filter = QuotientFilter::new(1000, 0.001).unwrap();
+        let (stores, remainder_size) = (filter.num_stores(), filter.remainder_size());
+        for i in 0..5000u64 {
+            filter.insert(&i).unwrap();
+        }
+        filter.insert(&7u64).unwrap();
+        // grew past its capacity, one remainder bit per doubling
+        assert!(filter.capacity() >= 5000);
+        let doublings = (filter.num_stores() / stores).trailing_zeros() as u8;
+        assert!(doublings > 0);
+        assert_eq!(filter.remainder_size(), remainder_size - doublings);
+        assert!((0..5000u64).all(|i| filter.contains(&i)));
+        assert_eq!(filter.count(&7u64), 2);
+
+        filter.resize().unwrap();
+        assert!((0..5000u64).all(|i| filter.contains(&i)));
+        assert!(filter.delete(&4999u64) && filter.len() == 5000);
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        assert!(matches!(
+            QuotientFilter::new(10, 0.0),
+            Err(RangeFilterError::InvalidConfig(_))
+        ));
+        assert!(QuotientFilter::new_with_remainder_size(10, 0).is_err());
+        assert!(QuotientFilter::new_with_remainder_size(10, 55).is_err());
+
+        let mut tiny = QuotientFilter::new_with_remainder_size(10, 1).unwrap();
+        assert!(tiny.resize().is_err());
+        tiny.insert(&1u64).unwrap();
+        assert!(tiny.contains(&1u64));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::Key;
+use crate::key_codec::KeyCodec;
+
+/// Common interface shared by every filter in the crate
+///
+/// Lets benchmarking and application code be written once and run against
+/// `Diva`, `BloomFilter`, `GrafiteFilter`, `RosettaFilter`, or any other
+/// implementor. Signed and floating point keys go through the `_typed`
+/// queries, see `KeyCodec`.
+///
+/// # Example
+/// ```rust
+/// use range_filters::{Diva, RangeFilter};
+///
+/// fn any_in_range<F: RangeFilter>(filter: &F, start: u64, end: u64) -> bool {
+///     filter.range_query(start, end)
+/// }
+///
+/// let diva = Diva::new_with_keys(&[10, 20, 30], 1024, 0.01);
+/// assert!(any_in_range(&diva, 15, 25));
+/// ```
+pub trait RangeFilter {
+    /// Point query: check if a key might exist in the filter
+    /// Returns true if key might exist (with FPR), false if it definitely doesn't exist
+    fn point_query(&self, key: Key) -> bool;
+
+    /// Range query: check if any key might exist in the range [start, end] (inclusive)
+    /// Returns true if at least one key might exist (with FPR), false if definitely no keys exist
+    fn range_query(&self, start: Key, end: Key) -> bool;
+
+    /// Point query for any key type with an order-preserving `KeyCodec`
+    fn point_query_typed<K: KeyCodec>(&self, key: K) -> bool
+    where
+        Self: Sized,
+    {
+        self.point_query(key.encode())
+    }
+
+    /// Range query for any key type with an order-preserving `KeyCodec`
+    fn range_query_typed<K: KeyCodec>(&self, start: K, end: K) -> bool
+    where
+        Self: Sized,
+    {
+        self.range_query(start.encode(), end.encode())
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{
+        Arf, BlockedBloomFilter, BloomFilter, CountingBloomFilter, Diva, GrafiteBucketFilter,
+        GrafiteFilter, MementoFilter, PrefixBloomFilter, Proteus, REncoder, RosettaFilter, Snarf,
+        SuffixMode, Surf,
+    };
+
+    fn check_no_false_negatives<F: RangeFilter + ?Sized>(filter: &F, keys: &[Key]) {
+        for &key in keys {
+            assert!(filter.point_query(key), "key {} should be found", key);
+            assert!(
+                filter.range_query(key.saturating_sub(5), key + 5),
+                "range around {} should be non-empty",
+                key
+            );
+        }
+        assert!(
+            !filter.range_query(500, 100),
+            "inverted range should be empty"
+        );
+    }
+
+    #[test]
+    fn test_all_filters_through_trait() {
+        let keys: Vec<Key> = (1..200).map(|i| i * 1000).collect();
+
+        let filters: Vec<Box<dyn RangeFilter>> = vec![
+            Box::new(Diva::new_with_keys(&keys, 64, 0.01)),
+            Box::new(BloomFilter::new_with_keys(&keys, 0.01)),
+            Box::new(CountingBloomFilter::new_with_keys(&keys, 0.01)),
+            Box::new(BlockedBloomFilter::new_with_keys(&keys, 0.01)),
+            Box::new(GrafiteFilter::new_with_keys(&keys, 0.01)),
+            Box::new(GrafiteBucketFilter::new_with_keys(&keys, 0.01)),
+            Box::new(RosettaFilter::new_with_keys(&keys, 0.01)),
+            Box::new(REncoder::new_with_keys(&keys, 0.01)),
+            Box::new(Arf::new_with_keys(&keys, 4 * keys.len())),
+            Box::new(PrefixBloomFilter::new_with_keys(&keys, 48, 0.01)),
+            Box::new(Surf::new_with_keys(&keys, SuffixMode::Real(8))),
+            Box::new(Snarf::new_with_keys(&keys, 0.01)),
+            Box::new(Proteus::new_with_keys(
+                &keys,
+                12.0,
+                &[(1, 999), (2001, 2999)],
+            )),
+            Box::new(MementoFilter::new_with_keys(&keys, 1 << 10, 0.01).unwrap()),
+        ];
+
+        for filter in &filters {
+            check_no_false_negatives(filter.as_ref(), &keys);
+        }
+    }
+
+    #[test]
+    fn test_typed_queries_on_all_filters() {
+        // bloom filter range queries probe every key, so keep the ranges short
+        let keys: Vec<i64> = vec![-1_000_000, -250, -75, 0, 325, 1 << 40];
+        let encoded = i64::encode_all(&keys);
+
+        fn check<F: RangeFilter>(filter: &F, keys: &[i64]) {
+            for &key in keys {
+                assert!(filter.point_query_typed(key), "key {} should be found", key);
+                assert!(filter.range_query_typed(key - 5, key + 5));
+            }
+        }
+        check(&Diva::new_with_keys(&encoded, 2, 0.01), &keys);
+        check(&BloomFilter::new_with_keys(&encoded, 0.01), &keys);
+        check(&GrafiteFilter::new_with_keys(&encoded, 0.01), &keys);
+        check(&GrafiteBucketFilter::new_with_keys(&encoded, 0.01), &keys);
+        check(&RosettaFilter::new_with_keys(&encoded, 0.01), &keys);
+        check(&REncoder::new_with_keys(&encoded, 0.01), &keys);
+        check(&Arf::new_with_keys(&encoded, 64), &keys);
+        check(
+            &PrefixBloomFilter::new_with_whole_keys(&encoded, 32, 0.01),
+            &keys,
+        );
+        check(&Surf::new_with_keys(&encoded, SuffixMode::Base), &keys);
+        check(&Snarf::new_with_keys(&encoded, 0.01), &keys);
+        check(
+            &MementoFilter::new_with_keys(&encoded, 16, 0.01).unwrap(),
+            &keys,
+        );
+    }
+
+    #[test]
+    fn test_typed_f64_ranges() {
+        let keys: Vec<f64> = vec![-1e9, -2.5, -0.75, 0.0, 3.25, 1e12];
+        let diva = Diva::new_with_keys(&f64::encode_all(&keys), 2, 0.01);
+
+        for &key in &keys {
+            assert!(diva.point_query_typed(key), "key {} should be found", key);
+        }
+        assert!(diva.range_query_typed(-3.0, -2.0));
+        assert!(diva.range_query_typed(-1.0, -0.5));
+        assert!(!diva.range_query_typed(-2.0, -1.0));
+        assert!(!diva.range_query_typed(-0.5, -0.25));
+    }
+
+    #[test]
+    fn test_diva_trait_matches_inherent() {
+        let keys: Vec<Key> = vec![100, 200, 300, 400, 500];
+        let diva = Diva::new_with_keys(&keys, 2, 0.01);
+
+        for key in [100, 150, 250, 500, 501] {
+            assert_eq!(RangeFilter::point_query(&diva, key), diva.contains(key));
+        }
+        assert_eq!(
+            RangeFilter::range_query(&diva, 125, 149),
+            diva.range_query(125, 149)
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Block rank directory over a bitmap
+//!
+//! Stores the number of set bits before every 256-bit block, so `rank` reads
+//! one count plus at most four words and `select` binary-searches the counts
+//! before scanning a single block. The bitmap itself is not owned: every call
+//! takes the words the directory was built over, and mutations of the bitmap
+//! are mirrored with `update` and the `shift_*` hooks.
+
+use crate::U64_BITS;
+use crate::bitmap::{get_bit, rank, select};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// Bits covered by one directory entry
+const BLOCK_BITS: usize = 256;
+const BLOCK_WORDS: usize = BLOCK_BITS / U64_BITS;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct RankDirectory {
+    // counts[i] = number of set bits in [0, i * BLOCK_BITS)
+    counts: Box<[u32]>,
+}
+
+impl RankDirectory {
+    /// Build the directory over the first `num_bits` bits of `bits`
+    pub(crate) fn build(bits: &[u64], num_bits: usize) -> Self {
+        let num_blocks = num_bits.div_ceil(BLOCK_BITS).max(1);
+        let mut counts = Vec::with_capacity(num_blocks);
+        let mut count = 0u32;
+        for block in 0..num_blocks {
+            counts.push(count);
+            let start = (block * BLOCK_WORDS).min(bits.len());
+            let end = ((block + 1) * BLOCK_WORDS).min(bits.len());
+            count += bits[start..end]
+                .iter()
+                .map(|word| word.count_ones())
+                .sum::<u32>();
+        }
+        Self {
+            counts: counts.into_boxed_slice(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// memory used by the counts
+    pub(crate) fn heap_size(&self) -> usize {
+        self.counts.len() * core::mem::size_of::<u32>()
+    }
+
+    /// Number of set bits in [0, pos)
+    #[inline]
+    pub(crate) fn rank(&self, bits: &[u64], pos: usize) -> usize {
+        let block = (pos / BLOCK_BITS).min(self.counts.len() - 1);
+        let start = block * BLOCK_BITS;
+        self.counts[block] as usize + rank(&bits[start / U64_BITS..], pos - start)
+    }
+
+    /// Position of the set bit with the given rank (0-based)
+    #[inline]
+    pub(crate) fn select(&self, bits: &[u64], rank_val: usize) -> Option<usize> {
+        // last block starting with fewer than rank_val + 1 set bits before it
+        let block = self
+            .counts
+            .partition_point(|&count| count as usize <= rank_val)
+            - 1;
+        let start = block * BLOCK_WORDS;
+        let end = if block + 1 < self.counts.len() {
+            (start + BLOCK_WORDS).min(bits.len())
+        } else {
+            bits.len()
+        };
+        select(&bits[start..end], rank_val - self.counts[block] as usize)
+            .map(|pos| pos + start * U64_BITS)
+    }
+
+    /// Mirror setting (`true`) or clearing a bit at `pos` that changed state
+    pub(crate) fn update(&mut self, pos: usize, set: bool) {
+        for count in &mut self.counts[pos / BLOCK_BITS + 1..] {
+            if set {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+
+    /// Mirror moving bits [start, end) one position right, with `start`
+    /// cleared afterwards; call before the move
+    pub(crate) fn shift_right(&mut self, bits: &[u64], start: usize, end: usize) {
+        // the last bit of every block in the moved range crosses into the next block
+        for block in start / BLOCK_BITS + 1..self.counts.len() {
+            let boundary = block * BLOCK_BITS;
+            if boundary > end {
+                break;
+            }
+            if get_bit(bits, boundary - 1) {
+                self.counts[block] -= 1;
+            }
+        }
+    }
+
+    /// Mirror moving bits [start + 1, end) one position left, overwriting
+    /// `start`, with `end - 1` cleared afterwards; call before the move
+    pub(crate) fn shift_left(&mut self, bits: &[u64], start: usize, end: usize) {
+        let removed = get_bit(bits, start);
+        for block in start / BLOCK_BITS + 1..self.counts.len() {
+            let boundary = block * BLOCK_BITS;
+            if boundary >= end && !removed {
+                break;
+            }
+            // the first bit of the block crosses into the previous block
+            let entering = boundary < end && get_bit(bits, boundary);
+            match (removed, entering) {
+                (true, false) => self.counts[block] -= 1,
+                (false, true) => self.counts[block] += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::{clear_bit, move_bits, set_bit};
+
+    fn next(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_rank_select_match_bitmap() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for num_bits in [1usize, 64, 255, 256, 700, 2326] {
+            let mut bits = vec![0u64; num_bits.div_ceil(U64_BITS)];
+            for pos in 0..num_bits {
+                if next(&mut state).is_multiple_of(3) {
+                    set_bit(&mut bits, pos);
+                }
+            }
+            let directory = RankDirectory::build(&bits, num_bits);
+            for pos in 0..=num_bits {
+                assert_eq!(directory.rank(&bits, pos), rank(&bits, pos));
+            }
+            let total = rank(&bits, num_bits);
+            for rank_val in 0..=total {
+                assert_eq!(directory.select(&bits, rank_val), select(&bits, rank_val));
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutations_keep_counts_exact() {
+        let num_bits: usize = 1000;
+        let mut bits = vec![0u64; num_bits.div_ceil(U64_BITS)];
+        let mut directory = RankDirectory::build(&bits, num_bits);
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut len = 0;
+        for _ in 0..3000 {
+            let value = next(&mut state);
+            let pos = (value >> 8) as usize % (len + 1);
+            if value.is_multiple_of(2) && len < num_bits - 1 {
+                // insert a bit at pos
+                directory.shift_right(&bits, pos, len);
+                move_bits(&mut bits, pos, pos + 1, len - pos);
+                clear_bit(&mut bits, pos);
+                len += 1;
+                if value.is_multiple_of(3) {
+                    set_bit(&mut bits, pos);
+                    directory.update(pos, true);
+                }
+            } else if len > 0 {
+                // remove the bit at pos
+                let pos = pos.min(len - 1);
+                directory.shift_left(&bits, pos, len);
+                move_bits(&mut bits, pos + 1, pos, len - pos - 1);
+                clear_bit(&mut bits, len - 1);
+                len -= 1;
+            }
+            assert_eq!(directory, RankDirectory::build(&bits, num_bits));
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Rank9 rank/select index over an immutable bit vector
+//!
+//! Every 512-bit superblock stores the number of set bits before it plus, packed
+//! into a second word, seven 9-bit counts of the set bits before each of its
+//! words (Vigna, "Broadword Implementation of Rank/Select Queries", 2008).
+//! `rank` is two index reads and one popcount. `select` starts from a sample
+//! taken every `SELECT_SAMPLE` set bits, binary-searches the superblocks up to
+//! the next sample and finishes inside one word.
+//!
+//! Unlike the rank directories an InfixStore keeps over its own data words,
+//! the index is not updated in place: it is built once over bits that no
+//! longer change, e.g. a frozen store's bitmaps or a trie's level bitmaps.
+//!
+//! `RankSelectOps` is the query interface it shares with the sparse
+//! `EliasFano` encoding, and `CompactBitmap` picks the smaller of the two.
+
+use crate::bitmap::{BitVec, select_in_word};
+use crate::elias_fano::EliasFano;
+use alloc::vec::Vec;
+
+const WORD_BITS: usize = 64;
+const SUPERBLOCK_WORDS: usize = 8;
+/// set bits between two select samples
+const SELECT_SAMPLE: usize = 512;
+
+/// Bit vector with constant-time rank and near constant-time select
+///
+/// # Example
+/// ```rust
+/// use range_filters::bitmap::BitVec;
+/// use range_filters::rank_select::RankSelect;
+///
+/// let mut bits = BitVec::new(1000);
+/// for pos in (0..1000).step_by(3) {
+///     bits.set(pos);
+/// }
+/// let index = RankSelect::new(bits);
+/// assert_eq!(index.rank(10), 4);
+/// assert_eq!(index.select(4), Some(12));
+/// assert_eq!(index.select(334), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RankSelect {
+    bits: BitVec,
+    // per superblock: [set bits before it, 9-bit counts before words 1..8 of it]
+    counts: Vec<u64>,
+    // superblock holding the (i * SELECT_SAMPLE)-th set bit
+    samples: Vec<u32>,
+    ones: usize,
+}
+
+impl RankSelect {
+    /// Build the index over `bits`
+    pub fn new(bits: BitVec) -> Self {
+        let words = bits.words();
+        // one extra superblock so rank(len) never reads past the counts
+        let num_superblocks = words.len() / SUPERBLOCK_WORDS + 1;
+        let mut counts = Vec::with_capacity(num_superblocks * 2);
+        let mut samples = Vec::new();
+        let mut ones = 0;
+        for superblock in 0..num_superblocks {
+            let first = superblock * SUPERBLOCK_WORDS;
+            let block = &words[first.min(words.len())..(first + SUPERBLOCK_WORDS).min(words.len())];
+            let mut relative = 0u64;
+            let mut inside = 0;
+            for (j, word) in block.iter().enumerate() {
+                if j > 0 {
+                    relative |= (inside as u64) << (9 * (j - 1));
+                }
+                inside += word.count_ones() as usize;
+            }
+            // words past the end count no bits
+            for j in block.len().max(1)..SUPERBLOCK_WORDS {
+                relative |= (inside as u64) << (9 * (j - 1));
+            }
+            while samples.len() * SELECT_SAMPLE < ones + inside {
+                samples.push(superblock as u32);
+            }
+            counts.push(ones as u64);
+            counts.push(relative);
+            ones += inside;
+        }
+        Self {
+            bits,
+            counts,
+            samples,
+            ones,
+        }
+    }
+
+    /// The indexed bits
+    pub fn bits(&self) -> &BitVec {
+        &self.bits
+    }
+
+    pub fn into_bits(self) -> BitVec {
+        self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    #[inline]
+    pub fn get(&self, pos: usize) -> bool {
+        self.bits.get(pos)
+    }
+
+    /// number of set bits
+    pub fn count_ones(&self) -> usize {
+        self.ones
+    }
+
+    /// Number of set bits in [0, pos)
+    #[inline]
+    pub fn rank(&self, pos: usize) -> usize {
+        debug_assert!(
+            pos <= self.len(),
+            "pos: {} is out of bounds for {} bits",
+            pos,
+            self.len()
+        );
+        let word = pos / WORD_BITS;
+        let superblock = word / SUPERBLOCK_WORDS;
+        let mut rank = self.counts[2 * superblock] as usize + self.before_word(superblock, word);
+        let offset = pos % WORD_BITS;
+        if offset > 0 {
+            rank += (self.bits.words()[word] & ((1 << offset) - 1)).count_ones() as usize;
+        }
+        rank
+    }
+
+    /// Position of the set bit with the given rank (0-based)
+    #[inline]
+    pub fn select(&self, rank_val: usize) -> Option<usize> {
+        if rank_val >= self.ones {
+            return None;
+        }
+        // the superblocks between two samples hold the rank
+        let sample = rank_val / SELECT_SAMPLE;
+        let low = self.samples[sample] as usize;
+        let high = self
+            .samples
+            .get(sample + 1)
+            .map_or(self.counts.len() / 2, |&superblock| superblock as usize + 1);
+        // last superblock in [low, high) with at most rank_val set bits before it
+        let (mut superblock, mut high) = (low, high);
+        while superblock + 1 < high {
+            let mid = (superblock + high) / 2;
+            if self.counts[2 * mid] as usize <= rank_val {
+                superblock = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        let mut remaining = rank_val - self.counts[2 * superblock] as usize;
+        let first = superblock * SUPERBLOCK_WORDS;
+        let mut word = first;
+        for j in 1..SUPERBLOCK_WORDS {
+            if self.before_word(superblock, first + j) > remaining {
+                break;
+            }
+            word = first + j;
+        }
+        remaining -= self.before_word(superblock, word);
+        select_in_word(self.bits.words()[word], remaining).map(|pos| word * WORD_BITS + pos)
+    }
+
+    /// memory used by the bits and the index
+    pub fn heap_size(&self) -> usize {
+        self.bits.heap_size()
+            + self.counts.capacity() * core::mem::size_of::<u64>()
+            + self.samples.capacity() * core::mem::size_of::<u32>()
+    }
+
+    /// set bits in the superblock before `word`, which must lie in it
+    #[inline]
+    fn before_word(&self, superblock: usize, word: usize) -> usize {
+        match word % SUPERBLOCK_WORDS {
+            0 => 0,
+            j => ((self.counts[2 * superblock + 1] >> (9 * (j - 1))) & 0x1FF) as usize,
+        }
+    }
+}
+
+impl From<BitVec> for RankSelect {
+    fn from(bits: BitVec) -> Self {
+        Self::new(bits)
+    }
+}
+
+/// Read-only rank/select queries over a bitmap
+pub trait RankSelectOps {
+    /// number of bits
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// number of set bits
+    fn count_ones(&self) -> usize;
+
+    fn get(&self, pos: usize) -> bool;
+
+    /// Number of set bits in [0, pos)
+    fn rank(&self, pos: usize) -> usize;
+
+    /// Position of the set bit with the given rank (0-based)
+    fn select(&self, rank_val: usize) -> Option<usize>;
+}
+
+impl RankSelectOps for RankSelect {
+    fn len(&self) -> usize {
+        RankSelect::len(self)
+    }
+
+    fn count_ones(&self) -> usize {
+        RankSelect::count_ones(self)
+    }
+
+    fn get(&self, pos: usize) -> bool {
+        RankSelect::get(self, pos)
+    }
+
+    fn rank(&self, pos: usize) -> usize {
+        RankSelect::rank(self, pos)
+    }
+
+    fn select(&self, rank_val: usize) -> Option<usize> {
+        RankSelect::select(self, rank_val)
+    }
+}
+
+/// Read-only bitmap stored plainly or Elias-Fano encoded, whichever is smaller
+///
+/// Sparse bitmaps, like the occupieds of a partition holding few keys, shrink
+/// to a few bytes; dense ones keep the plain words and O(1) rank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactBitmap {
+    Dense(RankSelect),
+    Sparse(EliasFano),
+}
+
+impl CompactBitmap {
+    /// Encode the first `len` bits of `words`
+    pub fn from_words(words: &[u64], len: usize) -> Self {
+        let sparse = EliasFano::from_words(words, len);
+        // plain words plus one rank9 counts word per 8 words
+        let num_words = len.div_ceil(64);
+        let dense_size = (num_words + num_words / 4 + 2) * core::mem::size_of::<u64>();
+        if sparse.heap_size() < dense_size {
+            CompactBitmap::Sparse(sparse)
+        } else {
+            let bits = BitVec::from_words(words[..num_words].to_vec(), len);
+            CompactBitmap::Dense(RankSelect::new(bits))
+        }
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        matches!(self, CompactBitmap::Sparse(_))
+    }
+
+    /// memory used by the encoded bits
+    pub fn heap_size(&self) -> usize {
+        match self {
+            CompactBitmap::Dense(bits) => bits.heap_size(),
+            CompactBitmap::Sparse(bits) => bits.heap_size(),
+        }
+    }
+
+    fn ops(&self) -> &dyn RankSelectOps {
+        match self {
+            CompactBitmap::Dense(bits) => bits,
+            CompactBitmap::Sparse(bits) => bits,
+        }
+    }
+}
+
+impl RankSelectOps for CompactBitmap {
+    fn len(&self) -> usize {
+        self.ops().len()
+    }
+
+    fn count_ones(&self) -> usize {
+        self.ops().count_ones()
+    }
+
+    fn get(&self, pos: usize) -> bool {
+        self.ops().get(pos)
+    }
+
+    fn rank(&self, pos: usize) -> usize {
+        self.ops().rank(pos)
+    }
+
+    fn select(&self, rank_val: usize) -> Option<usize> {
+        self.ops().select(rank_val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::{rank, select};
+
+    #[test]
+    fn test_matches_linear_rank_select() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for len in [0usize, 1, 63, 64, 511, 512, 513, 4096, 10_000] {
+            for density in [0u64, 1, 8, 32, 64] {
+                let mut bits = BitVec::new(len);
+                for pos in 0..len {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    // set with probability density / 64
+                    if (state >> 40) % 64 < density {
+                        bits.set(pos);
+                    }
+                }
+                let index = RankSelect::new(bits.clone());
+                let words = bits.words();
+                assert_eq!(index.count_ones(), bits.count_ones());
+                for pos in 0..=len {
+                    assert_eq!(index.rank(pos), rank(words, pos), "len {} pos {}", len, pos);
+                }
+                for rank_val in 0..=index.count_ones() {
+                    assert_eq!(index.select(rank_val), select(words, rank_val));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparse_bits_span_many_superblocks() {
+        // a handful of ones far apart, so samples cover long superblock ranges
+        let ones = [3usize, 700, 40_000, 40_001, 99_999];
+        let mut bits = BitVec::new(100_000);
+        for &pos in &ones {
+            bits.set(pos);
+        }
+        let index = RankSelect::from(bits);
+        for (rank_val, &pos) in ones.iter().enumerate() {
+            assert_eq!(index.select(rank_val), Some(pos));
+            assert_eq!(index.rank(pos), rank_val);
+            assert_eq!(index.rank(pos + 1), rank_val + 1);
+        }
+        assert_eq!(index.select(ones.len()), None);
+        assert_eq!(index.rank(100_000), ones.len());
+        assert!(index.get(40_001) && !index.get(40_002));
+    }
+}
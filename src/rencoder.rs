@@ -0,0 +1,359 @@
+//! REncoder-style range filter: dyadic prefixes encoded as local subtrees
+//!
+//! The dyadic levels are cut into groups of six. Within a group, the prefixes
+//! of the keys below one prefix of the group's top level form a binary subtree
+//! of 63 nodes, stored as a 63-bit bitmap whose node `(1 << depth) | path` is
+//! set when a key passes through it. Each bitmap is ORed into `k` hashed words
+//! of one shared word array, rotated by a hashed amount in each: a Bloom
+//! filter over words instead of bits.
+//!
+//! A query fetches the `k` words of a subtree and ANDs them rotated back, which
+//! gives a superset of its bitmap, and then decodes the subtree locally:
+//! descending from a maybe-present node into its children costs no more
+//! memory accesses until the walk leaves the group. Rosetta pays `k` probes per level, this
+//! pays them once per six levels, which is what makes short ranges fast.
+//!
+//! Blocks above the indexed levels, which only occur in ranges at least
+//! `2^(6 * groups)` keys wide, are answered with "maybe", as in
+//! `RosettaFilter`.
+
+use crate::Key;
+use crate::U64_BITS;
+use crate::range_filter::RangeFilter;
+use crate::utils::mix64;
+
+/// levels per subtree, 2^6 - 1 = 63 nodes fit a word
+const GROUP_LEVELS: usize = 6;
+/// most groups whose levels stay below 64
+const MAX_GROUPS: usize = U64_BITS / GROUP_LEVELS;
+/// 24 levels, ranges up to 2^23 keys wide are fully indexed
+const DEFAULT_GROUPS: usize = 4;
+
+pub struct REncoder {
+    words: Vec<u64>,
+    num_hashes: u32,
+    num_levels: usize,
+    fpr: f64,
+    num_keys: usize,
+    seed: u64,
+}
+
+impl REncoder {
+    /// Create a new REncoder with the given keys and false positive rate.
+    ///
+    /// Indexes 4 groups of 6 levels, see `new_with_groups`.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `fpr` - Target false positive rate of every node (e.g., 0.01 for 1%)
+    pub fn new_with_keys(keys: &[Key], fpr: f64) -> Self {
+        Self::new_with_groups(keys, fpr, DEFAULT_GROUPS)
+    }
+
+    /// Create a new REncoder indexing `groups` groups of 6 dyadic levels.
+    ///
+    /// The word array is sized like a Bloom filter over every distinct
+    /// (prefix, level) node at rate `fpr`, about what `RosettaFilter` spends
+    /// on the same levels.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `fpr` - Target false positive rate of every node (e.g., 0.01 for 1%)
+    /// * `groups` - Number of level groups, 1 up to 10
+    ///
+    /// Panics if `groups` is 0 or above 10.
+    pub fn new_with_groups(keys: &[Key], fpr: f64, groups: usize) -> Self {
+        assert!(
+            (1..=MAX_GROUPS).contains(&groups),
+            "groups must be between 1 and {}, got {}",
+            MAX_GROUPS,
+            groups
+        );
+        let mut sorted_keys = keys.to_vec();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+
+        // (group, subtree root, bitmap) of every subtree, roots ascending per group
+        let mut subtrees = Vec::new();
+        let mut num_nodes = 0;
+        for group in 0..groups {
+            let top = group * GROUP_LEVELS + GROUP_LEVELS - 1;
+            let mut current: Option<(Key, u64)> = None;
+            for &key in &sorted_keys {
+                let root = key >> top;
+                let path = Self::path_mask(key >> (top + 1 - GROUP_LEVELS));
+                current = match current {
+                    Some((last, bitmap)) if last == root => Some((root, bitmap | path)),
+                    Some(done) => {
+                        num_nodes += done.1.count_ones() as usize;
+                        subtrees.push((group, done.0, done.1));
+                        Some((root, path))
+                    }
+                    None => Some((root, path)),
+                };
+            }
+            if let Some(done) = current {
+                num_nodes += done.1.count_ones() as usize;
+                subtrees.push((group, done.0, done.1));
+            }
+        }
+
+        // m = -n ln(p) / ln(2)^2 and k = log2(1 / p) for n nodes
+        let fpr = fpr.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = -(num_nodes.max(1) as f64) * fpr.ln() / (ln2 * ln2);
+        let num_words = (num_bits / U64_BITS as f64).ceil().max(1.0) as usize;
+        let num_hashes = (-fpr.log2()).round().clamp(1.0, 16.0) as u32;
+
+        let mut filter = Self {
+            words: vec![0; num_words],
+            num_hashes,
+            num_levels: groups * GROUP_LEVELS,
+            fpr,
+            num_keys: keys.len(),
+            seed: rand::random(),
+        };
+        for (group, root, bitmap) in subtrees {
+            for (word, rotation) in filter.word_positions(group, root) {
+                filter.words[word] |= bitmap.rotate_left(rotation);
+            }
+        }
+        filter
+    }
+
+    /// bitmap of the nodes a key passes through in its subtree, given the
+    /// key's prefix at the group's bottom level
+    fn path_mask(bottom_prefix: Key) -> u64 {
+        (0..GROUP_LEVELS).fold(0, |mask, depth| {
+            let path = (bottom_prefix >> (GROUP_LEVELS - 1 - depth)) & ((1 << depth) - 1);
+            mask | (1 << ((1 << depth) | path))
+        })
+    }
+
+    /// the `k` words a subtree is ORed into, each with the rotation of the
+    /// bitmap in it
+    ///
+    /// Rotating the bitmap by its own amount in every word spreads each node
+    /// over all 64 bit columns, otherwise the root bit would be set in every
+    /// word and a leaf would only ever meet the same leaf of other subtrees.
+    fn word_positions(
+        &self,
+        group: usize,
+        root: Key,
+    ) -> impl Iterator<Item = (usize, u32)> + use<> {
+        let hash = mix64(root ^ mix64(self.seed.wrapping_add(group as u64)));
+        let num_words = self.words.len() as u128;
+        // a hash per word, h1 + i * h2 walks overlapping progressions in small arrays
+        (1..=self.num_hashes as u64).map(move |i| {
+            let word_hash = mix64(hash.wrapping_add(i));
+            let word = ((word_hash as u128 * num_words) >> 64) as usize;
+            (word, (word_hash % U64_BITS as u64) as u32)
+        })
+    }
+
+    /// a superset of the bitmap of a subtree
+    fn subtree(&self, group: usize, root: Key) -> u64 {
+        self.word_positions(group, root)
+            .fold(u64::MAX, |bitmap, (word, rotation)| {
+                bitmap & self.words[word].rotate_right(rotation)
+            })
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist (with false positive rate `fpr`)
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.num_keys > 0 && self.doubt(key, 0)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// Probes the largest aligned blocks covering the range like
+    /// `RosettaFilter::range_query`, decoding each in its subtree.
+    ///
+    /// # Returns
+    /// * `true` if at least one key in the range might exist
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end || self.num_keys == 0 {
+            return false;
+        }
+        let mut lo = start;
+        loop {
+            // the largest aligned block starting at lo that ends by end
+            let mut level = (lo.trailing_zeros() as usize).min(U64_BITS - 1);
+            while level > 0 && end - lo < (1u64 << level) - 1 {
+                level -= 1;
+            }
+            if self.doubt(lo >> level, level) {
+                return true;
+            }
+            let block_end = lo + ((1u64 << level) - 1);
+            if block_end >= end {
+                return false;
+            }
+            lo = block_end + 1;
+        }
+    }
+
+    /// whether the block of `prefix` at `level` may hold a key
+    fn doubt(&self, prefix: Key, level: usize) -> bool {
+        if level >= self.num_levels {
+            // above the indexed levels
+            return true;
+        }
+        let group = level / GROUP_LEVELS;
+        let top = group * GROUP_LEVELS + GROUP_LEVELS - 1;
+        let bitmap = self.subtree(group, prefix >> (top - level));
+        self.decode(bitmap, prefix, level)
+    }
+
+    /// `doubt` inside a subtree already fetched, going to memory again only
+    /// below its bottom level
+    fn decode(&self, bitmap: u64, prefix: Key, level: usize) -> bool {
+        let depth = GROUP_LEVELS - 1 - level % GROUP_LEVELS;
+        let node = (1 << depth) | (prefix & ((1 << depth) - 1));
+        if bitmap & (1 << node) == 0 {
+            return false;
+        }
+        let (left, right) = (prefix << 1, (prefix << 1) | 1);
+        match level {
+            0 => true,
+            // the children are roots of the group below
+            _ if level.is_multiple_of(GROUP_LEVELS) => {
+                self.doubt(left, level - 1) || self.doubt(right, level - 1)
+            }
+            _ => self.decode(bitmap, left, level - 1) || self.decode(bitmap, right, level - 1),
+        }
+    }
+
+    /// Get the configured false positive rate of every node.
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of indexed dyadic levels.
+    pub fn num_levels(&self) -> usize {
+        self.num_levels
+    }
+
+    /// Get the number of words fetched per subtree.
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Get the memory used by the filter: the struct plus the Bloom filter
+    /// words the encoded trie nodes are hashed into.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + std::mem::size_of_val(self.words.as_slice())
+    }
+
+    /// Get the memory per key the filter was built from in bits, duplicate
+    /// keys included although they add no trie nodes.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for REncoder {
+    fn point_query(&self, key: Key) -> bool {
+        REncoder::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        REncoder::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_no_false_negatives_and_fpr() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..2000).map(|_| rng.gen_range(0..1u64 << 40)).collect();
+        let set: BTreeSet<Key> = keys.iter().copied().collect();
+        let filter = REncoder::new_with_keys(&keys, 0.01);
+
+        for &key in &keys {
+            assert!(filter.point_query(key));
+            assert!(filter.range_query(key, key));
+            assert!(filter.range_query(key.saturating_sub(1000), key + 3));
+        }
+        let mut false_positives = 0;
+        let mut empty = 0;
+        for _ in 0..5000 {
+            let start = rng.gen_range(0..1u64 << 40);
+            let end = start + rng.gen_range(0..1 << 16);
+            if set.range(start..=end).next().is_some() {
+                assert!(filter.range_query(start, end), "[{}, {}]", start, end);
+            } else {
+                empty += 1;
+                false_positives += filter.range_query(start, end) as usize;
+            }
+        }
+        // every block costs a few node probes at 1% each, as in Rosetta
+        assert!(
+            false_positives * 10 < empty,
+            "{} false positives in {} empty ranges",
+            false_positives,
+            empty
+        );
+    }
+
+    #[test]
+    fn test_path_mask() {
+        // root, then left, right, right, left, right
+        let mask = REncoder::path_mask(0b01101);
+        let nodes: Vec<u32> = (0..64).filter(|&bit| mask & (1 << bit) != 0).collect();
+        assert_eq!(nodes, [1, 2, 5, 11, 22, 45]);
+        assert_eq!(REncoder::path_mask(Key::MAX).count_ones(), 6);
+    }
+
+    #[test]
+    fn test_block_edges_across_groups() {
+        let filter = REncoder::new_with_keys(&[0, 1023, 1024, Key::MAX], 1e-7);
+        assert!(filter.range_query(0, 0));
+        assert!(filter.range_query(1, 1023));
+        assert!(filter.range_query(1024, 2047));
+        assert!(!filter.range_query(1025, 1 << 19));
+        assert!(filter.range_query(Key::MAX - 5, Key::MAX));
+        assert!(!filter.range_query(Key::MAX - (1 << 19), Key::MAX - 1));
+        assert!(!filter.range_query(10, 5));
+    }
+
+    #[test]
+    fn test_ranges_above_the_levels_are_maybe() {
+        let filter = REncoder::new_with_groups(&[1 << 30], 1e-7, 1);
+        assert_eq!(filter.num_levels(), 6);
+        // blocks of up to 32 keys are indexed
+        assert!(!filter.range_query(0, 31));
+        assert!(!filter.range_query(3, 60));
+        // [0, 63] is one block of level 6
+        assert!(filter.range_query(0, 63));
+
+        let empty = REncoder::new_with_keys(&[], 0.01);
+        assert!(!empty.range_query(0, Key::MAX));
+        assert!(!empty.point_query(0));
+        assert_eq!(empty.bits_per_key(), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "groups must be between 1 and 10")]
+    fn test_rejects_zero_groups() {
+        REncoder::new_with_groups(&[1], 0.01, 0);
+    }
+}
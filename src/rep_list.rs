@@ -0,0 +1,332 @@
+//! Doubly linked list of the x-fast trie representatives in key order
+//!
+//! The links between representatives are `Weak`, and the list only holds
+//! strong references to its head and tail: the nodes in between are kept
+//! alive by whoever else shares them, in an `XFastTrie` the leaf level table.
+//! `RepList` is the one place that rewires the links and the two ends, so an
+//! insert or delete cannot leave a stale head, tail or neighbour pointer.
+//!
+//! Every representative carries a payload, a `RepPayload`: the y-fast trie
+//! hangs a `BinarySearchTreeGroup` off each one, the default, while a trie
+//! partitioning keys directly can attach an `InfixStore`.
+
+use crate::Key;
+use crate::binary_search_tree::BinarySearchTreeGroup;
+use crate::infix_store::InfixStore;
+use crate::sync::{Arc, RwLock, Weak};
+use core::iter::FusedIterator;
+
+/// Data attached to every representative
+///
+/// Payloads are shared behind `Arc<RwLock<_>>` with readers on other threads,
+/// so they must be `Send + Sync`.
+pub trait RepPayload: Default + Send + Sync {
+    /// memory used by the payload, itself included
+    fn size_in_bytes(&self) -> usize;
+}
+
+impl<P: RepPayload> RepPayload for BinarySearchTreeGroup<P> {
+    fn size_in_bytes(&self) -> usize {
+        BinarySearchTreeGroup::size_in_bytes(self)
+    }
+}
+
+impl RepPayload for InfixStore {
+    fn size_in_bytes(&self) -> usize {
+        InfixStore::size_in_bytes(self)
+    }
+}
+
+/// no payload, for tries that only order keys
+impl RepPayload for () {
+    fn size_in_bytes(&self) -> usize {
+        0
+    }
+}
+
+/// a representative as shared between the list and the level tables
+pub type SharedRep<T = BinarySearchTreeGroup> = Arc<RwLock<RepNode<T>>>;
+
+#[derive(Debug, Default)]
+pub struct RepNode<T = BinarySearchTreeGroup> {
+    pub key: Key,
+    pub left: Option<Weak<RwLock<RepNode<T>>>>,
+    pub right: Option<Weak<RwLock<RepNode<T>>>>,
+    pub payload: Option<Arc<RwLock<T>>>,
+}
+
+impl<T: RepPayload> RepNode<T> {
+    /// unlinked representative of `key` with an empty payload
+    pub fn new(key: Key) -> SharedRep<T> {
+        Arc::new(RwLock::new(Self {
+            key,
+            left: None,
+            right: None,
+            payload: Some(Arc::new(RwLock::new(T::default()))),
+        }))
+    }
+}
+
+impl<T> RepNode<T> {
+    /// the representative of the next smaller key
+    pub fn prev(&self) -> Option<SharedRep<T>> {
+        self.left.as_ref().and_then(Weak::upgrade)
+    }
+
+    /// the representative of the next larger key
+    pub fn next(&self) -> Option<SharedRep<T>> {
+        self.right.as_ref().and_then(Weak::upgrade)
+    }
+}
+
+/// Representatives in ascending key order
+///
+/// # Example
+/// ```rust
+/// use range_filters::{RepList, RepNode};
+///
+/// let reps: Vec<_> = [10, 20, 30].into_iter().map(RepNode::new).collect();
+/// let mut list: RepList = RepList::new();
+/// list.push_back(&reps[0]);
+/// list.push_back(&reps[2]);
+/// list.insert_after(Some(&reps[0]), &reps[1]);
+/// assert_eq!(list.keys().collect::<Vec<_>>(), vec![10, 20, 30]);
+///
+/// list.remove(&reps[0]);
+/// assert_eq!(list.head().map(|head| head.read().unwrap().key), Some(20));
+/// ```
+#[derive(Debug)]
+pub struct RepList<T = BinarySearchTreeGroup> {
+    head: Option<SharedRep<T>>,
+    tail: Option<SharedRep<T>>,
+}
+
+impl<T> Default for RepList<T> {
+    fn default() -> Self {
+        Self {
+            head: None,
+            tail: None,
+        }
+    }
+}
+
+impl<T> RepList<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// representative of the smallest key
+    pub fn head(&self) -> Option<&SharedRep<T>> {
+        self.head.as_ref()
+    }
+
+    /// representative of the largest key
+    pub fn tail(&self) -> Option<&SharedRep<T>> {
+        self.tail.as_ref()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// link the unlinked `rep` right after `predecessor`, or at the front
+    /// without one
+    ///
+    /// The caller keeps the keys ordered: `rep` must sort between
+    /// `predecessor` and its current successor.
+    pub fn insert_after(&mut self, predecessor: Option<&SharedRep<T>>, rep: &SharedRep<T>) {
+        let successor = match predecessor {
+            Some(pred) => pred.read().ok().and_then(|pred| pred.next()),
+            None => self.head.clone(),
+        };
+        if cfg!(debug_assertions) {
+            let key_of = |node: &SharedRep<T>| node.read().ok().map(|node| node.key);
+            let key = key_of(rep);
+            debug_assert!(
+                predecessor.and_then(key_of) < key,
+                "predecessor must sort before"
+            );
+            debug_assert!(
+                successor
+                    .as_ref()
+                    .and_then(key_of)
+                    .is_none_or(|succ| Some(succ) > key),
+                "successor must sort after"
+            );
+        }
+
+        if let Some(mut pred_guard) = predecessor.and_then(|pred| pred.write().ok()) {
+            pred_guard.right = Some(Arc::downgrade(rep));
+        }
+        if let Some(mut succ_guard) = successor.as_ref().and_then(|succ| succ.write().ok()) {
+            succ_guard.left = Some(Arc::downgrade(rep));
+        }
+        if let Ok(mut rep_guard) = rep.write() {
+            rep_guard.left = predecessor.map(Arc::downgrade);
+            rep_guard.right = successor.as_ref().map(Arc::downgrade);
+        }
+
+        if predecessor.is_none() {
+            self.head = Some(rep.clone());
+        }
+        if successor.is_none() {
+            self.tail = Some(rep.clone());
+        }
+    }
+
+    /// link the unlinked `rep` after the tail, for representatives added in key order
+    pub fn push_back(&mut self, rep: &SharedRep<T>) {
+        let tail = self.tail.clone();
+        self.insert_after(tail.as_ref(), rep);
+    }
+
+    /// unlink `rep` and return its former neighbours
+    pub fn remove(&mut self, rep: &SharedRep<T>) -> (Option<SharedRep<T>>, Option<SharedRep<T>>) {
+        let (predecessor, successor) = match rep.write() {
+            Ok(mut rep_guard) => {
+                let neighbours = (rep_guard.prev(), rep_guard.next());
+                rep_guard.left = None;
+                rep_guard.right = None;
+                neighbours
+            }
+            Err(_) => return (None, None),
+        };
+
+        if let Some(mut pred_guard) = predecessor.as_ref().and_then(|pred| pred.write().ok()) {
+            pred_guard.right = successor.as_ref().map(Arc::downgrade);
+        }
+        if let Some(mut succ_guard) = successor.as_ref().and_then(|succ| succ.write().ok()) {
+            succ_guard.left = predecessor.as_ref().map(Arc::downgrade);
+        }
+        if self
+            .head
+            .as_ref()
+            .is_some_and(|head| Arc::ptr_eq(head, rep))
+        {
+            self.head = successor.clone();
+        }
+        if self
+            .tail
+            .as_ref()
+            .is_some_and(|tail| Arc::ptr_eq(tail, rep))
+        {
+            self.tail = predecessor.clone();
+        }
+        (predecessor, successor)
+    }
+
+    /// representatives from head to tail
+    pub fn iter(&self) -> RepIter<T> {
+        RepIter {
+            next: self.head.clone(),
+        }
+    }
+
+    /// keys from head to tail
+    pub fn keys(&self) -> impl Iterator<Item = Key> + use<T> {
+        self.iter()
+            .filter_map(|rep| rep.read().ok().map(|rep| rep.key))
+    }
+}
+
+/// Iterator over the representatives of a `RepList`, see `RepList::iter`
+#[derive(Debug)]
+pub struct RepIter<T = BinarySearchTreeGroup> {
+    next: Option<SharedRep<T>>,
+}
+
+impl<T> Iterator for RepIter<T> {
+    type Item = SharedRep<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next.take()?;
+        self.next = current.read().ok().and_then(|rep| rep.next());
+        Some(current)
+    }
+}
+
+impl<T> FusedIterator for RepIter<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys_backwards(list: &RepList) -> Vec<Key> {
+        let mut keys = Vec::new();
+        let mut current = list.tail().cloned();
+        while let Some(rep) = current {
+            let rep = rep.read().unwrap();
+            keys.push(rep.key);
+            current = rep.prev();
+        }
+        keys.reverse();
+        keys
+    }
+
+    fn check(list: &RepList, expected: &[Key]) {
+        assert_eq!(list.keys().collect::<Vec<_>>(), expected);
+        assert_eq!(keys_backwards(list), expected);
+        let key_of = |rep: Option<&SharedRep>| rep.map(|rep| rep.read().unwrap().key);
+        assert_eq!(key_of(list.head()), expected.first().copied());
+        assert_eq!(key_of(list.tail()), expected.last().copied());
+        assert_eq!(list.is_empty(), expected.is_empty());
+    }
+
+    #[test]
+    fn test_insert_at_both_ends_and_between() {
+        let reps: Vec<_> = (0..6).map(|i| RepNode::new(i * 10)).collect();
+        let mut list: RepList = RepList::new();
+        check(&list, &[]);
+
+        list.insert_after(None, &reps[2]);
+        check(&list, &[20]);
+        // new head
+        list.insert_after(None, &reps[1]);
+        check(&list, &[10, 20]);
+        // new tail
+        list.insert_after(Some(&reps[2]), &reps[4]);
+        check(&list, &[10, 20, 40]);
+        list.push_back(&reps[5]);
+        check(&list, &[10, 20, 40, 50]);
+        list.insert_after(Some(&reps[2]), &reps[3]);
+        check(&list, &[10, 20, 30, 40, 50]);
+        list.insert_after(None, &reps[0]);
+        check(&list, &[0, 10, 20, 30, 40, 50]);
+    }
+
+    #[test]
+    fn test_remove_then_insert() {
+        let reps: Vec<_> = (0..5).map(|i| RepNode::new(i * 10)).collect();
+        let mut list: RepList = RepList::new();
+        for rep in &reps {
+            list.push_back(rep);
+        }
+
+        // head
+        let (pred, succ) = list.remove(&reps[0]);
+        assert!(pred.is_none() && Arc::ptr_eq(&succ.unwrap(), &reps[1]));
+        check(&list, &[10, 20, 30, 40]);
+        // tail
+        let (pred, succ) = list.remove(&reps[4]);
+        assert!(Arc::ptr_eq(&pred.unwrap(), &reps[3]) && succ.is_none());
+        check(&list, &[10, 20, 30]);
+        // middle
+        list.remove(&reps[2]);
+        check(&list, &[10, 30]);
+        assert!(reps[2].read().unwrap().prev().is_none());
+        assert!(reps[2].read().unwrap().next().is_none());
+
+        // the removed nodes link back in at both ends and in between
+        list.insert_after(None, &reps[0]);
+        list.push_back(&reps[4]);
+        list.insert_after(Some(&reps[1]), &reps[2]);
+        check(&list, &[0, 10, 20, 30, 40]);
+
+        for rep in &reps {
+            list.remove(rep);
+        }
+        check(&list, &[]);
+        list.push_back(&reps[3]);
+        check(&list, &[30]);
+    }
+}
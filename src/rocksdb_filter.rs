@@ -0,0 +1,218 @@
+use crate::Key;
+use crate::diva::Diva;
+use crate::error::RangeFilterError;
+
+/// Name stored in SST files next to the filter block, see `DivaFilterPolicy::name`
+pub const FILTER_POLICY_NAME: &str = "range_filters.DivaFilterPolicy";
+
+/// Map a RocksDB byte key to a filter key: its first 8 bytes read big-endian,
+/// zero-padded when shorter
+///
+/// The mapping is monotone, `a <= b` implies `key_prefix(a) <= key_prefix(b)`,
+/// so a byte range maps to a key range containing the prefix of every key in
+/// it. Keys sharing their first 8 bytes share a filter key, which only adds
+/// false positives.
+pub fn key_prefix(key: &[u8]) -> Key {
+    let mut bytes = [0u8; 8];
+    let len = key.len().min(8);
+    bytes[..len].copy_from_slice(&key[..len]);
+    Key::from_be_bytes(bytes)
+}
+
+/// Range filter policy for RocksDB block-based tables, backed by `Diva`
+///
+/// Mirrors RocksDB's `FilterPolicy`: the table builder feeds the keys of a block
+/// (or of the whole SST with partitioned filters) to a `DivaFilterBuilder` and
+/// stores the bytes from `finish` in the filter block. At read time the block
+/// contents are handed to `reader`, and the resulting `DivaFilterReader` answers
+/// point lookups and range scans. The filter block is the `Diva::save_to` format,
+/// so a C++ `FilterPolicy` shim can build it and read it through the `ffi`
+/// functions after mapping keys with the same big-endian prefix as `key_prefix`.
+///
+/// # Example
+/// ```rust
+/// use range_filters::rocksdb_filter::DivaFilterPolicy;
+///
+/// let policy = DivaFilterPolicy::new(1024, 0.01).unwrap();
+/// let mut builder = policy.builder();
+/// for key in [&b"user:0001"[..], b"user:0042", b"user:0100"] {
+///     builder.add_key(key);
+/// }
+/// let block = builder.finish().unwrap();
+///
+/// let reader = policy.reader(&block);
+/// assert!(reader.key_may_match(b"user:0042"));
+/// assert!(reader.range_may_match(b"user:0040", b"user:0050"));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct DivaFilterPolicy {
+    target_size: usize,
+    fpr: f64,
+}
+
+impl DivaFilterPolicy {
+    /// Create a policy building every filter with the given `Diva` parameters
+    pub fn new(target_size: usize, fpr: f64) -> Result<Self, RangeFilterError> {
+        Diva::builder().target_size(target_size).fpr(fpr).build()?;
+        Ok(Self { target_size, fpr })
+    }
+
+    /// Policy name recorded in the table properties; RocksDB only uses a filter
+    /// block whose recorded name matches the configured policy
+    pub fn name(&self) -> &'static str {
+        FILTER_POLICY_NAME
+    }
+
+    /// Start a filter for a new block
+    pub fn builder(&self) -> DivaFilterBuilder {
+        DivaFilterBuilder {
+            policy: *self,
+            keys: Vec::new(),
+        }
+    }
+
+    /// Open the contents of a filter block
+    ///
+    /// A block that cannot be decoded (corrupted, or written by another policy)
+    /// gives a reader that matches everything, like RocksDB does for filters it
+    /// cannot use: a filter may only ever cost a read, never lose one.
+    pub fn reader(&self, filter_block: &[u8]) -> DivaFilterReader {
+        DivaFilterReader {
+            diva: Diva::load_from(&mut &filter_block[..]).ok(),
+        }
+    }
+}
+
+/// Collects the keys of one block, see `DivaFilterPolicy::builder`
+#[derive(Debug)]
+pub struct DivaFilterBuilder {
+    policy: DivaFilterPolicy,
+    keys: Vec<Key>,
+}
+
+impl DivaFilterBuilder {
+    /// Add a key of the block; keys arrive in comparator order and may repeat
+    /// their prefix
+    pub fn add_key(&mut self, key: &[u8]) {
+        let prefix = key_prefix(key);
+        if self.keys.last() != Some(&prefix) {
+            self.keys.push(prefix);
+        }
+    }
+
+    /// Number of distinct key prefixes added so far
+    pub fn num_added(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Build the filter and serialize it into the bytes of the filter block
+    pub fn finish(mut self) -> Result<Vec<u8>, RangeFilterError> {
+        // comparators other than the bytewise one may deliver keys out of prefix order
+        self.keys.sort_unstable();
+        self.keys.dedup();
+
+        let diva = Diva::builder()
+            .target_size(self.policy.target_size)
+            .fpr(self.policy.fpr)
+            .build_with_keys(&self.keys)?;
+        let mut block = Vec::new();
+        diva.save_to(&mut block)
+            .expect("writing to a Vec cannot fail");
+        Ok(block)
+    }
+}
+
+/// Answers queries against one filter block, see `DivaFilterPolicy::reader`
+pub struct DivaFilterReader {
+    // None when the block could not be decoded
+    diva: Option<Diva>,
+}
+
+impl DivaFilterReader {
+    /// Whether the block may contain `key`
+    pub fn key_may_match(&self, key: &[u8]) -> bool {
+        self.diva
+            .as_ref()
+            .is_none_or(|diva| diva.contains(key_prefix(key)))
+    }
+
+    /// Whether the block may contain a key in [start, end] (inclusive)
+    pub fn range_may_match(&self, start: &[u8], end: &[u8]) -> bool {
+        self.diva
+            .as_ref()
+            .is_none_or(|diva| diva.range_query(key_prefix(start), key_prefix(end)))
+    }
+
+    /// false when the block could not be decoded and every query matches
+    pub fn is_valid(&self) -> bool {
+        self.diva.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_key_prefix() {
+        assert_eq!(key_prefix(b""), 0);
+        assert_eq!(key_prefix(b"\x01"), 1 << 56);
+        assert_eq!(key_prefix(b"abcdefgh"), key_prefix(b"abcdefghij"));
+        assert!(key_prefix(b"abc") < key_prefix(b"abd"));
+        assert!(key_prefix(b"ab") <= key_prefix(b"ab\x00"));
+    }
+
+    #[test]
+    fn test_filter_block_roundtrip() {
+        let mut rng = rand::thread_rng();
+        let mut keys: Vec<Vec<u8>> = (0..5000)
+            .map(|_| {
+                let len = rng.gen_range(1..16);
+                (0..len).map(|_| rng.r#gen()).collect()
+            })
+            .collect();
+        keys.sort();
+
+        let policy = DivaFilterPolicy::new(256, 0.01).unwrap();
+        let mut builder = policy.builder();
+        for key in &keys {
+            builder.add_key(key);
+        }
+        let block = builder.finish().unwrap();
+
+        let reader = policy.reader(&block);
+        assert!(reader.is_valid());
+        for key in &keys {
+            assert!(reader.key_may_match(key));
+            assert!(reader.range_may_match(key, key));
+            assert!(reader.range_may_match(&key[..key.len() / 2], key));
+        }
+    }
+
+    #[test]
+    fn test_range_may_match_rejects_empty_ranges() {
+        let policy = DivaFilterPolicy::new(1024, 0.01).unwrap();
+        let mut builder = policy.builder();
+        builder.add_key(b"order:2024-01-01");
+        builder.add_key(b"order:2024-01-01#2");
+        builder.add_key(b"user:alice");
+        assert_eq!(builder.num_added(), 2);
+        let reader = policy.reader(&builder.finish().unwrap());
+
+        assert!(reader.range_may_match(b"order:2024", b"order:2025"));
+        assert!(!reader.range_may_match(b"p", b"t"));
+        assert!(!reader.range_may_match(b"zzz", b"zzzz"));
+    }
+
+    #[test]
+    fn test_unreadable_block_matches_everything() {
+        let policy = DivaFilterPolicy::new(1024, 0.01).unwrap();
+        let reader = policy.reader(b"not a filter block");
+        assert!(!reader.is_valid());
+        assert!(reader.key_may_match(b"anything"));
+        assert!(reader.range_may_match(b"a", b"b"));
+
+        assert!(DivaFilterPolicy::new(0, 0.01).is_err());
+    }
+}
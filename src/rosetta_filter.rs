@@ -0,0 +1,262 @@
+//! Rosetta-style range filter: one Bloom filter per dyadic level
+//!
+//! Level `l` holds the prefixes `key >> l` of every key, level 0 the keys
+//! themselves. A range query splits `[start, end]` into aligned dyadic blocks
+//! `[p << l, ((p + 1) << l) - 1]`, at most two per level, and probes the
+//! prefix `p` of each at its level. A block that answers "maybe" above level 0
+//! is confirmed by descending into its two halves, so a false positive at one
+//! level only costs a few more probes further down instead of a wrong answer.
+//!
+//! Only the lowest `levels` levels are indexed. Blocks above them, which only
+//! occur in ranges at least `2^levels` keys wide, are answered with "maybe",
+//! as in Rosetta. Every level is sized for its number of distinct prefixes at
+//! the same false positive rate.
+
+use fastbloom::BloomFilter as FastBloomFilter;
+
+use crate::Key;
+use crate::U64_BITS;
+use crate::range_filter::RangeFilter;
+
+/// ranges up to 2^20 keys wide, about `GrafiteFilter`'s maximum query range
+const DEFAULT_LEVELS: usize = 21;
+
+pub struct RosettaFilter {
+    // levels[l] holds the prefixes key >> l
+    levels: Vec<FastBloomFilter>,
+    fpr: f64,
+    num_keys: usize,
+}
+
+impl RosettaFilter {
+    /// Create a new Rosetta filter with the given keys and false positive rate.
+    ///
+    /// Indexes 21 levels, so ranges up to 2^20 keys wide are answered without
+    /// falling back to "maybe", see `new_with_levels`.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `fpr` - Target false positive rate of every level (e.g., 0.01 for 1%)
+    pub fn new_with_keys(keys: &[Key], fpr: f64) -> Self {
+        Self::new_with_levels(keys, fpr, DEFAULT_LEVELS)
+    }
+
+    /// Create a new Rosetta filter indexing `levels` dyadic levels.
+    ///
+    /// Every level costs about as much memory as a Bloom filter over the keys,
+    /// less at the upper levels where keys share prefixes.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `fpr` - Target false positive rate of every level (e.g., 0.01 for 1%)
+    /// * `levels` - Number of levels, 1 for a plain Bloom filter up to 64
+    ///
+    /// Panics if `levels` is 0 or above 64.
+    pub fn new_with_levels(keys: &[Key], fpr: f64, levels: usize) -> Self {
+        assert!(
+            (1..=U64_BITS).contains(&levels),
+            "levels must be between 1 and {}, got {}",
+            U64_BITS,
+            levels
+        );
+        let mut prefixes = keys.to_vec();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        let seed: u128 = rand::random();
+        let mut filters = Vec::with_capacity(levels);
+        for level in 0..levels {
+            if level > 0 {
+                for prefix in &mut prefixes {
+                    *prefix >>= 1;
+                }
+                prefixes.dedup();
+            }
+            let mut filter = FastBloomFilter::with_false_pos(fpr)
+                .seed(&seed.wrapping_add(level as u128))
+                .expected_items(prefixes.len().max(1));
+            for prefix in &prefixes {
+                filter.insert(prefix);
+            }
+            filters.push(filter);
+        }
+        Self {
+            levels: filters,
+            fpr,
+            num_keys: keys.len(),
+        }
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist (with false positive rate `fpr`)
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.num_keys > 0 && self.levels[0].contains(&key)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// Probes the largest aligned blocks covering the range, O(log(end - start))
+    /// of them, see the module documentation.
+    ///
+    /// # Returns
+    /// * `true` if at least one key in the range might exist
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end || self.num_keys == 0 {
+            return false;
+        }
+        let mut lo = start;
+        loop {
+            // the largest aligned block starting at lo that ends by end
+            let mut level = (lo.trailing_zeros() as usize).min(U64_BITS - 1);
+            while level > 0 && end - lo < (1u64 << level) - 1 {
+                level -= 1;
+            }
+            if self.doubt(lo >> level, level) {
+                return true;
+            }
+            let block_end = lo + ((1u64 << level) - 1);
+            if block_end >= end {
+                return false;
+            }
+            lo = block_end + 1;
+        }
+    }
+
+    /// whether the block of `prefix` at `level` may hold a key
+    fn doubt(&self, prefix: Key, level: usize) -> bool {
+        let Some(filter) = self.levels.get(level) else {
+            // above the indexed levels
+            return true;
+        };
+        if !filter.contains(&prefix) {
+            return false;
+        }
+        level == 0 || self.doubt(prefix << 1, level - 1) || self.doubt((prefix << 1) | 1, level - 1)
+    }
+
+    /// Get the configured false positive rate of every level.
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of indexed dyadic levels.
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Get the memory used by the filter: the struct plus the Bloom filter of
+    /// every dyadic level.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self
+                .levels
+                .iter()
+                .map(|filter| {
+                    std::mem::size_of::<FastBloomFilter>()
+                        + std::mem::size_of_val(filter.as_slice())
+                })
+                .sum::<usize>()
+    }
+
+    /// Get the memory per key in bits, summed over all levels, so it grows
+    /// with `num_levels`.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for RosettaFilter {
+    fn point_query(&self, key: Key) -> bool {
+        RosettaFilter::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        RosettaFilter::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..2000).map(|_| rng.gen_range(0..1u64 << 40)).collect();
+        let set: BTreeSet<Key> = keys.iter().copied().collect();
+        let filter = RosettaFilter::new_with_keys(&keys, 0.01);
+
+        for &key in &keys {
+            assert!(filter.point_query(key));
+            assert!(filter.range_query(key, key));
+            assert!(filter.range_query(key.saturating_sub(1000), key + 3));
+        }
+        let mut false_positives = 0;
+        let mut empty = 0;
+        for _ in 0..5000 {
+            let start = rng.gen_range(0..1u64 << 40);
+            let end = start + rng.gen_range(0..1 << 16);
+            if set.range(start..=end).next().is_some() {
+                assert!(filter.range_query(start, end), "[{}, {}]", start, end);
+            } else {
+                empty += 1;
+                false_positives += filter.range_query(start, end) as usize;
+            }
+        }
+        // every block costs a few probes at 1% each
+        assert!(
+            false_positives * 10 < empty,
+            "{} false positives in {} empty ranges",
+            false_positives,
+            empty
+        );
+    }
+
+    #[test]
+    fn test_block_edges() {
+        let filter = RosettaFilter::new_with_keys(&[0, 1023, 1024, Key::MAX], 1e-7);
+        assert!(filter.range_query(0, 0));
+        assert!(filter.range_query(1, 1023));
+        assert!(filter.range_query(1024, 2047));
+        assert!(!filter.range_query(1025, 1 << 19));
+        assert!(filter.range_query(Key::MAX - 5, Key::MAX));
+        assert!(!filter.range_query(Key::MAX - (1 << 19), Key::MAX - 1));
+        assert!(!filter.range_query(10, 5));
+    }
+
+    #[test]
+    fn test_ranges_above_the_levels_are_maybe() {
+        let filter = RosettaFilter::new_with_levels(&[1 << 30], 1e-7, 4);
+        assert_eq!(filter.num_levels(), 4);
+        // blocks of up to 8 keys are indexed
+        assert!(!filter.range_query(0, 7));
+        assert!(!filter.range_query(3, 12));
+        // [0, 15] is one block of level 4
+        assert!(filter.range_query(0, 15));
+
+        let empty = RosettaFilter::new_with_keys(&[], 0.01);
+        assert!(!empty.range_query(0, Key::MAX));
+        assert!(!empty.point_query(0));
+        assert_eq!(empty.bits_per_key(), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "levels must be between 1 and 64")]
+    fn test_rejects_zero_levels() {
+        RosettaFilter::new_with_levels(&[1], 0.01, 0);
+    }
+}
@@ -0,0 +1,228 @@
+//! Vectorized scans over the remainders of a run
+//!
+//! Remainders are bit-packed, so a run is unpacked `CHUNK` slots at a time into
+//! a `[u64; CHUNK]` buffer and every lane is compared against the queried
+//! remainder range at once. On x86_64 the comparison uses AVX2 or SSE4.2 when
+//! the CPU has them (detected at runtime with `std`, at compile time through
+//! `target_feature` without it), on other targets the scalar loop.
+//!
+//! SSE4.2/AVX2 only compare signed 64-bit lanes, so values and bounds get
+//! their sign bit flipped first, which orders them like the unsigned values
+//! and covers 64-bit remainders.
+
+use crate::bitmap::read_bits;
+
+/// Number of remainders unpacked and compared per step
+pub(crate) const CHUNK: usize = 8;
+
+/// Unpack the `count` (at most `CHUNK`) remainders of `remainder_size` bits
+/// stored from slot `first` on into the front of `out`
+#[inline]
+pub(crate) fn unpack(
+    slots: &[u64],
+    first: usize,
+    count: usize,
+    remainder_size: u8,
+    out: &mut [u64; CHUNK],
+) {
+    let remainder_size = remainder_size as usize;
+    let mut pos = first * remainder_size;
+    for value in &mut out[..count] {
+        *value = read_bits(slots, pos, remainder_size);
+        pos += remainder_size;
+    }
+}
+
+/// Whether any of `values` lies in [low, high] (inclusive)
+#[inline]
+pub(crate) fn any_in_range(values: &[u64], low: u64, high: u64) -> bool {
+    if low > high {
+        return false;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    {
+        if x86::has_avx2() {
+            // SAFETY: the CPU supports AVX2
+            return unsafe { x86::any_in_range_avx2(values, low, high) };
+        }
+        if x86::has_sse42() {
+            // SAFETY: the CPU supports SSE4.2
+            return unsafe { x86::any_in_range_sse42(values, low, high) };
+        }
+    }
+    any_in_range_scalar(values, low, high)
+}
+
+/// Portable version of `any_in_range`
+#[inline]
+pub(crate) fn any_in_range_scalar(values: &[u64], low: u64, high: u64) -> bool {
+    values.iter().any(|&value| low <= value && value <= high)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use super::any_in_range_scalar;
+    use core::arch::x86_64::*;
+
+    #[inline]
+    pub(super) fn has_avx2() -> bool {
+        #[cfg(feature = "std")]
+        {
+            std::is_x86_feature_detected!("avx2")
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            cfg!(target_feature = "avx2")
+        }
+    }
+
+    #[inline]
+    pub(super) fn has_sse42() -> bool {
+        #[cfg(feature = "std")]
+        {
+            std::is_x86_feature_detected!("sse4.2")
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            cfg!(target_feature = "sse4.2")
+        }
+    }
+
+    /// flip the sign bit so signed compares order values like unsigned ones
+    #[inline]
+    fn biased(value: u64) -> i64 {
+        (value ^ (1 << 63)) as i64
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn any_in_range_avx2(values: &[u64], low: u64, high: u64) -> bool {
+        let sign = _mm256_set1_epi64x(i64::MIN);
+        let low_lanes = _mm256_set1_epi64x(biased(low));
+        let high_lanes = _mm256_set1_epi64x(biased(high));
+        let mut chunks = values.chunks_exact(4);
+        for chunk in &mut chunks {
+            // SAFETY: the chunk holds 4 u64, an unaligned 256-bit load
+            let lanes = unsafe { _mm256_loadu_si256(chunk.as_ptr().cast()) };
+            let lanes = _mm256_xor_si256(lanes, sign);
+            let outside = _mm256_or_si256(
+                _mm256_cmpgt_epi64(lanes, high_lanes),
+                _mm256_cmpgt_epi64(low_lanes, lanes),
+            );
+            if _mm256_movemask_epi8(outside) != -1 {
+                return true;
+            }
+        }
+        any_in_range_scalar(chunks.remainder(), low, high)
+    }
+
+    #[target_feature(enable = "sse4.2")]
+    pub(super) fn any_in_range_sse42(values: &[u64], low: u64, high: u64) -> bool {
+        let sign = _mm_set1_epi64x(i64::MIN);
+        let low_lanes = _mm_set1_epi64x(biased(low));
+        let high_lanes = _mm_set1_epi64x(biased(high));
+        let mut chunks = values.chunks_exact(2);
+        for chunk in &mut chunks {
+            // SAFETY: the chunk holds 2 u64, an unaligned 128-bit load
+            let lanes = unsafe { _mm_loadu_si128(chunk.as_ptr().cast()) };
+            let lanes = _mm_xor_si128(lanes, sign);
+            let outside = _mm_or_si128(
+                _mm_cmpgt_epi64(lanes, high_lanes),
+                _mm_cmpgt_epi64(low_lanes, lanes),
+            );
+            if _mm_movemask_epi8(outside) != 0xFFFF {
+                return true;
+            }
+        }
+        any_in_range_scalar(chunks.remainder(), low, high)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::write_bits;
+
+    #[test]
+    fn test_unpack_matches_read_bits() {
+        for remainder_size in [0u8, 1, 5, 8, 13, 31, 63, 64] {
+            let mask = u64::MAX
+                .checked_shr(64 - remainder_size as u32)
+                .unwrap_or(0);
+            let mut slots = vec![0u64; 64];
+            for slot in 0..40 {
+                let value = (slot as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) & mask;
+                write_bits(
+                    &mut slots,
+                    slot * remainder_size as usize,
+                    remainder_size as usize,
+                    value,
+                );
+            }
+            let mut out = [0u64; CHUNK];
+            for first in [0, 3, 17, 32] {
+                unpack(&slots, first, CHUNK, remainder_size, &mut out);
+                for (i, &value) in out.iter().enumerate() {
+                    let pos = (first + i) * remainder_size as usize;
+                    assert_eq!(value, read_bits(&slots, pos, remainder_size as usize));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_any_in_range_matches_scalar() {
+        let max = i64::MAX as u64;
+        let values: [u64; 13] = [
+            0,
+            1,
+            7,
+            8,
+            100,
+            1 << 20,
+            1 << 40,
+            max - 1,
+            max,
+            42,
+            43,
+            max + 1,
+            u64::MAX,
+        ];
+        let bounds = [
+            0,
+            1,
+            7,
+            8,
+            9,
+            42,
+            99,
+            100,
+            101,
+            1 << 40,
+            max - 1,
+            max,
+            max + 1,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+        for len in 0..=values.len() {
+            for &low in &bounds {
+                for &high in &bounds {
+                    let expected = any_in_range_scalar(&values[..len], low, high);
+                    assert_eq!(any_in_range(&values[..len], low, high), expected);
+                    #[cfg(target_arch = "x86_64")]
+                    if low <= high {
+                        if x86::has_avx2() {
+                            let avx2 = unsafe { x86::any_in_range_avx2(&values[..len], low, high) };
+                            assert_eq!(avx2, expected);
+                        }
+                        if x86::has_sse42() {
+                            let sse = unsafe { x86::any_in_range_sse42(&values[..len], low, high) };
+                            assert_eq!(sse, expected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
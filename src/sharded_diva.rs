@@ -0,0 +1,247 @@
+use crate::Key;
+use crate::U64_BITS;
+use crate::diva::Diva;
+use crate::error::RangeFilterError;
+use crate::range_filter::RangeFilter;
+
+/// largest number of high-order bits used to pick a shard (65536 shards)
+pub const MAX_SHARD_BITS: u8 = 16;
+
+/// Range filter over the whole key universe split into independent Divas
+///
+/// The top `shard_bits` bits of a key select one of `2^shard_bits` shards, each
+/// an independent `Diva` over a contiguous slice of the universe. Shards are
+/// built in parallel, keep their partitions (and InfixStore element counts)
+/// small, and a range query only visits the shards it overlaps.
+///
+/// # Example
+/// ```rust
+/// use range_filters::sharded_diva::ShardedDiva;
+///
+/// let keys: Vec<u64> = (0..10_000).map(|i| i << 50).collect();
+/// let sharded = ShardedDiva::new_with_keys(&keys, 4, 1024, 0.01).unwrap();
+///
+/// assert_eq!(sharded.num_shards(), 16);
+/// assert!(sharded.contains(3 << 50));
+/// assert!(sharded.range_query(1 << 60, (1 << 60) + (1 << 50)));
+/// ```
+pub struct ShardedDiva {
+    shards: Vec<Diva>,
+    shard_bits: u8,
+}
+
+impl ShardedDiva {
+    /// Create an empty filter with `2^shard_bits` shards
+    pub fn new(shard_bits: u8, target_size: usize, fpr: f64) -> Result<Self, RangeFilterError> {
+        Self::new_with_keys(&[], shard_bits, target_size, fpr)
+    }
+
+    /// Build a filter over the given keys, constructing every shard on its own thread
+    /// (sequentially on wasm32)
+    ///
+    /// # Arguments
+    /// * `keys` - Keys to insert, in any order
+    /// * `shard_bits` - Number of high-order key bits selecting the shard, at most `MAX_SHARD_BITS`
+    /// * `target_size` - Target size of every shard
+    /// * `fpr` - False positive rate of every shard
+    pub fn new_with_keys(
+        keys: &[Key],
+        shard_bits: u8,
+        target_size: usize,
+        fpr: f64,
+    ) -> Result<Self, RangeFilterError> {
+        if shard_bits > MAX_SHARD_BITS {
+            return Err(RangeFilterError::InvalidConfig(format!(
+                "shard bits must be at most {}, got {}",
+                MAX_SHARD_BITS, shard_bits
+            )));
+        }
+        // validate the shared shard parameters once
+        Diva::builder().target_size(target_size).fpr(fpr).build()?;
+
+        let mut shard_keys = vec![Vec::new(); 1 << shard_bits];
+        for &key in keys {
+            shard_keys[Self::shard_of(key, shard_bits)].push(key);
+        }
+
+        // wasm32 has no threads to spawn, so shards are built one after another there
+        #[cfg(not(target_family = "wasm"))]
+        let shards = std::thread::scope(|scope| {
+            let handles: Vec<_> = shard_keys
+                .iter()
+                .map(|keys| scope.spawn(move || Diva::new_with_keys(keys, target_size, fpr)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("shard construction panicked"))
+                .collect()
+        });
+        #[cfg(target_family = "wasm")]
+        let shards = shard_keys
+            .iter()
+            .map(|keys| Diva::new_with_keys(keys, target_size, fpr))
+            .collect();
+
+        Ok(Self { shards, shard_bits })
+    }
+
+    /// index of the shard a key belongs to
+    fn shard_of(key: Key, shard_bits: u8) -> usize {
+        match shard_bits {
+            0 => 0,
+            bits => (key >> (U64_BITS as u8 - bits)) as usize,
+        }
+    }
+
+    /// smallest and largest key of a shard
+    fn shard_bounds(&self, index: usize) -> (Key, Key) {
+        if self.shard_bits == 0 {
+            return (Key::MIN, Key::MAX);
+        }
+        let shift = U64_BITS as u8 - self.shard_bits;
+        let low = (index as Key) << shift;
+        (low, low | (Key::MAX >> self.shard_bits))
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn shard_bits(&self) -> u8 {
+        self.shard_bits
+    }
+
+    /// the shard covering a key
+    pub fn shard_for(&self, key: Key) -> &Diva {
+        &self.shards[Self::shard_of(key, self.shard_bits)]
+    }
+
+    /// every shard in key order
+    pub fn shards(&self) -> &[Diva] {
+        &self.shards
+    }
+
+    pub fn insert(&mut self, key: Key) -> Result<(), RangeFilterError> {
+        self.shards[Self::shard_of(key, self.shard_bits)].insert(key)
+    }
+
+    pub fn delete(&mut self, key: Key) -> Result<(), RangeFilterError> {
+        self.shards[Self::shard_of(key, self.shard_bits)].delete(key)
+    }
+
+    pub fn contains(&self, key: Key) -> bool {
+        self.shard_for(key).contains(key)
+    }
+
+    /// Range query across shards: each overlapped shard is queried with the
+    /// part of the range that falls inside it
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end {
+            return false;
+        }
+        let first = Self::shard_of(start, self.shard_bits);
+        let last = Self::shard_of(end, self.shard_bits);
+        (first..=last).any(|index| {
+            let (low, high) = self.shard_bounds(index);
+            self.shards[index].range_query(start.max(low), end.min(high))
+        })
+    }
+
+    /// number of keys over all shards
+    pub fn num_keys(&self) -> usize {
+        self.shards.iter().map(Diva::num_keys).sum()
+    }
+
+    /// memory used by the shard list and every shard's samples, trie and stores
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.shards.iter().map(Diva::size_in_bytes).sum::<usize>()
+    }
+
+    /// memory of all shards per key in bits, so empty shards raise it
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys() {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for ShardedDiva {
+    fn point_query(&self, key: Key) -> bool {
+        self.contains(key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        ShardedDiva::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_shard_routing() {
+        let sharded = ShardedDiva::new(3, 64, 0.01).unwrap();
+        assert_eq!(sharded.num_shards(), 8);
+        assert_eq!(ShardedDiva::shard_of(0, 3), 0);
+        assert_eq!(ShardedDiva::shard_of(Key::MAX, 3), 7);
+        assert_eq!(ShardedDiva::shard_of(1 << 61, 3), 1);
+        assert_eq!(ShardedDiva::shard_of(Key::MAX, 0), 0);
+        assert_eq!(sharded.shard_bounds(1), (1 << 61, (2 << 61) - 1));
+        assert_eq!(sharded.shard_bounds(7).1, Key::MAX);
+
+        assert!(ShardedDiva::new(MAX_SHARD_BITS + 1, 64, 0.01).is_err());
+        assert!(ShardedDiva::new(2, 0, 0.01).is_err());
+    }
+
+    #[test]
+    fn test_matches_single_diva() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..20_000).map(|_| rng.r#gen()).collect();
+        let sharded = ShardedDiva::new_with_keys(&keys, 4, 256, 0.01).unwrap();
+
+        assert_eq!(sharded.num_keys(), keys.len());
+        for &key in &keys {
+            assert!(sharded.contains(key), "Key {} should be found", key);
+            assert!(sharded.range_query(key.saturating_sub(10), key.saturating_add(10)));
+        }
+        let every_shard_has_keys = sharded.shards().iter().all(|shard| shard.num_keys() > 0);
+        assert!(every_shard_has_keys);
+    }
+
+    #[test]
+    fn test_range_query_spans_shards() {
+        // keys only in the first and last of four shards
+        let keys = [10, 20, Key::MAX - 20, Key::MAX - 10];
+        let sharded = ShardedDiva::new_with_keys(&keys, 2, 64, 0.01).unwrap();
+
+        assert!(sharded.range_query(15, Key::MAX - 15));
+        assert!(sharded.range_query(0, Key::MAX));
+        assert!(!sharded.range_query(1 << 62, (3 << 62) - 1));
+        assert!(!sharded.range_query(30, 1 << 63));
+        assert!(!sharded.range_query(20, 10));
+    }
+
+    #[test]
+    fn test_insert_and_delete_route_to_shards() {
+        let mut sharded = ShardedDiva::new(2, 64, 0.01).unwrap();
+        let keys = [5, 1 << 62, (2 << 62) + 7, Key::MAX];
+        for &key in &keys {
+            sharded.insert(key).unwrap();
+        }
+        for (index, &key) in keys.iter().enumerate() {
+            assert!(sharded.contains(key));
+            assert_eq!(sharded.shards()[index].num_keys(), 1);
+        }
+
+        assert_eq!(sharded.delete(1 << 62), Ok(()));
+        assert!(!sharded.contains(1 << 62));
+        assert_eq!(
+            sharded.delete(1 << 62),
+            Err(RangeFilterError::KeyNotFound(1 << 62))
+        );
+        assert_eq!(sharded.num_keys(), 3);
+    }
+}
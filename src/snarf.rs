@@ -0,0 +1,307 @@
+//! SNARF, a learned range filter
+//!
+//! A piecewise linear model of the key CDF maps every key to a position in a
+//! bit array `1 / fpr` times as long as the key count, and the positions of the
+//! keys are set. The model is monotone, so the keys of `[start, end]` land
+//! between the positions of `start` and `end` and a query is two ranks: no
+//! false negatives, and a false positive needs another key to land in the few
+//! positions the range maps to. The array is almost empty and Elias-Fano
+//! encoded, about `2 + log2(1 / fpr)` bits per key.
+//!
+//! The model is a linear spline over (key, rank) points fitted with a greedy
+//! spline corridor: a knot is added whenever the straight line from the last
+//! knot would miss the rank of a key by more than `max_error`. Dense and sparse
+//! regions, and the gaps between them, each get their own segments, and the
+//! size of the model trades off against its accuracy through that bound. The
+//! line across a wide gap may run on through the first `max_error` keys after
+//! it, squeezing them onto a few positions where queries are mostly positive.
+
+use crate::Key;
+use crate::elias_fano::EliasFano;
+use crate::range_filter::RangeFilter;
+use crate::rank_select::RankSelectOps;
+
+/// ranks the CDF model may be off by
+const DEFAULT_MAX_ERROR: usize = 16;
+
+pub struct Snarf {
+    // (key, rank of the key) spline points, the first and last key included
+    knots: Vec<(Key, usize)>,
+    bits: EliasFano,
+    // bit positions per rank, 1 / fpr
+    scale: f64,
+    fpr: f64,
+    num_keys: usize,
+}
+
+impl Snarf {
+    /// Create a new SNARF with the given keys and false positive rate.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `fpr` - Target false positive rate (e.g., 0.01 for 1%)
+    pub fn new_with_keys(keys: &[Key], fpr: f64) -> Self {
+        Self::new_with_max_error(keys, fpr, DEFAULT_MAX_ERROR)
+    }
+
+    /// Create a new SNARF whose CDF model is off by at most `max_error` ranks.
+    ///
+    /// A smaller bound follows the CDF closer with more knots, 128 bits each.
+    ///
+    /// Panics if `fpr` is not in (0, 1) or `max_error` is 0.
+    pub fn new_with_max_error(keys: &[Key], fpr: f64, max_error: usize) -> Self {
+        assert!(fpr > 0.0 && fpr < 1.0, "fpr must be in (0, 1), got {}", fpr);
+        assert!(max_error > 0, "max_error must be at least 1");
+        let mut sorted_keys = keys.to_vec();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+
+        let mut filter = Self {
+            knots: Self::fit_spline(&sorted_keys, max_error as f64),
+            bits: EliasFano::from_ones([], 0),
+            scale: 1.0 / fpr,
+            fpr,
+            num_keys: sorted_keys.len(),
+        };
+        let len = match sorted_keys.len() {
+            0 => 0,
+            num_keys => filter.position(sorted_keys[num_keys - 1]) + 1,
+        };
+        let mut positions: Vec<usize> = sorted_keys
+            .iter()
+            .map(|&key| filter.position(key))
+            .collect();
+        positions.dedup();
+        filter.bits = EliasFano::from_ones(positions, len);
+        filter
+    }
+
+    /// greedy spline corridor over the (key, rank) points of the sorted keys
+    fn fit_spline(sorted_keys: &[Key], max_error: f64) -> Vec<(Key, usize)> {
+        let Some(&first) = sorted_keys.first() else {
+            return Vec::new();
+        };
+        // slope from `base` to (key, rank) is above the one to (other_key, other_rank)
+        let steeper =
+            |base: (Key, usize), (key, rank): (Key, f64), (other_key, other_rank): (Key, f64)| {
+                (rank - base.1 as f64) * (other_key - base.0) as f64
+                    > (other_rank - base.1 as f64) * (key - base.0) as f64
+            };
+
+        let mut knots = vec![(first, 0)];
+        // the lines from the last knot that stay within max_error of every point since
+        let mut corridor: Option<((Key, f64), (Key, f64))> = None;
+        let mut previous = (first, 0);
+        for (rank, &key) in sorted_keys.iter().enumerate().skip(1) {
+            let base = *knots.last().expect("the first key is a knot");
+            let point = (key, rank as f64);
+            let upper = (key, rank as f64 + max_error);
+            let lower = (key, rank as f64 - max_error);
+            corridor = match corridor {
+                Some((high, low)) if steeper(base, point, high) || steeper(base, low, point) => {
+                    // the line to this point leaves the corridor, restart at the previous one
+                    knots.push(previous);
+                    Some((upper, lower))
+                }
+                Some((high, low)) => Some((
+                    if steeper(base, high, upper) {
+                        upper
+                    } else {
+                        high
+                    },
+                    if steeper(base, lower, low) {
+                        lower
+                    } else {
+                        low
+                    },
+                )),
+                None => Some((upper, lower)),
+            };
+            previous = (key, rank);
+        }
+        if knots.last() != Some(&previous) {
+            knots.push(previous);
+        }
+        knots
+    }
+
+    /// bit position of a key between the first and last key
+    ///
+    /// Monotone in `key`: every operation below is, floats included, and the
+    /// interpolation ends at exactly the next knot's rank.
+    fn position(&self, key: Key) -> usize {
+        let index = self.knots.partition_point(|&(knot, _)| knot <= key) - 1;
+        let (low_key, low_rank) = self.knots[index];
+        let rank = match self.knots.get(index + 1) {
+            None => low_rank as f64,
+            Some(&(high_key, high_rank)) => {
+                let fraction = (key - low_key) as f64 / (high_key - low_key) as f64;
+                low_rank as f64 + fraction * (high_rank - low_rank) as f64
+            }
+        };
+        (rank * self.scale) as usize
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist (with false positive rate `fpr`)
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.range_query(key, key)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// # Returns
+    /// * `true` if at least one key in the range might exist
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        let (Some(&(min, _)), Some(&(max, _))) = (self.knots.first(), self.knots.last()) else {
+            return false;
+        };
+        if start > end || end < min || start > max {
+            return false;
+        }
+        let first = self.position(start.max(min));
+        let last = self.position(end.min(max));
+        self.bits.rank(last + 1) > self.bits.rank(first)
+    }
+
+    /// Get the configured false positive rate.
+    pub fn fpr(&self) -> f64 {
+        self.fpr
+    }
+
+    /// Get the number of distinct keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of knots of the spline modeling the CDF.
+    pub fn num_knots(&self) -> usize {
+        self.knots.len()
+    }
+
+    /// Get the memory used by the filter: the struct, the model and the bit array.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + std::mem::size_of_val(self.knots.as_slice())
+            + self.bits.heap_size()
+    }
+
+    /// Get the memory per distinct key in bits, the spline knots included.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for Snarf {
+    fn point_query(&self, key: Key) -> bool {
+        Snarf::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        Snarf::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_no_false_negatives_and_fpr() {
+        let mut rng = rand::thread_rng();
+        // clusters of different densities, the model has to follow them
+        let mut clustered = |i: u64| (i % 20) * (1 << 50) + rng.gen_range(0..1u64 << (20 + i % 20));
+        let keys: Vec<Key> = (0..20_000).map(&mut clustered).collect();
+        let set: BTreeSet<Key> = keys.iter().copied().collect();
+        let filter = Snarf::new_with_keys(&keys, 0.01);
+        for &key in &keys {
+            assert!(filter.point_query(key));
+            assert!(filter.range_query(key.saturating_sub(1000), key));
+        }
+
+        let (mut empty, mut false_positives) = (0, 0);
+        for i in 0..20_000 {
+            // drawn like the keys, the rate only holds for queries that follow the CDF
+            let probe = clustered(i);
+            if !set.contains(&probe) {
+                empty += 1;
+                false_positives += filter.point_query(probe) as usize;
+            }
+        }
+        // 1% expected, plus about 16 squeezed keys after each of the 20 gaps
+        assert!(
+            false_positives * 20 < empty,
+            "{} false positives in {} empty points",
+            false_positives,
+            empty
+        );
+        assert!(
+            filter.bits_per_key() < 12.0,
+            "{} bits per key",
+            filter.bits_per_key()
+        );
+    }
+
+    #[test]
+    fn test_knots_and_edges() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 1000 + 500).collect();
+        let filter = Snarf::new_with_max_error(&keys, 0.001, 10);
+        // evenly spaced keys lie on one line
+        assert_eq!(filter.num_knots(), 2);
+        assert!(!filter.range_query(0, 499));
+        assert!(filter.range_query(0, 500));
+        assert!(filter.range_query(999_500, Key::MAX));
+        assert!(!filter.range_query(999_501, Key::MAX));
+        // modeled exactly, 1000 positions per gap
+        assert!(!filter.range_query(1_001, 1_499));
+        assert!(!filter.range_query(10, 5));
+
+        let single = Snarf::new_with_keys(&[Key::MAX], 0.01);
+        assert!(single.point_query(Key::MAX) && !single.range_query(0, Key::MAX - 1));
+
+        let empty = Snarf::new_with_keys(&[], 0.01);
+        assert!(!empty.range_query(0, Key::MAX));
+        assert_eq!(empty.bits_per_key(), 0.0);
+    }
+
+    #[test]
+    fn test_spline_stays_within_max_error() {
+        let mut rng = rand::thread_rng();
+        let mut keys: Vec<Key> = (0..5000)
+            .map(|i| (i % 7) * (1 << 40) + rng.gen_range(0..1u64 << (10 + 3 * (i % 7))))
+            .collect();
+        keys.sort_unstable();
+        keys.dedup();
+        let filter = Snarf::new_with_max_error(&keys, 0.5, 8);
+        assert!(
+            filter.num_knots() < keys.len() / 4,
+            "{} knots",
+            filter.num_knots()
+        );
+        for (rank, &key) in keys.iter().enumerate() {
+            // scale 2, and one more rank for the float rounding
+            let modeled = filter.position(key) as f64 / 2.0;
+            assert!(
+                (modeled - rank as f64).abs() <= 9.0,
+                "{} at {}",
+                key,
+                modeled
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "fpr must be in (0, 1)")]
+    fn test_rejects_zero_fpr() {
+        Snarf::new_with_keys(&[1], 0.0);
+    }
+}
@@ -0,0 +1,373 @@
+//! SuRF, the Succinct Range Filter
+//!
+//! Keys are read as 8 big-endian bytes and every key is cut down to its
+//! shortest prefix that tells it apart from its neighbours. The prefixes form
+//! a byte trie, stored level by level in LOUDS-Sparse form: the labels of all
+//! nodes in breadth-first order, a `has_child` bit per label telling inner
+//! labels from leaves, and a `louds` bit per label marking the first label of
+//! each node. Following a label to its child node is one rank and one select.
+//!
+//! A leaf stands for every key starting with its prefix, so a query asks
+//! whether the first leaf at or after `start` begins at or before `end`. With
+//! `SuffixMode::Real` every leaf also stores the next bits of its key after
+//! the prefix, narrowing the keys it stands for (SuRF-Real). `SuffixMode::Base`
+//! stores the trie alone (SuRF-Base).
+//!
+//! This is the LOUDS-Sparse half of the paper's design: the dense encoding of
+//! the upper levels is a speed optimization and left out.
+
+use std::collections::VecDeque;
+
+use crate::Key;
+use crate::U64_BITS;
+use crate::bitmap::{BitVec, read_bits, write_bits};
+use crate::range_filter::RangeFilter;
+use crate::rank_select::RankSelect;
+
+/// What every leaf stores besides its prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuffixMode {
+    /// nothing, SuRF-Base
+    Base,
+    /// the given number of key bits following the prefix, SuRF-Real
+    Real(u8),
+}
+
+impl SuffixMode {
+    fn bits(self) -> usize {
+        match self {
+            SuffixMode::Base => 0,
+            SuffixMode::Real(bits) => bits as usize,
+        }
+    }
+}
+
+pub struct Surf {
+    labels: Vec<u8>,
+    has_child: RankSelect,
+    louds: RankSelect,
+    // suffix_bits per leaf, leaves in breadth-first order
+    suffixes: Vec<u64>,
+    mode: SuffixMode,
+    num_keys: usize,
+}
+
+/// positions of the labels followed from the root, one per level
+type Path = Vec<(usize, usize)>;
+
+impl Surf {
+    /// Create a new SuRF over the given keys.
+    ///
+    /// # Arguments
+    /// * `keys` - A slice of keys to insert into the filter
+    /// * `mode` - Suffix stored with every leaf
+    ///
+    /// Panics if a `SuffixMode::Real` suffix is 0 or above 64 bits.
+    pub fn new_with_keys(keys: &[Key], mode: SuffixMode) -> Self {
+        if let SuffixMode::Real(bits) = mode {
+            assert!(
+                (1..=U64_BITS).contains(&(bits as usize)),
+                "real suffixes must be between 1 and {} bits, got {}",
+                U64_BITS,
+                bits
+            );
+        }
+        let mut sorted_keys = keys.to_vec();
+        sorted_keys.sort_unstable();
+        sorted_keys.dedup();
+        let byte_at = |key: Key, depth: usize| (key >> (56 - 8 * depth)) as u8;
+
+        let mut labels = Vec::new();
+        let mut has_child = Vec::new();
+        let mut louds = Vec::new();
+        let mut leaves = Vec::new();
+        // nodes are runs of keys sharing their first `depth` bytes
+        let mut queue = VecDeque::new();
+        if !sorted_keys.is_empty() {
+            queue.push_back((0, sorted_keys.len(), 0));
+        }
+        while let Some((low, high, depth)) = queue.pop_front() {
+            let mut first = low;
+            while first < high {
+                let byte = byte_at(sorted_keys[first], depth);
+                let last = first
+                    + sorted_keys[first..high].partition_point(|&key| byte_at(key, depth) == byte);
+                labels.push(byte);
+                louds.push(first == low);
+                // distinct keys differ within 8 bytes, so a run of one is a leaf
+                has_child.push(last - first > 1);
+                if last - first > 1 {
+                    queue.push_back((first, last, depth + 1));
+                } else {
+                    leaves.push((sorted_keys[first], depth));
+                }
+                first = last;
+            }
+        }
+
+        let suffix_bits = mode.bits();
+        let mut suffixes = vec![0u64; (leaves.len() * suffix_bits).div_ceil(64)];
+        for (leaf, &(key, depth)) in leaves.iter().enumerate() {
+            let used = Self::suffix_len(suffix_bits, depth);
+            if used > 0 {
+                let suffix = (key << (8 * (depth + 1))) >> (U64_BITS - used);
+                write_bits(&mut suffixes, leaf * suffix_bits, used, suffix);
+            }
+        }
+
+        Self {
+            labels,
+            has_child: Self::rank_select(&has_child),
+            louds: Self::rank_select(&louds),
+            suffixes,
+            mode,
+            num_keys: sorted_keys.len(),
+        }
+    }
+
+    fn rank_select(bits: &[bool]) -> RankSelect {
+        let mut bit_vec = BitVec::new(bits.len());
+        for (pos, _) in bits.iter().enumerate().filter(|&(_, &bit)| bit) {
+            bit_vec.set(pos);
+        }
+        RankSelect::new(bit_vec)
+    }
+
+    /// suffix bits a leaf at `depth` keeps, its prefix holds `depth + 1` bytes
+    fn suffix_len(suffix_bits: usize, depth: usize) -> usize {
+        suffix_bits.min(U64_BITS - 8 * (depth + 1))
+    }
+
+    /// Perform a point query to check if a key might exist in the filter.
+    ///
+    /// # Returns
+    /// * `true` if the key might exist
+    /// * `false` if the key definitely does not exist
+    pub fn point_query(&self, key: Key) -> bool {
+        self.range_query(key, key)
+    }
+
+    /// Perform a range query to check if any key might exist in the given range [start, end] (inclusive).
+    ///
+    /// # Returns
+    /// * `true` if at least one key in the range might exist
+    /// * `false` if no keys in the range exist
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        if start > end || self.num_keys == 0 {
+            return false;
+        }
+        self.first_leaf_from(start)
+            .is_some_and(|(low, _)| low <= end)
+    }
+
+    /// keys covered by the first leaf that covers a key at or after `start`
+    fn first_leaf_from(&self, start: Key) -> Option<(Key, Key)> {
+        let mut path = Path::new();
+        let (mut begin, mut node_end) = self.node_bounds(0);
+        loop {
+            let byte = (start >> (56 - 8 * path.len())) as u8;
+            let pos = begin + self.labels[begin..node_end].partition_point(|&label| label < byte);
+            if pos == node_end || self.labels[pos] != byte {
+                // every key below the labels from pos on is above start
+                return self.leftmost_leaf(pos, node_end, &mut path);
+            }
+            if !self.has_child.get(pos) {
+                let leaf = self.leaf_range(pos, &path);
+                if leaf.1 >= start {
+                    return Some(leaf);
+                }
+                return self.leftmost_leaf(pos + 1, node_end, &mut path);
+            }
+            path.push((pos, node_end));
+            (begin, node_end) = self.node_bounds(self.has_child.rank(pos + 1));
+        }
+    }
+
+    /// the first leaf under the labels `pos..node_end`, or after them
+    fn leftmost_leaf(
+        &self,
+        mut pos: usize,
+        mut node_end: usize,
+        path: &mut Path,
+    ) -> Option<(Key, Key)> {
+        // climb until a node has labels left
+        while pos == node_end {
+            let (parent, parent_end) = path.pop()?;
+            (pos, node_end) = (parent + 1, parent_end);
+        }
+        while self.has_child.get(pos) {
+            path.push((pos, node_end));
+            (pos, node_end) = self.node_bounds(self.has_child.rank(pos + 1));
+        }
+        Some(self.leaf_range(pos, path))
+    }
+
+    /// first and one-past-last label positions of a node
+    fn node_bounds(&self, node: usize) -> (usize, usize) {
+        let begin = self.louds.select(node).expect("child node out of range");
+        let end = self.louds.select(node + 1).unwrap_or(self.labels.len());
+        (begin, end)
+    }
+
+    /// smallest and largest key the leaf at `pos` below `path` stands for
+    fn leaf_range(&self, pos: usize, path: &Path) -> (Key, Key) {
+        let depth = path.len();
+        let mut low = path
+            .iter()
+            .enumerate()
+            .fold(0, |prefix, (level, &(label_pos, _))| {
+                prefix | (self.labels[label_pos] as Key) << (56 - 8 * level)
+            });
+        low |= (self.labels[pos] as Key) << (56 - 8 * depth);
+
+        let suffix_bits = self.mode.bits();
+        let used = Self::suffix_len(suffix_bits, depth);
+        let free = U64_BITS - 8 * (depth + 1) - used;
+        if used > 0 {
+            let leaf = pos - self.has_child.rank(pos);
+            low |= read_bits(&self.suffixes, leaf * suffix_bits, used) << free;
+        }
+        let high = match free {
+            0 => low,
+            free => low | (Key::MAX >> (U64_BITS - free)),
+        };
+        (low, high)
+    }
+
+    /// Get the suffix mode the filter was built with.
+    pub fn suffix_mode(&self) -> SuffixMode {
+        self.mode
+    }
+
+    /// Get the number of distinct keys inserted into the filter.
+    pub fn num_keys(&self) -> usize {
+        self.num_keys
+    }
+
+    /// Get the number of trie labels, inner and leaf.
+    pub fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Get the memory used by the filter: the struct, labels, bit vectors and suffixes.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.labels.capacity()
+            + self.has_child.heap_size()
+            + self.louds.heap_size()
+            + std::mem::size_of_val(self.suffixes.as_slice())
+    }
+
+    /// Get the memory per distinct key in bits, which grows with the suffix
+    /// mode and with how long the keys' distinguishing prefixes are.
+    pub fn bits_per_key(&self) -> f64 {
+        match self.num_keys {
+            0 => 0.0,
+            num_keys => (self.size_in_bytes() * 8) as f64 / num_keys as f64,
+        }
+    }
+}
+
+impl RangeFilter for Surf {
+    fn point_query(&self, key: Key) -> bool {
+        Surf::point_query(self, key)
+    }
+
+    fn range_query(&self, start: Key, end: Key) -> bool {
+        Surf::range_query(self, start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..5000).map(|_| rng.gen_range(0..1u64 << 48)).collect();
+        let set: BTreeSet<Key> = keys.iter().copied().collect();
+        for mode in [SuffixMode::Base, SuffixMode::Real(8)] {
+            let filter = Surf::new_with_keys(&keys, mode);
+            assert_eq!(filter.num_keys(), set.len());
+            for &key in &keys {
+                assert!(filter.point_query(key));
+                assert!(filter.range_query(key.saturating_sub(50), key));
+            }
+            for _ in 0..5000 {
+                let start = rng.gen_range(0..1u64 << 48);
+                let end = start + rng.gen_range(0..1 << 36);
+                if set.range(start..=end).next().is_some() {
+                    assert!(filter.range_query(start, end), "[{}, {}]", start, end);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_real_suffixes_cut_false_positives() {
+        let mut rng = rand::thread_rng();
+        let keys: Vec<Key> = (0..5000).map(|_| rng.gen_range(0..Key::MAX)).collect();
+        let set: BTreeSet<Key> = keys.iter().copied().collect();
+        let base = Surf::new_with_keys(&keys, SuffixMode::Base);
+        let real = Surf::new_with_keys(&keys, SuffixMode::Real(8));
+
+        let (mut base_fp, mut real_fp) = (0, 0);
+        for _ in 0..20_000 {
+            let key = rng.gen_range(0..Key::MAX);
+            if !set.contains(&key) {
+                base_fp += base.point_query(key) as usize;
+                real_fp += real.point_query(key) as usize;
+            }
+        }
+        // 2 or 3 prefix bytes cover 1 in a few hundred keys, 8 more bits divide that by 256
+        assert!(real_fp * 20 < base_fp.max(20), "{} vs {}", real_fp, base_fp);
+        assert!(real.bits_per_key() > base.bits_per_key());
+        assert!(base.bits_per_key() < 32.0, "{}", base.bits_per_key());
+    }
+
+    #[test]
+    fn test_full_length_keys_and_edges() {
+        let keys = [
+            0,
+            1,
+            255,
+            256,
+            0x0100_0000_0000_0000,
+            Key::MAX - 1,
+            Key::MAX,
+        ];
+        let filter = Surf::new_with_keys(&keys, SuffixMode::Real(64));
+        for &key in &keys {
+            assert!(filter.point_query(key));
+        }
+        assert!(!filter.point_query(2));
+        assert!(!filter.range_query(2, 254));
+        assert!(filter.range_query(2, 255));
+        assert!(!filter.range_query(257, 0x00FF_FFFF_FFFF_FFFF));
+        assert!(!filter.range_query(0x0100_0000_0000_0001, Key::MAX - 2));
+        assert!(!filter.range_query(10, 5));
+
+        let empty = Surf::new_with_keys(&[], SuffixMode::Base);
+        assert!(!empty.range_query(0, Key::MAX));
+        assert_eq!(empty.bits_per_key(), 0.0);
+    }
+
+    #[test]
+    fn test_base_leaves_cover_their_prefix() {
+        // one key: the root alone, one leaf for its first byte
+        let filter = Surf::new_with_keys(&[0x1234_5678_0000_0000], SuffixMode::Base);
+        assert_eq!(filter.num_labels(), 1);
+        assert!(filter.point_query(0x12FF_FFFF_FFFF_FFFF));
+        assert!(!filter.range_query(0, 0x11FF_FFFF_FFFF_FFFF));
+        assert!(!filter.point_query(0x1300_0000_0000_0000));
+    }
+
+    #[test]
+    #[should_panic(expected = "real suffixes must be between 1 and 64 bits")]
+    fn test_rejects_empty_real_suffix() {
+        Surf::new_with_keys(&[1], SuffixMode::Real(0));
+    }
+}
@@ -0,0 +1,186 @@
+//! Shared state of the tries and Diva, with or without `std`
+//!
+//! With `std`, `RwLock` is `std::sync::RwLock` and `LevelTable`, the hash table
+//! of an x-fast trie level, is a `DashMap`. With only `alloc` they are a spin
+//! lock keeping the `std` API, which never reports poisoning, and a
+//! `hashbrown` table with the part of the `DashMap` API the tries use. Its
+//! updates take `&mut self`, which the tries hold for every update anyway.
+
+pub use alloc::sync::{Arc, Weak};
+
+#[cfg(feature = "std")]
+pub use dashmap::DashMap as LevelTable;
+#[cfg(feature = "std")]
+pub use std::sync::RwLock;
+
+#[cfg(not(feature = "std"))]
+pub use self::spin_impl::{LevelTable, PoisonError, Ref, RefMulti, RefMut, RwLock};
+
+#[cfg(not(feature = "std"))]
+mod spin_impl {
+    use core::fmt;
+    use core::hash::{BuildHasher, Hash};
+    use core::ops::{Deref, DerefMut};
+
+    /// Reader-writer spin lock with the API of `std::sync::RwLock`
+    #[derive(Default)]
+    pub struct RwLock<T: ?Sized>(spin::RwLock<T>);
+
+    /// Never returned, spin locks are not poisoned by a panicking writer
+    #[derive(Debug)]
+    pub struct PoisonError {
+        _private: (),
+    }
+
+    pub type LockResult<G> = Result<G, PoisonError>;
+
+    impl<T> RwLock<T> {
+        pub const fn new(value: T) -> Self {
+            Self(spin::RwLock::new(value))
+        }
+
+        pub fn into_inner(self) -> LockResult<T> {
+            Ok(self.0.into_inner())
+        }
+    }
+
+    impl<T: ?Sized> RwLock<T> {
+        pub fn read(&self) -> LockResult<spin::RwLockReadGuard<'_, T>> {
+            Ok(self.0.read())
+        }
+
+        pub fn write(&self) -> LockResult<spin::RwLockWriteGuard<'_, T>> {
+            Ok(self.0.write())
+        }
+
+        pub fn get_mut(&mut self) -> LockResult<&mut T> {
+            Ok(self.0.get_mut())
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("RwLock")
+                .field("data", &&*self.0.read())
+                .finish()
+        }
+    }
+
+    /// Hash table of an x-fast trie level
+    #[derive(Clone, Default)]
+    pub struct LevelTable<K, V, S> {
+        map: hashbrown::HashMap<K, V, S>,
+    }
+
+    /// Value found by `LevelTable::get`
+    pub struct Ref<'a, V>(&'a V);
+
+    /// Value found by `LevelTable::get_mut`
+    pub struct RefMut<'a, V>(&'a mut V);
+
+    /// Entry visited by `LevelTable::iter`
+    pub struct RefMulti<'a, K, V> {
+        key: &'a K,
+        value: &'a V,
+    }
+
+    impl<V> Deref for Ref<'_, V> {
+        type Target = V;
+
+        fn deref(&self) -> &V {
+            self.0
+        }
+    }
+
+    impl<V> Deref for RefMut<'_, V> {
+        type Target = V;
+
+        fn deref(&self) -> &V {
+            self.0
+        }
+    }
+
+    impl<V> DerefMut for RefMut<'_, V> {
+        fn deref_mut(&mut self) -> &mut V {
+            self.0
+        }
+    }
+
+    impl<'a, K, V> RefMulti<'a, K, V> {
+        pub fn key(&self) -> &'a K {
+            self.key
+        }
+
+        pub fn value(&self) -> &'a V {
+            self.value
+        }
+    }
+
+    impl<K: Eq + Hash, V, S: BuildHasher> LevelTable<K, V, S> {
+        pub fn with_hasher(hasher: S) -> Self {
+            Self::with_capacity_and_hasher(0, hasher)
+        }
+
+        pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+            Self {
+                map: hashbrown::HashMap::with_capacity_and_hasher(capacity, hasher),
+            }
+        }
+
+        pub fn hasher(&self) -> &S {
+            self.map.hasher()
+        }
+
+        pub fn get(&self, key: &K) -> Option<Ref<'_, V>> {
+            self.map.get(key).map(Ref)
+        }
+
+        pub fn get_mut(&mut self, key: &K) -> Option<RefMut<'_, V>> {
+            self.map.get_mut(key).map(RefMut)
+        }
+
+        pub fn contains_key(&self, key: &K) -> bool {
+            self.map.contains_key(key)
+        }
+
+        pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+            self.map.insert(key, value)
+        }
+
+        pub fn remove(&mut self, key: &K) -> Option<(K, V)> {
+            self.map.remove_entry(key)
+        }
+
+        pub fn len(&self) -> usize {
+            self.map.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.map.is_empty()
+        }
+
+        pub fn capacity(&self) -> usize {
+            self.map.capacity()
+        }
+
+        pub fn shrink_to_fit(&mut self) {
+            self.map.shrink_to_fit();
+        }
+
+        pub fn iter(&self) -> impl Iterator<Item = RefMulti<'_, K, V>> {
+            self.map.iter().map(|(key, value)| RefMulti { key, value })
+        }
+    }
+
+    impl<K: Eq + Hash, V, S: BuildHasher> Extend<(K, V)> for LevelTable<K, V, S> {
+        fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+            self.map.extend(entries);
+        }
+    }
+
+    impl<K: fmt::Debug, V: fmt::Debug, S> fmt::Debug for LevelTable<K, V, S> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_map().entries(self.map.iter()).finish()
+        }
+    }
+}
@@ -3,3 +3,122 @@ use crate::Key;
 pub fn longest_common_prefix_length(key1: Key, key2: Key) -> u32 {
     (key1 ^ key2).leading_zeros()
 }
+
+/// bytes of a heap allocation behind `Arc<T>`: strong and weak counts plus the value
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub(crate) const fn arc_allocation_size<T>() -> usize {
+    2 * core::mem::size_of::<usize>() + core::mem::size_of::<T>()
+}
+
+/// 64-bit finalizer of MurmurHash3, mixes every input bit into every output bit
+#[cfg(any(feature = "std", feature = "alloc"))]
+pub(crate) fn mix64(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+    x ^ (x >> 33)
+}
+
+/// compute redundant bits after first differing bit
+/// redundant bits are consecutive bits with opposite patterns in pred/succ
+/// that can be reconstructed knowing the key is in this range
+fn compute_redundant_bits(key_1: Key, key_2: Key, shared_prefix_len: u8) -> u8 {
+    if shared_prefix_len >= 63 {
+        return 0;
+    }
+
+    let mut redundant_bits = 0u8;
+
+    // start after shared prefix + 1 (skip first differing bit)
+    let start_pos = shared_prefix_len + 1;
+
+    for bit_pos in start_pos..64 {
+        let shift = 63 - bit_pos;
+        let bit_1 = (key_1 >> shift) & 1;
+        let bit_2 = (key_2 >> shift) & 1;
+
+        // redundant if pred has 0 and succ has 1 (opposite of first diff bit)
+        if bit_1 == 0 && bit_2 == 1 {
+            redundant_bits += 1;
+        } else {
+            break; // stop at first non-redundant bit
+        }
+    }
+
+    redundant_bits
+}
+
+/// shared prefix length, redundant bits and quotient size of the partition
+/// between two keys, see `Diva::get_shared_ignore_implicit_size_with`
+pub(crate) fn shared_ignore_implicit_size(
+    key_1: &Key,
+    key_2: &Key,
+    use_redundant_bits: bool,
+    base_implicit_size: u8,
+) -> (u8, u8, u8) {
+    // step 1: find shared prefix length (LCP)
+    let shared = longest_common_prefix_length(*key_1, *key_2) as u8;
+
+    // step 2: compute redundant bits
+    let redundant_bits = if use_redundant_bits {
+        compute_redundant_bits(*key_1, *key_2, shared)
+    } else {
+        0
+    };
+
+    // step 3: compute quotient size or aka implicit bits
+    let bits_used = shared + redundant_bits;
+
+    if bits_used >= 64 {
+        return (shared, redundant_bits, 0);
+    }
+
+    let remaining_bits = 64 - bits_used;
+
+    // try to use base_implicit_size quotient bits
+    if remaining_bits < base_implicit_size {
+        return (shared, redundant_bits, remaining_bits);
+    }
+
+    // extract quotient bits from both keys to check sparsity
+    // let shift = remaining_bits - BASE_IMPLICIT_SIZE as u8;
+    // let quotient_1 = (key_1 >> shift) & ((1u64 << BASE_IMPLICIT_SIZE) - 1);
+    // let quotient_2 = (key_2 >> shift) & ((1u64 << BASE_IMPLICIT_SIZE) - 1);
+
+    // TODO: check if there is a better heuristic for the quotient size
+    // add 1 bit if range is sparse (uses < 50% of quotient space)
+    // let range_size = quotient_2 - quotient_1 + 1;
+    // let quotient_bits = if 2 * range_size < (1u64 << BASE_IMPLICIT_SIZE) {
+    //     (BASE_IMPLICIT_SIZE + 1).min(remaining_bits as u32) as u8
+    // } else {
+    //     BASE_IMPLICIT_SIZE as u8
+    // };
+
+    (shared, redundant_bits, base_implicit_size)
+}
+
+/// infix of a key in a partition, see `Diva::extract_partial_key`
+pub(crate) fn extract_partial_key(
+    key: Key,
+    shared_prefix_len: u8,
+    redundant_bits: u8,
+    quotient_bits: u8,
+    remainder_bits: u8,
+) -> Key {
+    let start_bit = shared_prefix_len + redundant_bits;
+
+    if start_bit >= 64 {
+        return 0;
+    }
+
+    let remaining_bits = 64 - start_bit;
+    let bits_to_extract = (quotient_bits + remainder_bits).min(remaining_bits);
+
+    if bits_to_extract == 0 {
+        return 0;
+    }
+
+    let shift_amount = 64 - start_bit - bits_to_extract;
+    (key >> shift_amount) & ((1 << bits_to_extract) - 1)
+}
@@ -0,0 +1,82 @@
+use crate::Key;
+use crate::diva::Diva;
+use wasm_bindgen::prelude::*;
+
+/// JavaScript binding of `Diva`, exported to JS as `Diva`
+///
+/// Keys are `u64` and cross the boundary as `BigInt` (`BigUint64Array` for
+/// key lists). A filter built on a server with `Diva::save_to` can be shipped
+/// to the browser and opened with `Diva.fromBytes`.
+///
+/// ```js
+/// import { Diva } from "range-filters";
+///
+/// const diva = new Diva(new BigUint64Array([10n, 20n, 30n]), 1024, 0.01);
+/// diva.contains(20n);          // true
+/// diva.rangeQuery(11n, 19n);   // false unless a false positive
+/// ```
+#[wasm_bindgen(js_name = Diva)]
+pub struct WasmDiva {
+    inner: Diva,
+}
+
+#[wasm_bindgen(js_class = Diva)]
+impl WasmDiva {
+    /// Build a filter over the given keys
+    #[wasm_bindgen(constructor)]
+    pub fn new(keys: &[Key], target_size: usize, fpr: f64) -> Result<WasmDiva, JsError> {
+        let inner = Diva::builder()
+            .target_size(target_size)
+            .fpr(fpr)
+            .build_with_keys(keys)?;
+        Ok(Self { inner })
+    }
+
+    /// Open a filter written by `Diva::save_to` or `toBytes`
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmDiva, JsError> {
+        let inner = Diva::load_from(&mut &bytes[..])?;
+        Ok(Self { inner })
+    }
+
+    /// Serialize the filter in the `Diva::save_to` format
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        let mut bytes = Vec::new();
+        self.inner.save_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn insert(&mut self, key: Key) -> Result<(), JsError> {
+        Ok(self.inner.insert(key)?)
+    }
+
+    pub fn delete(&mut self, key: Key) -> Result<(), JsError> {
+        Ok(self.inner.delete(key)?)
+    }
+
+    pub fn contains(&self, key: Key) -> bool {
+        self.inner.contains(key)
+    }
+
+    #[wasm_bindgen(js_name = rangeQuery)]
+    pub fn range_query(&self, start: Key, end: Key) -> bool {
+        self.inner.range_query(start, end)
+    }
+
+    #[wasm_bindgen(getter, js_name = numKeys)]
+    pub fn num_keys(&self) -> usize {
+        self.inner.num_keys()
+    }
+
+    #[wasm_bindgen(getter, js_name = sizeInBytes)]
+    pub fn size_in_bytes(&self) -> usize {
+        self.inner.size_in_bytes()
+    }
+}
+
+impl From<Diva> for WasmDiva {
+    fn from(inner: Diva) -> Self {
+        Self { inner }
+    }
+}
@@ -1,208 +1,649 @@
 use crate::Key;
 use crate::binary_search_tree::BinarySearchTreeGroup;
-use dashmap::DashMap;
-use std::fmt;
-use std::sync::{Arc, RwLock, Weak};
+pub use crate::rep_list::RepNode;
+use crate::rep_list::{RepList, RepPayload, SharedRep};
+use crate::sync::{Arc, LevelTable, RwLock};
+use crate::utils::arc_allocation_size;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::BuildHasher;
+use core::iter::FusedIterator;
+use core::ops::{Bound, RangeBounds};
+use rustc_hash::FxBuildHasher;
 
 pub const ROOT_KEY: Key = 67;
 
+/// keys below which a bulk load fills all levels on the calling thread
+#[cfg(feature = "std")]
+const PARALLEL_LOAD_KEYS: usize = 1 << 14;
+
+/// threads to bulk load `num_keys` keys on, one below `PARALLEL_LOAD_KEYS` or
+/// without threads (wasm, no `std`)
+#[cfg(feature = "std")]
+pub(crate) fn bulk_load_threads(num_keys: usize) -> usize {
+    match num_keys {
+        _ if cfg!(target_family = "wasm") => 1,
+        len if len < PARALLEL_LOAD_KEYS => 1,
+        _ => std::thread::available_parallelism().map_or(1, usize::from),
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn bulk_load_threads(_num_keys: usize) -> usize {
+    1
+}
+
+/// X-fast trie over `no_levels`-bit keys
+///
+/// The level tables hash prefixes with FxHash unless a different hasher is given
+/// to `with_hasher`: a predecessor query probes one table per step of its binary
+/// search over the levels, so the hash function shows up in every lookup.
+///
+/// With a level stride k > 1 (`with_level_stride`) only every k-th prefix length
+/// gets a table, plus the full keys, which cuts the entries per key by about k.
+/// A query that ends at a stored prefix without reaching a key then scans up to
+/// 2^k - 1 sibling prefixes of the next stored level.
+///
+/// # Concurrency
+///
+/// Single writer, many readers: `insert`, `delete` and `shrink_to_fit` take
+/// `&mut self`, so a write never overlaps another write or a query, while the
+/// `&self` queries run from any number of threads at once. A trie that keeps
+/// changing while it is queried is shared behind a `RwLock`. Bulk loads from
+/// sorted keys fill the levels on several threads.
 #[derive(Debug)]
-pub struct XFastTrie {
-    pub levels: Vec<XFastLevel>,
-    // representatives
-    // pub reps: HashMap<Key, Arc<RwLock<RepNode>>>,
-    pub head_rep: Option<Arc<RwLock<RepNode>>>,
-    pub tail_rep: Option<Arc<RwLock<RepNode>>>,
+pub struct XFastTrie<S: BuildHasher + Clone = FxBuildHasher, T = BinarySearchTreeGroup> {
+    pub levels: Vec<XFastLevel<S, T>>,
+    // representatives in key order, each carrying a T
+    pub reps: RepList<T>,
 
     // no. of levels = no. of bits in the keys
     pub no_levels: usize,
+    // bits between the prefix lengths of two stored levels, 1 stores every level
+    pub level_stride: usize,
 }
 
 #[derive(Debug, Default, Clone)]
-pub struct XFastLevel {
-    pub table: DashMap<Key, XFastValue>,
+pub struct XFastLevel<S: BuildHasher + Clone = FxBuildHasher, T = BinarySearchTreeGroup> {
+    pub table: LevelTable<Key, XFastValue<T>, S>,
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct XFastValue {
-    pub left_child: Option<Arc<RwLock<XFastValue>>>,
-    pub right_child: Option<Arc<RwLock<XFastValue>>>,
+#[derive(Debug)]
+pub struct XFastValue<T = BinarySearchTreeGroup> {
+    // whether the prefixes extended by a 0 / 1 bit are in the next level,
+    // only kept with a level stride of 1
+    pub has_left_child: bool,
+    pub has_right_child: bool,
+
+    // smallest and largest key below the prefix
+    pub min_rep: Option<SharedRep<T>>,
+    pub max_rep: Option<SharedRep<T>>,
+}
 
-    // pub representative: Option<Arc<RwLock<RepNode>>>
-    pub min_rep: Option<Arc<RwLock<RepNode>>>,
-    pub max_rep: Option<Arc<RwLock<RepNode>>>,
+// not derived, which would require T: Default / T: Clone for the shared pointers
+impl<T> Default for XFastValue<T> {
+    fn default() -> Self {
+        Self {
+            has_left_child: false,
+            has_right_child: false,
+            min_rep: None,
+            max_rep: None,
+        }
+    }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct RepNode {
-    pub key: Key,
-    pub left: Option<Weak<RwLock<RepNode>>>,
-    pub right: Option<Weak<RwLock<RepNode>>>,
-    pub bst_group: Option<Arc<RwLock<BinarySearchTreeGroup>>>,
+impl<T> Clone for XFastValue<T> {
+    fn clone(&self) -> Self {
+        Self {
+            has_left_child: self.has_left_child,
+            has_right_child: self.has_right_child,
+            min_rep: self.min_rep.clone(),
+            max_rep: self.max_rep.clone(),
+        }
+    }
+}
+
+impl<T> XFastValue<T> {
+    /// descendant pointer of a prefix missing a child
+    ///
+    /// Without a left child it threads to the smallest key of the right subtree,
+    /// without a right child to the largest key of the left subtree. A prefix
+    /// with both children has none.
+    pub fn descendant(&self) -> Option<&SharedRep<T>> {
+        match (self.has_left_child, self.has_right_child) {
+            (false, _) => self.min_rep.as_ref(),
+            (true, false) => self.max_rep.as_ref(),
+            (true, true) => None,
+        }
+    }
+}
+
+impl<S: BuildHasher + Clone, T> XFastLevel<S, T> {
+    /// shards of the level table, one without `std`
+    pub fn num_shards(&self) -> usize {
+        #[cfg(feature = "std")]
+        return self.table.shards().len();
+        #[cfg(not(feature = "std"))]
+        1
+    }
+
+    /// move the table to about one shard per `ENTRIES_PER_SHARD` entries
+    #[cfg(feature = "std")]
+    fn reshard(&mut self) {
+        const ENTRIES_PER_SHARD: usize = 16;
+        let shards = self.table.shards().len();
+        let wanted = (self.table.len() / ENTRIES_PER_SHARD)
+            .next_power_of_two()
+            .clamp(2, shards);
+        if wanted < shards {
+            let table = LevelTable::with_capacity_and_hasher_and_shard_amount(
+                self.table.len(),
+                self.table.hasher().clone(),
+                wanted,
+            );
+            for (prefix, value) in core::mem::replace(&mut self.table, table) {
+                self.table.insert(prefix, value);
+            }
+        }
+    }
 }
 
 impl XFastTrie {
     pub fn new(no_levels: usize) -> Self {
-        let mut levels = Vec::with_capacity(no_levels + 1);
-        let root = XFastLevel::default();
+        Self::with_hasher(no_levels, FxBuildHasher)
+    }
+
+    /// empty trie storing only every `level_stride`-th prefix length
+    pub fn with_level_stride(no_levels: usize, level_stride: usize) -> Self {
+        Self::with_hasher_and_level_stride(no_levels, level_stride, FxBuildHasher)
+    }
+
+    /// trie holding `keys`, built level by level instead of key by key
+    ///
+    /// Panics if the keys are not strictly increasing or do not fit in
+    /// `no_levels` bits.
+    pub fn new_with_sorted_keys(keys: &[Key], no_levels: usize) -> Self {
+        let mut trie = Self::new(no_levels);
+        trie.load_sorted(keys);
+        trie
+    }
+}
+
+impl<S: BuildHasher + Clone> XFastTrie<S> {
+    /// empty trie whose level tables hash prefixes with `hasher`
+    pub fn with_hasher(no_levels: usize, hasher: S) -> Self {
+        Self::with_hasher_and_level_stride(no_levels, 1, hasher)
+    }
 
+    /// Panics unless `level_stride` is in 1..=8, as queries scan up to
+    /// 2^level_stride prefixes.
+    pub fn with_hasher_and_level_stride(no_levels: usize, level_stride: usize, hasher: S) -> Self {
+        Self::with_config(no_levels, level_stride, hasher)
+    }
+}
+
+impl<S: BuildHasher + Clone, T: RepPayload> XFastTrie<S, T> {
+    /// empty trie whose representatives carry a `T`, e.g. an InfixStore in
+    /// place of the default BST group
+    ///
+    /// Panics unless `level_stride` is in 1..=8.
+    ///
+    /// ```rust
+    /// use range_filters::{InfixStore, XFastTrie};
+    /// use rustc_hash::FxBuildHasher;
+    ///
+    /// let mut trie: XFastTrie<FxBuildHasher, InfixStore> =
+    ///     XFastTrie::with_config(16, 1, FxBuildHasher);
+    /// trie.insert(42);
+    /// let rep = trie.lookup(42).unwrap();
+    /// assert!(rep.read().unwrap().payload.is_some());
+    /// ```
+    pub fn with_config(no_levels: usize, level_stride: usize, hasher: S) -> Self {
+        assert!(
+            (1..=8).contains(&level_stride),
+            "level stride {} is not in 1..=8",
+            level_stride
+        );
+        let num_levels = no_levels.div_ceil(level_stride) + 1;
+        let mut levels = Vec::with_capacity(num_levels);
+        for _ in 0..num_levels {
+            levels.push(XFastLevel {
+                table: LevelTable::with_hasher(hasher.clone()),
+            });
+        }
         // insert the root level
         // use a random key for the root level
-        root.table.insert(ROOT_KEY, XFastValue::default());
-        levels.push(root);
-        for _ in 1..=no_levels {
-            let new_level = XFastLevel::default();
-            levels.push(new_level);
-        }
+        levels[0].table.insert(ROOT_KEY, XFastValue::default());
         Self {
             levels,
-            head_rep: None,
-            tail_rep: None,
+            reps: RepList::new(),
             no_levels: no_levels,
+            level_stride,
+        }
+    }
+
+    /// fill an empty trie with strictly increasing keys
+    ///
+    /// Sorted keys sharing a prefix are adjacent, so every level is one pass
+    /// over the keys that creates each prefix entry once, with its min and max
+    /// representatives taken from the ends of its run of keys.
+    pub(crate) fn load_sorted(&mut self, keys: &[Key])
+    where
+        S: Send + Sync,
+    {
+        self.load_sorted_on_threads(keys, bulk_load_threads(keys.len()));
+    }
+
+    fn load_sorted_on_threads(&mut self, keys: &[Key], threads: usize)
+    where
+        S: Send + Sync,
+    {
+        debug_assert!(self.is_empty());
+        assert!(
+            keys.windows(2).all(|w| w[0] < w[1])
+                && keys.last().is_none_or(|&key| !self.past_universe(key)),
+            "keys must be strictly increasing and fit in {} bits",
+            self.no_levels
+        );
+        if keys.is_empty() {
+            return;
+        }
+
+        // step 1: create the representatives and link them in key order
+        let reps: Vec<SharedRep<T>> = keys.iter().map(|&key| RepNode::new(key)).collect();
+        for rep in &reps {
+            self.reps.push_back(rep);
+        }
+
+        // step 2: fill the levels, split across the threads
+        let mut tables = self.sorted_level_tables(keys, &reps, threads);
+        tables.sort_unstable_by_key(|&(level, _)| level);
+        for (level, table) in tables {
+            self.levels[level] = table;
+        }
+    }
+
+    /// the table of every level, each thread taking every `threads`-th level
+    #[cfg(feature = "std")]
+    fn sorted_level_tables(
+        &self,
+        keys: &[Key],
+        reps: &[SharedRep<T>],
+        threads: usize,
+    ) -> Vec<(usize, XFastLevel<S, T>)>
+    where
+        S: Send + Sync,
+    {
+        let num_levels = self.levels.len();
+        let threads = threads.clamp(1, num_levels);
+        if threads == 1 {
+            return (0..num_levels)
+                .map(|level| (level, self.sorted_level_table(level, keys, reps)))
+                .collect();
+        }
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|first| {
+                    scope.spawn(move || {
+                        (first..num_levels)
+                            .step_by(threads)
+                            .map(|level| (level, self.sorted_level_table(level, keys, reps)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("level construction panicked"))
+                .collect()
+        })
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn sorted_level_tables(
+        &self,
+        keys: &[Key],
+        reps: &[SharedRep<T>],
+        _threads: usize,
+    ) -> Vec<(usize, XFastLevel<S, T>)> {
+        (0..self.levels.len())
+            .map(|level| (level, self.sorted_level_table(level, keys, reps)))
+            .collect()
+    }
+
+    /// `level` over strictly increasing keys and their representatives,
+    /// one entry per run of keys sharing the level's prefix
+    fn sorted_level_table(
+        &self,
+        level: usize,
+        keys: &[Key],
+        reps: &[SharedRep<T>],
+    ) -> XFastLevel<S, T> {
+        let leaf_level = self.leaf_level();
+        let mut entries = Vec::new();
+        let mut start = 0;
+        while start < keys.len() {
+            let prefix = self.prefix(keys[start], level);
+            let end =
+                start + keys[start..].partition_point(|&key| self.prefix(key, level) == prefix);
+            let (has_left_child, has_right_child) = if self.level_stride == 1 && level < leaf_level
+            {
+                (
+                    self.prefix(keys[start], level + 1) & 1 == 0,
+                    self.prefix(keys[end - 1], level + 1) & 1 == 1,
+                )
+            } else {
+                (false, false)
+            };
+            entries.push((
+                prefix,
+                XFastValue {
+                    has_left_child,
+                    has_right_child,
+                    min_rep: Some(reps[start].clone()),
+                    max_rep: Some(reps[end - 1].clone()),
+                },
+            ));
+            start = end;
+        }
+
+        let hasher = self.levels[level].table.hasher().clone();
+        let mut table = LevelTable::with_capacity_and_hasher(entries.len(), hasher);
+        table.extend(entries);
+        XFastLevel { table }
+    }
+
+    /// index of the level holding the full keys
+    fn leaf_level(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// length of the prefixes stored at `level`
+    pub fn prefix_length(&self, level: usize) -> usize {
+        (level * self.level_stride).min(self.no_levels)
+    }
+
+    /// the prefix of `key` stored at `level`, the root key at level 0
+    fn prefix(&self, key: Key, level: usize) -> Key {
+        match level {
+            0 => ROOT_KEY,
+            _ => key >> (self.no_levels - self.prefix_length(level)),
         }
     }
 
     pub fn len(&self) -> usize {
-        let mut count = 0;
-        if let Some(head) = &self.head_rep {
-            let mut current = Some(head.clone());
-            while let Some(node) = current {
-                count += 1;
-                if let Ok(n) = node.read() {
-                    current = n.right.as_ref().and_then(|w| w.upgrade());
-                } else {
-                    break;
-                }
-            }
+        self.iter().count()
+    }
+
+    /// keys in ascending order
+    pub fn iter(&self) -> XFastIter<T> {
+        XFastIter {
+            next: self.reps.head().cloned(),
+            end: Bound::Unbounded,
         }
-        count
     }
 
-    // find length of longest prefix of key
-    fn find_longest_prefix_length(&self, key: Key) -> usize {
-        // check if tree is empty
-        if self.levels[1].table.is_empty() {
-            return 0;
+    /// keys within `range` in ascending order
+    ///
+    /// Starts at the successor of the range start and follows the representative
+    /// list until a key passes the range end.
+    pub fn range<R: RangeBounds<Key>>(&self, range: R) -> XFastIter<T> {
+        let next = match range.start_bound() {
+            Bound::Included(&start) => self.successor_rep(start),
+            Bound::Excluded(&start) => start.checked_add(1).and_then(|k| self.successor_rep(k)),
+            Bound::Unbounded => self.reps.head().cloned(),
+        };
+        XFastIter {
+            next,
+            end: range.end_bound().cloned(),
+        }
+    }
+
+    /// keys whose top `prefix_len` bits, out of `no_levels`, equal `prefix`, in
+    /// ascending order
+    ///
+    /// When `prefix_len` is a stored prefix length the matching keys are read
+    /// from the min and max representatives of that prefix's entry, otherwise
+    /// they are the range spanned by the prefix. Panics if `prefix_len` exceeds
+    /// `no_levels`.
+    pub fn keys_with_prefix(&self, prefix: Key, prefix_len: usize) -> XFastIter<T> {
+        assert!(
+            prefix_len <= self.no_levels,
+            "prefix length {} exceeds {} levels",
+            prefix_len,
+            self.no_levels
+        );
+        let empty = XFastIter {
+            next: None,
+            end: Bound::Unbounded,
+        };
+        if prefix
+            .checked_shr(prefix_len as u32)
+            .is_some_and(|high| high != 0)
+        {
+            return empty;
+        }
+        if prefix_len == 0 {
+            return self.iter();
+        }
+
+        let level = match prefix_len {
+            len if len == self.no_levels => Some(self.leaf_level()),
+            len if len % self.level_stride == 0 => Some(len / self.level_stride),
+            _ => None,
+        };
+        let Some(level) = level else {
+            let shift = self.no_levels - prefix_len;
+            let first = prefix << shift;
+            return self.range(first..=first | ((1 << shift) - 1));
+        };
+        let Some(value) = self.levels[level].table.get(&prefix) else {
+            return empty;
+        };
+        let Some(last) = value
+            .max_rep
+            .as_ref()
+            .and_then(|rep| rep.read().ok().map(|rep| rep.key))
+        else {
+            return empty;
+        };
+        XFastIter {
+            next: value.min_rep.clone(),
+            end: Bound::Included(last),
         }
+    }
 
+    /// the deepest level holding a prefix of `key`, 0 if no stored key shares
+    /// the first stored prefix
+    ///
+    /// `key` shares at least `prefix_length(level)` leading bits with some
+    /// stored key, and with a level stride of 1 that is the longest common
+    /// prefix with any stored key.
+    pub fn longest_prefix_level(&self, key: Key) -> usize {
         let mut low = 0;
-        let mut high = self.no_levels;
+        let mut high = self.leaf_level();
 
         while low < high {
             let mid = (low + high + 1) / 2;
-            let prefix = key >> (self.no_levels - mid);
-            if self.levels[mid as usize].table.contains_key(&prefix) {
-                // println!("prefix: {} found at level {}", prefix, mid);
+            let prefix = self.prefix(key, mid);
+            if self.levels[mid].table.contains_key(&prefix) {
+                trace!("prefix {} found at level {}", prefix, mid);
                 low = mid;
             } else {
                 high = mid - 1;
-                // println!("prefix: {} not found at level {}, searching in level {}", prefix, mid, high);
+                trace!(
+                    "prefix {} not found at level {}, searching up to {}",
+                    prefix, mid, high
+                );
             }
         }
 
-        low as usize
+        low
     }
 
-    pub fn predecessor(&self, key: Key) -> Option<Arc<RwLock<RepNode>>> {
-        // empty trie
-        if self.levels[1].table.is_empty() {
-            return None;
+    /// memory used by the trie, its level tables, representatives and BST groups
+    ///
+    /// Level tables are counted by their cache-padded shards plus their allocated
+    /// capacity with one control byte per bucket, which approximates the hash map
+    /// allocations.
+    pub fn size_in_bytes(&self) -> usize {
+        let mut total = core::mem::size_of::<Self>()
+            + self.levels.capacity() * core::mem::size_of::<XFastLevel<S, T>>();
+
+        let bucket_size = core::mem::size_of::<(Key, XFastValue<T>)>() + 1;
+        for level in &self.levels {
+            total += level.table.capacity() * bucket_size;
+            #[cfg(feature = "std")]
+            {
+                total += core::mem::size_of_val(level.table.shards());
+            }
         }
 
-        let longest_prefix_length = self.find_longest_prefix_length(key);
-
-        if longest_prefix_length == 0 && key >> (self.no_levels - 1) == 1 {
-            // find the max representative of the root level
-            if let Some(root_value) = self.levels[1].table.get(&0) {
-                return Some(root_value.max_rep.clone()?);
+        for node in self.reps.iter() {
+            total += arc_allocation_size::<RwLock<RepNode<T>>>();
+            let Ok(rep) = node.read() else { break };
+            if let Some(payload) = rep.payload.as_ref().and_then(|p| p.read().ok()) {
+                total += arc_allocation_size::<RwLock<T>>() - core::mem::size_of::<T>()
+                    + payload.size_in_bytes();
             }
-        } else if longest_prefix_length == 0 && key >> (self.no_levels - 1) == 0 {
-            return None;
         }
+        total
+    }
 
-        let prefix = key >> (self.no_levels - longest_prefix_length);
-
-        let x_fast_value = self.levels[longest_prefix_length as usize]
-            .table
-            .get(&prefix)?;
-
-        if let Some(representative) = &x_fast_value.max_rep {
-            if let Ok(rep) = representative.read() {
-                if rep.key <= key {
-                    return Some(representative.clone());
-                }
-            }
+    /// entries and shards of every level, representatives and estimated bytes
+    ///
+    /// Without `std` a level table is a single shard.
+    pub fn stats(&self) -> XFastStats {
+        XFastStats {
+            entries_per_level: self.levels.iter().map(|level| level.table.len()).collect(),
+            shards_per_level: self.levels.iter().map(XFastLevel::num_shards).collect(),
+            rep_count: self.len(),
+            bytes: self.size_in_bytes(),
         }
-        if let Some(representative) = &x_fast_value.min_rep {
-            if let Ok(rep) = representative.read() {
-                if rep.key <= key {
-                    return Some(representative.clone());
-                } else {
-                    // need to find predecessor by traversing left
-                    if let Some(left_weak) = &rep.left {
-                        return left_weak.upgrade();
-                    } else {
-                        return None;
-                    }
-                }
-            }
+    }
+
+    /// Release unused table capacity and shards
+    ///
+    /// Every level is left with about one shard per `ENTRIES_PER_SHARD` entries,
+    /// at least 2 (DashMap's minimum) and at most what it had, so the almost empty
+    /// levels near the root and the empty ones stop paying for a full set of
+    /// cache-padded shards.
+    pub fn shrink_to_fit(&mut self) {
+        for level in &mut self.levels {
+            #[cfg(feature = "std")]
+            level.reshard();
+            level.table.shrink_to_fit();
         }
+        self.levels.shrink_to_fit();
+    }
 
-        None
+    pub fn is_empty(&self) -> bool {
+        self.reps.is_empty()
     }
 
-    pub fn successor(&self, key: Key) -> Option<Arc<RwLock<RepNode>>> {
-        // empty trie
-        if self.levels[1].table.is_empty() {
-            return None;
-        }
+    /// whether `key` needs more than `no_levels` bits
+    fn past_universe(&self, key: Key) -> bool {
+        key.checked_shr(self.no_levels as u32)
+            .is_some_and(|high| high != 0)
+    }
 
-        let longest_prefix_length = self.find_longest_prefix_length(key);
+    /// largest key in the trie that is <= `key`
+    pub fn predecessor(&self, key: Key) -> Option<Key> {
+        let rep = self.predecessor_rep(key)?;
+        rep.read().ok().map(|rep| rep.key)
+    }
+
+    /// smallest key in the trie that is >= `key`
+    pub fn successor(&self, key: Key) -> Option<Key> {
+        let rep = self.successor_rep(key)?;
+        rep.read().ok().map(|rep| rep.key)
+    }
 
-        if longest_prefix_length == 0 && key >> (self.no_levels - 1) == 1 {
+    /// representative of the largest key in the trie that is <= `key`
+    pub fn predecessor_rep(&self, key: Key) -> Option<SharedRep<T>> {
+        if self.is_empty() {
             return None;
-        } else if longest_prefix_length == 0 && key >> (self.no_levels - 1) == 0 {
-            // find the min representative of the root level
-            if let Some(root_value) = self.levels[1].table.get(&1) {
-                return Some(root_value.min_rep.clone()?);
-            }
+        }
+        // keys past the universe follow every stored key
+        if self.past_universe(key) {
+            return self.reps.tail().cloned();
         }
 
-        let prefix = key >> (self.no_levels - longest_prefix_length);
+        let closest = self.closest_rep(key)?;
+        let rep = closest.read().ok()?;
+        if rep.key <= key {
+            drop(rep);
+            Some(closest)
+        } else {
+            rep.prev()
+        }
+    }
 
-        let x_fast_value = self.levels[longest_prefix_length as usize]
-            .table
-            .get(&prefix)?;
+    /// representative of the smallest key in the trie that is >= `key`
+    pub fn successor_rep(&self, key: Key) -> Option<SharedRep<T>> {
+        if self.is_empty() || self.past_universe(key) {
+            return None;
+        }
 
-        if let Some(representative) = &x_fast_value.min_rep {
-            if let Ok(rep) = representative.read() {
-                if rep.key >= key {
-                    return Some(representative.clone());
-                }
-            }
+        let closest = self.closest_rep(key)?;
+        let rep = closest.read().ok()?;
+        if rep.key >= key {
+            drop(rep);
+            Some(closest)
+        } else {
+            rep.next()
         }
+    }
 
-        if let Some(representative) = &x_fast_value.max_rep {
-            if let Ok(rep) = representative.read() {
-                if rep.key >= key {
-                    return Some(representative.clone());
-                } else {
-                    // need to find successor by traversing right
-                    if let Some(right_weak) = &rep.right {
-                        return right_weak.upgrade();
-                    } else {
-                        return None;
-                    }
-                }
-            }
+    /// the key itself if stored, otherwise the descendant pointer of its longest
+    /// stored prefix, which is either its predecessor or its successor
+    fn closest_rep(&self, key: Key) -> Option<SharedRep<T>> {
+        let level = self.longest_prefix_level(key);
+        let value = self.levels[level].table.get(&self.prefix(key, level))?;
+        if level == self.leaf_level() {
+            return value.min_rep.clone();
+        }
+        if self.level_stride == 1 {
+            // the child towards key is missing, so the pointer leads to the other side
+            return value.descendant().cloned();
         }
 
-        None
+        // a stored prefix can have up to 2^level_stride children and key may fall
+        // between two of them, then the closest child below key holds its predecessor
+        let (min_rep, max_rep) = (value.min_rep.clone()?, value.max_rep.clone()?);
+        drop(value);
+        if key < min_rep.read().ok()?.key {
+            return Some(min_rep);
+        }
+        if key > max_rep.read().ok()?.key {
+            return Some(max_rep);
+        }
+        let child_level = level + 1;
+        let child_prefix = self.prefix(key, child_level);
+        let shift = (self.prefix_length(child_level) - self.prefix_length(level)) as u32;
+        let first_child = child_prefix
+            .checked_shr(shift)
+            .map_or(0, |parent| parent << shift);
+        (first_child..child_prefix).rev().find_map(|sibling| {
+            self.levels[child_level]
+                .table
+                .get(&sibling)?
+                .max_rep
+                .clone()
+        })
     }
 
     //  TODO: support variable length keys
-    pub fn lookup(&self, key: Key) -> Option<Arc<RwLock<RepNode>>> {
-        let x_fast_value = self.levels[self.no_levels as usize].table.get(&key)?;
+    pub fn lookup(&self, key: Key) -> Option<SharedRep<T>> {
+        if self.past_universe(key) {
+            return None;
+        }
+        let leaf_level = self.leaf_level();
+        let x_fast_value = self.levels[leaf_level]
+            .table
+            .get(&self.prefix(key, leaf_level))?;
         if let Some(min_rep) = &x_fast_value.min_rep {
             if let Ok(min_rep_guard) = min_rep.read() {
                 assert_eq!(min_rep_guard.key, key);
@@ -211,208 +652,349 @@ impl XFastTrie {
         x_fast_value.min_rep.clone()
     }
 
-    // insert a key into the x-fast trie
+    // insert a key into the x-fast trie, a key already in it is left as is
     pub fn insert(&mut self, key: Key) {
-        // step 1: find the longest prefix length
-        let longest_prefix_length = self.find_longest_prefix_length(key);
+        if self.lookup(key).is_some() {
+            return;
+        }
 
-        // println!("longest_prefix_length: {}", longest_prefix_length);
+        // step 1: find the deepest level holding a prefix of key
+        let longest_level = self.longest_prefix_level(key);
 
-        let predecessor = self.predecessor(key);
-        let successor = self.successor(key);
+        trace!(
+            "inserting {} below a shared prefix of {} bits",
+            key,
+            self.prefix_length(longest_level)
+        );
+
+        let predecessor = self.predecessor_rep(key);
 
         // step 2: create representative
-        let representative = Arc::new(RwLock::new(RepNode {
-            key,
-            left: None,
-            right: None,
-            bst_group: None,
-        }));
-
-        // step 3: create child prefixes from longest_prefix_length+1 to no_levels
-        for prefix_length in (longest_prefix_length + 1)..=self.no_levels {
-            let prefix = key >> (self.no_levels - prefix_length);
+        let representative = RepNode::new(key);
+
+        // step 3: create child prefixes from longest_level+1 to the leaf level
+        for level in (longest_level + 1)..=self.leaf_level() {
+            let prefix = self.prefix(key, level);
             let new_x_fast_value = XFastValue {
-                left_child: None,
-                right_child: None,
+                has_left_child: false,
+                has_right_child: false,
                 min_rep: Some(representative.clone()),
                 max_rep: Some(representative.clone()),
             };
-            self.levels[prefix_length as usize]
-                .table
-                .insert(prefix, new_x_fast_value.clone());
+            self.levels[level].table.insert(prefix, new_x_fast_value);
 
             // update parent's child pointers
-            if prefix_length > 1 {
-                let parent_prefix = key >> (self.no_levels - (prefix_length - 1));
-                if let Some(mut parent_value) = self.levels[(prefix_length - 1) as usize]
-                    .table
-                    .get_mut(&parent_prefix)
-                {
-                    let bit = (key >> (self.no_levels - prefix_length)) & 1;
-                    if bit == 0 {
-                        parent_value.left_child =
-                            Some(Arc::new(RwLock::new(new_x_fast_value.clone())));
-                    } else {
-                        parent_value.right_child =
-                            Some(Arc::new(RwLock::new(new_x_fast_value.clone())));
-                    }
-                }
-            } else {
-                // update root level child pointers
-                if let Some(mut root_value) = self.levels[0].table.get_mut(&ROOT_KEY) {
-                    let bit = key >> (self.no_levels - prefix_length);
-                    if bit == 0 {
-                        root_value.left_child =
-                            Some(Arc::new(RwLock::new(new_x_fast_value.clone())));
+            if self.level_stride == 1 {
+                let parent = self.prefix(key, level - 1);
+                if let Some(mut parent) = self.levels[level - 1].table.get_mut(&parent) {
+                    if prefix & 1 == 0 {
+                        parent.has_left_child = true;
                     } else {
-                        root_value.right_child =
-                            Some(Arc::new(RwLock::new(new_x_fast_value.clone())));
+                        parent.has_right_child = true;
                     }
                 }
             }
         }
 
-        // step 4: update all prefixes' parents' min and max representatives
-        if longest_prefix_length > 0 {
-            for prefix_length in (1..=self.no_levels - 1).rev() {
-                let prefix = key >> (self.no_levels - prefix_length);
-                let mut x_fast_value = self.levels[prefix_length as usize]
-                    .table
-                    .get_mut(&prefix)
-                    .unwrap();
+        // step 4: update the min and max representatives of the existing prefixes,
+        // the root included
+        for level in (0..=longest_level).rev() {
+            let prefix = self.prefix(key, level);
+            let Some(mut x_fast_value) = self.levels[level].table.get_mut(&prefix) else {
+                continue;
+            };
 
-                let rep_key = representative.read().unwrap().key;
+            let should_update_min = x_fast_value
+                .min_rep
+                .as_ref()
+                .and_then(|m| m.read().ok())
+                .map(|m| key < m.key)
+                .unwrap_or(true);
+
+            let should_update_max = x_fast_value
+                .max_rep
+                .as_ref()
+                .and_then(|m| m.read().ok())
+                .map(|m| key > m.key)
+                .unwrap_or(true);
+
+            if should_update_min {
+                x_fast_value.min_rep = Some(representative.clone());
+            }
+            if should_update_max {
+                x_fast_value.max_rep = Some(representative.clone());
+            }
+        }
 
-                let should_update_min = x_fast_value
-                    .min_rep
-                    .as_ref()
-                    .and_then(|m| m.read().ok())
-                    .map(|m| rep_key < m.key)
-                    .unwrap_or(false);
+        // step 5: link the representative after its predecessor
+        self.reps
+            .insert_after(predecessor.as_ref(), &representative);
+    }
 
-                let should_update_max = x_fast_value
-                    .max_rep
-                    .as_ref()
-                    .and_then(|m| m.read().ok())
-                    .map(|m| rep_key > m.key)
-                    .unwrap_or(false);
+    /// delete a representative key, returns false if the key is not in the trie
+    pub fn delete(&mut self, key: Key) -> bool {
+        // step 1: find the representative and unlink it from its neighbours
+        let Some(representative) = self.lookup(key) else {
+            return false;
+        };
+        let (predecessor, successor) = self.reps.remove(&representative);
+
+        // step 2: walk the prefixes bottom-up, dropping the ones that only held this
+        // key and handing min/max representatives over to the neighbours otherwise
+        let is_rep =
+            |r: &Option<SharedRep<T>>| r.as_ref().is_some_and(|r| Arc::ptr_eq(r, &representative));
+        let hand_over = |value: &mut XFastValue<T>| {
+            if is_rep(&value.min_rep) {
+                value.min_rep = successor.clone();
+            }
+            if is_rep(&value.max_rep) {
+                value.max_rep = predecessor.clone();
+            }
+        };
+        for level in (1..=self.leaf_level()).rev() {
+            let prefix = self.prefix(key, level);
+            let only_key = match self.levels[level].table.get(&prefix) {
+                Some(value) => is_rep(&value.min_rep) && is_rep(&value.max_rep),
+                None => continue,
+            };
 
-                if should_update_min {
-                    x_fast_value.min_rep = Some(representative.clone());
-                }
-                if should_update_max {
-                    x_fast_value.max_rep = Some(representative.clone());
+            if only_key {
+                self.levels[level].table.remove(&prefix);
+
+                // clear the parent's child pointer
+                if self.level_stride == 1 {
+                    let parent = self.prefix(key, level - 1);
+                    if let Some(mut parent) = self.levels[level - 1].table.get_mut(&parent) {
+                        if prefix & 1 == 0 {
+                            parent.has_left_child = false;
+                        } else {
+                            parent.has_right_child = false;
+                        }
+                    }
                 }
+            } else if let Some(mut value) = self.levels[level].table.get_mut(&prefix) {
+                hand_over(&mut value);
             }
         }
-
-        // step 5: update linked list pointers
-        // update predecessor's right pointer
-        if let Some(pred) = &predecessor {
-            if let Ok(mut pred_guard) = pred.write() {
-                pred_guard.right = Some(Arc::downgrade(&representative));
-            }
+        // the root stays, emptied if this was the last key
+        if let Some(mut root) = self.levels[0].table.get_mut(&ROOT_KEY) {
+            hand_over(&mut root);
         }
+        true
+    }
 
-        // update successor's left pointer
-        if let Some(succ) = &successor {
-            if let Ok(mut succ_guard) = succ.write() {
-                succ_guard.left = Some(Arc::downgrade(&representative));
-            }
-        }
+    #[cfg(feature = "std")]
+    pub fn pretty_print(&self) {
+        print!("{}", self);
+    }
+}
 
-        // set representative's pointers
-        if let Ok(mut rep_guard) = representative.write() {
-            rep_guard.left = predecessor.as_ref().map(|p| Arc::downgrade(p));
-            rep_guard.right = successor.map(|s| Arc::downgrade(&s));
-            rep_guard.bst_group = Some(Arc::new(RwLock::new(BinarySearchTreeGroup::default())));
-        }
+/// Memory statistics of an XFastTrie, see `XFastTrie::stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct XFastStats {
+    /// prefix entries per level, the root level first
+    pub entries_per_level: Vec<usize>,
+    /// DashMap shards per level, one without `std`
+    pub shards_per_level: Vec<usize>,
+    /// representatives, one per key
+    pub rep_count: usize,
+    /// estimated memory, see `XFastTrie::size_in_bytes`
+    pub bytes: usize,
+}
 
-        // step 6: update head and tail representatives
-        let should_update_head = if let Some(head_rep) = &self.head_rep {
-            if let Ok(head) = head_rep.read() {
-                head.key > key
-            } else {
-                false
-            }
-        } else {
-            // first key being inserted
-            true
-        };
+impl XFastStats {
+    pub fn total_entries(&self) -> usize {
+        self.entries_per_level.iter().sum()
+    }
+}
 
-        if should_update_head {
-            self.head_rep = Some(representative.clone());
-        }
+/// Iterator over the keys of an XFastTrie, see `XFastTrie::iter` and `XFastTrie::range`
+pub struct XFastIter<T = BinarySearchTreeGroup> {
+    next: Option<SharedRep<T>>,
+    end: Bound<Key>,
+}
 
-        let should_update_tail = if let Some(tail_rep) = &self.tail_rep {
-            if let Ok(tail) = tail_rep.read() {
-                tail.key < key
-            } else {
-                false
-            }
-        } else {
-            // first key being inserted
-            true
+impl<T> Iterator for XFastIter<T> {
+    type Item = Key;
+
+    fn next(&mut self) -> Option<Key> {
+        let node = self.next.take()?;
+        let rep = node.read().ok()?;
+        let in_range = match self.end {
+            Bound::Included(end) => rep.key <= end,
+            Bound::Excluded(end) => rep.key < end,
+            Bound::Unbounded => true,
         };
+        if !in_range {
+            return None;
+        }
+        self.next = rep.next();
+        Some(rep.key)
+    }
+}
+
+impl<T> FusedIterator for XFastIter<T> {}
 
-        if should_update_tail {
-            self.tail_rep = Some(representative.clone());
+/// Serialized form of an X-Fast Trie
+///
+/// The level tables and the representative linked list are a graph of
+/// `Arc`/`Weak` pointers, so only the representatives are persisted: each is
+/// its key with the sorted keys of its BST group and their attached
+/// InfixStores. The level tables are bulk-built again from the keys on load.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_impl {
+    use super::*;
+    use crate::infix_store::InfixStore;
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// a payload shared with the live structure, serialized under its read lock
+    pub(crate) struct SharedPayload<'a, P>(pub(crate) &'a Arc<RwLock<P>>);
+
+    impl<P: Serialize> Serialize for SharedPayload<'_, P> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let payload = self
+                .0
+                .read()
+                .map_err(|_| S::Error::custom("payload lock poisoned"))?;
+            payload.serialize(serializer)
         }
     }
 
-    pub fn pretty_print(&self) {
-        print!("{}", self);
+    /// sorted keys of a BST group with their payloads, InfixStores by default
+    pub(crate) type SharedGroup<'a, P = InfixStore> = Vec<(Key, Option<SharedPayload<'a, P>>)>;
+    /// owned `SharedGroup`, as deserialized
+    pub(crate) type OwnedGroup<P = InfixStore> = Vec<(Key, Option<P>)>;
+
+    /// the entries of a group in the borrowed serialized form
+    pub(crate) fn shared_group<P>(entries: &[(Key, Option<Arc<RwLock<P>>>)]) -> SharedGroup<'_, P> {
+        entries
+            .iter()
+            .map(|(key, payload)| (*key, payload.as_ref().map(SharedPayload)))
+            .collect()
+    }
+
+    #[derive(Serialize)]
+    struct XFastTrieRef<'a> {
+        no_levels: usize,
+        level_stride: usize,
+        reps: Vec<(Key, SharedGroup<'a>)>,
     }
 
-    fn format_linked_list(start: &Arc<RwLock<RepNode>>, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Ok(node) = start.read() {
-            write!(f, "  {} ", node.key)?;
+    #[derive(Deserialize)]
+    struct XFastTrieOwned {
+        no_levels: usize,
+        level_stride: usize,
+        reps: Vec<(Key, OwnedGroup)>,
+    }
 
-            if let Some(right_weak) = &node.right {
-                if let Some(right_arc) = right_weak.upgrade() {
-                    write!(f, "→ ")?;
-                    Self::format_linked_list_helper(&right_arc, f)?;
+    impl<S, P> XFastTrie<S, BinarySearchTreeGroup<P>>
+    where
+        S: BuildHasher + Clone + Default + Send + Sync,
+        P: RepPayload,
+    {
+        /// trie over the representative keys, in increasing order, with each
+        /// representative's BST group rebuilt from its entries
+        pub(crate) fn from_groups(
+            no_levels: usize,
+            level_stride: usize,
+            reps: Vec<(Key, OwnedGroup<P>)>,
+        ) -> Result<Self, &'static str> {
+            if no_levels > crate::U64_BITS {
+                return Err("invalid number of levels");
+            }
+            if !(1..=8).contains(&level_stride) {
+                return Err("level stride is not in 1..=8");
+            }
+            let mut trie = Self::with_config(no_levels, level_stride, S::default());
+            let keys: Vec<Key> = reps.iter().map(|(key, _)| *key).collect();
+            if !keys.windows(2).all(|w| w[0] < w[1]) {
+                return Err("representative keys are not strictly increasing");
+            }
+            if keys.last().is_some_and(|&key| trie.past_universe(key)) {
+                return Err("representative key does not fit in the levels");
+            }
+            if !reps
+                .iter()
+                .all(|(_, group)| group.windows(2).all(|w| w[0].0 < w[1].0))
+            {
+                return Err("group keys are not strictly increasing");
+            }
+
+            trie.load_sorted(&keys);
+            for ((_, group), rep_node) in reps.into_iter().zip(trie.reps.iter()) {
+                let mut rep = rep_node
+                    .write()
+                    .map_err(|_| "representative lock poisoned")?;
+                let keys: Vec<Key> = group.iter().map(|(key, _)| *key).collect();
+                let mut bst = BinarySearchTreeGroup::from_keys(&keys);
+                for (key, store) in group {
+                    if let Some(store) = store {
+                        bst.set_infix_store(key, store);
+                    }
                 }
+                rep.payload = Some(Arc::new(RwLock::new(bst)));
             }
-            writeln!(f)?;
+            Ok(trie)
         }
-        Ok(())
     }
 
-    fn format_linked_list_helper(
-        node: &Arc<RwLock<RepNode>>,
-        f: &mut fmt::Formatter,
-    ) -> fmt::Result {
-        if let Ok(node_guard) = node.read() {
-            write!(f, "{} ", node_guard.key)?;
-
-            if let Some(right_weak) = &node_guard.right {
-                if let Some(right_arc) = right_weak.upgrade() {
-                    write!(f, "→ ")?;
-                    Self::format_linked_list_helper(&right_arc, f)?;
-                }
+    impl<H: BuildHasher + Clone> Serialize for XFastTrie<H> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            // collect the shared stores first, the serialized form borrows them
+            let mut reps = Vec::new();
+            for node in self.reps.iter() {
+                let rep = node
+                    .read()
+                    .map_err(|_| S::Error::custom("representative lock poisoned"))?;
+                let entries = match &rep.payload {
+                    Some(bst_group) => bst_group
+                        .read()
+                        .map_err(|_| S::Error::custom("BST group lock poisoned"))?
+                        .entries(),
+                    None => Vec::new(),
+                };
+                reps.push((rep.key, entries));
             }
+            let repr = XFastTrieRef {
+                no_levels: self.no_levels,
+                level_stride: self.level_stride,
+                reps: reps
+                    .iter()
+                    .map(|(key, entries)| (*key, shared_group(entries)))
+                    .collect(),
+            };
+            repr.serialize(serializer)
+        }
+    }
+
+    impl<'de, S: BuildHasher + Clone + Default + Send + Sync> Deserialize<'de> for XFastTrie<S> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let repr = XFastTrieOwned::deserialize(deserializer)?;
+            Self::from_groups(repr.no_levels, repr.level_stride, repr.reps)
+                .map_err(D::Error::custom)
         }
-        Ok(())
     }
 }
 
-impl fmt::Display for XFastTrie {
+impl<S: BuildHasher + Clone, T: RepPayload> fmt::Display for XFastTrie<S, T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "\n=== X-Fast Trie Structure ===")?;
 
         writeln!(f, "\nRepresentatives (Linked List):")?;
-        if let Some(head) = &self.head_rep {
-            Self::format_linked_list(head, f)?;
-        } else {
+        if self.reps.is_empty() {
             writeln!(f, "  Empty")?;
+        } else {
+            let keys: Vec<String> = self.reps.keys().map(|key| key.to_string()).collect();
+            writeln!(f, "  {} ", keys.join(" → "))?;
         }
 
         writeln!(f, "\nTrie Levels:")?;
         for (level, x_fast_level) in self.levels.iter().enumerate() {
             if !x_fast_level.table.is_empty() {
-                writeln!(f, "  Level {} (prefix length {}):", level, level)?;
+                let prefix_length = self.prefix_length(level);
+                writeln!(f, "  Level {} (prefix length {}):", level, prefix_length)?;
                 let mut entries: Vec<_> = x_fast_level.table.iter().collect();
                 entries.sort_by_key(|entry| *entry.key());
 
@@ -422,7 +1004,7 @@ impl fmt::Display for XFastTrie {
                     let prefix_str = if level == 0 {
                         "ε".to_string()
                     } else {
-                        format!("{:0width$b}", prefix, width = level)
+                        format!("{:0width$b}", prefix, width = prefix_length)
                     };
 
                     write!(f, "    {}: ", prefix_str)?;
@@ -437,10 +1019,10 @@ impl fmt::Display for XFastTrie {
                             write!(f, "max_rep→{} ", rep_guard.key)?;
                         }
                     }
-                    if value.left_child.is_some() {
+                    if value.has_left_child {
                         write!(f, "L ")?;
                     }
-                    if value.right_child.is_some() {
+                    if value.has_right_child {
                         write!(f, "R ")?;
                     }
 
@@ -464,13 +1046,13 @@ mod tests {
         trie.insert(42);
 
         // verify head and tail are set
-        assert!(trie.head_rep.is_some());
-        assert!(trie.tail_rep.is_some());
+        assert!(trie.reps.head().is_some());
+        assert!(trie.reps.tail().is_some());
 
-        if let Some(head) = &trie.head_rep {
-            if let Ok(head_guard) = head.read() {
-                assert_eq!(head_guard.key, 42);
-            }
+        if let Some(head) = trie.reps.head()
+            && let Ok(head_guard) = head.read()
+        {
+            assert_eq!(head_guard.key, 42);
         }
     }
 
@@ -484,17 +1066,29 @@ mod tests {
         }
 
         // verify head is smallest, tail is largest
-        if let Some(head) = &trie.head_rep {
-            if let Ok(head_guard) = head.read() {
-                assert_eq!(head_guard.key, 3);
-            }
+        if let Some(head) = trie.reps.head()
+            && let Ok(head_guard) = head.read()
+        {
+            assert_eq!(head_guard.key, 3);
         }
 
-        if let Some(tail) = &trie.tail_rep {
-            if let Ok(tail_guard) = tail.read() {
-                assert_eq!(tail_guard.key, 15);
-            }
+        if let Some(tail) = trie.reps.tail()
+            && let Ok(tail_guard) = tail.read()
+        {
+            assert_eq!(tail_guard.key, 15);
+        }
+    }
+
+    #[test]
+    fn test_insert_existing_key_is_noop() {
+        let mut trie = XFastTrie::new(8);
+        for key in [10, 5, 15, 10, 5, 15] {
+            trie.insert(key);
         }
+        assert_eq!(trie.reps.keys().collect::<Vec<_>>(), vec![5, 10, 15]);
+        assert_eq!(trie.stats().rep_count, 3);
+        assert_eq!(trie.predecessor(12), Some(10));
+        assert_eq!(trie.successor(11), Some(15));
     }
 
     #[test]
@@ -506,48 +1100,566 @@ mod tests {
             trie.insert(*key);
         }
 
-        // test predecessor queries
-        if let Some(pred) = trie.predecessor(25) {
-            if let Ok(pred_guard) = pred.read() {
-                assert_eq!(pred_guard.key, 20);
+        assert_eq!(trie.predecessor(25), Some(20));
+        assert_eq!(trie.predecessor(35), Some(30));
+        // exact match
+        assert_eq!(trie.predecessor(30), Some(30));
+        assert_eq!(trie.predecessor(9), None);
+        assert_eq!(trie.predecessor(255), Some(40));
+        assert_eq!(trie.predecessor_rep(25).unwrap().read().unwrap().key, 20);
+    }
+
+    #[test]
+    fn test_successor() {
+        let mut trie = XFastTrie::new(8);
+        let keys = vec![10, 20, 30, 40];
+
+        for key in &keys {
+            trie.insert(*key);
+        }
+
+        assert_eq!(trie.successor(25), Some(30));
+        assert_eq!(trie.successor(15), Some(20));
+        // exact match
+        assert_eq!(trie.successor(20), Some(20));
+        assert_eq!(trie.successor(0), Some(10));
+        assert_eq!(trie.successor(41), None);
+        assert_eq!(trie.successor_rep(15).unwrap().read().unwrap().key, 20);
+    }
+
+    #[test]
+    fn test_predecessor_successor_match_oracle() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for (no_levels, with_extremes) in [1usize, 2, 5, 8, 12, 64]
+            .into_iter()
+            .flat_map(|no_levels| [(no_levels, false), (no_levels, true)])
+        {
+            let max_key = Key::MAX >> (64 - no_levels);
+            let mut trie = XFastTrie::new(no_levels);
+            let mut oracle = std::collections::BTreeSet::new();
+            let extremes = if with_extremes {
+                vec![0, max_key]
+            } else {
+                vec![]
+            };
+            for key in extremes.into_iter().chain((0..40).map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state & max_key
+            })) {
+                if oracle.insert(key) {
+                    trie.insert(key);
+                }
+            }
+
+            let mut probes: Vec<Key> = oracle
+                .iter()
+                .flat_map(|&k| [k.wrapping_sub(1), k, k.wrapping_add(1)])
+                .collect();
+            probes.extend([0, 1, max_key / 2, max_key, Key::MAX]);
+            probes.extend(max_key.checked_add(1));
+            for probe in probes {
+                assert_eq!(
+                    trie.predecessor(probe),
+                    oracle.range(..=probe).next_back().copied(),
+                    "no_levels {} predecessor({})",
+                    no_levels,
+                    probe
+                );
+                assert_eq!(
+                    trie.successor(probe),
+                    oracle.range(probe..).next().copied(),
+                    "no_levels {} successor({})",
+                    no_levels,
+                    probe
+                );
             }
         }
+    }
+
+    #[test]
+    fn test_descendant_pointers() {
+        let mut trie = XFastTrie::new(8);
+        for key in [0b0010_0000, 0b0011_0000, 0b1100_0000] {
+            trie.insert(key);
+        }
+        let descendant = |trie: &XFastTrie, level: usize, prefix: Key| {
+            let value = trie.levels[level].table.get(&prefix).unwrap();
+            value.descendant().map(|rep| rep.read().unwrap().key)
+        };
+        // prefixes with both children have no descendant pointer
+        assert_eq!(descendant(&trie, 0, ROOT_KEY), None);
+        assert_eq!(descendant(&trie, 3, 0b001), None);
+        // missing right child: largest key on the left
+        assert_eq!(descendant(&trie, 1, 0b0), Some(0b0011_0000));
+        // missing left child: smallest key on the right
+        assert_eq!(descendant(&trie, 2, 0b00), Some(0b0010_0000));
+        assert_eq!(descendant(&trie, 1, 0b1), Some(0b1100_0000));
+
+        // queries ending at internal prefixes resolve through the pointers
+        assert_eq!(trie.predecessor(0b0001_0000), None);
+        assert_eq!(trie.successor(0b0001_0000), Some(0b0010_0000));
+        assert_eq!(trie.predecessor(0b0111_1111), Some(0b0011_0000));
+        assert_eq!(trie.successor(0b0111_1111), Some(0b1100_0000));
+        assert_eq!(trie.predecessor(0b1000_0000), Some(0b0011_0000));
+
+        assert!(trie.delete(0b1100_0000));
+        verify_min_max(&trie, 0, ROOT_KEY, 0b0010_0000, 0b0011_0000);
+        assert_eq!(descendant(&trie, 0, ROOT_KEY), Some(0b0011_0000));
+        assert_eq!(trie.successor(0b0111_1111), None);
+    }
+
+    #[test]
+    fn test_iter_and_range() {
+        let mut trie = XFastTrie::new(16);
+        assert_eq!(trie.iter().next(), None);
+        assert_eq!(trie.range(..).next(), None);
 
-        if let Some(pred) = trie.predecessor(35) {
-            if let Ok(pred_guard) = pred.read() {
-                assert_eq!(pred_guard.key, 30);
+        let mut oracle = std::collections::BTreeSet::new();
+        for key in [900, 7, 65535, 0, 300, 301, 4096, 12] {
+            trie.insert(key);
+            oracle.insert(key);
+        }
+        assert!(trie.iter().eq(oracle.iter().copied()));
+        assert_eq!(trie.len(), oracle.len());
+
+        let bounds = [0, 1, 7, 12, 13, 300, 301, 302, 4096, 65534, 65535];
+        for &lo in &bounds {
+            for &hi in &bounds {
+                if lo > hi {
+                    continue;
+                }
+                assert!(
+                    trie.range(lo..=hi).eq(oracle.range(lo..=hi).copied()),
+                    "{}..={}",
+                    lo,
+                    hi
+                );
+                assert!(
+                    trie.range(lo..hi).eq(oracle.range(lo..hi).copied()),
+                    "{}..{}",
+                    lo,
+                    hi
+                );
             }
+            assert!(trie.range(lo..).eq(oracle.range(lo..).copied()));
+            assert!(trie.range(..=lo).eq(oracle.range(..=lo).copied()));
+            let after: Vec<Key> = oracle.range(lo + 1..).copied().collect();
+            let excluded = (Bound::Excluded(lo), Bound::Unbounded);
+            assert_eq!(trie.range(excluded).collect::<Vec<_>>(), after);
         }
+        // past the universe
+        assert_eq!(trie.range(70_000..).next(), None);
+        assert_eq!(
+            trie.range((Bound::Excluded(Key::MAX), Bound::Unbounded))
+                .next(),
+            None
+        );
+
+        trie.delete(300);
+        assert_eq!(
+            trie.range(13..=4096).collect::<Vec<_>>(),
+            vec![301, 900, 4096]
+        );
+    }
 
-        // test exact match
-        if let Some(pred) = trie.predecessor(30) {
-            if let Ok(pred_guard) = pred.read() {
-                assert_eq!(pred_guard.key, 30);
+    #[test]
+    fn test_keys_with_prefix_and_longest_prefix_level() {
+        let keys = [0u64, 7, 12, 300, 301, 900, 4096, 4097, 40_000, 65535];
+        let oracle: std::collections::BTreeSet<Key> = keys.iter().copied().collect();
+        // 16 bits of common prefix with the closest stored key
+        let common_prefix = |key: Key| {
+            keys.iter()
+                .map(|&stored| ((stored ^ key).leading_zeros() as usize).saturating_sub(48))
+                .max()
+                .unwrap()
+        };
+        for level_stride in [1, 2, 3] {
+            let mut trie = XFastTrie::with_level_stride(16, level_stride);
+            trie.load_sorted(&keys);
+            for prefix_len in 0..=16 {
+                let shift = 16 - prefix_len;
+                for prefix in [0u64, 1, 2, 3, 9, 18, 35, 140, 4096 >> shift, 65535 >> shift] {
+                    let expected: Vec<Key> = oracle
+                        .iter()
+                        .copied()
+                        .filter(|&key| key >> shift == prefix)
+                        .collect();
+                    let found: Vec<Key> = trie.keys_with_prefix(prefix, prefix_len).collect();
+                    let case = format!("stride {} prefix {}/{}", level_stride, prefix, prefix_len);
+                    assert_eq!(found, expected, "{}", case);
+                }
+            }
+            for key in (0..=65535).step_by(61).chain(keys) {
+                let level = trie.longest_prefix_level(key);
+                let length = trie.prefix_length(level);
+                assert!(
+                    length <= common_prefix(key),
+                    "stride {} key {}",
+                    level_stride,
+                    key
+                );
+                if level < trie.leaf_level() {
+                    assert!(common_prefix(key) < trie.prefix_length(level + 1));
+                }
+                if level_stride == 1 {
+                    assert_eq!(length, common_prefix(key));
+                }
             }
         }
+        let trie = XFastTrie::new(16);
+        assert_eq!(trie.longest_prefix_level(5), 0);
+        assert_eq!(trie.keys_with_prefix(0, 4).next(), None);
+        assert_eq!(trie.keys_with_prefix(16, 4).next(), None);
+        assert_eq!(trie.keys_with_prefix(1, 0).next(), None);
     }
 
     #[test]
-    fn test_successor() {
-        let mut trie = XFastTrie::new(8);
-        let keys = vec![10, 20, 30, 40];
+    fn test_with_hasher() {
+        let mut trie = XFastTrie::with_hasher(16, std::collections::hash_map::RandomState::new());
+        for key in [5, 900, 65535] {
+            trie.insert(key);
+        }
+        assert_eq!(trie.predecessor(899), Some(5));
+        assert_eq!(trie.successor(901), Some(65535));
+        assert!(trie.delete(900));
+        assert_eq!(trie.iter().collect::<Vec<_>>(), vec![5, 65535]);
+    }
 
-        for key in &keys {
-            trie.insert(*key);
+    #[test]
+    fn test_infix_store_payload() {
+        use crate::infix_store::InfixStore;
+
+        let mut trie: XFastTrie<FxBuildHasher, InfixStore> =
+            XFastTrie::with_config(16, 2, FxBuildHasher);
+        for key in [900, 5, 65535] {
+            trie.insert(key);
+        }
+        let empty_size = trie.size_in_bytes();
+        let rep = trie.predecessor_rep(1000).unwrap();
+        let store = rep.read().unwrap().payload.clone().unwrap();
+        *store.write().unwrap() = InfixStore::new_with_infixes(&[1, 2, 3], 8);
+
+        assert!(trie.size_in_bytes() > empty_size);
+        assert_eq!(trie.iter().collect::<Vec<_>>(), vec![5, 900, 65535]);
+        let store = trie
+            .lookup(900)
+            .unwrap()
+            .read()
+            .unwrap()
+            .payload
+            .clone()
+            .unwrap();
+        assert_eq!(store.read().unwrap().elem_count(), 3);
+        assert!(trie.delete(900));
+        assert_eq!(trie.predecessor(1000), Some(5));
+    }
+
+    #[test]
+    fn test_level_stride_matches_oracle() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for no_levels in [5usize, 12, 64] {
+            for level_stride in [2usize, 3, 4, 8] {
+                let max_key = Key::MAX >> (64 - no_levels);
+                let mut trie = XFastTrie::with_level_stride(no_levels, level_stride);
+                assert_eq!(trie.levels.len(), no_levels.div_ceil(level_stride) + 1);
+                let mut oracle = std::collections::BTreeSet::new();
+                for step in 0..300 {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    // four clusters, with the low bits of each cluster random
+                    let key = ((state % 4) << (no_levels - 2)) | ((state >> 8) & (max_key >> 4));
+                    if step % 3 == 2 {
+                        assert_eq!(trie.delete(key), oracle.remove(&key));
+                    } else if oracle.insert(key) {
+                        trie.insert(key);
+                    }
+                }
+                assert!(trie.iter().eq(oracle.iter().copied()));
+
+                let probes = oracle.iter().flat_map(|&k| [k.wrapping_sub(1), k, k + 1]);
+                for probe in probes.chain([0, max_key / 3, max_key, Key::MAX]) {
+                    assert_eq!(
+                        trie.predecessor(probe),
+                        oracle.range(..=probe).next_back().copied(),
+                        "{} levels, stride {}: predecessor({})",
+                        no_levels,
+                        level_stride,
+                        probe
+                    );
+                    assert_eq!(
+                        trie.successor(probe),
+                        oracle.range(probe..).next().copied(),
+                        "{} levels, stride {}: successor({})",
+                        no_levels,
+                        level_stride,
+                        probe
+                    );
+                    assert_eq!(trie.lookup(probe).is_some(), oracle.contains(&probe));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_level_stride_saves_memory() {
+        // 2000 keys in a handful of clusters, as sample keys of clustered data are
+        let keys: Vec<Key> = (0..2000u64)
+            .map(|i| ((i % 5) << 56) | ((i * 0x9E37_79B9) & 0xFFFF_FFFF))
+            .collect();
+        let mut full = XFastTrie::new(64);
+        let mut strided = XFastTrie::with_level_stride(64, 4);
+        for &key in &keys {
+            full.insert(key);
+            strided.insert(key);
+        }
+        assert!(full.iter().eq(strided.iter()));
+        let entries = |trie: &XFastTrie| trie.levels.iter().map(|l| l.table.len()).sum::<usize>();
+        assert!(entries(&full) > 3 * entries(&strided));
+        assert!(
+            full.size_in_bytes() > 2 * strided.size_in_bytes(),
+            "{} vs {} bytes",
+            full.size_in_bytes(),
+            strided.size_in_bytes()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "level stride")]
+    fn test_level_stride_out_of_range() {
+        XFastTrie::with_level_stride(64, 9);
+    }
+
+    #[test]
+    fn test_new_with_sorted_keys_matches_inserts() {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for no_levels in [1usize, 8, 64] {
+            let max_key = Key::MAX >> (64 - no_levels);
+            let mut keys: Vec<Key> = (0..500)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state & max_key
+                })
+                .collect();
+            keys.sort_unstable();
+            keys.dedup();
+
+            let bulk = XFastTrie::new_with_sorted_keys(&keys, no_levels);
+            let mut inserted = XFastTrie::new(no_levels);
+            for &key in &keys {
+                inserted.insert(key);
+            }
+            assert!(bulk.iter().eq(keys.iter().copied()));
+
+            // same prefixes, child flags and min/max representatives on every level
+            let rep_key =
+                |rep: &Option<Arc<RwLock<RepNode>>>| rep.as_ref().map(|r| r.read().unwrap().key);
+            for (bulk_level, level) in bulk.levels.iter().zip(&inserted.levels) {
+                assert_eq!(bulk_level.table.len(), level.table.len());
+                for entry in level.table.iter() {
+                    let bulk_value = bulk_level.table.get(entry.key()).unwrap();
+                    assert_eq!(bulk_value.has_left_child, entry.has_left_child);
+                    assert_eq!(bulk_value.has_right_child, entry.has_right_child);
+                    assert_eq!(rep_key(&bulk_value.min_rep), rep_key(&entry.min_rep));
+                    assert_eq!(rep_key(&bulk_value.max_rep), rep_key(&entry.max_rep));
+                }
+            }
+        }
+
+        let mut trie = XFastTrie::new_with_sorted_keys(&[3, 9, 200], 8);
+        assert_eq!(trie.predecessor(100), Some(9));
+        trie.insert(100);
+        assert!(trie.delete(9));
+        assert_eq!(trie.iter().collect::<Vec<_>>(), vec![3, 100, 200]);
+        assert!(XFastTrie::new_with_sorted_keys(&[], 8).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "strictly increasing")]
+    fn test_new_with_sorted_keys_rejects_unsorted() {
+        XFastTrie::new_with_sorted_keys(&[5, 5], 8);
+    }
+
+    #[test]
+    fn test_stats_and_shrink_to_fit() {
+        let mut trie = XFastTrie::with_level_stride(64, 4);
+        let empty = trie.stats();
+        assert_eq!(empty.entries_per_level.len(), 17);
+        assert_eq!(empty.total_entries(), 1);
+        assert_eq!(empty.rep_count, 0);
+
+        let keys: Vec<Key> = (0..1000u64)
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            .collect();
+        for &key in &keys {
+            trie.insert(key);
+        }
+        for &key in &keys[..500] {
+            trie.delete(key);
+        }
+        let before = trie.stats();
+        assert_eq!(before.rep_count, 500);
+        assert_eq!(before.entries_per_level[0], 1);
+        assert_eq!(before.entries_per_level[16], 500);
+        assert!(before.entries_per_level.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(before.bytes, trie.size_in_bytes());
+
+        // force a few shards per level, so there is something to compact even on one CPU
+        for level in &mut trie.levels {
+            let table = dashmap::DashMap::with_hasher_and_shard_amount(FxBuildHasher, 64);
+            for (prefix, value) in std::mem::replace(&mut level.table, table) {
+                level.table.insert(prefix, value);
+            }
+        }
+        let padded = trie.stats();
+        trie.shrink_to_fit();
+        let after = trie.stats();
+        assert_eq!(after.entries_per_level, before.entries_per_level);
+        assert_eq!(after.shards_per_level[0], 2);
+        assert_eq!(after.shards_per_level[16], 32);
+        assert!(after.bytes < padded.bytes && after.bytes <= before.bytes);
+
+        let remaining: std::collections::BTreeSet<Key> = keys[500..].iter().copied().collect();
+        assert!(trie.iter().eq(remaining));
+        trie.insert(keys[0]);
+        assert_eq!(trie.successor(keys[0]), Some(keys[0]));
+        assert_eq!(trie.stats().rep_count, 501);
+        assert!(trie.stats().total_entries() > after.total_entries());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        use crate::infix_store::InfixStore;
+
+        let mut trie = XFastTrie::with_level_stride(16, 2);
+        for key in [5, 900, 901, 4096, 65535] {
+            trie.insert(key);
+        }
+        if let Some(rep_node) = trie.lookup(900) {
+            let rep = rep_node.read().unwrap();
+            let mut bst = rep.payload.as_ref().unwrap().write().unwrap();
+            *bst = BinarySearchTreeGroup::new_with_keys(&[900, 903, 950]);
+            bst.set_infix_store(903, InfixStore::new_with_infixes(&[3, 70, 71], 6));
+        }
+
+        let json = serde_json::to_string(&trie).unwrap();
+        let restored: XFastTrie = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.level_stride, 2);
+        assert!(restored.iter().eq(trie.iter()));
+        assert_eq!(
+            restored.stats().entries_per_level,
+            trie.stats().entries_per_level
+        );
+        for key in (0..=65535).step_by(97) {
+            assert_eq!(restored.predecessor(key), trie.predecessor(key));
+            assert_eq!(restored.successor(key), trie.successor(key));
         }
 
-        // test successor queries
-        if let Some(succ) = trie.successor(25) {
-            if let Ok(succ_guard) = succ.read() {
-                assert_eq!(succ_guard.key, 30);
+        let rep_node = restored.lookup(900).unwrap();
+        let bst_group = rep_node.read().unwrap().payload.clone().unwrap();
+        let bst = bst_group.read().unwrap();
+        let keys: Vec<Key> = bst.entries().iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, vec![900, 903, 950]);
+        let store = bst.get_infix_store(903).unwrap();
+        assert_eq!(store.read().unwrap().elem_count(), 3);
+        assert!(bst.get_infix_store(900).is_none());
+
+        let json = serde_json::to_string(&XFastTrie::new(8)).unwrap();
+        let empty: XFastTrie = serde_json::from_str(&json).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rejects_invalid() {
+        let parse = |json: &str| serde_json::from_str::<XFastTrie>(json).map(|_| ());
+        assert!(parse(r#"{"no_levels":8,"level_stride":1,"reps":[[7,[]],[3,[]]]}"#).is_err());
+        assert!(parse(r#"{"no_levels":8,"level_stride":1,"reps":[[256,[]]]}"#).is_err());
+        assert!(parse(r#"{"no_levels":8,"level_stride":9,"reps":[]}"#).is_err());
+        assert!(parse(r#"{"no_levels":65,"level_stride":1,"reps":[]}"#).is_err());
+        let duplicate_keys = r#"{"no_levels":8,"level_stride":1,"reps":[[3,[[4,null],[4,null]]]]}"#;
+        assert!(parse(duplicate_keys).is_err());
+        assert!(parse(r#"{"no_levels":8,"level_stride":1,"reps":[[3,[[3,null]]]]}"#).is_ok());
+    }
+
+    #[test]
+    fn test_parallel_load_matches_inserts() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<XFastTrie>();
+
+        let keys: Vec<Key> = (0..5000u64).map(|i| i * 977).collect();
+        let mut inserted = XFastTrie::new(32);
+        for &key in &keys {
+            inserted.insert(key);
+        }
+        for threads in [3, 8, 64] {
+            let mut bulk = XFastTrie::new(32);
+            bulk.load_sorted_on_threads(&keys, threads);
+            assert_eq!(
+                bulk.stats().entries_per_level,
+                inserted.stats().entries_per_level
+            );
+            assert!(bulk.iter().eq(keys.iter().copied()));
+            for probe in (0..keys.len() as u64 * 977).step_by(4099) {
+                assert_eq!(bulk.predecessor(probe), inserted.predecessor(probe));
+                assert_eq!(bulk.successor(probe), inserted.successor(probe));
             }
         }
+    }
 
-        if let Some(succ) = trie.successor(15) {
-            if let Ok(succ_guard) = succ.read() {
-                assert_eq!(succ_guard.key, 20);
+    #[test]
+    fn test_concurrent_readers_with_one_writer() {
+        let trie = Arc::new(RwLock::new(XFastTrie::new(16)));
+        let readers: Vec<_> = (0..4u64)
+            .map(|seed| {
+                let trie = trie.clone();
+                std::thread::spawn(move || {
+                    let mut probe = seed;
+                    for _ in 0..2000 {
+                        probe = (probe * 31 + 7) % 65536;
+                        let trie = trie.read().unwrap();
+                        // every read lock sees a consistent trie
+                        let keys: Vec<Key> = trie.iter().collect();
+                        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                        let position = keys.partition_point(|&key| key <= probe);
+                        let predecessor = position.checked_sub(1).map(|i| keys[i]);
+                        assert_eq!(trie.predecessor(probe), predecessor);
+                        let successor = keys.get(keys.partition_point(|&key| key < probe));
+                        assert_eq!(trie.successor(probe), successor.copied());
+                    }
+                })
+            })
+            .collect();
+
+        for round in 0..2000u64 {
+            let key = (round * 7919) % 65536;
+            let mut trie = trie.write().unwrap();
+            if round % 3 == 2 {
+                trie.delete((key + 13) % 65536);
+            } else {
+                trie.insert(key);
             }
         }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_zero_level_trie() {
+        let mut trie = XFastTrie::new(0);
+        assert_eq!(trie.predecessor(0), None);
+        assert_eq!(trie.successor(0), None);
+        trie.insert(0);
+        assert_eq!(trie.predecessor(0), Some(0));
+        assert_eq!(trie.predecessor(Key::MAX), Some(0));
+        assert_eq!(trie.successor(0), Some(0));
+        assert_eq!(trie.successor(1), None);
     }
 
     #[test]
@@ -782,4 +1894,76 @@ mod tests {
         verify_min_max(&trie, 16, 0b0000000010000000, 128, 128);
         verify_min_max(&trie, 16, 0b0000000011111111, 255, 255);
     }
+
+    #[test]
+    fn test_delete() {
+        let mut trie = XFastTrie::new(16);
+        let mut oracle = std::collections::BTreeSet::new();
+        for key in [1, 64, 128, 129, 255, 4000, 65535] {
+            trie.insert(key);
+            oracle.insert(key);
+        }
+
+        let rep_key = |rep: Option<Arc<RwLock<RepNode>>>| rep.map(|r| r.read().unwrap().key);
+        for key in [128, 1, 65535, 4000] {
+            assert!(trie.delete(key));
+            assert!(!trie.delete(key));
+            oracle.remove(&key);
+
+            assert_eq!(trie.len(), oracle.len());
+            assert_eq!(rep_key(trie.reps.head().cloned()), oracle.first().copied());
+            assert_eq!(rep_key(trie.reps.tail().cloned()), oracle.last().copied());
+            for probe in (0..=65535).step_by(7) {
+                assert_eq!(
+                    trie.predecessor(probe),
+                    oracle.range(..=probe).next_back().copied()
+                );
+                assert_eq!(trie.successor(probe), oracle.range(probe..).next().copied());
+            }
+        }
+
+        // min/max reps handed over to the remaining keys
+        verify_min_max(&trie, 9, 0b000000001, 129, 255);
+        assert!(trie.lookup(128).is_none());
+        assert!(trie.levels[16].table.get(&128).is_none());
+    }
+
+    #[test]
+    fn test_delete_releases_representative() {
+        let mut trie = XFastTrie::new(8);
+        for key in [0b0100_0000, 0b0100_0001, 0b0100_0011, 0b1000_0000] {
+            trie.insert(key);
+        }
+        // 0b0100_0000 created the prefixes it shares with the keys inserted after it
+        for key in [0b0100_0000, 0b0100_0011] {
+            let representative = trie.lookup(key).unwrap();
+            assert!(trie.delete(key));
+            assert_eq!(
+                Arc::strong_count(&representative),
+                1,
+                "key {} still referenced",
+                key
+            );
+        }
+        verify_min_max(&trie, 2, 0b01, 0b0100_0001, 0b0100_0001);
+        assert_eq!(trie.len(), 2);
+    }
+
+    #[test]
+    fn test_delete_all_then_reinsert() {
+        let mut trie = XFastTrie::new(8);
+        for key in [3, 7, 200] {
+            trie.insert(key);
+        }
+        for key in [7, 3, 200] {
+            assert!(trie.delete(key));
+        }
+        assert!(trie.reps.head().is_none());
+        assert!(trie.reps.tail().is_none());
+        assert_eq!(trie.predecessor(255), None);
+
+        trie.insert(42);
+        verify_min_max(&trie, 1, 0b0, 42, 42);
+        assert_eq!(trie.successor(0), Some(42));
+    }
 }
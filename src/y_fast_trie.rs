@@ -1,76 +1,244 @@
 use crate::Key;
 use crate::binary_search_tree::BinarySearchTreeGroup;
+use crate::error::RangeFilterError;
 use crate::infix_store::InfixStore;
-use crate::x_fast_trie::XFastTrie;
-use std::fmt;
-use std::sync::{Arc, RwLock};
+use crate::rep_list::{RepPayload, SharedRep};
+use crate::sync::{Arc, RwLock};
+use crate::x_fast_trie::{XFastStats, XFastTrie, bulk_load_threads};
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::FusedIterator;
+use core::ops::{Bound, RangeBounds};
+use rustc_hash::FxBuildHasher;
+
+pub(crate) type GroupEntry<P = InfixStore> = (Key, Option<Arc<RwLock<P>>>);
+pub(crate) type GroupEntries<P = InfixStore> = Vec<GroupEntry<P>>;
+type SharedBstGroup<P> = Arc<RwLock<BinarySearchTreeGroup<P>>>;
+
+/// x-fast trie over the group boundaries, each representative holding its group
+pub type GroupTrie<P = InfixStore> = XFastTrie<FxBuildHasher, BinarySearchTreeGroup<P>>;
+
+/// How many keys the BST groups of a YFastTrie hold
+///
+/// A policy fixes a target group length, which bulk-built groups are filled to.
+/// Inserts split a group that grows past the maximum length in half, and
+/// deletes merge a group that shrinks below the minimum into a neighbour.
+/// Larger groups mean fewer boundaries, so a smaller x-fast trie, at the cost
+/// of deeper BST walks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GroupSizePolicy {
+    /// groups of `len` keys, kept between `len / 2` and `2 * len`
+    Fixed(usize),
+    /// groups of `factor` times the key length in bits, kept between half and
+    /// twice that; a factor of 1 is the classic y-fast trie
+    LogMultiple(usize),
+    /// groups of at most `capacity` keys, bulk-built to `load_factor` of it and
+    /// merged below half of that
+    LoadFactor { capacity: usize, load_factor: f64 },
+}
+
+impl Default for GroupSizePolicy {
+    fn default() -> Self {
+        GroupSizePolicy::LogMultiple(1)
+    }
+}
+
+impl GroupSizePolicy {
+    /// check that the policy allows groups of at least one key
+    pub fn validate(&self) -> Result<(), RangeFilterError> {
+        match *self {
+            GroupSizePolicy::Fixed(0) => Err(RangeFilterError::InvalidConfig(
+                "fixed group size must be at least 1".to_string(),
+            )),
+            GroupSizePolicy::LogMultiple(0) => Err(RangeFilterError::InvalidConfig(
+                "group size factor must be at least 1".to_string(),
+            )),
+            GroupSizePolicy::LoadFactor {
+                capacity,
+                load_factor,
+            } if capacity == 0 || !(load_factor > 0.0 && load_factor <= 1.0) => {
+                Err(RangeFilterError::InvalidConfig(format!(
+                    "group capacity must be at least 1 and load factor in (0, 1], got {} and {}",
+                    capacity, load_factor
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// length of bulk-built groups for `no_levels`-bit keys
+    pub fn target_len(&self, no_levels: usize) -> usize {
+        let len = match *self {
+            GroupSizePolicy::Fixed(len) => len,
+            GroupSizePolicy::LogMultiple(factor) => factor * no_levels,
+            GroupSizePolicy::LoadFactor {
+                capacity,
+                load_factor,
+            } => (capacity as f64 * load_factor) as usize,
+        };
+        len.max(1)
+    }
+
+    /// longest group before an insert splits it
+    pub fn max_len(&self, no_levels: usize) -> usize {
+        match *self {
+            GroupSizePolicy::LoadFactor { capacity, .. } => capacity.max(1),
+            _ => 2 * self.target_len(no_levels),
+        }
+    }
 
-pub struct YFastTrie {
-    pub x_fast_trie: XFastTrie,
+    /// shortest group before a delete merges it into a neighbour
+    pub fn min_len(&self, no_levels: usize) -> usize {
+        self.target_len(no_levels) / 2
+    }
+}
+
+/// Y-fast trie whose keys each carry an optional payload of type `P`
+///
+/// Diva attaches an InfixStore to every sample key, the default. Any other
+/// `RepPayload` works the same way, e.g. `()` to only order keys.
+///
+/// With the `serde` feature enabled, a trie whose payload is serializable is
+/// saved with its groups and payloads and rebuilt on load.
+pub struct YFastTrie<P = InfixStore> {
+    pub x_fast_trie: GroupTrie<P>,
+    group_size: GroupSizePolicy,
 }
 
 impl YFastTrie {
     pub fn new(no_levels: usize) -> Self {
+        Self::empty(no_levels)
+    }
+
+    pub fn new_with_keys(keys: &[Key], no_levels: usize) -> Self {
+        Self::from_keys(keys, no_levels)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn pretty_print(&self) {
+        print!("{}", self);
+    }
+}
+
+impl<P: RepPayload> YFastTrie<P> {
+    /// empty trie over `no_levels`-bit keys
+    pub fn empty(no_levels: usize) -> Self {
+        Self::with_group_size(no_levels, GroupSizePolicy::default())
+    }
+
+    /// empty trie over `no_levels`-bit keys whose groups follow `group_size`
+    ///
+    /// Panics if the policy is invalid, see `GroupSizePolicy::validate`.
+    pub fn with_group_size(no_levels: usize, group_size: GroupSizePolicy) -> Self {
+        if let Err(error) = group_size.validate() {
+            panic!("{}", error);
+        }
         Self {
-            x_fast_trie: XFastTrie::new(no_levels),
+            x_fast_trie: XFastTrie::with_config(no_levels, 1, FxBuildHasher),
+            group_size,
         }
     }
 
-    pub fn new_with_keys(keys: &[Key], no_levels: usize) -> Self {
+    /// trie holding `keys`, in any order, without payloads
+    pub fn from_keys(keys: &[Key], no_levels: usize) -> Self {
+        Self::from_keys_with_group_size(keys, no_levels, GroupSizePolicy::default())
+    }
+
+    /// trie holding `keys`, in any order, in groups of the policy's target length
+    ///
+    /// Panics if the policy is invalid, see `GroupSizePolicy::validate`.
+    pub fn from_keys_with_group_size(
+        keys: &[Key],
+        no_levels: usize,
+        group_size: GroupSizePolicy,
+    ) -> Self {
+        let mut trie = Self::with_group_size(no_levels, group_size);
         if keys.is_empty() {
-            return Self::new(no_levels);
+            return trie;
         }
 
         // step 1: sort and dedup keys
         let mut sorted_keys = keys.to_vec();
-        sorted_keys.sort();
+        sorted_keys.sort_unstable();
         sorted_keys.dedup();
 
-        let bst_group_size = no_levels;
+        let bst_group_size = group_size.target_len(no_levels);
 
-        let mut x_fast_trie = XFastTrie::new(no_levels);
+        // step 2: partition all keys into BST group chunks of the target length
+        // (by default log U, e.g. 64 keys per group for 64 bit keys)
+        let chunks: Vec<&[Key]> = sorted_keys.chunks(bst_group_size).collect();
 
-        // step 2: partition all keys into BST group chunks of size ~log U (e.g. 64 keys per group for 64 bit keys)
-        for chunk_start in (0..sorted_keys.len()).step_by(bst_group_size) {
-            let chunk_end = (chunk_start + bst_group_size).min(sorted_keys.len());
-            let chunk = &sorted_keys[chunk_start..chunk_end];
+        // step 3: build a balanced BST group over every chunk, on several
+        // threads for large inputs
+        let groups = Self::build_groups(&chunks, bulk_load_threads(sorted_keys.len()));
 
-            // boundary key is the first key of this chunk
-            let boundary_key = chunk[0];
-
-            // step 3: insert boundary key into x-fast trie
-            x_fast_trie.insert(boundary_key);
+        // step 4: bulk build the x-fast trie over the first key of every chunk
+        // and attach each group to its boundary
+        let boundaries: Vec<Key> = chunks.iter().map(|chunk| chunk[0]).collect();
+        trie.x_fast_trie.load_sorted(&boundaries);
+        for (bst_group, rep_node) in groups.into_iter().zip(trie.x_fast_trie.reps.iter()) {
+            let Ok(mut rep) = rep_node.write() else { break };
+            rep.payload = Some(Arc::new(RwLock::new(bst_group)));
+        }
 
-            // step 4: create a balanced BST group with all keys in this chunk
-            let bst_group = BinarySearchTreeGroup::new_with_keys(chunk);
-            let bst_group_arc = Arc::new(RwLock::new(bst_group));
+        trie
+    }
 
-            // step 5: attach the BST group to the boundary representative
-            if let Some(rep_node) = x_fast_trie.lookup(boundary_key) {
-                if let Ok(mut rep) = rep_node.write() {
-                    rep.bst_group = Some(bst_group_arc);
-                }
-            }
+    /// balanced BST groups over sorted chunks of keys, in chunk order
+    ///
+    /// The chunks are split into one contiguous run per thread.
+    #[cfg(feature = "std")]
+    fn build_groups(chunks: &[&[Key]], threads: usize) -> Vec<BinarySearchTreeGroup<P>> {
+        let threads = threads.clamp(1, chunks.len().max(1));
+        if threads == 1 {
+            return chunks
+                .iter()
+                .map(|chunk| BinarySearchTreeGroup::from_keys(chunk))
+                .collect();
         }
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .chunks(chunks.len().div_ceil(threads))
+                .map(|run| {
+                    scope.spawn(move || {
+                        run.iter()
+                            .map(|chunk| BinarySearchTreeGroup::from_keys(chunk))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("group construction panicked"))
+                .collect()
+        })
+    }
 
-        Self { x_fast_trie }
+    #[cfg(not(feature = "std"))]
+    fn build_groups(chunks: &[&[Key]], _threads: usize) -> Vec<BinarySearchTreeGroup<P>> {
+        chunks
+            .iter()
+            .map(|chunk| BinarySearchTreeGroup::from_keys(chunk))
+            .collect()
+    }
+
+    /// the policy the groups follow
+    pub fn group_size_policy(&self) -> GroupSizePolicy {
+        self.group_size
     }
 
     pub fn len(&self) -> usize {
         let mut total = 0;
-        if let Some(head) = &self.x_fast_trie.head_rep {
-            let mut current = Some(head.clone());
-            while let Some(node) = current {
-                if let Ok(n) = node.read() {
-                    if let Some(bst_group) = &n.bst_group {
-                        if let Ok(bst) = bst_group.read() {
-                            total += bst.len();
-                        }
-                    }
-                    current = n.right.as_ref().and_then(|w| w.upgrade());
-                } else {
-                    break;
-                }
+        for node in self.x_fast_trie.reps.iter() {
+            let Ok(n) = node.read() else { break };
+            if let Some(bst_group) = &n.payload
+                && let Ok(bst) = bst_group.read()
+            {
+                total += bst.len();
             }
         }
         total
@@ -80,132 +248,333 @@ impl YFastTrie {
         self.x_fast_trie.len()
     }
 
-    pub fn get_infix_store(&self, key: Key) -> Option<Arc<RwLock<InfixStore>>> {
-        // find the boundary representative
-        let rep_node = self.x_fast_trie.lookup(key)?;
-        let rep = rep_node.read().ok()?;
+    /// memory used by the trie, including all BST groups and InfixStores
+    pub fn size_in_bytes(&self) -> usize {
+        core::mem::size_of::<Self>() - core::mem::size_of::<GroupTrie<P>>()
+            + self.x_fast_trie.size_in_bytes()
+    }
 
-        // get the BST group and call its get_infix_store
-        if let Some(bst_group) = &rep.bst_group {
-            if let Ok(bst) = bst_group.read() {
-                return bst.get_infix_store(key);
-            }
+    /// group count and sizes, x-fast trie entries and estimated bytes
+    ///
+    /// Groups far from the policy's target length show up as a wide spread
+    /// between `min_group_len` and `max_group_len`.
+    pub fn stats(&self) -> YFastStats {
+        let mut group_lens: Vec<usize> = self
+            .x_fast_trie
+            .reps
+            .iter()
+            .filter_map(|rep| {
+                let rep = rep.read().ok()?;
+                let bst_group = rep.payload.as_ref()?.read().ok()?;
+                Some(bst_group.len())
+            })
+            .collect();
+        group_lens.sort_unstable();
+        YFastStats {
+            group_count: group_lens.len(),
+            key_count: group_lens.iter().sum(),
+            min_group_len: group_lens.first().copied().unwrap_or(0),
+            median_group_len: group_lens.get(group_lens.len() / 2).copied().unwrap_or(0),
+            max_group_len: group_lens.last().copied().unwrap_or(0),
+            x_fast: self.x_fast_trie.stats(),
+            bytes: self.size_in_bytes(),
         }
+    }
 
-        None
+    /// the group that holds `key` if it is a sample, see `with_group_mut`
+    fn group_of_key(&self, key: Key) -> Result<SharedBstGroup<P>, RangeFilterError> {
+        let rep_node = self
+            .x_fast_trie
+            .predecessor_rep(key)
+            .ok_or(RangeFilterError::KeyNotFound(key))?;
+        let rep = rep_node
+            .read()
+            .map_err(|_| RangeFilterError::LockPoisoned)?;
+        rep.payload
+            .clone()
+            .ok_or(RangeFilterError::KeyNotFound(key))
     }
 
-    pub fn set_infix_store(&mut self, key: Key, infix_store: InfixStore) {
-        // find the boundary representative
-        if let Some(rep_node) = self.x_fast_trie.predecessor(key) {
-            if let Ok(rep) = rep_node.read() {
-                if let Some(bst_group) = &rep.bst_group {
-                    if let Ok(mut bst) = bst_group.write() {
-                        bst.set_infix_store(key, infix_store);
-                    }
-                }
+    /// run `f` on the group of the sample `key` under its write lock
+    fn with_group_mut<R>(
+        &self,
+        key: Key,
+        f: impl FnOnce(&mut BinarySearchTreeGroup<P>) -> Option<R>,
+    ) -> Result<R, RangeFilterError> {
+        let bst_group = self.group_of_key(key)?;
+        let mut bst = bst_group
+            .write()
+            .map_err(|_| RangeFilterError::LockPoisoned)?;
+        f(&mut bst).ok_or(RangeFilterError::KeyNotFound(key))
+    }
+
+    /// the store attached to the sample `key`
+    pub fn get_infix_store(&self, key: Key) -> Option<Arc<RwLock<P>>> {
+        let bst_group = self.group_of_key(key).ok()?;
+        let bst = bst_group.read().ok()?;
+        bst.get_infix_store(key)
+    }
+
+    /// attach an already shared store to the sample `key`, returning the store it
+    /// replaces
+    ///
+    /// Fails with `KeyNotFound` if `key` is not a sample.
+    pub fn attach_infix_store(
+        &mut self,
+        key: Key,
+        infix_store: Arc<RwLock<P>>,
+    ) -> Result<Option<Arc<RwLock<P>>>, RangeFilterError> {
+        self.with_group_mut(key, |bst| bst.replace_infix_store(key, Some(infix_store)))
+    }
+
+    /// attach a new store to the sample `key`, returning the store it replaces
+    ///
+    /// Fails with `KeyNotFound` if `key` is not a sample.
+    pub fn replace_infix_store(
+        &mut self,
+        key: Key,
+        infix_store: P,
+    ) -> Result<Option<Arc<RwLock<P>>>, RangeFilterError> {
+        self.attach_infix_store(key, Arc::new(RwLock::new(infix_store)))
+    }
+
+    /// attach a new store to the sample `key`, ignored if `key` is not a sample
+    pub fn set_infix_store(&mut self, key: Key, infix_store: P) {
+        let _ = self.replace_infix_store(key, infix_store);
+    }
+
+    /// detach the store of the sample `key` and return it
+    pub fn remove_infix_store(&mut self, key: Key) -> Option<Arc<RwLock<P>>> {
+        self.with_group_mut(key, |bst| bst.take_infix_store(key))
+            .ok()
+    }
+
+    /// insert a sample key, returns false if it is already present
+    ///
+    /// Keys below the first boundary join the first group as its new boundary.
+    /// A group that grows past the policy's maximum length is split in half and
+    /// the first key of the upper half becomes a new boundary.
+    pub fn insert(&mut self, key: Key) -> bool {
+        if self.contains(key) {
+            return false;
+        }
+
+        let Some(rep_node) = self.x_fast_trie.predecessor_rep(key) else {
+            let mut entries = vec![(key, None)];
+            let head = self.x_fast_trie.reps.head().cloned();
+            if let Some((head_key, head_entries)) = head.and_then(|head| Self::group_of(&head)) {
+                self.x_fast_trie.delete(head_key);
+                entries.extend(head_entries);
             }
+            self.regroup(entries);
+            return true;
+        };
+        let Some(bst_group) = rep_node.read().ok().and_then(|rep| rep.payload.clone()) else {
+            return false;
+        };
+
+        let group_len = match bst_group.write() {
+            Ok(mut bst) => {
+                bst.insert(key);
+                bst.len()
+            }
+            Err(_) => return false,
+        };
+        if group_len > self.group_size.max_len(self.x_fast_trie.no_levels) {
+            self.split_group(&bst_group);
         }
+        true
     }
 
-    // TODO: add insert method
-    // TODO: add next, previous methods
-    // TODO: create an iterator for the trie
+    /// delete a sample key and its infix store, returns false if it is not present
+    ///
+    /// A group left shorter than the policy's minimum length is merged into its
+    /// predecessor group, or its successor for the first group, and split again
+    /// if that runs past the maximum. Otherwise deleting a boundary promotes the
+    /// next key of its group to boundary, or drops the group if the boundary was
+    /// its only key.
+    pub fn delete(&mut self, key: Key) -> bool {
+        let Some(rep_node) = self.x_fast_trie.predecessor_rep(key) else {
+            return false;
+        };
+        let (boundary_key, bst_group, neighbour) = match rep_node.read() {
+            Ok(rep) => (
+                rep.key,
+                rep.payload.clone(),
+                rep.prev().or_else(|| rep.next()),
+            ),
+            Err(_) => return false,
+        };
+        let Some(bst_group) = bst_group else {
+            return false;
+        };
 
-    pub fn predecessor(&self, key: Key) -> Option<Key> {
-        // find the boundary representative
-        let rep_node = self.x_fast_trie.predecessor(key)?;
-        let rep = rep_node.read().ok()?;
+        let mut entries = match bst_group.read() {
+            Ok(bst) => bst.entries(),
+            Err(_) => Vec::new(),
+        };
+        let Some(position) = entries.iter().position(|(k, _)| *k == key) else {
+            return false;
+        };
+        entries.remove(position);
+
+        if entries.len() < self.group_size.min_len(self.x_fast_trie.no_levels)
+            && let Some((neighbour_key, neighbour_entries)) =
+                neighbour.and_then(|neighbour| Self::group_of(&neighbour))
+        {
+            self.x_fast_trie.delete(boundary_key);
+            self.x_fast_trie.delete(neighbour_key);
+            if neighbour_key < boundary_key {
+                entries.splice(0..0, neighbour_entries);
+            } else {
+                entries.extend(neighbour_entries);
+            }
+            self.regroup(entries);
+            return true;
+        }
 
-        // search within the BST group
-        if let Some(bst_group) = &rep.bst_group {
-            if let Ok(bst) = bst_group.read() {
-                return bst.predecessor(key);
+        if key != boundary_key {
+            if let Ok(mut bst) = bst_group.write() {
+                bst.delete(key);
             }
+            return true;
         }
 
-        Some(rep.key)
+        self.x_fast_trie.delete(key);
+        Self::push_entries(&mut self.x_fast_trie, &entries);
+        true
     }
 
-    pub fn predecessor_infix_store(&self, key: Key) -> Option<Arc<RwLock<InfixStore>>> {
-        // find boundary via x-fast trie
-        let rep_node = self.x_fast_trie.predecessor(key)?;
+    /// the boundary and sorted entries of the group of a representative
+    fn group_of(rep_node: &SharedRep<BinarySearchTreeGroup<P>>) -> Option<(Key, GroupEntries<P>)> {
         let rep = rep_node.read().ok()?;
+        let bst = rep.payload.as_ref()?.read().ok()?;
+        Some((rep.key, bst.entries()))
+    }
 
-        // get the BST group and call its predecessor_infix_store
-        if let Some(bst_group) = &rep.bst_group {
-            if let Ok(bst) = bst_group.read() {
-                return bst.predecessor_infix_store(key);
-            }
+    /// push sorted entries whose boundaries were removed back as one group, or
+    /// as equal parts of at most the maximum group length
+    fn regroup(&mut self, entries: GroupEntries<P>) {
+        let max_len = self.group_size.max_len(self.x_fast_trie.no_levels);
+        let parts = entries.len().div_ceil(max_len);
+        for part in entries.chunks(entries.len().div_ceil(parts.max(1)).max(1)) {
+            Self::push_entries(&mut self.x_fast_trie, part);
         }
-        None
     }
 
-    pub fn successor_infix_store(&self, key: Key) -> Option<Arc<RwLock<InfixStore>>> {
-        // find the containing bucket via predecessor boundary
-        if let Some(rep_node) = self.x_fast_trie.predecessor(key) {
-            if let Ok(rep) = rep_node.read() {
-                // search within the BST group
-                if let Some(bst_group) = &rep.bst_group {
-                    if let Ok(bst) = bst_group.read() {
-                        if let Some(result) = bst.successor_infix_store(key) {
-                            return Some(result);
-                        }
-                    }
-                }
+    /// split a group in half, moving the upper half under a new boundary
+    fn split_group(&mut self, bst_group: &SharedBstGroup<P>) {
+        let upper = match bst_group.write() {
+            Ok(mut bst) => {
+                let mut entries = bst.drain();
+                let upper = entries.split_off(entries.len() / 2);
+                *bst = Self::build_group(&entries);
+                upper
+            }
+            Err(_) => return,
+        };
+        Self::push_entries(&mut self.x_fast_trie, &upper);
+    }
 
-                // key is > all keys in this bucket, try next bucket
-                if let Some(next_weak) = &rep.right {
-                    if let Some(next_rep) = next_weak.upgrade() {
-                        if let Ok(next) = next_rep.read() {
-                            if let Some(bst_group) = &next.bst_group {
-                                if let Ok(bst) = bst_group.read() {
-                                    return bst.get_infix_store(next.key);
-                                }
-                            }
-                        }
-                    }
-                }
+    /// build a balanced BST group from sorted entries, keeping their infix stores
+    fn build_group(entries: &[GroupEntry<P>]) -> BinarySearchTreeGroup<P> {
+        let keys: Vec<Key> = entries.iter().map(|(key, _)| *key).collect();
+        let mut bst = BinarySearchTreeGroup::from_keys(&keys);
+        for (key, store) in entries {
+            if let Some(store) = store {
+                bst.attach_infix_store(*key, store.clone());
             }
         }
+        bst
+    }
 
-        None
+    /// insert the first of the sorted entries as a boundary and attach a group
+    /// holding all of them to its representative
+    fn push_entries(x_fast_trie: &mut GroupTrie<P>, entries: &[GroupEntry<P>]) {
+        let Some(&(boundary_key, _)) = entries.first() else {
+            return;
+        };
+        x_fast_trie.insert(boundary_key);
+        let bst_group = Arc::new(RwLock::new(Self::build_group(entries)));
+        let Some(rep_node) = x_fast_trie.lookup(boundary_key) else {
+            return;
+        };
+        if let Ok(mut rep) = rep_node.write() {
+            rep.payload = Some(bst_group);
+        }
     }
 
-    pub fn successor(&self, key: Key) -> Option<Key> {
-        // find the containing bucket via predecessor boundary
-        if let Some(rep_node) = self.x_fast_trie.predecessor(key) {
-            if let Ok(rep) = rep_node.read() {
-                // search within the BST group
-                if let Some(bst_group) = &rep.bst_group {
-                    if let Ok(bst) = bst_group.read() {
-                        if let Some(result) = bst.successor(key) {
-                            return Some(result);
-                        }
-                    }
-                }
+    // TODO: add next, previous methods
+    // TODO: create an iterator for the trie
 
-                // key is > all keys in this bucket, try next bucket
-                if let Some(next_weak) = &rep.right {
-                    if let Some(next_rep) = next_weak.upgrade() {
-                        if let Ok(next) = next_rep.read() {
-                            return Some(next.key);
-                        }
-                    }
-                }
-            }
-        } else {
-            // key < first boundary, return first key
-            if let Some(head) = &self.x_fast_trie.head_rep {
-                if let Ok(head_guard) = head.read() {
-                    return Some(head_guard.key);
-                }
+    /// the first hit of `find` over the groups, starting at the group of the
+    /// predecessor boundary of `key` and following the representative links
+    /// towards smaller keys (`backwards`) or larger ones
+    ///
+    /// The group of the boundary usually answers, its neighbours only do when
+    /// it holds no key on the searched side of `key`.
+    fn find_in_groups<R>(
+        &self,
+        key: Key,
+        backwards: bool,
+        find: impl Fn(&BinarySearchTreeGroup<P>) -> Option<R>,
+    ) -> Option<R> {
+        let mut current = match self.x_fast_trie.predecessor_rep(key) {
+            Some(rep_node) => Some(rep_node),
+            // key < first boundary, so only the groups after it can answer
+            None if !backwards => self.x_fast_trie.reps.head().cloned(),
+            None => None,
+        };
+        while let Some(rep_node) = current {
+            let rep = rep_node.read().ok()?;
+            let bst = rep
+                .payload
+                .as_ref()
+                .and_then(|bst_group| bst_group.read().ok());
+            if let Some(found) = bst.and_then(|bst| find(&bst)) {
+                return Some(found);
             }
+            current = if backwards { rep.prev() } else { rep.next() };
         }
-
         None
     }
 
+    pub fn predecessor(&self, key: Key) -> Option<Key> {
+        self.find_in_groups(key, true, |bst| bst.predecessor(key))
+    }
+
+    /// the largest sample at most `key` with its store, in one descent
+    ///
+    /// In Diva that is the sample starting the partition of `key` and the
+    /// partition's InfixStore, if it has one.
+    pub fn floor_entry(&self, key: Key) -> Option<GroupEntry<P>> {
+        self.find_in_groups(key, true, |bst| bst.floor_entry(key))
+    }
+
+    /// the smallest sample at least `key` with its store, in one descent
+    pub fn ceiling_entry(&self, key: Key) -> Option<GroupEntry<P>> {
+        self.find_in_groups(key, false, |bst| bst.ceiling_entry(key))
+    }
+
+    pub fn predecessor_infix_store(&self, key: Key) -> Option<Arc<RwLock<P>>> {
+        self.find_in_groups(key, true, |bst| {
+            bst.predecessor(key)
+                .map(|_| bst.predecessor_infix_store(key))
+        })
+        .flatten()
+    }
+
+    pub fn successor_infix_store(&self, key: Key) -> Option<Arc<RwLock<P>>> {
+        self.find_in_groups(key, false, |bst| {
+            bst.successor(key).map(|_| bst.successor_infix_store(key))
+        })
+        .flatten()
+    }
+
+    pub fn successor(&self, key: Key) -> Option<Key> {
+        self.find_in_groups(key, false, |bst| bst.successor(key))
+    }
+
     pub fn contains(&self, key: Key) -> bool {
         // first check x-fast trie for direct hit
         if self.x_fast_trie.lookup(key).is_some() {
@@ -213,38 +582,198 @@ impl YFastTrie {
         }
 
         // find the predecessor boundary representative
-        if let Some(rep_node) = self.x_fast_trie.predecessor(key) {
-            if let Ok(rep) = rep_node.read() {
-                // then check if key is in the BST group
-                if let Some(bst_group) = &rep.bst_group {
-                    if let Ok(bst) = bst_group.read() {
-                        return bst.contains(key);
-                    }
-                }
+        if let Some(rep_node) = self.x_fast_trie.predecessor_rep(key)
+            && let Ok(rep) = rep_node.read()
+        {
+            // then check if key is in the BST group
+            if let Some(bst_group) = &rep.payload
+                && let Ok(bst) = bst_group.read()
+            {
+                return bst.contains(key);
             }
         }
         false
     }
 
-    pub fn pretty_print(&self) {
-        print!("{}", self);
+    /// keys within `range` in ascending order
+    ///
+    /// Starts at the group of the predecessor boundary of the range start, or the
+    /// first group, and walks the groups in boundary order, each in order, until
+    /// a key passes the range end.
+    pub fn range<R: RangeBounds<Key>>(&self, range: R) -> YFastIter<P> {
+        let first_group = match range.start_bound() {
+            Bound::Included(&start) | Bound::Excluded(&start) => {
+                self.x_fast_trie.predecessor_rep(start)
+            }
+            Bound::Unbounded => None,
+        };
+        YFastIter {
+            next_group: first_group.or_else(|| self.x_fast_trie.reps.head().cloned()),
+            keys: Vec::new().into_iter(),
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        }
     }
 
-    // helper to collect all keys from BST in sorted order
-    fn collect_bst_keys(node: &Option<Box<crate::binary_search_tree::TreeNode>>) -> Vec<Key> {
-        let mut keys = Vec::new();
-        Self::collect_bst_keys_recursive(node, &mut keys);
-        keys
+    /// collect every group as its sorted (key, infix store) entries, in boundary order
+    pub(crate) fn group_entries(&self) -> Vec<GroupEntries<P>> {
+        let mut groups = Vec::new();
+        for node in self.x_fast_trie.reps.iter() {
+            let Ok(n) = node.read() else { break };
+            let entries = n
+                .payload
+                .as_ref()
+                .and_then(|bst_group| bst_group.read().ok().map(|bst| bst.entries()));
+            groups.push(entries.unwrap_or_default());
+        }
+        groups
     }
+}
+
+/// Iterator over the keys of a `YFastTrie` within a range, see `YFastTrie::range`
+///
+/// Copies out the keys of one group at a time, so no group lock is held
+/// between calls to `next`.
+pub struct YFastIter<P = InfixStore> {
+    next_group: Option<SharedRep<BinarySearchTreeGroup<P>>>,
+    // the not yet returned keys of the current group
+    keys: vec::IntoIter<Key>,
+    start: Bound<Key>,
+    end: Bound<Key>,
+}
 
-    fn collect_bst_keys_recursive(
-        node: &Option<Box<crate::binary_search_tree::TreeNode>>,
-        keys: &mut Vec<Key>,
-    ) {
-        if let Some(n) = node {
-            Self::collect_bst_keys_recursive(&n.left, keys);
-            keys.push(n.key);
-            Self::collect_bst_keys_recursive(&n.right, keys);
+impl<P: RepPayload> Iterator for YFastIter<P> {
+    type Item = Key;
+
+    fn next(&mut self) -> Option<Key> {
+        loop {
+            if let Some(key) = self.keys.next() {
+                let after_start = match self.start {
+                    Bound::Included(start) => key >= start,
+                    Bound::Excluded(start) => key > start,
+                    Bound::Unbounded => true,
+                };
+                if !after_start {
+                    continue;
+                }
+                let before_end = match self.end {
+                    Bound::Included(end) => key <= end,
+                    Bound::Excluded(end) => key < end,
+                    Bound::Unbounded => true,
+                };
+                if !before_end {
+                    self.next_group = None;
+                    self.keys = Vec::new().into_iter();
+                    return None;
+                }
+                return Some(key);
+            }
+
+            let node = self.next_group.take()?;
+            let rep = node.read().ok()?;
+            let keys = rep.payload.as_ref().and_then(|bst_group| {
+                let bst = bst_group.read().ok()?;
+                Some(bst.iter().map(|(key, _)| key).collect::<Vec<_>>())
+            });
+            self.keys = keys.unwrap_or_default().into_iter();
+            self.next_group = rep.next();
+        }
+    }
+}
+
+impl<P: RepPayload> FusedIterator for YFastIter<P> {}
+
+/// Balance and memory statistics of a YFastTrie, see `YFastTrie::stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct YFastStats {
+    /// BST groups, one per boundary
+    pub group_count: usize,
+    /// keys over all groups
+    pub key_count: usize,
+    /// keys in the smallest group, 0 without groups
+    pub min_group_len: usize,
+    /// keys in the upper median group, 0 without groups
+    pub median_group_len: usize,
+    /// keys in the largest group, 0 without groups
+    pub max_group_len: usize,
+    /// the x-fast trie over the group boundaries
+    pub x_fast: XFastStats,
+    /// estimated memory, see `YFastTrie::size_in_bytes`
+    pub bytes: usize,
+}
+
+/// Serialized form of a Y-Fast Trie
+///
+/// The x-fast level tables and the representative linked list are a graph of
+/// `Arc`/`Weak` pointers, so only the groups and their size policy are
+/// persisted: each group is its sorted keys with any attached payloads, the
+/// InfixStores in Diva. The first key of every group is its boundary, and the
+/// x-fast trie is bulk-built over the boundaries on load.
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+    use crate::x_fast_trie::serde_impl::{OwnedGroup, SharedGroup, shared_group};
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    struct YFastTrieRef<'a, P> {
+        no_levels: usize,
+        group_size: GroupSizePolicy,
+        groups: Vec<SharedGroup<'a, P>>,
+    }
+
+    #[derive(Deserialize)]
+    struct YFastTrieOwned<P> {
+        no_levels: usize,
+        // absent from tries saved before group size policies
+        #[serde(default)]
+        group_size: GroupSizePolicy,
+        groups: Vec<OwnedGroup<P>>,
+    }
+
+    impl<P: RepPayload + Serialize> Serialize for YFastTrie<P> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let groups = self.group_entries();
+            let repr = YFastTrieRef {
+                no_levels: self.x_fast_trie.no_levels,
+                group_size: self.group_size,
+                groups: groups.iter().map(|entries| shared_group(entries)).collect(),
+            };
+            repr.serialize(serializer)
+        }
+    }
+
+    impl<'de, P: RepPayload + Deserialize<'de>> Deserialize<'de> for YFastTrie<P> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let repr = YFastTrieOwned::<P>::deserialize(deserializer)?;
+            if repr.no_levels == 0 || repr.no_levels > crate::U64_BITS {
+                return Err(D::Error::custom("invalid number of levels"));
+            }
+
+            // groups must be non-empty, sorted, and disjoint to rebuild the trie
+            let mut prev_key: Option<Key> = None;
+            for (key, _) in repr.groups.iter().flatten() {
+                if prev_key.is_some_and(|prev| prev >= *key) {
+                    return Err(D::Error::custom("group keys are not strictly increasing"));
+                }
+                prev_key = Some(*key);
+            }
+            let mut reps = Vec::with_capacity(repr.groups.len());
+            for group in repr.groups {
+                let Some(&(boundary_key, _)) = group.first() else {
+                    return Err(D::Error::custom("empty group"));
+                };
+                reps.push((boundary_key, group));
+            }
+
+            let x_fast_trie =
+                XFastTrie::from_groups(repr.no_levels, 1, reps).map_err(D::Error::custom)?;
+            repr.group_size.validate().map_err(D::Error::custom)?;
+            Ok(Self {
+                x_fast_trie,
+                group_size: repr.group_size,
+            })
         }
     }
 }
@@ -268,6 +797,12 @@ impl fmt::Display for YFastTrie {
         writeln!(f, "\nStats:")?;
         writeln!(f, "  Total keys:        {}", self.len())?;
         writeln!(f, "  Sample count:      {}", self.sample_count())?;
+        let stats = self.stats();
+        writeln!(
+            f,
+            "  Group sizes:       min {} / median {} / max {}",
+            stats.min_group_len, stats.median_group_len, stats.max_group_len
+        )?;
         writeln!(f, "  Levels:            {}", self.x_fast_trie.no_levels)?;
 
         // print x-fast trie structure
@@ -287,7 +822,7 @@ impl fmt::Display for YFastTrie {
             "╚════════════════════════════════════════════════════════╝\n"
         )?;
 
-        if let Some(head) = &self.x_fast_trie.head_rep {
+        if let Some(head) = self.x_fast_trie.reps.head() {
             let mut current = Some(head.clone());
             let mut bucket_index = 0;
 
@@ -295,24 +830,23 @@ impl fmt::Display for YFastTrie {
                 if let Ok(n) = node.read() {
                     writeln!(f, "Bucket {} (representative: {})", bucket_index, n.key)?;
 
-                    if let Some(bst_group) = &n.bst_group {
+                    if let Some(bst_group) = &n.payload {
                         if let Ok(bst) = bst_group.read() {
                             write!(f, "{}", bst)?;
 
                             // check for InfixStores attached to keys in this BST
-                            let keys = Self::collect_bst_keys(&bst.root);
                             let mut infix_stats = Vec::new();
 
-                            for &key in &keys {
-                                if let Some(infix_store_arc) = bst.get_infix_store(key) {
-                                    if let Ok(infix_store) = infix_store_arc.read() {
-                                        infix_stats.push((
-                                            key,
-                                            infix_store.elem_count(),
-                                            infix_store.remainder_size(),
-                                            infix_store.num_slots(),
-                                        ));
-                                    }
+                            for (key, infix_store_arc) in bst.iter() {
+                                if let Some(infix_store_arc) = infix_store_arc
+                                    && let Ok(infix_store) = infix_store_arc.read()
+                                {
+                                    infix_stats.push((
+                                        key,
+                                        infix_store.elem_count(),
+                                        infix_store.remainder_size(),
+                                        infix_store.num_slots(),
+                                    ));
                                 }
                             }
 
@@ -331,7 +865,7 @@ impl fmt::Display for YFastTrie {
                         writeln!(f, "  (no BST group attached)")?;
                     }
 
-                    current = n.right.as_ref().and_then(|w| w.upgrade());
+                    current = n.next();
                     bucket_index += 1;
                 } else {
                     break;
@@ -495,75 +1029,279 @@ mod tests {
     }
 
     #[test]
-    fn test_infix_stores() {
-        use crate::infix_store::InfixStore;
+    fn test_predecessor_successor_fall_back_to_neighbouring_groups() {
+        // groups at 0, 8, 16, 24 and 32
+        let keys: Vec<Key> = (0..40).collect();
+        let mut trie = YFastTrie::new_with_keys(&keys, 8);
+        trie.set_infix_store(7, InfixStore::default());
+        trie.set_infix_store(24, InfixStore::default());
+        let set_group = |boundary: Key, keys: &[Key]| {
+            let rep = trie.x_fast_trie.lookup(boundary).unwrap();
+            let bst_group = rep.read().unwrap().payload.clone().unwrap();
+            *bst_group.write().unwrap() = BinarySearchTreeGroup::from_keys(keys);
+        };
+        // the groups of 8 and 16 hold nothing at or below their boundaries
+        set_group(8, &[12, 13]);
+        set_group(16, &[]);
+
+        assert_eq!(trie.predecessor(8), Some(7));
+        assert_eq!(trie.predecessor(11), Some(7));
+        assert_eq!(trie.predecessor(20), Some(13));
+        assert_eq!(trie.successor(14), Some(24));
+        assert_eq!(trie.successor(16), Some(24));
+        let stored_at = |store: Option<Arc<RwLock<InfixStore>>>, key: Key| {
+            let expected = trie.get_infix_store(key).unwrap();
+            assert!(Arc::ptr_eq(&store.unwrap(), &expected));
+        };
+        stored_at(trie.predecessor_infix_store(10), 7);
+        stored_at(trie.successor_infix_store(20), 24);
+    }
 
-        // 24 keys: boundaries at 0, 24, 48
+    #[test]
+    fn test_range() {
+        // groups at 0, 24 and 48
         let keys: Vec<Key> = (0..24).map(|i| i * 3).collect();
-        let trie = YFastTrie::new_with_keys(&keys, 8);
+        let mut trie = YFastTrie::new_with_keys(&keys, 8);
+        let range = |trie: &YFastTrie, lo: Key, hi: Key| trie.range(lo..=hi).collect::<Vec<_>>();
+
+        assert_eq!(trie.range(..).collect::<Vec<_>>(), keys);
+        assert_eq!(range(&trie, 20, 28), vec![21, 24, 27]);
+        assert_eq!(range(&trie, 22, 23), Vec::<Key>::new());
+        assert_eq!(range(&trie, 60, 1000), vec![60, 63, 66, 69]);
+        assert_eq!(trie.range(66..).collect::<Vec<_>>(), vec![66, 69]);
+        assert_eq!(trie.range(..6).collect::<Vec<_>>(), vec![0, 3]);
+        assert_eq!(
+            trie.range((Bound::Excluded(45), Bound::Excluded(51)))
+                .count(),
+            1
+        );
+
+        // ranges keep up with inserts below the first boundary and group splits
+        for key in [1, 2, 4, 5, 7, 8, 10, 11, 13, 14] {
+            trie.insert(key);
+        }
+        assert!(trie.sample_count() > 3);
+        assert_eq!(range(&trie, 0, 8), vec![0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        trie.delete(24);
+        assert_eq!(range(&trie, 20, 28), vec![21, 27]);
+        assert!(YFastTrie::new(8).range(..).next().is_none());
+    }
 
-        // attach infix stores to some keys across buckets
-        let store_6 = InfixStore::default();
-        let store_12 = InfixStore::default();
-        let store_30 = InfixStore::default();
-
-        // manually set infix stores in BST groups
-        if let Some(rep) = trie.x_fast_trie.lookup(0) {
-            if let Ok(r) = rep.read() {
-                if let Some(bst_group) = &r.bst_group {
-                    if let Ok(mut bst) = bst_group.write() {
-                        bst.set_infix_store(6, store_6);
-                    }
-                }
-            }
+    #[test]
+    fn test_group_size_policies() {
+        let keys: Vec<Key> = (0..100).map(|i| i * 2).collect();
+        let group_lens = |trie: &YFastTrie| -> Vec<usize> {
+            trie.x_fast_trie
+                .reps
+                .iter()
+                .map(|rep| {
+                    rep.read()
+                        .unwrap()
+                        .payload
+                        .as_ref()
+                        .unwrap()
+                        .read()
+                        .unwrap()
+                        .len()
+                })
+                .collect()
+        };
+
+        let default = YFastTrie::new_with_keys(&keys, 16);
+        assert_eq!(default.group_size_policy(), GroupSizePolicy::LogMultiple(1));
+        assert_eq!(group_lens(&default), vec![16, 16, 16, 16, 16, 16, 4]);
+        let doubled: YFastTrie =
+            YFastTrie::from_keys_with_group_size(&keys, 16, GroupSizePolicy::LogMultiple(2));
+        assert_eq!(doubled.sample_count(), 4);
+        let load_factor = GroupSizePolicy::LoadFactor {
+            capacity: 40,
+            load_factor: 0.75,
+        };
+        let loaded = YFastTrie::from_keys_with_group_size(&keys, 16, load_factor);
+        assert_eq!(group_lens(&loaded), vec![30, 30, 30, 10]);
+
+        // a fixed group of 4 splits past 8 keys and merges below 2
+        let mut trie = YFastTrie::from_keys_with_group_size(
+            &[2, 4, 6, 8, 10, 12, 14, 16],
+            16,
+            GroupSizePolicy::Fixed(4),
+        );
+        for key in [3, 5, 7, 9, 11] {
+            trie.insert(key);
+        }
+        assert_eq!(group_lens(&trie), vec![8, 5]);
+        // below the first boundary the key joins the first group, which splits
+        trie.insert(1);
+        assert_eq!(group_lens(&trie), vec![5, 4, 5]);
+        for key in [10, 11, 12] {
+            trie.delete(key);
+        }
+        assert_eq!(group_lens(&trie), vec![5, 4, 2]);
+        trie.delete(14);
+        assert_eq!(group_lens(&trie), vec![5, 5]);
+        let remaining = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 16];
+        assert_eq!(trie.range(..).collect::<Vec<_>>(), remaining);
+        for key in remaining {
+            assert!(trie.delete(key));
         }
+        assert_eq!(trie.sample_count(), 0);
+    }
 
-        if let Some(rep) = trie.x_fast_trie.lookup(0) {
-            if let Ok(r) = rep.read() {
-                if let Some(bst_group) = &r.bst_group {
-                    if let Ok(mut bst) = bst_group.write() {
-                        bst.set_infix_store(12, store_12);
-                    }
-                }
-            }
+    #[test]
+    fn test_stats() {
+        let empty: YFastTrie = YFastTrie::new(8);
+        assert_eq!(empty.stats().group_count, 0);
+        assert_eq!(empty.stats().max_group_len, 0);
+
+        let keys: Vec<Key> = (0..10).collect();
+        let mut trie: YFastTrie =
+            YFastTrie::from_keys_with_group_size(&keys, 8, GroupSizePolicy::Fixed(4));
+        let stats = trie.stats();
+        assert_eq!(stats.group_count, 3);
+        assert_eq!(stats.key_count, 10);
+        assert_eq!(
+            (
+                stats.min_group_len,
+                stats.median_group_len,
+                stats.max_group_len
+            ),
+            (2, 4, 4)
+        );
+        assert_eq!(stats.x_fast.rep_count, 3);
+        assert_eq!(stats.bytes, trie.size_in_bytes());
+
+        // growing the first group past the target spreads the sizes
+        for key in [100, 101, 102] {
+            trie.insert(key);
         }
+        let stats = trie.stats();
+        assert_eq!(stats.key_count, trie.len());
+        assert_eq!((stats.min_group_len, stats.max_group_len), (4, 5));
+    }
 
-        if let Some(rep) = trie.x_fast_trie.lookup(24) {
-            if let Ok(r) = rep.read() {
-                if let Some(bst_group) = &r.bst_group {
-                    if let Ok(mut bst) = bst_group.write() {
-                        bst.set_infix_store(30, store_30);
-                    }
-                }
-            }
+    #[test]
+    fn test_invalid_group_size_policies() {
+        assert!(GroupSizePolicy::Fixed(0).validate().is_err());
+        assert!(GroupSizePolicy::LogMultiple(0).validate().is_err());
+        for (capacity, load_factor) in [(0, 0.5), (10, 0.0), (10, 1.5), (10, f64::NAN)] {
+            let policy = GroupSizePolicy::LoadFactor {
+                capacity,
+                load_factor,
+            };
+            assert!(policy.validate().is_err());
         }
+        assert!(GroupSizePolicy::default().validate().is_ok());
+        let result = std::panic::catch_unwind(|| {
+            YFastTrie::<()>::with_group_size(16, GroupSizePolicy::Fixed(0))
+        });
+        assert!(result.is_err());
+    }
 
-        // get reference stores for comparison
-        let ref_store_6 = {
-            let rep = trie.x_fast_trie.lookup(0).unwrap();
-            let r = rep.read().unwrap();
-            let bst_group = r.bst_group.as_ref().unwrap();
-            let bst = bst_group.read().unwrap();
-            bst.get_infix_store(6).unwrap()
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip_with_groups() {
+        let keys: Vec<Key> = (0..100).map(|i| i * 7).collect();
+        let mut trie = YFastTrie::new_with_keys(&keys, 16);
+        // stores on boundaries and inside groups
+        for &key in keys.iter().step_by(3) {
+            trie.set_infix_store(key, InfixStore::new_with_infixes(&[key % 64, 100], 8));
+        }
+        let json = serde_json::to_string(&trie).unwrap();
+        let loaded: YFastTrie = serde_json::from_str(&json).unwrap();
+
+        let contents = |trie: &YFastTrie| -> Vec<Vec<(Key, Option<Vec<u64>>)>> {
+            trie.group_entries()
+                .into_iter()
+                .map(|group| {
+                    group
+                        .into_iter()
+                        .map(|(key, store)| (key, store.map(|s| s.read().unwrap().infixes())))
+                        .collect()
+                })
+                .collect()
         };
+        assert_eq!(contents(&loaded), contents(&trie));
+        assert_eq!(loaded.sample_count(), trie.sample_count());
+        assert!(loaded.range(..).eq(keys.iter().copied()));
+        assert_eq!(loaded.predecessor(500), Some(497));
+
+        // any serializable payload round-trips
+        let unit = YFastTrie::<()>::from_keys(&keys, 16);
+        let json = serde_json::to_string(&unit).unwrap();
+        let loaded: YFastTrie<()> = serde_json::from_str(&json).unwrap();
+        assert!(loaded.range(..).eq(keys.iter().copied()));
+    }
 
-        let ref_store_12 = {
-            let rep = trie.x_fast_trie.lookup(0).unwrap();
-            let r = rep.read().unwrap();
-            let bst_group = r.bst_group.as_ref().unwrap();
-            let bst = bst_group.read().unwrap();
-            bst.get_infix_store(12).unwrap()
-        };
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_keeps_group_size_policy() {
+        let keys: Vec<Key> = (0..50).collect();
+        let trie: YFastTrie =
+            YFastTrie::from_keys_with_group_size(&keys, 16, GroupSizePolicy::Fixed(5));
+        let json = serde_json::to_string(&trie).unwrap();
+        let loaded: YFastTrie = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.group_size_policy(), GroupSizePolicy::Fixed(5));
+        assert_eq!(loaded.sample_count(), 10);
+
+        // tries saved without a policy load with the default one
+        let legacy = r#"{"no_levels":16,"groups":[[[3,null],[4,null]]]}"#;
+        let loaded: YFastTrie = serde_json::from_str(legacy).unwrap();
+        assert_eq!(loaded.group_size_policy(), GroupSizePolicy::default());
+        assert!(serde_json::from_str::<YFastTrie>(&json.replace(":5}", ":0}")).is_err());
+    }
 
-        let ref_store_30 = {
-            let rep = trie.x_fast_trie.lookup(24).unwrap();
-            let r = rep.read().unwrap();
-            let bst_group = r.bst_group.as_ref().unwrap();
-            let bst = bst_group.read().unwrap();
-            println!("bst: {:?}", bst);
-            bst.get_infix_store(30).unwrap()
+    #[test]
+    fn test_build_groups_on_threads() {
+        let keys: Vec<Key> = (0..1000).map(|i| i * 3 + 1).collect();
+        let chunks: Vec<&[Key]> = keys.chunks(7).collect();
+        let keys_of = |groups: Vec<BinarySearchTreeGroup>| -> Vec<Vec<Key>> {
+            groups
+                .iter()
+                .map(|bst| bst.entries().into_iter().map(|(key, _)| key).collect())
+                .collect()
         };
 
+        let expected: Vec<Vec<Key>> = chunks.iter().map(|chunk| chunk.to_vec()).collect();
+        for threads in [1, 2, 3, 8, 200] {
+            assert_eq!(keys_of(YFastTrie::build_groups(&chunks, threads)), expected);
+        }
+        assert!(YFastTrie::<InfixStore>::build_groups(&[], 4).is_empty());
+
+        // enough keys for from_keys to build on all threads
+        let keys: Vec<Key> = (0..1 << 15).map(|i| i * 5).collect();
+        let trie = YFastTrie::<()>::from_keys(&keys, 64);
+        assert!(trie.range(..).eq(keys.iter().copied()));
+        assert_eq!(trie.sample_count(), keys.len() / 64);
+    }
+
+    #[test]
+    fn test_infix_stores() {
+        use crate::infix_store::InfixStore;
+
+        // 24 keys: boundaries at 0, 24, 48
+        let keys: Vec<Key> = (0..24).map(|i| i * 3).collect();
+        let mut trie = YFastTrie::new_with_keys(&keys, 8);
+
+        // attach infix stores to some keys across buckets
+        let ref_store_6 = Arc::new(RwLock::new(InfixStore::default()));
+        let ref_store_12 = Arc::new(RwLock::new(InfixStore::default()));
+        let ref_store_30 = Arc::new(RwLock::new(InfixStore::default()));
+        assert!(
+            trie.attach_infix_store(6, ref_store_6.clone())
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            trie.attach_infix_store(12, ref_store_12.clone())
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            trie.attach_infix_store(30, ref_store_30.clone())
+                .unwrap()
+                .is_none()
+        );
+
         assert!(Arc::ptr_eq(
             &trie.predecessor_infix_store(8).unwrap(),
             &ref_store_6
@@ -595,4 +1333,183 @@ mod tests {
         assert!(trie.predecessor_infix_store(2).is_none());
         assert!(trie.successor_infix_store(1000).is_none());
     }
+
+    #[test]
+    fn test_attach_replace_remove_infix_store() {
+        let keys: Vec<Key> = (0..40).map(|i| i * 2).collect();
+        let mut trie = YFastTrie::new_with_keys(&keys, 8);
+        let store = |infix: u64| InfixStore::new_with_infixes(&[infix], 8);
+        let infixes = |store: Arc<RwLock<InfixStore>>| store.read().unwrap().infixes();
+
+        // a boundary and a key inside a group
+        for key in [16, 18] {
+            assert!(trie.replace_infix_store(key, store(1)).unwrap().is_none());
+            let previous = trie.replace_infix_store(key, store(2)).unwrap();
+            assert_eq!(previous.map(infixes), Some(vec![1]));
+            assert_eq!(trie.get_infix_store(key).map(infixes), Some(vec![2]));
+        }
+
+        let shared = Arc::new(RwLock::new(store(3)));
+        let previous = trie.attach_infix_store(18, shared.clone()).unwrap();
+        assert_eq!(previous.map(infixes), Some(vec![2]));
+        assert!(Arc::ptr_eq(&trie.get_infix_store(18).unwrap(), &shared));
+        assert!(Arc::ptr_eq(&trie.remove_infix_store(18).unwrap(), &shared));
+        assert!(trie.get_infix_store(18).is_none());
+        assert!(trie.remove_infix_store(18).is_none());
+
+        // only samples take stores
+        for key in [17, 1000] {
+            assert_eq!(
+                trie.replace_infix_store(key, store(4)).unwrap_err(),
+                RangeFilterError::KeyNotFound(key)
+            );
+            assert!(trie.remove_infix_store(key).is_none());
+        }
+        let mut empty = YFastTrie::new(8);
+        assert!(empty.attach_infix_store(5, shared).is_err());
+    }
+
+    #[test]
+    fn test_floor_and_ceiling_entries() {
+        // groups at 0, 16, 32 and 48
+        let keys: Vec<Key> = (0..32).map(|i| i * 2).collect();
+        let mut trie = YFastTrie::new_with_keys(&keys, 8);
+        for key in [14, 16, 30] {
+            trie.set_infix_store(key, InfixStore::new_with_infixes(&[key], 8));
+        }
+        let entry = |entry: Option<GroupEntry>| {
+            entry.map(|(key, store)| (key, store.map(|store| store.read().unwrap().infixes())))
+        };
+
+        assert_eq!(entry(trie.floor_entry(15)), Some((14, Some(vec![14]))));
+        assert_eq!(entry(trie.floor_entry(16)), Some((16, Some(vec![16]))));
+        assert_eq!(entry(trie.floor_entry(17)), Some((16, Some(vec![16]))));
+        assert_eq!(entry(trie.floor_entry(19)), Some((18, None)));
+        assert_eq!(entry(trie.floor_entry(1000)), Some((62, None)));
+        assert_eq!(entry(trie.ceiling_entry(13)), Some((14, Some(vec![14]))));
+        assert_eq!(entry(trie.ceiling_entry(29)), Some((30, Some(vec![30]))));
+        assert_eq!(entry(trie.ceiling_entry(31)), Some((32, None)));
+        assert!(trie.ceiling_entry(63).is_none());
+
+        // the entries agree with predecessor, successor and get_infix_store
+        for key in 0..70 {
+            let floor = trie.floor_entry(key);
+            assert_eq!(floor.as_ref().map(|(k, _)| *k), trie.predecessor(key));
+            let ceiling = trie.ceiling_entry(key);
+            assert_eq!(ceiling.as_ref().map(|(k, _)| *k), trie.successor(key));
+            for (sample, store) in floor.into_iter().chain(ceiling) {
+                assert_eq!(store.is_some(), trie.get_infix_store(sample).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        use rand::seq::SliceRandom;
+        use std::collections::BTreeSet;
+
+        let mut rng = rand::thread_rng();
+        let mut keys: Vec<Key> = (0..500).map(|i| i * 37).collect();
+        keys.shuffle(&mut rng);
+
+        let mut trie = YFastTrie::new(16);
+        let mut oracle = BTreeSet::new();
+        for &key in &keys {
+            assert!(trie.insert(key));
+            oracle.insert(key);
+        }
+        assert!(!trie.insert(keys[0]));
+        assert_eq!(trie.len(), oracle.len());
+        // groups are split once they grow past 2 * log U keys
+        assert!(trie.x_fast_trie.len() >= oracle.len() / 32);
+
+        keys.shuffle(&mut rng);
+        for &key in &keys[..300] {
+            assert!(trie.delete(key));
+            assert!(!trie.delete(key));
+            oracle.remove(&key);
+        }
+        assert_eq!(trie.len(), oracle.len());
+
+        for probe in (0..500 * 37).step_by(5) {
+            assert_eq!(trie.contains(probe), oracle.contains(&probe));
+            assert_eq!(
+                trie.predecessor(probe),
+                oracle.range(..=probe).next_back().copied()
+            );
+            assert_eq!(trie.successor(probe), oracle.range(probe..).next().copied());
+        }
+    }
+
+    #[test]
+    fn test_generic_payloads() {
+        let keys: Vec<Key> = (0..300).map(|i| i * 11).collect();
+        let mut trie: YFastTrie<()> = YFastTrie::from_keys(&keys, 16);
+        assert_eq!(trie.len(), keys.len());
+        assert!(trie.insert(5) && trie.delete(11));
+        assert_eq!(trie.predecessor(12), Some(5));
+        assert_eq!(trie.successor(12), Some(22));
+        // only the groups and their tree nodes, no stores
+        assert!(trie.size_in_bytes() > 0);
+
+        // a value attached to a key, moved along when its group splits
+        #[derive(Debug, Default)]
+        struct Value(u32);
+        impl RepPayload for Value {
+            fn size_in_bytes(&self) -> usize {
+                std::mem::size_of::<Self>()
+            }
+        }
+        let mut trie: YFastTrie<Value> = YFastTrie::empty(16);
+        for key in 0..200 {
+            trie.insert(key * 3);
+        }
+        trie.set_infix_store(300, Value(7));
+        for key in 0..200 {
+            trie.insert(key * 3 + 1);
+        }
+        assert!(trie.sample_count() > 10);
+        let value = trie.get_infix_store(300).unwrap();
+        assert_eq!(value.read().unwrap().0, 7);
+        assert!(Arc::ptr_eq(
+            &trie.predecessor_infix_store(300).unwrap(),
+            &value
+        ));
+    }
+
+    #[test]
+    fn test_insert_delete_keep_infix_stores() {
+        use crate::infix_store::InfixStore;
+
+        let keys: Vec<Key> = (0..16).map(|i| i * 10).collect();
+        let mut trie = YFastTrie::new_with_keys(&keys, 8);
+        for &key in &keys {
+            trie.set_infix_store(key, InfixStore::new_with_infixes(&[key + 1], 8));
+        }
+
+        // split the first group and delete its boundary
+        for key in [1, 2, 3, 4, 5, 6, 7, 8, 9] {
+            assert!(trie.insert(key));
+        }
+        assert!(trie.delete(0));
+
+        for &key in &keys[1..] {
+            let store = trie.get_infix_store(key).unwrap();
+            assert_eq!(store.read().unwrap().infixes(), vec![key + 1]);
+        }
+        assert!(trie.get_infix_store(1).is_none());
+    }
+
+    #[test]
+    fn test_get_infix_store_of_non_boundary_sample() {
+        use crate::infix_store::InfixStore;
+
+        // 40 keys with group size 8: sample 13 is inside the group of boundary 8
+        let keys: Vec<Key> = (0..40).collect();
+        let mut trie = YFastTrie::new_with_keys(&keys, 8);
+        trie.set_infix_store(13, InfixStore::new_with_infixes(&[1, 2], 8));
+
+        assert!(trie.get_infix_store(13).is_some());
+        assert!(trie.get_infix_store(8).is_none());
+    }
 }
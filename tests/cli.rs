@@ -0,0 +1,87 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const BIN: &str = env!("CARGO_BIN_EXE_range-filters");
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("range_filters_cli_{}_{}", std::process::id(), name))
+}
+
+#[test]
+fn test_build_query_stats() {
+    let keys_path = temp_path("keys.txt");
+    let filter_path = temp_path("filter.diva");
+    let keys: Vec<String> = (0..5000).map(|i| (i * 100).to_string()).collect();
+    std::fs::write(&keys_path, keys.join("\n")).unwrap();
+
+    let status = Command::new(BIN)
+        .args([
+            "build",
+            keys_path.to_str().unwrap(),
+            filter_path.to_str().unwrap(),
+        ])
+        .args(["--target-size", "256", "--fpr", "0.01"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut child = Command::new(BIN)
+        .args(["query", filter_path.to_str().unwrap()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"300\n250 350\n\n1000000 2000000\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "true\ntrue\nfalse\n"
+    );
+
+    let output = Command::new(BIN)
+        .args(["stats", filter_path.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stats = String::from_utf8(output.stdout).unwrap();
+    assert!(stats.contains("keys:          5000"));
+    assert!(stats.contains("target size:   256"));
+
+    let output = Command::new(BIN)
+        .args(["bench", filter_path.to_str().unwrap(), "--queries", "1000"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    std::fs::remove_file(&keys_path).unwrap();
+    std::fs::remove_file(&filter_path).unwrap();
+}
+
+#[test]
+fn test_invalid_input() {
+    let output = Command::new(BIN).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    let keys_path = temp_path("bad_keys.txt");
+    std::fs::write(&keys_path, "10\nnot-a-key\n").unwrap();
+    let output = Command::new(BIN)
+        .args(["build", keys_path.to_str().unwrap(), "/dev/null"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("line 2"));
+    std::fs::remove_file(&keys_path).unwrap();
+
+    let output = Command::new(BIN)
+        .args(["query", "/nonexistent/filter.diva"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
@@ -0,0 +1,181 @@
+//! Differential tests of InfixStore against a `BTreeMap` oracle
+//!
+//! Every case bulk-loads a store, replays a random sequence of inserts,
+//! deletes, range deletes, counts and compactions on both the store and the
+//! oracle, and after every step checks that the store holds exactly the
+//! oracle's infixes and passes `validate`. Infixes are drawn from a few hot
+//! quotients half of the time, so long runs, mid-run deletes and resizes
+//! through the size grades all come up.
+
+use proptest::prelude::*;
+use range_filters::{InfixStore, RangeFilterError, StoreLayout};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(u64),
+    Delete(u64),
+    DeleteRange(u64, u64),
+    Count(u64, u64),
+    Compact,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    remainder_size: u8,
+    small_layout: bool,
+    counting: bool,
+    lazy_delete: bool,
+}
+
+impl Config {
+    fn layout(&self) -> StoreLayout {
+        if self.small_layout {
+            // 64 quotients and a handful of grades, so stores fill up and resize often
+            StoreLayout::new(6, &[8, 16, 24, 40, 64]).unwrap()
+        } else {
+            StoreLayout::DEFAULT
+        }
+    }
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    (
+        prop_oneof![Just(0u8), 1u8..=20],
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+    )
+        .prop_map(
+            |(remainder_size, small_layout, counting, lazy_delete)| Config {
+                remainder_size,
+                small_layout,
+                counting,
+                lazy_delete,
+            },
+        )
+}
+
+fn infix(config: Config) -> impl Strategy<Value = u64> {
+    let layout = config.layout();
+    let num_quotients = layout.num_quotients() as u64;
+    let remainders = 1u64 << config.remainder_size;
+    (prop_oneof![0..num_quotients, 0..4u64], 0..remainders)
+        .prop_map(move |(quotient, remainder)| (quotient << config.remainder_size) | remainder)
+}
+
+fn op(config: Config) -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => infix(config).prop_map(Op::Insert),
+        3 => infix(config).prop_map(Op::Delete),
+        1 => (infix(config), 0..64u64).prop_map(|(start, len)| Op::DeleteRange(start, start + len)),
+        2 => (infix(config), 0..64u64).prop_map(|(start, len)| Op::Count(start, start + len)),
+        1 => Just(Op::Compact),
+    ]
+}
+
+fn case() -> impl Strategy<Value = (Config, Vec<u64>, Vec<Op>)> {
+    config().prop_flat_map(|config| {
+        let max_initial = config.layout().max_capacity() / 2;
+        (
+            Just(config),
+            prop::collection::vec(infix(config), 0..=max_initial),
+            prop::collection::vec(op(config), 1..200),
+        )
+    })
+}
+
+fn count(oracle: &BTreeMap<u64, usize>, start: u64, end: u64) -> usize {
+    oracle.range(start..=end).map(|(_, copies)| copies).sum()
+}
+
+fn check(store: &InfixStore, oracle: &BTreeMap<u64, usize>) -> Result<(), TestCaseError> {
+    prop_assert_eq!(store.validate(), Ok(()));
+    let remainder_size = store.remainder_size();
+    let stored: Vec<u64> = store
+        .iter()
+        .map(|(quotient, remainder)| (quotient << remainder_size) | remainder)
+        .collect();
+    let expected: Vec<u64> = oracle
+        .iter()
+        .flat_map(|(&infix, &copies)| std::iter::repeat_n(infix, copies))
+        .collect();
+    prop_assert_eq!(stored, expected);
+    prop_assert_eq!(
+        store.elem_count() - store.tombstone_count(),
+        count(oracle, 0, u64::MAX)
+    );
+    Ok(())
+}
+
+fn run(config: Config, mut initial: Vec<u64>, ops: Vec<Op>) -> Result<(), TestCaseError> {
+    initial.sort_unstable();
+    if !config.counting {
+        initial.dedup();
+    }
+    let layout = config.layout();
+    let mut store = if config.counting {
+        InfixStore::new_counting(&initial, config.remainder_size, layout.clone())
+    } else {
+        InfixStore::new_with_layout(&initial, config.remainder_size, layout.clone())
+    };
+    store.set_lazy_delete(config.lazy_delete);
+    let mut oracle = BTreeMap::new();
+    for infix in initial {
+        *oracle.entry(infix).or_insert(0) += 1;
+    }
+    check(&store, &oracle)?;
+
+    for op in ops {
+        match op {
+            Op::Insert(infix) => {
+                if count(&oracle, 0, u64::MAX) == layout.max_capacity() {
+                    prop_assert_eq!(store.insert(infix), Err(RangeFilterError::PartitionFull));
+                } else {
+                    prop_assert_eq!(store.insert(infix), Ok(()));
+                    let copies = oracle.entry(infix).or_insert(0);
+                    *copies = if config.counting { *copies + 1 } else { 1 };
+                }
+            }
+            Op::Delete(infix) => match oracle.get_mut(&infix) {
+                Some(copies) => {
+                    prop_assert_eq!(store.delete(infix), Ok(()));
+                    *copies -= 1;
+                    if *copies == 0 {
+                        oracle.remove(&infix);
+                    }
+                }
+                None => {
+                    prop_assert_eq!(
+                        store.delete(infix),
+                        Err(RangeFilterError::KeyNotFound(infix))
+                    );
+                }
+            },
+            Op::DeleteRange(start, end) => {
+                prop_assert_eq!(store.delete_range(start, end), count(&oracle, start, end));
+                oracle.retain(|&infix, _| infix < start || infix > end);
+            }
+            Op::Count(start, end) => {
+                // no false negatives and, being exact at the infix level, no false positives
+                prop_assert_eq!(store.count_in_range(start, end), count(&oracle, start, end));
+                prop_assert_eq!(store.count(start), count(&oracle, start, start));
+            }
+            Op::Compact => {
+                store.compact();
+                prop_assert_eq!(store.tombstone_count(), 0);
+            }
+        }
+        check(&store, &oracle)?;
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_store_matches_oracle((config, initial, ops) in case()) {
+        run(config, initial, ops)?;
+    }
+}
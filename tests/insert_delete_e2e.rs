@@ -1,12 +1,12 @@
+use range_filters::RangeFilterError;
 use range_filters::diva::Diva;
-use std::panic;
 
 #[test]
 fn test_basic_roundtrip() {
     let mut diva = Diva::new_with_keys(&[0, 10000], 1024, 0.01);
-    assert!(diva.insert(5000));
-    assert!(diva.delete(5000));
-    assert!(!diva.delete(5000));
+    assert_eq!(diva.insert(5000), Ok(()));
+    assert_eq!(diva.delete(5000), Ok(()));
+    assert_eq!(diva.delete(5000), Err(RangeFilterError::KeyNotFound(5000)));
 }
 
 #[test]
@@ -15,20 +15,16 @@ fn test_bulk_operations() {
     let mut inserted = Vec::new();
 
     for key in 10000..11000 {
-        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-            (&mut *(&mut diva as *mut Diva)).insert(key)
-        }));
-        if let Ok(true) = result {
-            inserted.push(key);
-        }
+        assert_eq!(diva.insert(key), Ok(()));
+        inserted.push(key);
     }
 
     for &key in &inserted {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 
     for &key in &inserted {
-        assert!(!diva.delete(key));
+        assert_eq!(diva.delete(key), Err(RangeFilterError::KeyNotFound(key)));
     }
 }
 
@@ -38,16 +34,12 @@ fn test_dense_insertion() {
     let mut inserted = Vec::new();
 
     for key in 5000..5200 {
-        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-            (&mut *(&mut diva as *mut Diva)).insert(key)
-        }));
-        if let Ok(true) = result {
-            inserted.push(key);
-        }
+        assert_eq!(diva.insert(key), Ok(()));
+        inserted.push(key);
     }
 
     for &key in &inserted {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 }
 
@@ -57,11 +49,11 @@ fn test_sparse_insertion() {
     let keys = vec![1000, 50000, 100000, 500000, 900000];
 
     for &key in &keys {
-        assert!(diva.insert(key));
+        assert_eq!(diva.insert(key), Ok(()));
     }
 
     for &key in &keys {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 }
 
@@ -69,41 +61,41 @@ fn test_sparse_insertion() {
 fn test_interleaved_ops() {
     let mut diva = Diva::new_with_keys(&[0, 100000], 1024, 0.01);
 
-    assert!(diva.insert(10000));
-    assert!(diva.insert(20000));
-    assert!(diva.delete(10000));
-    assert!(diva.insert(30000));
-    assert!(diva.delete(20000));
-    assert!(diva.insert(40000));
-    assert!(diva.delete(30000));
-    assert!(diva.delete(40000));
+    assert_eq!(diva.insert(10000), Ok(()));
+    assert_eq!(diva.insert(20000), Ok(()));
+    assert_eq!(diva.delete(10000), Ok(()));
+    assert_eq!(diva.insert(30000), Ok(()));
+    assert_eq!(diva.delete(20000), Ok(()));
+    assert_eq!(diva.insert(40000), Ok(()));
+    assert_eq!(diva.delete(30000), Ok(()));
+    assert_eq!(diva.delete(40000), Ok(()));
 }
 
 #[test]
 fn test_boundary_cases() {
     let mut diva = Diva::new_with_keys(&[1000, 5000, 10000], 1024, 0.01);
 
-    assert!(diva.insert(1001));
-    assert!(diva.insert(4999));
-    assert!(diva.insert(5001));
-    assert!(diva.insert(9999));
+    assert_eq!(diva.insert(1001), Ok(()));
+    assert_eq!(diva.insert(4999), Ok(()));
+    assert_eq!(diva.insert(5001), Ok(()));
+    assert_eq!(diva.insert(9999), Ok(()));
 
-    assert!(diva.delete(1001));
-    assert!(diva.delete(4999));
-    assert!(diva.delete(5001));
-    assert!(diva.delete(9999));
+    assert_eq!(diva.delete(1001), Ok(()));
+    assert_eq!(diva.delete(4999), Ok(()));
+    assert_eq!(diva.delete(5001), Ok(()));
+    assert_eq!(diva.delete(9999), Ok(()));
 }
 
 #[test]
 fn test_duplicate_handling() {
     let mut diva = Diva::new_with_keys(&[0, 10000], 1024, 0.01);
 
-    assert!(diva.insert(5000));
-    assert!(diva.insert(5000));
-    assert!(diva.insert(5000));
+    assert_eq!(diva.insert(5000), Ok(()));
+    assert_eq!(diva.insert(5000), Ok(()));
+    assert_eq!(diva.insert(5000), Ok(()));
 
-    assert!(diva.delete(5000));
-    assert!(!diva.delete(5000));
+    assert_eq!(diva.delete(5000), Ok(()));
+    assert_eq!(diva.delete(5000), Err(RangeFilterError::KeyNotFound(5000)));
 }
 
 #[test]
@@ -112,30 +104,22 @@ fn test_partial_deletion() {
     let mut inserted = Vec::new();
 
     for key in 10000..10100 {
-        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-            (&mut *(&mut diva as *mut Diva)).insert(key)
-        }));
-        if let Ok(true) = result {
-            inserted.push(key);
-        }
+        assert_eq!(diva.insert(key), Ok(()));
+        inserted.push(key);
     }
 
     let half = inserted.len() / 2;
     for &key in &inserted[..half] {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 
     for key in 10100..10150 {
-        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-            (&mut *(&mut diva as *mut Diva)).insert(key)
-        }));
-        if let Ok(true) = result {
-            inserted.push(key);
-        }
+        assert_eq!(diva.insert(key), Ok(()));
+        inserted.push(key);
     }
 
     for &key in &inserted[half..] {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 }
 
@@ -145,16 +129,12 @@ fn test_reverse_deletion() {
     let mut inserted = Vec::new();
 
     for key in 10000..10050 {
-        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-            (&mut *(&mut diva as *mut Diva)).insert(key)
-        }));
-        if let Ok(true) = result {
-            inserted.push(key);
-        }
+        assert_eq!(diva.insert(key), Ok(()));
+        inserted.push(key);
     }
 
     for &key in inserted.iter().rev() {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 }
 
@@ -173,64 +153,46 @@ fn test_clustered_data() {
 
     for cluster in clusters {
         for key in cluster {
-            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-                (&mut *(&mut diva as *mut Diva)).insert(key)
-            }));
-            if let Ok(true) = result {
-                inserted.push(key);
-            }
+            assert_eq!(diva.insert(key), Ok(()));
+            inserted.push(key);
         }
     }
 
     for &key in &inserted {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 }
 
 #[test]
 fn test_out_of_bounds_below() {
     let mut diva = Diva::new_with_keys(&[1000, 10000], 1024, 0.01);
-    let insert_result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-        (&mut *(&mut diva as *mut Diva)).insert(500)
-    }));
-    if let Ok(true) = insert_result {
-        let delete_result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-            (&mut *(&mut diva as *mut Diva)).delete(500)
-        }));
-        assert!(matches!(delete_result, Ok(true)));
-    }
+    assert_eq!(diva.insert(500), Ok(()));
+    assert_eq!(diva.delete(500), Ok(()));
 }
 
 #[test]
 fn test_out_of_bounds_above() {
     let mut diva = Diva::new_with_keys(&[1000, 10000], 1024, 0.01);
-    let insert_result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-        (&mut *(&mut diva as *mut Diva)).insert(20000)
-    }));
-    if let Ok(true) = insert_result {
-        let delete_result = panic::catch_unwind(panic::AssertUnwindSafe(|| unsafe {
-            (&mut *(&mut diva as *mut Diva)).delete(20000)
-        }));
-        assert!(matches!(delete_result, Ok(true)));
-    }
+    assert_eq!(diva.insert(20000), Ok(()));
+    assert_eq!(diva.delete(20000), Ok(()));
 }
 
 #[test]
 fn test_insert_sample_key() {
     let mut diva = Diva::new_with_keys(&[1000, 5000, 10000], 1024, 0.01);
-    assert!(diva.insert(1000));
-    assert!(diva.insert(5000));
-    assert!(diva.insert(10000));
+    assert_eq!(diva.insert(1000), Ok(()));
+    assert_eq!(diva.insert(5000), Ok(()));
+    assert_eq!(diva.insert(10000), Ok(()));
 }
 
 #[test]
 fn test_reinsert_after_delete() {
     let mut diva = Diva::new_with_keys(&[0, 10000], 1024, 0.01);
-    assert!(diva.insert(5000));
-    assert!(diva.delete(5000));
-    assert!(diva.insert(5000));
-    assert!(diva.delete(5000));
-    assert!(!diva.delete(5000));
+    assert_eq!(diva.insert(5000), Ok(()));
+    assert_eq!(diva.delete(5000), Ok(()));
+    assert_eq!(diva.insert(5000), Ok(()));
+    assert_eq!(diva.delete(5000), Ok(()));
+    assert_eq!(diva.delete(5000), Err(RangeFilterError::KeyNotFound(5000)));
 }
 
 #[test]
@@ -239,15 +201,27 @@ fn test_reinsert_multiple_keys() {
     let keys = vec![1000, 2000, 3000];
 
     for &key in &keys {
-        assert!(diva.insert(key));
+        assert_eq!(diva.insert(key), Ok(()));
     }
     for &key in &keys {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
     for &key in &keys {
-        assert!(diva.insert(key));
+        assert_eq!(diva.insert(key), Ok(()));
     }
     for &key in &keys {
-        assert!(diva.delete(key));
+        assert_eq!(diva.delete(key), Ok(()));
     }
 }
+
+#[test]
+fn test_delete_sample_key() {
+    let mut diva = Diva::new_with_keys(&[1000, 2000, 5000, 10000], 2, 0.01);
+    assert_eq!(diva.delete(5000), Ok(()));
+    assert_eq!(diva.delete(5000), Err(RangeFilterError::KeyNotFound(5000)));
+    assert!(diva.contains(2000));
+    assert!(diva.contains(10000));
+
+    assert_eq!(diva.delete(1000), Ok(()));
+    assert!(diva.contains(2000));
+}
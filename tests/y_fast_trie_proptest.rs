@@ -0,0 +1,137 @@
+//! Differential tests of YFastTrie against a `BTreeSet` oracle
+//!
+//! Every case bulk-loads a trie over 16-bit keys under a random group size
+//! policy, replays a random sequence of
+//! inserts, deletes, lookups and range scans on both the trie and the oracle, and checks
+//! predecessor, successor and contains around every touched key. Keys are drawn
+//! from a narrow range half of the time, so groups split, lose their boundaries
+//! and leave queries whose answer sits in a neighbouring group.
+
+use proptest::prelude::*;
+use range_filters::{GroupSizePolicy, YFastTrie};
+use std::collections::BTreeSet;
+
+const NO_LEVELS: usize = 16;
+const MAX_KEY: u64 = (1 << NO_LEVELS) - 1;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(u64),
+    Delete(u64),
+    Query(u64),
+    Range(u64, u64),
+}
+
+fn key() -> impl Strategy<Value = u64> {
+    prop_oneof![0..=MAX_KEY, 1000..1200u64]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => key().prop_map(Op::Insert),
+        3 => key().prop_map(Op::Delete),
+        2 => key().prop_map(Op::Query),
+        1 => (key(), 0..500u64).prop_map(|(lo, len)| Op::Range(lo, lo + len)),
+    ]
+}
+
+fn group_size() -> impl Strategy<Value = GroupSizePolicy> {
+    prop_oneof![
+        (1..12usize).prop_map(GroupSizePolicy::Fixed),
+        (1..3usize).prop_map(GroupSizePolicy::LogMultiple),
+        (1..40usize, 0.1..=1.0f64).prop_map(|(capacity, load_factor)| {
+            GroupSizePolicy::LoadFactor {
+                capacity,
+                load_factor,
+            }
+        }),
+    ]
+}
+
+fn case() -> impl Strategy<Value = (GroupSizePolicy, Vec<u64>, Vec<Op>)> {
+    (
+        group_size(),
+        prop::collection::vec(key(), 0..200),
+        prop::collection::vec(op(), 1..300),
+    )
+}
+
+fn check_around(
+    trie: &YFastTrie<()>,
+    oracle: &BTreeSet<u64>,
+    key: u64,
+) -> Result<(), TestCaseError> {
+    for probe in [key.saturating_sub(1), key, (key + 1).min(MAX_KEY)] {
+        prop_assert_eq!(
+            trie.contains(probe),
+            oracle.contains(&probe),
+            "contains {}",
+            probe
+        );
+        prop_assert_eq!(
+            trie.predecessor(probe),
+            oracle.range(..=probe).next_back().copied(),
+            "predecessor {}",
+            probe
+        );
+        prop_assert_eq!(
+            trie.successor(probe),
+            oracle.range(probe..).next().copied(),
+            "successor {}",
+            probe
+        );
+    }
+    Ok(())
+}
+
+fn run(group_size: GroupSizePolicy, initial: Vec<u64>, ops: Vec<Op>) -> Result<(), TestCaseError> {
+    let mut trie = YFastTrie::<()>::from_keys_with_group_size(&initial, NO_LEVELS, group_size);
+    let mut oracle: BTreeSet<u64> = initial.into_iter().collect();
+    prop_assert_eq!(trie.len(), oracle.len());
+
+    for op in ops {
+        let key = match op {
+            Op::Insert(key) => {
+                prop_assert_eq!(trie.insert(key), oracle.insert(key));
+                key
+            }
+            Op::Delete(key) => {
+                prop_assert_eq!(trie.delete(key), oracle.remove(&key));
+                key
+            }
+            Op::Query(key) => key,
+            Op::Range(lo, hi) => {
+                let keys: Vec<u64> = trie.range(lo..=hi).collect();
+                let expected: Vec<u64> = oracle.range(lo..=hi).copied().collect();
+                prop_assert_eq!(keys, expected);
+                lo
+            }
+        };
+        prop_assert_eq!(trie.len(), oracle.len());
+        let max_len = group_size.max_len(NO_LEVELS);
+        for rep in trie.x_fast_trie.reps.iter() {
+            let rep = rep.read().unwrap();
+            let group_len = rep.payload.as_ref().unwrap().read().unwrap().len();
+            prop_assert!(group_len <= max_len, "group of {} keys", group_len);
+        }
+        check_around(&trie, &oracle, key)?;
+        // the extremes and the oracle's neighbours of the key cross group boundaries
+        let below = oracle.range(..key).next_back().copied();
+        let above = oracle.range(key..).next().copied();
+        for neighbour in below.into_iter().chain(above) {
+            check_around(&trie, &oracle, neighbour)?;
+        }
+        check_around(&trie, &oracle, 0)?;
+        check_around(&trie, &oracle, MAX_KEY)?;
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_trie_matches_oracle((group_size, initial, ops) in case()) {
+        run(group_size, initial, ops)?;
+    }
+}